@@ -0,0 +1,58 @@
+#ifndef FLUMINURS_H
+#define FLUMINURS_H
+
+/*
+ * A C interface to fluminurs.
+ *
+ * Every function blocks until it is done. Strings passed in are UTF-8 and are only borrowed.
+ * Strings handed out, including error messages, belong to the caller and must be released
+ * with fluminurs_string_free. A session must not be used from two threads at once.
+ */
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Bumped whenever a function below changes in an incompatible way */
+#define FLUMINURS_ABI_VERSION 1
+
+typedef struct FluminursSession FluminursSession;
+
+uint32_t fluminurs_abi_version(void);
+
+/* Returns NULL and sets *error on failure */
+FluminursSession *fluminurs_login(const char *username, const char *password, char **error);
+
+void fluminurs_session_free(FluminursSession *session);
+
+/*
+ * A JSON array of {"id", "code", "name", "term"} objects. `term` (e.g. "2010") may be NULL,
+ * for the current and later terms. Returns NULL and sets *error on failure.
+ */
+char *fluminurs_modules_json(FluminursSession *session, const char *term, char **error);
+
+/*
+ * A JSON array of {"path", "lastUpdated", "size", "permalink"} objects for the files of a module
+ * from the last call to fluminurs_modules_json. lastUpdated is in seconds since the Unix epoch,
+ * and size and permalink may be null. Returns NULL and sets *error on failure.
+ */
+char *fluminurs_files_json(FluminursSession *session, const char *module_id, char **error);
+
+/*
+ * Downloads the file at `path`, as listed by the last fluminurs_files_json for its module, under
+ * `destination`. `overwrite` is one of "skip", "overwrite", "rename" or "trash", and may be NULL
+ * for "skip". Returns what happened, one of "new", "unchanged", "skipped", "updated", "renamed"
+ * or "trashed", or NULL and sets *error on failure.
+ */
+char *fluminurs_download_file(FluminursSession *session, const char *module_id, const char *path,
+                              const char *destination, const char *overwrite, char **error);
+
+void fluminurs_string_free(char *string);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
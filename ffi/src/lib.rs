@@ -0,0 +1,243 @@
+use std::collections::HashMap;
+use std::ffi::{CStr, CString, OsString};
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::ptr;
+use std::time::UNIX_EPOCH;
+
+use serde_json::json;
+use tokio::runtime::Runtime;
+
+use fluminurs_core::file::File;
+use fluminurs_core::module::Module;
+use fluminurs_core::resource::{OverwriteMode, OverwriteResult, Resource};
+use fluminurs_core::Api;
+
+// keep in sync with FLUMINURS_ABI_VERSION in include/fluminurs.h
+const ABI_VERSION: u32 = 1;
+
+// What the C side holds on to. Modules and files are kept from the last listing,
+// so that callers can refer to them by id and path instead of holding Rust objects.
+pub struct FluminursSession {
+    api: Api,
+    runtime: Runtime,
+    modules: Vec<Module>,
+    // module id => its files
+    files: HashMap<String, Vec<File>>,
+}
+
+type FfiResult<T> = std::result::Result<T, String>;
+
+unsafe fn borrow_str<'a>(string: *const c_char, what: &str) -> FfiResult<&'a str> {
+    if string.is_null() {
+        return Err(format!("{} must not be null", what));
+    }
+    CStr::from_ptr(string)
+        .to_str()
+        .map_err(|_| format!("{} is not valid UTF-8", what))
+}
+
+unsafe fn borrow_optional_str<'a>(string: *const c_char, what: &str) -> FfiResult<Option<&'a str>> {
+    if string.is_null() {
+        Ok(None)
+    } else {
+        borrow_str(string, what).map(Some)
+    }
+}
+
+fn into_c_string(string: String) -> *mut c_char {
+    // interior nul bytes can't be represented, and shouldn't be in anything we hand out anyway
+    CString::new(string.replace('\0', ""))
+        .map(CString::into_raw)
+        .unwrap_or(ptr::null_mut())
+}
+
+// Runs `f`, reporting failures (including panics, which must not unwind into C) through `error`
+unsafe fn guard<T, F: FnOnce() -> FfiResult<T>>(error: *mut *mut c_char, failed: T, f: F) -> T {
+    let result = panic::catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or_else(|_| Err("fluminurs panicked".to_owned()));
+    match result {
+        Ok(value) => value,
+        Err(message) => {
+            if !error.is_null() {
+                *error = into_c_string(message);
+            }
+            failed
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn fluminurs_abi_version() -> u32 {
+    ABI_VERSION
+}
+
+/// # Safety
+/// `username` and `password` must be nul-terminated strings, and `error` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn fluminurs_login(
+    username: *const c_char,
+    password: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut FluminursSession {
+    guard(error, ptr::null_mut(), || {
+        let username = borrow_str(username, "username")?;
+        let password = borrow_str(password, "password")?;
+        let runtime = Runtime::new().map_err(|e| e.to_string())?;
+        let api = runtime
+            .block_on(Api::with_login(username, password))
+            .map_err(|e| format!("{}. {}", e, e.advice()))?;
+        Ok(Box::into_raw(Box::new(FluminursSession {
+            api,
+            runtime,
+            modules: vec![],
+            files: HashMap::new(),
+        })))
+    })
+}
+
+/// # Safety
+/// `session` must have come from `fluminurs_login` and not been freed already, or be null.
+#[no_mangle]
+pub unsafe extern "C" fn fluminurs_session_free(session: *mut FluminursSession) {
+    if !session.is_null() {
+        drop(Box::from_raw(session));
+    }
+}
+
+/// # Safety
+/// `session` must be a live session, `term` null or a nul-terminated string, and `error` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn fluminurs_modules_json(
+    session: *mut FluminursSession,
+    term: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut c_char {
+    guard(error, ptr::null_mut(), || {
+        let session = session.as_mut().ok_or("session must not be null")?;
+        let term = borrow_optional_str(term, "term")?.map(|term| term.to_owned());
+        let modules = session
+            .runtime
+            .block_on(session.api.modules(term))
+            .map_err(|e| e.to_owned())?;
+        let listing = modules
+            .iter()
+            .map(|module| {
+                json!({
+                    "id": module.id,
+                    "code": module.code,
+                    "name": module.name,
+                    "term": module.term,
+                })
+            })
+            .collect::<Vec<_>>();
+        session.modules = modules;
+        session.files.clear();
+        Ok(into_c_string(json!(listing).to_string()))
+    })
+}
+
+/// # Safety
+/// `session` must be a live session, `module_id` a nul-terminated string, and `error` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn fluminurs_files_json(
+    session: *mut FluminursSession,
+    module_id: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut c_char {
+    guard(error, ptr::null_mut(), || {
+        let session = session.as_mut().ok_or("session must not be null")?;
+        let module_id = borrow_str(module_id, "module_id")?;
+        let module = session
+            .modules
+            .iter()
+            .find(|module| module.id == module_id)
+            .ok_or("Unknown module, list modules first")?;
+        let files = session
+            .runtime
+            .block_on(
+                module
+                    .workbin_root(|dir| dir.to_owned())
+                    .load(&session.api, false),
+            )
+            .map_err(|e| e.to_owned())?;
+        let listing = files
+            .iter()
+            .map(|file| {
+                json!({
+                    "path": file.path(),
+                    "lastUpdated": file
+                        .last_updated()
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |elapsed| elapsed.as_secs()),
+                    "size": file.size(),
+                    "permalink": file.permalink(),
+                })
+            })
+            .collect::<Vec<_>>();
+        session.files.insert(module_id.to_owned(), files);
+        Ok(into_c_string(json!(listing).to_string()))
+    })
+}
+
+/// # Safety
+/// `session` must be a live session, `module_id`, `path` and `destination` nul-terminated strings,
+/// `overwrite` null or a nul-terminated string, and `error` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn fluminurs_download_file(
+    session: *mut FluminursSession,
+    module_id: *const c_char,
+    path: *const c_char,
+    destination: *const c_char,
+    overwrite: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut c_char {
+    guard(error, ptr::null_mut(), || {
+        let session = session.as_mut().ok_or("session must not be null")?;
+        let module_id = borrow_str(module_id, "module_id")?;
+        let path = borrow_str(path, "path")?;
+        let destination = borrow_str(destination, "destination")?;
+        let overwrite = borrow_optional_str(overwrite, "overwrite")?.unwrap_or("skip");
+        let mode = OverwriteMode::parse(overwrite).ok_or_else(|| {
+            format!(
+                "overwrite must be one of {}",
+                OverwriteMode::NAMES.join(", ")
+            )
+        })?;
+        let file = session
+            .files
+            .get(module_id)
+            .and_then(|files| files.iter().find(|file| file.path() == Path::new(path)))
+            .ok_or("Unknown file, list the module's files first")?;
+        let file_path = Path::new(destination).join(file.path());
+        // same temporary name as the command line client, so either can clean up after the other
+        let mut temp_name = OsString::from("~!");
+        temp_name.push(file_path.file_name().unwrap_or_default());
+        let temp_path = file_path.with_file_name(temp_name);
+        let result = session
+            .runtime
+            .block_on(file.download(&session.api, &file_path, &temp_path, mode))
+            .map_err(|e| e.to_owned())?;
+        Ok(into_c_string(
+            match result {
+                OverwriteResult::NewFile => "new",
+                OverwriteResult::AlreadyHave => "unchanged",
+                OverwriteResult::Skipped => "skipped",
+                OverwriteResult::Overwritten => "updated",
+                OverwriteResult::Renamed { .. } => "renamed",
+                OverwriteResult::Trashed => "trashed",
+            }
+            .to_owned(),
+        ))
+    })
+}
+
+/// # Safety
+/// `string` must have been handed out by fluminurs and not been freed already, or be null.
+#[no_mangle]
+pub unsafe extern "C" fn fluminurs_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
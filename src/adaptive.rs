@@ -0,0 +1,128 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+// failures this close together are one spike, and only slow us down once
+const SLOWDOWN_COOLDOWN: Duration = Duration::from_secs(2);
+
+// How many downloads may run at once, adjusted as we go: halved when downloads start failing
+// or the server asks us to back off, and raised by one after a round of downloads goes well
+#[derive(Debug)]
+pub struct AdaptiveLimit {
+    semaphore: Arc<Semaphore>,
+    min: usize,
+    max: usize,
+    state: Mutex<LimitState>,
+}
+
+#[derive(Debug)]
+struct LimitState {
+    limit: usize,
+    // successes since the limit last changed
+    successes: usize,
+    // permits to take out of circulation as they come back, after the limit was lowered
+    // below what was already handed out
+    owed: usize,
+    last_slowdown: Option<Instant>,
+    slowdowns: usize,
+    lowest: usize,
+}
+
+// What the limit went through, for reporting
+#[derive(Debug, Clone, Copy)]
+pub struct LimitReport {
+    pub max: usize,
+    pub current: usize,
+    pub lowest: usize,
+    pub slowdowns: usize,
+}
+
+pub struct AdaptivePermit<'a> {
+    permit: Option<OwnedSemaphorePermit>,
+    limit: &'a AdaptiveLimit,
+}
+
+impl AdaptiveLimit {
+    pub fn new(max: usize) -> AdaptiveLimit {
+        let max = max.max(1);
+        AdaptiveLimit {
+            semaphore: Arc::new(Semaphore::new(max)),
+            min: 1,
+            max,
+            state: Mutex::new(LimitState {
+                limit: max,
+                successes: 0,
+                owed: 0,
+                last_slowdown: None,
+                slowdowns: 0,
+                lowest: max,
+            }),
+        }
+    }
+
+    pub async fn acquire(&self) -> AdaptivePermit<'_> {
+        AdaptivePermit {
+            permit: self.semaphore.clone().acquire_owned().await.ok(),
+            limit: self,
+        }
+    }
+
+    pub fn report_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.successes += 1;
+        if state.successes < state.limit || state.limit >= self.max {
+            return;
+        }
+        state.successes = 0;
+        state.limit += 1;
+        if state.owed > 0 {
+            state.owed -= 1;
+        } else {
+            self.semaphore.add_permits(1);
+        }
+    }
+
+    // for timeouts, dropped connections and the server telling us to slow down
+    pub fn report_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.successes = 0;
+        if state
+            .last_slowdown
+            .is_some_and(|last| last.elapsed() < SLOWDOWN_COOLDOWN)
+        {
+            return;
+        }
+        let lowered = (state.limit / 2).max(self.min);
+        if lowered == state.limit {
+            return;
+        }
+        state.owed += state.limit - lowered;
+        state.limit = lowered;
+        state.lowest = state.lowest.min(lowered);
+        state.slowdowns += 1;
+        state.last_slowdown = Some(Instant::now());
+    }
+
+    pub fn report(&self) -> LimitReport {
+        let state = self.state.lock().unwrap();
+        LimitReport {
+            max: self.max,
+            current: state.limit,
+            lowest: state.lowest,
+            slowdowns: state.slowdowns,
+        }
+    }
+}
+
+impl Drop for AdaptivePermit<'_> {
+    fn drop(&mut self) {
+        if let Some(permit) = self.permit.take() {
+            let mut state = self.limit.state.lock().unwrap();
+            if state.owed > 0 {
+                state.owed -= 1;
+                permit.forget();
+            }
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use regex::{Regex, RegexBuilder};
+
+use crate::module::Announcement;
+use crate::util::html_to_text;
+use crate::Result;
+
+// What makes an announcement urgent enough to raise an alert for, one rule per line: a word or
+// phrase such as `venue change`, or a regular expression between slashes such as `/cancel(led)?/`.
+// Case is ignored. Blank lines and lines starting with # are ignored.
+#[derive(Debug, Default)]
+pub struct AlertRules {
+    // the line as written => what it matches
+    rules: Vec<(String, Regex)>,
+}
+
+impl AlertRules {
+    // a missing file just means that nothing raises an alert
+    pub fn load(path: &Path) -> Result<AlertRules> {
+        match fs::read_to_string(path) {
+            Ok(content) => AlertRules::parse(&content),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(AlertRules::default()),
+            Err(_) => Err("Unable to read alerts file"),
+        }
+    }
+
+    pub fn parse(content: &str) -> Result<AlertRules> {
+        let mut rules = vec![];
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let pattern = match line
+                .strip_prefix('/')
+                .and_then(|rest| rest.strip_suffix('/'))
+            {
+                Some(pattern) if !pattern.is_empty() => pattern.to_owned(),
+                // whole words only, so that "exam" doesn't go off for "example"
+                _ => format!(r"\b{}\b", regex::escape(line)),
+            };
+            let regex = RegexBuilder::new(&pattern)
+                .case_insensitive(true)
+                .build()
+                .map_err(|_| "Invalid regular expression in alerts file")?;
+            rules.push((line.to_owned(), regex));
+        }
+        Ok(AlertRules { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    // the first rule that the announcement's title or text matches, as written in the file
+    pub fn matching(&self, announcement: &Announcement) -> Option<&str> {
+        let text = html_to_text(&announcement.description);
+        self.rules
+            .iter()
+            .find(|(_, regex)| regex.is_match(&announcement.title) || regex.is_match(&text))
+            .map(|(line, _)| line.as_str())
+    }
+}
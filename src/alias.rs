@@ -0,0 +1,75 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::util::closest_matches;
+use crate::Result;
+
+// Nicknames for modules, one `alias = CODE` per line, e.g. `algo = CS2040S`.
+// Blank lines and lines starting with # are ignored.
+#[derive(Debug, Default)]
+pub struct ModuleAliases {
+    // lowercased alias => module code
+    aliases: BTreeMap<String, String>,
+}
+
+impl ModuleAliases {
+    // a missing file just means that no aliases are defined
+    pub fn load(path: &Path) -> Result<ModuleAliases> {
+        match fs::read_to_string(path) {
+            Ok(content) => ModuleAliases::parse(&content),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(ModuleAliases::default()),
+            Err(_) => Err("Unable to read aliases file"),
+        }
+    }
+
+    pub fn parse(content: &str) -> Result<ModuleAliases> {
+        let mut aliases = BTreeMap::new();
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (alias, code) = line
+                .split_once('=')
+                .ok_or("Invalid line in aliases file, expected alias = CODE")?;
+            let (alias, code) = (alias.trim(), code.trim());
+            if alias.is_empty() || code.is_empty() {
+                return Err("Invalid line in aliases file, expected alias = CODE");
+            }
+            aliases.insert(alias.to_lowercase(), code.to_owned());
+        }
+        Ok(ModuleAliases { aliases })
+    }
+
+    // the module code that `reference` stands for, which is itself if it isn't an alias
+    pub fn expand<'a>(&'a self, reference: &'a str) -> &'a str {
+        self.aliases
+            .get(&reference.to_lowercase())
+            .map_or(reference, String::as_str)
+    }
+
+    // Finds which of `codes` `reference` names, ignoring case. When none does, the closest codes
+    // and aliases are returned instead, to suggest in place of what was probably a typo.
+    pub fn resolve<'a>(
+        &self,
+        reference: &str,
+        codes: &[&'a str],
+    ) -> std::result::Result<&'a str, Vec<String>> {
+        let expanded = self.expand(reference);
+        if let Some(code) = codes
+            .iter()
+            .find(|code| code.eq_ignore_ascii_case(expanded))
+        {
+            return Ok(code);
+        }
+        let candidates = codes.iter().map(|code| code.to_string()).chain(
+            self.aliases
+                .iter()
+                // aliases for modules we don't have wouldn't resolve either
+                .filter(|(_, code)| codes.iter().any(|c| c.eq_ignore_ascii_case(code)))
+                .map(|(alias, _)| alias.clone()),
+        );
+        Err(closest_matches(reference, candidates, 3))
+    }
+}
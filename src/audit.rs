@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use reqwest::Method;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+// An append-only record of every request that could change something on the server,
+// so that there is a local trail of what was done in the user's name
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AuditEntry<'a> {
+    timestamp: String,
+    method: &'a str,
+    endpoint: &'a str,
+    // the payload itself may hold marks or comments, so we only keep a digest of it
+    payload_digest: Option<String>,
+    result: &'a str,
+}
+
+impl AuditLog {
+    pub fn new<P: Into<PathBuf>>(path: P) -> AuditLog {
+        AuditLog { path: path.into() }
+    }
+
+    pub(crate) fn record(
+        &self,
+        method: &Method,
+        endpoint: &str,
+        form: Option<&HashMap<&str, &str>>,
+        result: &str,
+    ) {
+        let payload_digest = form
+            .and_then(|form| serde_urlencoded::to_string(form).ok())
+            .map(|payload| {
+                Sha256::digest(payload.as_bytes())
+                    .iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect::<String>()
+            });
+        let entry = AuditEntry {
+            timestamp: chrono::DateTime::<chrono::Utc>::from(SystemTime::now()).to_rfc3339(),
+            method: method.as_str(),
+            endpoint,
+            payload_digest,
+            result,
+        };
+        let line = match serde_json::to_string(&entry) {
+            Ok(line) => line,
+            Err(_) => return,
+        };
+        let written = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| writeln!(file, "{}", line));
+        if written.is_err() {
+            println!(
+                "Warning: unable to write to audit log {}",
+                self.path.to_string_lossy()
+            );
+        }
+    }
+}
@@ -1,20 +1,68 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::ffi::OsStr;
 use std::ffi::OsString;
 use std::fs;
 use std::io;
+use std::io::IsTerminal;
 use std::io::{Read, Write};
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
-use clap::{App, Arg};
-use futures_util::{future, stream, StreamExt};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use futures_util::future;
+use once_cell::sync::OnceCell;
 use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
 
-use fluminurs::file::File;
-use fluminurs::module::Module;
+use fluminurs::alert::AlertRules;
+use fluminurs::alias::ModuleAliases;
+use fluminurs::audit::AuditLog;
+use fluminurs::browse;
+use fluminurs::calendar::CalendarEvent;
+use fluminurs::canvas::Canvas;
+use fluminurs::deadline::Deadline;
+use fluminurs::dedupe::{self, Duplicates};
+use fluminurs::diagnose::Bundle;
+use fluminurs::diff::{is_diffable, ContentDiff};
+use fluminurs::dump::{self, KnownEndpoint};
+use fluminurs::extract;
+use fluminurs::file::{self, File, FolderCache};
+use fluminurs::group;
+use fluminurs::history::{self, FileChange, SyncRun};
+use fluminurs::i18n::{self, Lang};
+use fluminurs::ical;
+use fluminurs::ignore::{IgnoreFiles, IGNORE_FILE_NAME};
+use fluminurs::index as html_index;
+use fluminurs::manifest::{KnownModule, Manifest, ManifestLock};
+use fluminurs::message::Conversation;
+use fluminurs::module::{Announcement, FolderNameStyle, Module, ModuleTools};
+use fluminurs::moves::{self, Fingerprint, MovePolicy};
 use fluminurs::multimedia::Video;
-use fluminurs::resource::{OverwriteMode, OverwriteResult, Resource};
-use fluminurs::{Api, Result};
+use fluminurs::pdf;
+use fluminurs::platform::LearningPlatform;
+use fluminurs::profile::SyncProfiles;
+use fluminurs::report::{Digest, ModuleReport, Report};
+use fluminurs::resource::{
+    self, CompareBy, OverwriteMode, OverwriteResult, Resource, ORIGINALS_DIR,
+};
+use fluminurs::roster;
+use fluminurs::sniff;
+use fluminurs::standing::{self, Standing};
+use fluminurs::stats::{HostStats, TransferStats};
+use fluminurs::strict::DriftLog;
+use fluminurs::submission;
+use fluminurs::subtitle::TranscriptionHook;
+use fluminurs::sync::{self, Action, Found, Pipeline, PlannedSync, SyncPlan, Turns};
+use fluminurs::term::{terms_of, AcademicTerm};
+use fluminurs::transcode::{self, TranscodeProfile};
+use fluminurs::util::{
+    closest_matches, format_size, html_to_text, parse_duration, parse_size, sanitise_filename,
+};
+use fluminurs::versions::{RenameScheme, Retention};
+use fluminurs::{Api, Endpoints, Result};
 
 #[macro_use]
 extern crate bitflags;
@@ -22,6 +70,56 @@ extern crate bitflags;
 const PKG_NAME: &str = env!("CARGO_PKG_NAME");
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 const DESCRIPTION: &str = env!("CARGO_PKG_DESCRIPTION");
+// downloads in flight at once while things go well; fewer while they keep failing
+const MAX_DOWNLOADS: usize = 64;
+const PRIORITY_PARTS: [&str; 3] = ["announcements", "files", "multimedia"];
+// what --offline can't do, by argument name and how it is given on the command line
+const ONLINE_ONLY_ARGS: [(&str, &str); 18] = [
+    ("announcements", "--announcements"),
+    ("announcements-digest", "--announcements-digest"),
+    ("messages", "--messages"),
+    ("export-messages", "--export-messages-to"),
+    ("groups", "--groups"),
+    ("export-groups", "--export-groups-to"),
+    ("peer-evaluations", "--peer-evaluations"),
+    ("export-peer-evaluations", "--export-peer-evaluations-to"),
+    ("export-syllabus", "--export-syllabus-to"),
+    ("deadlines", "--deadlines"),
+    ("export-deadlines", "--export-deadlines-to"),
+    ("calendar", "--calendar"),
+    ("export-calendar", "--export-calendar-to"),
+    ("export-ics", "--export-ics-to"),
+    ("archive-expiring", "--archive-expiring-to"),
+    ("download", "--download-to"),
+    ("list-multimedia", "--list-multimedia"),
+    ("download-multimedia", "--download-multimedia-to"),
+];
+// chosen once from --lang or the locale, before anything is printed
+static LANG: OnceCell<Lang> = OnceCell::new();
+
+// the message in the chosen language, or as it is if it has no translation
+fn tr(message: &'static str) -> &'static str {
+    i18n::translate(LANG.get().copied().unwrap_or(Lang::English), message)
+}
+
+// println! for messages in the catalog, which only take plain {}s
+macro_rules! say {
+    ($message:literal) => {
+        println!("{}", tr($message))
+    };
+    ($message:literal, $($value:expr),+ $(,)?) => {
+        println!("{}", i18n::fill(tr($message), &[$(&$value as &dyn std::fmt::Display),+]))
+    };
+}
+
+// Shown once under --help, rather than on every option it applies to
+const UNVERIFIED_NOTE: &str = "Only the endpoints behind announcements, files, multimedia and \
+     the module list have been checked against LumiNUS itself. Options reading the others, such as \
+     --messages, --groups, --peer-evaluations, --deadlines, --calendar, --export-syllabus-to and \
+     their exports, and the roster subcommand, may not work there yet. api-dump lists which \
+     endpoints are unverified.";
+
+const ONLINE_ONLY_SUBCOMMANDS: [&str; 5] = ["plan", "apply", "adopt", "roster", "api-dump"];
 
 #[derive(Serialize, Deserialize)]
 struct Login {
@@ -36,6 +134,141 @@ bitflags! {
     }
 }
 
+impl ModuleTypeFlags {
+    const NAMES: &'static [&'static str] = &["taking", "teaching", "all"];
+
+    fn parse(module_type: &str) -> Option<ModuleTypeFlags> {
+        match module_type.to_lowercase().as_str() {
+            "taking" => Some(ModuleTypeFlags::TAKING),
+            "teaching" => Some(ModuleTypeFlags::TEACHING),
+            "all" => Some(ModuleTypeFlags::all()),
+            _ => None,
+        }
+    }
+}
+
+// clap validators, so that bad arguments are reported along with the usage instead of a panic
+fn validate_count(value: String) -> std::result::Result<(), String> {
+    match value.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(()),
+        _ => Err(format!("{} is not a positive number", value)),
+    }
+}
+
+fn validate_size(value: String) -> std::result::Result<(), String> {
+    parse_size(&value)
+        .map(|_| ())
+        .ok_or_else(|| format!("{} is not a size such as 512K, 64M or 2G", value))
+}
+
+fn validate_duration(value: String) -> std::result::Result<(), String> {
+    parse_duration(&value)
+        .map(|_| ())
+        .ok_or_else(|| format!("{} is not a duration such as 30m, 12h, 7d or 2w", value))
+}
+
+// a date such as 2020-09-01, from its start, or a duration such as 7d, back from now
+fn parse_since(value: &str) -> Option<chrono::DateTime<chrono::Local>> {
+    use chrono::TimeZone;
+    match chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        Ok(date) => chrono::Local
+            .from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+            .earliest(),
+        Err(_) => chrono::Local::now()
+            .checked_sub_signed(chrono::Duration::from_std(parse_duration(value)?).ok()?),
+    }
+}
+
+// the time `duration` before now, for --since. Nothing on LumiNUS is from before 1970.
+fn time_ago(duration: Duration) -> Result<SystemTime> {
+    SystemTime::now()
+        .checked_sub(duration)
+        .filter(|time| *time >= SystemTime::UNIX_EPOCH)
+        .ok_or("Duration for --since goes too far back")
+}
+
+fn validate_since(value: String) -> std::result::Result<(), String> {
+    parse_since(&value).map(|_| ()).ok_or_else(|| {
+        format!(
+            "{} is not a date such as 2020-09-01 or a duration such as 12h, 7d or 2w",
+            value
+        )
+    })
+}
+
+fn parse_percent(value: &str) -> Option<f64> {
+    value
+        .trim()
+        .trim_end_matches('%')
+        .parse::<f64>()
+        .ok()
+        .filter(|percent| percent.is_finite() && *percent >= 0.0)
+}
+
+fn validate_percent(value: String) -> std::result::Result<(), String> {
+    parse_percent(&value)
+        .map(|_| ())
+        .ok_or_else(|| format!("{} is not a percentage such as 75", value))
+}
+
+// e.g. "Final Exam=75"
+fn parse_assumption(value: &str) -> Option<(String, f64)> {
+    let (name, percent) = value.rsplit_once('=')?;
+    Some((name.trim().to_owned(), parse_percent(percent)?)).filter(|(name, _)| !name.is_empty())
+}
+
+fn validate_assumption(value: String) -> std::result::Result<(), String> {
+    parse_assumption(&value).map(|_| ()).ok_or_else(|| {
+        format!(
+            "{} is not a component and percentage such as \"Final Exam=75\"",
+            value
+        )
+    })
+}
+
+fn validate_term(value: String) -> std::result::Result<(), String> {
+    AcademicTerm::parse(&value)
+        .map(|_| ())
+        .ok_or_else(|| format!("{} is not a 4-digit term code such as 2010", value))
+}
+
+fn validate_module_transcode_profile(value: String) -> std::result::Result<(), String> {
+    let (_, name) = value
+        .split_once('=')
+        .ok_or_else(|| format!("{} is not of the form MODULE=PROFILE", value))?;
+    transcode::find_profile(name).map(|_| ()).ok_or_else(|| {
+        let names = transcode::PROFILES
+            .iter()
+            .map(|profile| profile.name)
+            .collect::<Vec<_>>();
+        format!(
+            "Unknown transcode profile {}, expected one of {}",
+            name,
+            names.join(", ")
+        )
+    })
+}
+
+fn validate_transcription_command(value: String) -> std::result::Result<(), String> {
+    TranscriptionHook::parse(&value)
+        .map(|_| ())
+        .map_err(|e| e.to_owned())
+}
+
+// The first Ctrl-C lets the downloads in flight finish and starts no more, the second quits right away
+fn stop_on_ctrl_c(cancellation: CancellationToken) {
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        say!("Stopping once the downloads in progress finish, press Ctrl-C again to quit now");
+        cancellation.cancel();
+        if tokio::signal::ctrl_c().await.is_ok() {
+            std::process::exit(130);
+        }
+    });
+}
+
 fn flush_stdout() {
     io::stdout().flush().expect("Unable to flush stdout");
 }
@@ -56,194 +289,3509 @@ fn get_password(prompt: &str) -> String {
     rpassword::read_password().expect("Unable to get non-echo input mode for password")
 }
 
-async fn print_announcements(api: &Api, modules: &[Module]) -> Result<()> {
-    let module_announcements = future::join_all(
+async fn load_announcements(api: &Api, modules: &[Module]) -> Vec<Result<Vec<Announcement>>> {
+    future::join_all(
         modules
             .iter()
             .map(|module| module.get_announcements(api, false)),
     )
-    .await;
+    .await
+}
+
+// Writes every module's announcements into one file, as HTML if the file is named so and Markdown otherwise
+fn write_announcement_digest(
+    modules: &[Module],
+    module_announcements: &[Result<Vec<Announcement>>],
+    path: &str,
+) -> Result<()> {
+    let entries = modules
+        .iter()
+        .zip(module_announcements)
+        .filter_map(|(module, announcements)| Some((module, announcements.as_ref().ok()?)))
+        .flat_map(|(module, announcements)| {
+            announcements
+                .iter()
+                .map(move |ann| (module.code.as_str(), module.name.as_str(), ann))
+        })
+        .collect::<Vec<_>>();
+    let count = entries.len();
+    let digest = Digest::new(entries);
+    let is_html = Path::new(path)
+        .extension()
+        .and_then(OsStr::to_str)
+        .is_some_and(|extension| {
+            extension.eq_ignore_ascii_case("html") || extension.eq_ignore_ascii_case("htm")
+        });
+    let content = if is_html {
+        digest.to_html()
+    } else {
+        digest.to_markdown()
+    };
+    fs::write(path, content).map_err(|_| "Unable to write announcements digest")?;
+    println!("Wrote {} announcement(s) to {}", count, path);
+    Ok(())
+}
+
+// Alerts are raised before anything else is shown, and once for each announcement, however many runs see it
+fn raise_alerts(
+    modules: &[Module],
+    module_announcements: &[Result<Vec<Announcement>>],
+    rules: &AlertRules,
+    manifest: &mut Manifest,
+    command: Option<&str>,
+) {
+    let announcements = modules
+        .iter()
+        .zip(module_announcements)
+        .filter_map(|(module, announcements)| Some((module, announcements.as_ref().ok()?)))
+        .flat_map(|(module, announcements)| announcements.iter().map(move |ann| (module, ann)));
+    for (module, ann) in announcements {
+        let rule = match rules.matching(ann) {
+            Some(rule) if !manifest.alerted_announcements.contains(&ann.id) => rule,
+            _ => continue,
+        };
+        say!(
+            "!!! Alert for {}: {} (matches {})",
+            module.code,
+            ann.title,
+            rule
+        );
+        if let Some(command) = command {
+            if let Err(e) = run_alert_command(command, module, ann, rule) {
+                say!("Failed to run the alert command: {}", e);
+                // left for the next run to raise again
+                continue;
+            }
+        }
+        manifest.alerted_announcements.insert(ann.id.clone());
+    }
+}
+
+fn run_alert_command(command: &str, module: &Module, ann: &Announcement, rule: &str) -> Result<()> {
+    // the command may come with arguments, e.g. "notify-send -u critical"
+    let mut words = command.split_whitespace();
+    let mut child = Command::new(words.next().ok_or("Alert command is empty")?)
+        .args(words)
+        .env("FLUMINURS_ALERT_MODULE", &module.code)
+        .env("FLUMINURS_ALERT_TITLE", &ann.title)
+        .env("FLUMINURS_ALERT_RULE", rule)
+        .env(
+            "FLUMINURS_ALERT_LINK",
+            ann.permalink.as_deref().unwrap_or_default(),
+        )
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|_| "Unable to start alert command")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // the command not reading its input is not an error
+        let _ = stdin.write_all(html_to_text(&ann.description).as_bytes());
+    }
+    match child.wait() {
+        Ok(status) if status.success() => Ok(()),
+        _ => Err("Alert command failed"),
+    }
+}
+
+// Which announcements are shown for each module
+#[derive(Copy, Clone)]
+struct AnnouncementFilter {
+    unread_only: bool,
+    since: Option<SystemTime>,
+    // the newest this many per module
+    limit: Option<usize>,
+}
+
+impl AnnouncementFilter {
+    fn select(&self, announcements: Vec<Announcement>, manifest: &Manifest) -> Vec<Announcement> {
+        let mut announcements = announcements
+            .into_iter()
+            .filter(|ann| !self.unread_only || !manifest.has_seen_announcement(&ann.id))
+            .filter(|ann| match (self.since, ann.displayed_from()) {
+                (Some(since), Some(displayed_from)) => displayed_from >= since,
+                _ => true,
+            })
+            .collect::<Vec<_>>();
+        if let Some(limit) = self.limit {
+            // newest first, and those without a date after the rest
+            announcements.sort_by_key(|ann| std::cmp::Reverse(ann.displayed_from()));
+            announcements.truncate(limit);
+        }
+        announcements
+    }
+
+    // nothing to show for a module is only worth a heading when everything was asked for
+    fn skips_empty(&self) -> bool {
+        self.unread_only || self.since.is_some()
+    }
+}
+
+fn format_announcements(
+    code: &str,
+    name: &str,
+    announcements: &[Announcement],
+    permalinks: bool,
+) -> String {
+    let mut text = format!("# {} {}\n\n", code, name);
+    for ann in announcements {
+        text.push_str(&format!("=== {} ===\n", ann.title));
+        if let Some(permalink) = ann.permalink.as_ref().filter(|_| permalinks) {
+            text.push_str(&format!("{}\n", permalink));
+        }
+        text.push_str(&format!("{}\n", html_to_text(&ann.description)));
+    }
+    text.push_str("\n\n");
+    text
+}
+
+// Shows text through $PAGER (less by default) when it won't fit on the terminal, and prints it otherwise
+fn page(text: &str, use_pager: bool) {
+    let height = std::env::var("LINES")
+        .ok()
+        .and_then(|lines| lines.parse::<usize>().ok())
+        .unwrap_or(24);
+    if !use_pager || !io::stdout().is_terminal() || text.lines().count() < height {
+        print!("{}", text);
+        return;
+    }
+    let pager = std::env::var("PAGER")
+        .ok()
+        .filter(|pager| !pager.trim().is_empty())
+        .unwrap_or_else(|| "less -R".to_owned());
+    // PAGER may come with arguments, e.g. "less -R"
+    let mut words = pager.split_whitespace();
+    let spawned = Command::new(words.next().unwrap())
+        .args(words)
+        .stdin(Stdio::piped())
+        .spawn();
+    match spawned {
+        Ok(mut child) => {
+            if let Some(mut stdin) = child.stdin.take() {
+                // the pager closing early is not an error
+                let _ = stdin.write_all(text.as_bytes());
+            }
+            let _ = child.wait();
+        }
+        Err(_) => print!("{}", text),
+    }
+}
+
+fn print_announcements(
+    modules: &[Module],
+    module_announcements: Vec<Result<Vec<Announcement>>>,
+    manifest: &mut Manifest,
+    filter: AnnouncementFilter,
+    permalinks: bool,
+    use_pager: bool,
+) -> Result<()> {
+    let mut text = String::new();
+    let mut shown = vec![];
     for (module, announcements) in modules.iter().zip(module_announcements) {
-        let announcements = announcements?;
+        let announcements = filter.select(announcements?, manifest);
+        if filter.skips_empty() && announcements.is_empty() {
+            continue;
+        }
+        text.push_str(&format_announcements(
+            &module.code,
+            &module.name,
+            &announcements,
+            permalinks,
+        ));
+        shown.extend(announcements);
+    }
+    page(&text, use_pager);
+    for ann in shown {
+        manifest.mark_announcement_seen(&ann.id);
+    }
+    Ok(())
+}
+
+async fn print_messages(api: &Api, export_destination: Option<&str>) -> Result<()> {
+    let conversations = Conversation::group(api.messages().await?);
+    println!("# Inbox");
+    println!();
+    for conversation in &conversations {
+        let latest = conversation.messages.last().unwrap();
+        println!(
+            "- {} ({} message(s), latest from {} at {})",
+            conversation.subject,
+            conversation.messages.len(),
+            latest.sender(),
+            latest.created_date
+        );
+    }
+    println!();
+
+    if let Some(destination) = export_destination {
+        let dest_path = Path::new(destination);
+        if !dest_path.is_dir() {
+            return Err("Message export destination does not exist or is not a directory");
+        }
+        for conversation in &conversations {
+            // conversations started on the same day can share a subject, so the id tells them apart
+            let path = dest_path.join(sanitise_filename(&format!(
+                "{} {} ({}).md",
+                conversation.started().get(..10).unwrap_or_default(),
+                conversation.subject,
+                conversation.id
+            )));
+            fs::write(&path, conversation.to_markdown())
+                .map_err(|_| "Unable to write exported conversation")?;
+            println!("Exported to {}", path.to_string_lossy());
+        }
+    }
+    Ok(())
+}
+
+async fn print_groups(
+    api: &Api,
+    modules: &[Module],
+    export_destination: Option<&str>,
+) -> Result<()> {
+    let module_groups = future::join_all(
+        modules
+            .iter()
+            .filter(|module| module.has_access())
+            .map(|module| async move { (module, module.get_groups(api).await) }),
+    )
+    .await;
+    for (module, groups) in module_groups {
+        let groups = match groups {
+            Ok(groups) => groups,
+            Err(e) => {
+                println!("Failed loading groups of {}: {}", module.code, e);
+                continue;
+            }
+        };
+        if groups.is_empty() {
+            continue;
+        }
         println!("# {} {}", module.code, module.name);
-        println!();
-        for ann in announcements {
-            println!("=== {} ===", ann.title);
-            let stripped = ammonia::Builder::new()
-                .tags(HashSet::new())
-                .clean(&ann.description)
-                .to_string();
-            let decoded = htmlescape::decode_html(&stripped)
-                .unwrap_or_else(|_| "Unable to decode HTML Entities".to_owned());
-            println!("{}", decoded);
+        for group in &groups {
+            println!("## {}", group.name);
+            for member in &group.members {
+                println!(
+                    "- {} <{}>",
+                    member.user_name_original,
+                    member.email.as_deref().unwrap_or("no email")
+                );
+            }
         }
         println!();
-        println!();
+
+        if let Some(destination) = export_destination {
+            let module_path = Path::new(destination).join(module.folder_path());
+            fs::create_dir_all(&module_path).map_err(|_| "Unable to create directory")?;
+            fs::write(module_path.join("Groups.csv"), group::to_csv(&groups))
+                .map_err(|_| "Unable to write group roster")?;
+            fs::write(module_path.join("Groups.vcf"), group::to_vcard(&groups))
+                .map_err(|_| "Unable to write group roster")?;
+            println!("Exported groups to {}", module_path.to_string_lossy());
+        }
+    }
+    Ok(())
+}
+
+async fn print_peer_evaluations(
+    api: &Api,
+    modules: &[Module],
+    export_destination: Option<&str>,
+) -> Result<()> {
+    let module_evaluations = future::join_all(
+        modules
+            .iter()
+            .filter(|module| module.has_access())
+            .map(|module| async move { (module, module.get_peer_evaluations(api).await) }),
+    )
+    .await;
+    for (module, evaluations) in module_evaluations {
+        let evaluations = match evaluations {
+            Ok(evaluations) => evaluations,
+            Err(e) => {
+                println!("Failed loading peer evaluations of {}: {}", module.code, e);
+                continue;
+            }
+        };
+        if evaluations.is_empty() {
+            continue;
+        }
+        println!(
+            "# {} {}: {} submitted, {} received",
+            module.code,
+            module.name,
+            evaluations.submitted.len(),
+            evaluations.received.len()
+        );
+
+        if let Some(destination) = export_destination {
+            let evaluation_path = Path::new(destination)
+                .join(module.folder_path())
+                .join("Peer Evaluations");
+            fs::create_dir_all(&evaluation_path).map_err(|_| "Unable to create directory")?;
+            fs::write(
+                evaluation_path.join("evaluations.json"),
+                evaluations.to_json()?,
+            )
+            .map_err(|_| "Unable to write peer evaluations")?;
+            fs::write(
+                evaluation_path.join("evaluations.md"),
+                evaluations.to_markdown(),
+            )
+            .map_err(|_| "Unable to write peer evaluations")?;
+            println!(
+                "Exported peer evaluations to {}",
+                evaluation_path.to_string_lossy()
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn export_syllabuses(api: &Api, modules: &[Module], destination: &str) -> Result<()> {
+    let dest_path = Path::new(destination);
+    if !dest_path.is_dir() {
+        return Err("Syllabus export destination does not exist or is not a directory");
+    }
+    let module_syllabuses =
+        future::join_all(modules.iter().filter(|module| module.has_access()).map(
+            |module| async move {
+                let (syllabus, gradebook) =
+                    future::join(module.get_syllabus(api), module.get_gradebook(api)).await;
+                (module, syllabus, gradebook)
+            },
+        ))
+        .await;
+    for (module, syllabus, gradebook) in module_syllabuses {
+        match syllabus {
+            Ok(mut syllabus) => {
+                // many modules don't show students their gradebook, so it is optional
+                if let Ok(gradebook) = gradebook {
+                    syllabus.add_gradebook(&gradebook);
+                }
+                let module_path = dest_path.join(module.folder_path());
+                fs::create_dir_all(&module_path).map_err(|_| "Unable to create directory")?;
+                let readme_path = module_path.join("README.md");
+                fs::write(
+                    &readme_path,
+                    syllabus.to_markdown(&module.code, &module.name),
+                )
+                .map_err(|_| "Unable to write module README")?;
+                println!("Wrote {}", readme_path.to_string_lossy());
+                if !syllabus.assessments.is_empty() {
+                    let assessment_path = module_path.join("assessment.json");
+                    fs::write(
+                        &assessment_path,
+                        syllabus.assessment_json(&module.code, &module.name)?,
+                    )
+                    .map_err(|_| "Unable to write assessment breakdown")?;
+                    println!("Wrote {}", assessment_path.to_string_lossy());
+                }
+            }
+            Err(e) => println!("Failed loading syllabus of {}: {}", module.code, e),
+        }
     }
     Ok(())
 }
 
+// Keeps the modules that have a tool switched on. If the server won't say, the module is kept,
+// and any real problem shows up when its contents are listed.
+async fn modules_with_tool<'a>(
+    api: &Api,
+    modules: Vec<&'a Module>,
+    tool_name: &str,
+    has_tool: fn(&ModuleTools) -> bool,
+) -> Vec<&'a Module> {
+    let tools = future::join_all(modules.iter().map(|module| module.get_tools(api))).await;
+    modules
+        .into_iter()
+        .zip(tools)
+        .filter(|(module, tools)| {
+            let enabled = tools.as_ref().map_or(true, has_tool);
+            if !enabled {
+                println!(
+                    "Skipping {} of {}: tool not enabled",
+                    tool_name, module.code
+                );
+            }
+            enabled
+        })
+        .map(|(module, _)| module)
+        .collect()
+}
+
 async fn load_modules_files(
     api: &Api,
     modules: &[Module],
     include_uploadable_folders: ModuleTypeFlags,
+    cache: Option<&FolderCache>,
 ) -> Result<Vec<File>> {
-    let root_dirs = modules
+    let readable = modules
         .iter()
-        .filter(|module| module.has_access())
+        .filter(|module| {
+            if !module.has_access() {
+                say!(
+                    "Skipping files of {}: no access yet, the lecturer hasn't opened the module",
+                    module.code
+                );
+            }
+            module.has_access()
+        })
+        .filter(|module| {
+            if !module.can_read() {
+                println!("Skipping files of {}: no read access", module.code);
+            }
+            module.can_read()
+        })
+        .collect::<Vec<_>>();
+    let root_dirs = modules_with_tool(api, readable, "files", ModuleTools::has_files)
+        .await
+        .into_iter()
         .map(|module| {
+            let include_uploadable = include_uploadable_folders.contains(if module.is_teaching() {
+                ModuleTypeFlags::TEACHING
+            } else {
+                ModuleTypeFlags::TAKING
+            });
             (
-                module.workbin_root(|code| Path::new(code).to_owned()),
-                module.is_teaching(),
+                module.workbin_root(|dir| dir.to_owned()),
+                include_uploadable,
             )
         })
         .collect::<Vec<_>>();
 
-    let (files, errors) = future::join_all(root_dirs.into_iter().map(|(root_dir, is_teaching)| {
-        root_dir.load(
-            api,
-            include_uploadable_folders.contains(if is_teaching {
-                ModuleTypeFlags::TEACHING
-            } else {
-                ModuleTypeFlags::TAKING
-            }),
-        )
-    }))
-    .await
-    .into_iter()
-    .fold((vec![], vec![]), move |(mut ok, mut err), res| {
-        match res {
-            Ok(mut dir) => {
-                ok.append(&mut dir);
-            }
-            Err(e) => {
-                err.push(e);
-            }
-        }
-        (ok, err)
-    });
+    let (mut files, errors) =
+        sync::traverse(root_dirs.into_iter().map(|(root_dir, include_uploadable)| {
+            root_dir.load_cached(api, include_uploadable, cache)
+        }))
+        .await;
     for e in errors {
-        println!("Failed loading module files: {}", e);
+        say!("Failed loading module files: {}", e);
     }
+    resource::sort_by_path(&mut files);
     Ok(files)
 }
 
 async fn load_modules_multimedia(api: &Api, modules: &[Module]) -> Result<Vec<Video>> {
-    let multimedias = modules
+    let readable = modules
         .iter()
-        .filter(|module| module.has_access())
-        .map(|module| module.multimedia_root(|code| Path::new(code).join(Path::new("Multimedia"))))
+        .filter(|module| module.can_read())
+        .collect::<Vec<_>>();
+    let multimedias = modules_with_tool(api, readable, "multimedia", ModuleTools::has_multimedia)
+        .await
+        .into_iter()
+        .map(|module| module.multimedia_root(|dir| dir.join("Multimedia")))
         .collect::<Vec<_>>();
 
-    let (files, errors) = future::join_all(
+    let (mut files, errors) = sync::traverse(
         multimedias
             .into_iter()
             .map(|multimedia| multimedia.load(api)),
     )
-    .await
-    .into_iter()
-    .fold((vec![], vec![]), move |(mut ok, mut err), res| {
-        match res {
-            Ok(mut dir) => {
-                ok.append(&mut dir);
-            }
-            Err(e) => {
-                err.push(e);
-            }
-        }
-        (ok, err)
-    });
+    .await;
 
     for e in errors {
-        println!("Failed loading module multimedia: {}", e);
+        say!("Failed loading module multimedia: {}", e);
     }
+    resource::sort_by_path(&mut files);
     Ok(files)
 }
 
-fn list_resources<T: Resource>(resources: &[T]) {
-    for resource in resources {
-        println!("{}", resource.path().display())
-    }
-}
-
-async fn download_resource<T: Resource>(
-    api: &Api,
-    file: &T,
-    path: PathBuf,
-    temp_path: PathBuf,
-    overwrite_mode: OverwriteMode,
-) {
-    match file.download(api, &path, &temp_path, overwrite_mode).await {
-        Ok(OverwriteResult::NewFile) => println!("Downloaded to {}", path.to_string_lossy()),
-        Ok(OverwriteResult::AlreadyHave) => {}
-        Ok(OverwriteResult::Skipped) => println!("Skipped {}", path.to_string_lossy()),
-        Ok(OverwriteResult::Overwritten) => println!("Updated {}", path.to_string_lossy()),
-        Ok(OverwriteResult::Renamed { renamed_path }) => println!(
-            "Renamed {} to {}",
-            path.to_string_lossy(),
-            renamed_path.to_string_lossy()
-        ),
-        Err(e) => println!("Failed to download file: {}", e),
+// total size of all files under `path`, or 0 if it doesn't exist
+fn dir_size(path: &Path) -> u64 {
+    match fs::read_dir(path) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| match entry.metadata() {
+                Ok(metadata) if metadata.is_dir() => dir_size(&entry.path()),
+                Ok(metadata) => metadata.len(),
+                Err(_) => 0,
+            })
+            .sum(),
+        Err(_) => 0,
     }
 }
 
-async fn download_resources<T: Resource>(
+async fn print_disk_usage(
     api: &Api,
-    files: &[T],
+    modules: &[Module],
     destination: &str,
-    overwrite_mode: OverwriteMode,
-    parallelism: usize,
+    multimedia_destination: &str,
+    include_uploadable_folders: ModuleTypeFlags,
+    screen_reader: bool,
 ) -> Result<()> {
-    println!("Download to {}", destination);
     let dest_path = Path::new(destination);
     if !dest_path.is_dir() {
-        return Err("Download destination does not exist or is not a directory");
+        return Err("Mirror directory does not exist or is not a directory");
     }
-
-    stream::iter(files.iter())
-        .map(|file| {
-            let temp_path = dest_path
-                .join(file.path().parent().unwrap())
-                .join(make_temp_file_name(file.path().file_name().unwrap()));
-            let real_path = dest_path.join(file.path());
-            download_resource(api, file, real_path, temp_path, overwrite_mode)
+    let files = load_modules_files(api, modules, include_uploadable_folders, None).await?;
+    let module_dirs = modules
+        .iter()
+        .map(|module| {
+            (
+                module.code.clone(),
+                module.folder_path(),
+                module.folder_path(),
+            )
         })
-        .buffer_unordered(parallelism)
-        .for_each(|_| future::ready(())) // do nothing, just complete the future
-        .await;
-
+        .collect::<Vec<_>>();
+    print_usage_table(
+        dest_path,
+        Path::new(multimedia_destination),
+        &module_dirs,
+        &files,
+        screen_reader,
+    );
     Ok(())
 }
 
-fn make_temp_file_name(name: &OsStr) -> OsString {
-    let prepend = OsStr::new("~!");
-    let mut res = OsString::with_capacity(prepend.len() + name.len());
-    res.push(prepend);
-    res.push(name);
-    res
+// What earlier runs left in the manifest
+fn print_status(manifest: &Manifest) {
+    say!(
+        "Known modules: {}, seen announcements: {}",
+        manifest.enrolled_modules.len(),
+        manifest.seen_announcements.len()
+    );
+    say!(
+        "Cached folders: {}, downloads left over: {}",
+        manifest.folder_cache.len(),
+        manifest.deferred_downloads.len()
+    );
 }
 
-fn get_credentials(credential_file: &str) -> Result<(String, String)> {
-    if let Ok(mut file) = fs::File::open(credential_file) {
-        let mut content = String::new();
-        file.read_to_string(&mut content)
-            .map_err(|_| "Unable to read credentials")?;
-        if let Ok(login) = serde_json::from_str::<Login>(&content) {
-            Ok((login.username, login.password))
-        } else {
-            println!("Corrupt credentials.json, deleting file...");
-            fs::remove_file(Path::new(credential_file))
-                .map_err(|_| "Unable to delete credential file")?;
-            get_credentials(credential_file)
+// Answers from the manifest and the folder listings cached by earlier runs, without going online.
+// Anything only the server knows is refused up front, rather than done halfway.
+fn run_offline(
+    matches: &ArgMatches,
+    manifest: &Manifest,
+    aliases: &ModuleAliases,
+    module_filter: Option<&[String]>,
+    specified_term: Option<&AcademicTerm>,
+    list_style: ListStyle,
+) -> Result<()> {
+    let online_only = ONLINE_ONLY_ARGS
+        .iter()
+        .filter(|(name, _)| matches.is_present(name))
+        .map(|(_, flag)| *flag)
+        .chain(
+            ONLINE_ONLY_SUBCOMMANDS
+                .iter()
+                .copied()
+                .filter(|name| matches.subcommand_matches(name).is_some()),
+        )
+        // nothing from Canvas is cached
+        .chain(Some("--platform canvas").filter(|_| matches.value_of("platform") == Some("canvas")))
+        .collect::<Vec<_>>();
+    if !online_only.is_empty() {
+        println!(
+            "These need LumiNUS, so they can't be done offline: {}",
+            online_only.join(", ")
+        );
+        return Err("Not available offline");
+    }
+
+    if matches.subcommand_matches("terms").is_some() {
+        let terms = manifest
+            .enrolled_modules
+            .values()
+            .map(|module| module.term.as_str())
+            .collect::<BTreeSet<_>>();
+        say!("You had modules in these terms as of the last run:");
+        for term in terms {
+            match AcademicTerm::parse(term) {
+                Some(term) => println!("- {} ({})", term, term.describe()),
+                None => println!("- {}", term),
+            }
         }
-    } else {
-        let username = get_input("Username (include the nusstu\\ prefix): ");
-        let password = get_password("Password: ");
-        Ok((username, password))
+        return Ok(());
+    }
+    if matches.subcommand_matches("status").is_some() {
+        say!("Offline, so LumiNUS was not contacted");
+        print_status(manifest);
+        return Ok(());
     }
-}
 
-fn store_credentials(credential_file: &str, username: &str, password: &str) -> Result<()> {
+    let modules = manifest
+        .enrolled_modules
+        .iter()
+        .filter(|(_, module)| specified_term.is_none_or(|term| module.term == term.code()))
+        .collect::<Vec<_>>();
+    let modules = match module_filter {
+        Some(references) => {
+            let codes = modules
+                .iter()
+                .map(|(_, module)| module.code.as_str())
+                .collect::<Vec<_>>();
+            let wanted = references
+                .iter()
+                .filter_map(|reference| resolve_module(aliases, reference, &codes))
+                .collect::<Vec<_>>();
+            if wanted.is_empty() {
+                return Err("None of the modules given to --modules were found");
+            }
+            modules
+                .into_iter()
+                .filter(|(_, module)| wanted.contains(&module.code))
+                .collect::<Vec<_>>()
+        }
+        None => modules
+            .into_iter()
+            .filter(|(_, module)| manifest.is_module_selected(&module.code))
+            .collect(),
+    };
+    let (module_ids, files): (Vec<_>, Vec<_>) = file::cached_files(&manifest.folder_cache)
+        .into_iter()
+        .filter(|(module_id, _)| modules.iter().any(|(id, _)| *id == module_id))
+        .unzip();
+    let module_rows = modules
+        .iter()
+        .map(|(id, module)| {
+            let module_files = module_ids
+                .iter()
+                .zip(&files)
+                .filter(|(module_id, _)| module_id == id)
+                .map(|(_, file)| file)
+                .collect::<Vec<_>>();
+            ModuleRow {
+                code: module.code.clone(),
+                name: module.name.clone(),
+                term: module.term.clone(),
+                role: None,
+                access: None,
+                files: Some(module_files.len()),
+                bytes: Some(module_files.iter().filter_map(|file| file.size()).sum()),
+            }
+        })
+        .collect::<Vec<_>>();
+    if list_style.format != ListFormat::Json {
+        println!("As of the last run:");
+    }
+    list_modules(&module_rows, list_style);
+
+    if matches.is_present("files") {
+        list_resources(&files, list_style);
+    }
+
+    if let Some(du_matches) = matches.subcommand_matches("du") {
+        let mirror = du_matches.value_of("mirror").unwrap_or(".");
+        let multimedia_mirror = du_matches.value_of("multimedia-mirror").unwrap_or(mirror);
+        let dest_path = Path::new(mirror);
+        if !dest_path.is_dir() {
+            return Err("Mirror directory does not exist or is not a directory");
+        }
+        // each destination remembers the folders that modules were last mirrored into there
+        let folder_in = |mirror: &str, id: &str, code: &str| {
+            manifest
+                .module_folders
+                .get(mirror)
+                .and_then(|folders| folders.get(id))
+                .map_or_else(|| PathBuf::from(code), PathBuf::from)
+        };
+        let module_dirs = modules
+            .iter()
+            .map(|(id, module)| {
+                (
+                    module.code.clone(),
+                    folder_in(mirror, id, &module.code),
+                    folder_in(multimedia_mirror, id, &module.code),
+                )
+            })
+            .collect::<Vec<_>>();
+        print_usage_table(
+            dest_path,
+            Path::new(multimedia_mirror),
+            &module_dirs,
+            &files,
+            list_style.screen_reader,
+        );
+        return Ok(());
+    }
+
+    if let Some(report_matches) = matches.subcommand_matches("report") {
+        let since = report_matches
+            .value_of("since")
+            .and_then(parse_duration)
+            .ok_or("Invalid duration for --since")?;
+        let since = time_ago(since)?;
+        println!("Announcements aren't kept between runs, so only files are reported offline");
+        let module_reports = modules
+            .iter()
+            .map(|(id, module)| ModuleReport {
+                code: module.code.clone(),
+                name: module.name.clone(),
+                new_files: module_ids
+                    .iter()
+                    .zip(&files)
+                    .filter(|(module_id, file)| module_id == id && file.last_updated() >= since)
+                    .map(|(_, file)| file.path().to_owned())
+                    .collect(),
+                announcements: vec![],
+                announcements_error: None,
+            })
+            .collect();
+        save_report(
+            Report {
+                since,
+                modules: module_reports,
+            },
+            report_matches.value_of("output"),
+        )?;
+    }
+    Ok(())
+}
+
+// What `option` was given on the command line, before the command line is parsed properly
+fn raw_arg_value(args: &[OsString], option: &str) -> Option<String> {
+    let prefix = format!("{}=", option);
+    args.iter().enumerate().find_map(|(i, arg)| {
+        let arg = arg.to_str()?;
+        if arg == option {
+            args.get(i + 1)?.to_str().map(str::to_owned)
+        } else {
+            arg.strip_prefix(&prefix).map(str::to_owned)
+        }
+    })
+}
+
+// Puts the options of the profile given with --profile into the command line, right after the program
+// name. Options that the command line gives as well are left out, so that the command line wins.
+fn apply_profile(args: Vec<OsString>) -> Result<Vec<OsString>> {
+    let name = match raw_arg_value(&args, "--profile") {
+        Some(name) => name,
+        None => return Ok(args),
+    };
+    let profiles_file =
+        raw_arg_value(&args, "--profiles-file").unwrap_or_else(|| "profiles.conf".to_owned());
+    let profiles = SyncProfiles::load(Path::new(&profiles_file))?;
+    let options = match profiles.get(&name) {
+        Some(options) => options,
+        None => {
+            let suggestions = closest_matches(&name, profiles.names().map(str::to_owned), 3);
+            if suggestions.is_empty() {
+                println!("There is no profile {} in {}", name, profiles_file);
+            } else {
+                println!(
+                    "There is no profile {}, did you mean {}?",
+                    name,
+                    suggestions.join(" or ")
+                );
+            }
+            return Err("No such profile");
+        }
+    };
+    let given = |option: &str| {
+        let flag = format!("--{}", option);
+        let prefix = format!("{}=", flag);
+        args.iter()
+            .filter_map(|arg| arg.to_str())
+            .any(|arg| arg == flag || arg.starts_with(&prefix))
+    };
+    let (program, rest) = args
+        .split_first()
+        .ok_or("No program name on the command line")?;
+    let mut spliced = vec![program.clone()];
+    for (option, value) in options.iter().filter(|(option, _)| !given(option)) {
+        spliced.push(OsString::from(match value {
+            Some(value) => format!("--{}={}", option, value),
+            None => format!("--{}", option),
+        }));
+    }
+    spliced.extend(rest.iter().cloned());
+    Ok(spliced)
+}
+
+// The code of the module that `reference` names, by code or alias, or None after suggesting what
+// might have been meant
+fn resolve_module(aliases: &ModuleAliases, reference: &str, codes: &[&str]) -> Option<String> {
+    match aliases.resolve(reference, codes) {
+        Ok(code) => Some(code.to_owned()),
+        Err(suggestions) if suggestions.is_empty() => {
+            println!("There is no module {}", reference);
+            None
+        }
+        Err(suggestions) => {
+            println!(
+                "There is no module {}, did you mean {}?",
+                reference,
+                suggestions.join(" or ")
+            );
+            None
+        }
+    }
+}
+
+// Module code, its folder under `dest_path` and its folder under `multimedia_path` => what it takes
+// up there, and what `files` would add. Multimedia can be downloaded to a mirror of its own.
+fn print_usage_table(
+    dest_path: &Path,
+    multimedia_path: &Path,
+    module_dirs: &[(String, PathBuf, PathBuf)],
+    files: &[File],
+    screen_reader: bool,
+) {
+    let shared_mirror = match (dest_path.canonicalize(), multimedia_path.canonicalize()) {
+        (Ok(dest_path), Ok(multimedia_path)) => dest_path == multimedia_path,
+        _ => dest_path == multimedia_path,
+    };
+    let print_row = |name: &str, files_size: u64, multimedia: u64, pending: u64| {
+        if screen_reader {
+            println!(
+                "{}: {} of files, {} of multimedia, {} in total, {} not downloaded yet",
+                name,
+                format_size(files_size),
+                format_size(multimedia),
+                format_size(files_size + multimedia),
+                format_size(pending)
+            );
+        } else {
+            println!(
+                "{:<12} {:>12} {:>12} {:>12} {:>12}",
+                name,
+                format_size(files_size),
+                format_size(multimedia),
+                format_size(files_size + multimedia),
+                format_size(pending)
+            );
+        }
+    };
+    if !screen_reader {
+        println!(
+            "{:<12} {:>12} {:>12} {:>12} {:>12}",
+            "Module", "Files", "Multimedia", "Total", "Not yet"
+        );
+    }
+    let (mut total_files, mut total_multimedia, mut total_pending) = (0, 0, 0);
+    for (code, module_dir, multimedia_dir) in module_dirs {
+        let module_path = dest_path.join(module_dir);
+        let multimedia = dir_size(&multimedia_path.join(multimedia_dir).join("Multimedia"));
+        // multimedia in the module's own folder is not counted twice
+        let files_size = if shared_mirror && module_dir == multimedia_dir {
+            dir_size(&module_path).saturating_sub(multimedia)
+        } else {
+            dir_size(&module_path)
+        };
+        // multimedia sizes are not reported by the server, so only files can be projected
+        let pending = files
+            .iter()
+            .filter(|file| file.path().starts_with(module_dir))
+            .filter(|file| !dest_path.join(file.path()).exists())
+            .filter_map(|file| file.size())
+            .sum::<u64>();
+        print_row(code, files_size, multimedia, pending);
+        total_files += files_size;
+        total_multimedia += multimedia;
+        total_pending += pending;
+    }
+    print_row("Total", total_files, total_multimedia, total_pending);
+}
+
+async fn write_report(
+    api: &Api,
+    modules: &[Module],
+    since: SystemTime,
+    include_uploadable_folders: ModuleTypeFlags,
+    output: Option<&str>,
+) -> Result<()> {
+    let (files, module_announcements) = future::join(
+        load_modules_files(api, modules, include_uploadable_folders, None),
+        future::join_all(
+            modules
+                .iter()
+                .map(|module| module.get_announcements(api, false)),
+        ),
+    )
+    .await;
+    let files = files?;
+
+    let mut module_reports = vec![];
+    for (module, announcements) in modules.iter().zip(module_announcements) {
+        let module_dir = module.folder_path();
+        // one module failing shouldn't take the rest of the report with it
+        let (announcements, announcements_error) = match announcements {
+            Ok(announcements) => (announcements, None),
+            Err(e) => {
+                println!("Failed loading announcements of {}: {}", module.code, e);
+                (vec![], Some(e))
+            }
+        };
+        let announcements = announcements
+            .into_iter()
+            .filter(|ann| ann.displayed_from().map(|t| t >= since).unwrap_or(false))
+            .collect::<Vec<_>>();
+        let mut new_files = files
+            .iter()
+            .filter(|file| file.path().starts_with(&module_dir) && file.last_updated() >= since)
+            .map(|file| file.path().to_owned())
+            .collect::<Vec<_>>();
+        new_files.sort();
+        module_reports.push(ModuleReport {
+            code: module.code.clone(),
+            name: module.name.clone(),
+            new_files,
+            announcements,
+            announcements_error,
+        });
+    }
+
+    save_report(
+        Report {
+            since,
+            modules: module_reports,
+        },
+        output,
+    )
+}
+
+fn save_report(report: Report, output: Option<&str>) -> Result<()> {
+    match output {
+        Some(output) => {
+            fs::write(output, report.to_markdown()).map_err(|_| "Unable to write report")?;
+            println!("Report written to {}", output);
+        }
+        None => print!("{}", report.to_markdown()),
+    }
+    Ok(())
+}
+
+// Upcoming deadlines of every module, soonest first
+async fn load_deadlines(api: &Api, modules: &[Module]) -> Vec<Deadline> {
+    let now = SystemTime::now();
+    let (mut deadlines, errors) = future::join_all(
+        modules
+            .iter()
+            .filter(|module| module.has_access())
+            .map(|module| module.get_deadlines(api)),
+    )
+    .await
+    .into_iter()
+    .fold((vec![], vec![]), move |(mut ok, mut err), res| {
+        match res {
+            Ok(mut deadlines) => {
+                ok.append(&mut deadlines);
+            }
+            Err(e) => {
+                err.push(e);
+            }
+        }
+        (ok, err)
+    });
+    for e in errors {
+        println!("Failed loading deadlines: {}", e);
+    }
+    deadlines.retain(|deadline| deadline.due >= now);
+    deadlines.sort_by_key(|deadline| deadline.due);
+    deadlines
+}
+
+async fn print_deadlines(
+    api: &Api,
+    modules: &[Module],
+    export_destination: Option<&str>,
+) -> Result<()> {
+    let deadlines = load_deadlines(api, modules).await;
+
+    println!("Upcoming deadlines:");
+    for deadline in &deadlines {
+        println!(
+            "- {} {} [{}] {}",
+            chrono::DateTime::<chrono::Local>::from(deadline.due).format("%a %Y-%m-%d %H:%M"),
+            deadline.module_code,
+            deadline.kind.name(),
+            deadline.title
+        );
+    }
+
+    if let Some(destination) = export_destination {
+        let events = deadlines
+            .iter()
+            .map(|deadline| deadline.to_event())
+            .collect::<Vec<_>>();
+        fs::write(destination, ical::to_ics(&events))
+            .map_err(|_| "Unable to write calendar file")?;
+        println!("Exported deadlines to {}", destination);
+    }
+    Ok(())
+}
+
+async fn load_calendar(api: &Api) -> Result<Vec<CalendarEvent>> {
+    let now = SystemTime::now();
+    Ok(api
+        .calendar()
+        .await?
+        .into_iter()
+        .filter(|event| event.end().unwrap_or_else(|| event.start()) >= now)
+        .collect())
+}
+
+async fn print_calendar(api: &Api, export_destination: Option<&str>) -> Result<()> {
+    let events = load_calendar(api).await?;
+
+    println!("Upcoming calendar events:");
+    for event in &events {
+        println!(
+            "- {} {}{}",
+            chrono::DateTime::<chrono::Local>::from(event.start()).format("%a %Y-%m-%d %H:%M"),
+            event.title,
+            event
+                .location
+                .as_deref()
+                .map(|location| format!(" @ {}", location))
+                .unwrap_or_default()
+        );
+    }
+
+    if let Some(destination) = export_destination {
+        let ics_events = events
+            .iter()
+            .map(|event| event.to_event())
+            .collect::<Vec<_>>();
+        fs::write(destination, ical::to_ics(&ics_events))
+            .map_err(|_| "Unable to write calendar file")?;
+        println!("Exported calendar to {}", destination);
+    }
+    Ok(())
+}
+
+// Everything with a date in one file, so that a single calendar subscription covers it all.
+// UIDs are the same as in the separate exports, so switching between them doesn't duplicate events.
+async fn export_ics(api: &Api, modules: &[Module], destination: &str) -> Result<()> {
+    let deadlines = load_deadlines(api, modules).await;
+    let calendar = match load_calendar(api).await {
+        Ok(calendar) => calendar,
+        Err(e) => {
+            println!("Failed loading calendar: {}", e);
+            vec![]
+        }
+    };
+    let events = deadlines
+        .iter()
+        .map(|deadline| deadline.to_event())
+        .chain(calendar.iter().map(|event| event.to_event()))
+        .collect::<Vec<_>>();
+    fs::write(destination, ical::to_ics(&events)).map_err(|_| "Unable to write calendar file")?;
+    println!(
+        "Exported {} deadlines and {} calendar events to {}",
+        deadlines.len(),
+        calendar.len(),
+        destination
+    );
+    Ok(())
+}
+
+// Everything that helps tell which LumiNUS response changed when something breaks, minus credentials.
+// Only reads files, so it works even when logging in or loading the manifest doesn't.
+fn write_diagnostics(matches: &ArgMatches, output: &str) -> Result<()> {
+    let file_arg = |name: &str, default: &str| matches.value_of(name).unwrap_or(default).to_owned();
+    let mut secrets = vec![];
+    if let Ok(login) = fs::read_to_string(file_arg("credential-file", "login.json"))
+        .map_err(|_| ())
+        .and_then(|content| serde_json::from_str::<Login>(&content).map_err(|_| ()))
+    {
+        // the part after nusstu\ is the student number, which turns up without the prefix too
+        if let Some((_, id)) = login.username.rsplit_once('\\') {
+            secrets.push(id.to_owned());
+        }
+        secrets.push(login.username);
+        secrets.push(login.password);
+    }
+    let mut bundle = Bundle::new(secrets);
+    bundle.add(
+        "versions.txt",
+        &format!(
+            "fluminurs {}\n{} {} ({})\n",
+            env!("CARGO_PKG_VERSION"),
+            std::env::consts::OS,
+            std::env::consts::ARCH,
+            std::env::consts::FAMILY
+        ),
+    );
+    bundle.add_file(
+        "failure.json",
+        Path::new(&file_arg("failure-file", "failure.json")),
+        usize::MAX,
+    );
+    bundle.add_file(
+        "audit.jsonl",
+        Path::new(&file_arg("audit-log-file", "audit.jsonl")),
+        200,
+    );
+    if let Some(endpoints_file) = matches.value_of("endpoints-file") {
+        bundle.add_config_file("endpoints.json", Path::new(endpoints_file));
+    }
+    bundle.add_config_file(
+        "aliases.conf",
+        Path::new(&file_arg("aliases-file", "aliases.conf")),
+    );
+    bundle.add_config_file(
+        "profiles.conf",
+        Path::new(&file_arg("profiles-file", "profiles.conf")),
+    );
+    // what the manifest holds rather than all of it, as it lists every file in the mirror
+    let manifest_file = file_arg("manifest-file", "manifest.json");
+    match fs::read_to_string(&manifest_file)
+        .map(|content| serde_json::from_str::<serde_json::Value>(&content))
+    {
+        Ok(Ok(serde_json::Value::Object(fields))) => {
+            let mut overview = String::new();
+            for (name, value) in &fields {
+                let size = match value {
+                    serde_json::Value::Array(items) => format!("{} entries", items.len()),
+                    serde_json::Value::Object(items) => format!("{} entries", items.len()),
+                    value => value.to_string(),
+                };
+                overview.push_str(&format!("{}: {}\n", name, size));
+            }
+            bundle.add("manifest.txt", &overview);
+            if let Some(serde_json::Value::Array(runs)) = fields.get("syncHistory") {
+                let recent = &runs[runs.len().saturating_sub(20)..];
+                bundle.add(
+                    "sync-history.json",
+                    &serde_json::to_string_pretty(recent).unwrap_or_default(),
+                );
+            }
+        }
+        Ok(_) => bundle.add("manifest.txt", "not valid JSON\n"),
+        Err(_) => bundle.add("manifest.txt", "missing\n"),
+    }
+    bundle.write(Path::new(output))?;
+    println!(
+        "Wrote {} with {}",
+        output,
+        bundle.names().collect::<Vec<_>>().join(", ")
+    );
+    println!(
+        "Credentials were removed, but please look through it before attaching it to an issue"
+    );
+    Ok(())
+}
+
+fn dedupe_mirror(mirror: &str, link: bool) -> Result<()> {
+    let duplicates = dedupe::find_duplicates(Path::new(mirror));
+    if duplicates.is_empty() {
+        println!("No duplicate files under {}", mirror);
+        return Ok(());
+    }
+    for set in &duplicates {
+        println!(
+            "{} copies of {} ({} wasted):",
+            set.paths.len(),
+            format_size(set.size),
+            format_size(set.wasted())
+        );
+        for path in &set.paths {
+            println!("  {}", path.to_string_lossy());
+        }
+    }
+    println!(
+        "{} set(s) of duplicates waste {}",
+        duplicates.len(),
+        format_size(duplicates.iter().map(Duplicates::wasted).sum())
+    );
+    if link {
+        let mut linked = 0;
+        for set in &duplicates {
+            match dedupe::link_duplicates(set) {
+                Ok(count) => linked += count,
+                Err(_) => println!(
+                    "Unable to link the copies of {}",
+                    set.paths[0].to_string_lossy()
+                ),
+            }
+        }
+        println!("Replaced {} duplicate(s) with hard links", linked);
+    }
+    Ok(())
+}
+
+fn clean_versions(rename_scheme: &RenameScheme, mirror: &str, dry_run: bool) -> Result<()> {
+    if !rename_scheme.retention.is_set() {
+        return Err("Use --keep-versions or --keep-versions-for to say which old versions to keep");
+    }
+    let dest_path = Path::new(mirror);
+    if !dest_path.is_dir() {
+        return Err("Mirror directory does not exist or is not a directory");
+    }
+    let mut count = 0;
+    let mut freed = 0;
+    for (path, versions) in rename_scheme.find_versions(dest_path) {
+        let expired = rename_scheme.retention.expired(&versions);
+        if expired.is_empty() {
+            continue;
+        }
+        println!(
+            "{}: keeping {} of {} old version(s)",
+            path.to_string_lossy(),
+            versions.len() - expired.len(),
+            versions.len()
+        );
+        for version in expired {
+            let size = fs::metadata(&version).map_or(0, |metadata| metadata.len());
+            if dry_run {
+                println!("  would delete {}", version.to_string_lossy());
+            } else if fs::remove_file(&version).is_ok() {
+                println!("  deleted {}", version.to_string_lossy());
+            } else {
+                println!("  unable to delete {}", version.to_string_lossy());
+                continue;
+            }
+            count += 1;
+            freed += size;
+        }
+    }
+    if dry_run {
+        println!(
+            "{} old version(s) would be deleted, freeing {}",
+            count,
+            format_size(freed)
+        );
+    } else {
+        println!(
+            "Deleted {} old version(s), freeing {}",
+            count,
+            format_size(freed)
+        );
+    }
+    Ok(())
+}
+
+// Where the student stands in each exported module, and what is needed on the rest for each target
+fn print_what_if(
+    export: &str,
+    aliases: &ModuleAliases,
+    module: Option<&str>,
+    targets: &[f64],
+    assumed: &[(String, f64)],
+) -> Result<()> {
+    let dest_path = Path::new(export);
+    if !dest_path.is_dir() {
+        return Err("Export directory does not exist or is not a directory");
+    }
+    let module = module.map(|module| aliases.expand(module));
+    let mut found = false;
+    let mut components = HashSet::new();
+    for (path, assessment) in standing::find_assessments(dest_path) {
+        let assessment = match assessment {
+            Some(assessment) => assessment,
+            None => {
+                println!(
+                    "Skipping {}, it is not an assessment breakdown",
+                    path.to_string_lossy()
+                );
+                continue;
+            }
+        };
+        if module.is_some_and(|module| !assessment.code.eq_ignore_ascii_case(module)) {
+            continue;
+        }
+        found = true;
+        components.extend(
+            assessment
+                .assessments
+                .iter()
+                .map(|component| component.name.trim().to_lowercase()),
+        );
+        let standing = Standing::of(&assessment, assumed);
+        println!("{} {}", assessment.code, assessment.name);
+        match standing.average() {
+            Some(average) => println!(
+                "  Graded so far: {:.1}% of the grade, at {:.1}% on average, earning {:.1}%",
+                standing.graded_weight, average, standing.earned
+            ),
+            None => println!("  Nothing graded yet"),
+        }
+        if let Some(overall) = standing.overall() {
+            println!("  Final standing: {:.1}%", overall);
+        } else {
+            println!(
+                "  Still to come: {}",
+                standing
+                    .remaining
+                    .iter()
+                    .map(|(name, weightage)| format!("{} ({}%)", name, weightage))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            for target in targets {
+                match standing.needed_for(*target) {
+                    Some(needed) if needed <= 0.0 => {
+                        println!("  {}% overall: already secured", target)
+                    }
+                    Some(needed) if needed > 100.0 => println!(
+                        "  {}% overall: out of reach, it would take {:.1}% on average on the rest",
+                        target, needed
+                    ),
+                    Some(needed) => println!(
+                        "  {}% overall: needs {:.1}% on average on the rest",
+                        target, needed
+                    ),
+                    None => {}
+                }
+            }
+        }
+        if !standing.uncounted.is_empty() {
+            println!(
+                "  Not counted, for lack of a weightage or maximum mark: {}",
+                standing.uncounted.join(", ")
+            );
+        }
+    }
+    if !found && module.is_some() {
+        return Err("No exported assessment breakdown for that module");
+    }
+    if !found {
+        return Err(
+            "No exported assessment breakdowns found, export them with --export-syllabus-to first",
+        );
+    }
+    for (name, _) in assumed {
+        if !components.contains(&name.trim().to_lowercase()) {
+            println!("There is no component named {} to assume a mark for", name);
+        }
+    }
+    Ok(())
+}
+
+// e.g. manifest.json.undo, holding a folder for the latest run that changed anything
+fn undo_root(manifest_file: &str) -> PathBuf {
+    PathBuf::from(format!("{}.undo", manifest_file))
+}
+
+fn discard_other_undo_dirs(undo_dir: &Path) {
+    let entries = match undo_dir.parent().map(fs::read_dir) {
+        Some(Ok(entries)) => entries,
+        _ => return,
+    };
+    for entry in entries.flatten() {
+        if entry.path() != undo_dir {
+            let _ = fs::remove_dir_all(entry.path());
+        }
+    }
+}
+
+// Puts back what the latest sync that changed anything overwrote, trashed or pruned, from the
+// copies it kept and the old versions it renamed. New files are left alone, since nothing was lost to them.
+fn undo_last_sync(manifest: &mut Manifest, undo_root: &Path, dry_run: bool) -> Result<()> {
+    let run = match manifest
+        .sync_history
+        .iter_mut()
+        .rev()
+        .find(|run| !run.changes.is_empty())
+    {
+        Some(run) => run,
+        None => {
+            println!("No sync on record changed any files");
+            return Ok(());
+        }
+    };
+    let started = chrono::DateTime::parse_from_rfc3339(&run.started)
+        .map(|started| started.format("%a %Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|_| run.started.clone());
+    if run.undone {
+        println!(
+            "The last sync that changed files, on {}, was already undone",
+            started
+        );
+        return Ok(());
+    }
+    let mut restored = 0;
+    let mut lost = 0;
+    for change in run.changes.iter().rev() {
+        let from = match (change.result.as_str(), &change.renamed_to, &change.kept_as) {
+            ("new", _, _) => continue,
+            ("renamed", Some(from), _) => from,
+            (_, _, Some(from)) => from,
+            _ => {
+                println!("No copy of the old {} was kept", change.path);
+                lost += 1;
+                continue;
+            }
+        };
+        if !Path::new(from).is_file() {
+            println!(
+                "The old version of {} is no longer at {}",
+                change.path, from
+            );
+            lost += 1;
+            continue;
+        }
+        if dry_run {
+            println!("Would restore {} from {}", change.path, from);
+            restored += 1;
+            continue;
+        }
+        let path = Path::new(&change.path);
+        match path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::rename(from, path))
+        {
+            Ok(_) => {
+                println!("Restored {}", change.path);
+                restored += 1;
+            }
+            Err(_) => {
+                println!("Unable to restore {} from {}", change.path, from);
+                lost += 1;
+            }
+        }
+    }
+    if dry_run {
+        println!(
+            "{} file(s) from the sync on {} would be restored",
+            restored, started
+        );
+        return Ok(());
+    }
+    run.undone = true;
+    if lost == 0 {
+        let _ = fs::remove_dir_all(undo_root);
+    }
+    println!("Restored {} file(s) from the sync on {}", restored, started);
+    if restored > 0 {
+        println!("The next sync will see them as out of date again, so use --updated rename or skip if they should stay");
+    }
+    Ok(())
+}
+
+// Takes over files that were downloaded by hand, so that syncing to `destination` doesn't fetch them
+// all again. They are marked as synced the same way downloads are, by their last modified time.
+async fn adopt_files(
+    api: &Api,
+    modules: &[Module],
+    include_uploadable_folders: ModuleTypeFlags,
+    destination: &str,
+    move_files: bool,
+) -> Result<()> {
+    let dest_path = Path::new(destination);
+    if !dest_path.is_dir() {
+        return Err("Nothing to adopt, the --to directory does not exist");
+    }
+    let files = load_modules_files(api, modules, include_uploadable_folders, None).await?;
+    let files = drop_ignored(files, dest_path);
+    let mark_synced = |path: &Path, file: &File| {
+        filetime::set_file_mtime(
+            path,
+            filetime::FileTime::from_system_time(file.last_updated()),
+        )
+        .map_err(|_| "Unable to set last modified time")
+    };
+    let (mut adopted, mut elsewhere, mut differs, mut missing) = (0, 0, 0, 0);
+    for (file, found) in files.iter().zip(sync::find_local(&files, dest_path)) {
+        let path = dest_path.join(file.path());
+        match found {
+            Found::InPlace => {
+                mark_synced(&path, file)?;
+                adopted += 1;
+            }
+            Found::Elsewhere(from) if move_files => {
+                path.parent()
+                    .map_or(Ok(()), fs::create_dir_all)
+                    .and_then(|_| fs::rename(&from, &path))
+                    .map_err(|_| "Unable to move file into place")?;
+                mark_synced(&path, file)?;
+                println!(
+                    "Moved {} to {}",
+                    from.to_string_lossy(),
+                    path.to_string_lossy()
+                );
+                adopted += 1;
+            }
+            Found::Elsewhere(from) => {
+                println!(
+                    "Found {} at {}",
+                    file.path().to_string_lossy(),
+                    from.to_string_lossy()
+                );
+                elsewhere += 1;
+            }
+            Found::Differs => {
+                println!(
+                    "Left {} alone, it differs in size from the one on LumiNUS",
+                    path.to_string_lossy()
+                );
+                differs += 1;
+            }
+            Found::Missing => missing += 1,
+        }
+    }
+    println!(
+        "Adopted {} files, {} differ from LumiNUS and {} will be downloaded by the next sync",
+        adopted, differs, missing
+    );
+    if elsewhere > 0 {
+        println!(
+            "{} files are in a different folder from where fluminurs keeps them, give --move to move them there",
+            elsewhere
+        );
+    }
+    Ok(())
+}
+
+// Finds where downloaded files that are missing from `destination` were moved to, and deals with them
+// by `policy`. Returns the resources that still need downloading, and the moves to note in the manifest.
+fn handle_moved_files<T: Resource>(
+    resources: Vec<T>,
+    destination: &str,
+    manifest: &Manifest,
+    policy: MovePolicy,
+) -> (Vec<T>, Vec<(String, String)>) {
+    let dest_path = Path::new(destination);
+    let mut moved = HashMap::new();
+    let mut lookup = vec![];
+    for resource in &resources {
+        let path = dest_path.join(resource.path());
+        if path.exists() {
+            continue;
+        }
+        let key = path.to_string_lossy().into_owned();
+        match (
+            manifest.moved_files.get(&key),
+            manifest.fingerprints.get(&key),
+        ) {
+            // noted on an earlier run, no need to look again
+            (Some(moved_to), _) if Path::new(moved_to).exists() => {
+                moved.insert(path, PathBuf::from(moved_to));
+            }
+            (_, Some(fingerprint)) => lookup.push((path, fingerprint)),
+            (_, None) => {}
+        }
+    }
+    if !lookup.is_empty() {
+        moved.extend(moves::find_moved(dest_path, &lookup));
+    }
+
+    let mut pending = vec![];
+    let mut notes = vec![];
+    for resource in resources {
+        let path = dest_path.join(resource.path());
+        let moved_to = match moved.get(&path) {
+            Some(moved_to) => moved_to,
+            None => {
+                pending.push(resource);
+                continue;
+            }
+        };
+        // a newer version on the server is downloaded as usual, the moved copy stays as it is
+        let outdated = fs::metadata(moved_to)
+            .and_then(|metadata| metadata.modified())
+            .map_or(true, |modified| resource.last_updated() > modified);
+        if outdated {
+            pending.push(resource);
+            continue;
+        }
+        match policy {
+            MovePolicy::Download => pending.push(resource),
+            MovePolicy::Leave => {
+                let key = path.to_string_lossy().into_owned();
+                if !manifest.moved_files.contains_key(&key) {
+                    println!(
+                        "Leaving {} where it was moved to, at {}",
+                        path.to_string_lossy(),
+                        moved_to.to_string_lossy()
+                    );
+                }
+                notes.push((key, moved_to.to_string_lossy().into_owned()));
+            }
+            MovePolicy::Relink => {
+                let linked = path
+                    .parent()
+                    .map_or(Ok(()), fs::create_dir_all)
+                    .and_then(|_| fs::hard_link(moved_to, &path));
+                match linked {
+                    Ok(()) => println!(
+                        "Linked {} back to {}",
+                        moved_to.to_string_lossy(),
+                        path.to_string_lossy()
+                    ),
+                    Err(_) => {
+                        println!(
+                            "Unable to link {} back to {}, downloading it again",
+                            moved_to.to_string_lossy(),
+                            path.to_string_lossy()
+                        );
+                        pending.push(resource);
+                    }
+                }
+            }
+        }
+    }
+    (pending, notes)
+}
+
+// Remembers what the files downloaded in this run look like, and forgets moves that no longer hold
+fn remember_fingerprints(manifest: &mut Manifest, downloaded: &[PathBuf]) {
+    for path in downloaded {
+        match Fingerprint::of(path) {
+            Ok(fingerprint) => {
+                manifest
+                    .fingerprints
+                    .insert(path.to_string_lossy().into_owned(), fingerprint);
+            }
+            Err(_) => println!("Unable to checksum {}", path.to_string_lossy()),
+        }
+    }
+    manifest
+        .moved_files
+        .retain(|from, to| !Path::new(from).exists() && Path::new(to).exists());
+}
+
+// Compares the modules on the account against the previous run, and remembers them for the next one
+fn check_enrollment(modules: &[Module], manifest: &mut Manifest, screen_reader: bool) {
+    let current = modules
+        .iter()
+        .map(|module| {
+            (
+                module.id.clone(),
+                KnownModule {
+                    code: module.code.clone(),
+                    name: module.name.clone(),
+                    term: module.term.clone(),
+                },
+            )
+        })
+        .collect::<BTreeMap<_, _>>();
+    let previous = std::mem::replace(&mut manifest.enrolled_modules, current);
+    if previous.is_empty() {
+        // first run, nothing to compare against
+        return;
+    }
+
+    for (id, module) in &manifest.enrolled_modules {
+        if !previous.contains_key(id) {
+            say!(
+                "New module since last run: {} {} (term {})",
+                module.code,
+                module.name,
+                module.term
+            );
+        }
+    }
+    let removed = previous
+        .iter()
+        .filter(|(id, _)| !manifest.enrolled_modules.contains_key(*id))
+        .map(|(_, module)| module)
+        .collect::<Vec<_>>();
+    if !removed.is_empty() && screen_reader {
+        say!("Warning: modules have disappeared from your account since the last run:");
+        for module in removed {
+            say!("{} {}, term {}", module.code, module.name, module.term);
+        }
+        say!("You may be about to lose access to the rest of their files.");
+    } else if !removed.is_empty() {
+        println!();
+        say!("!!! WARNING: modules have disappeared from your account since the last run !!!");
+        for module in removed {
+            println!(
+                "!!!   {} {} (term {})",
+                module.code, module.name, module.term
+            );
+        }
+        say!("!!! You may be about to lose access to the rest of their files.");
+        println!();
+    }
+}
+
+async fn archive_announcements(api: &Api, module: &Module, destination: &Path) -> Result<()> {
+    let (current, archived) = future::join(
+        module.get_announcements(api, false),
+        module.get_announcements(api, true),
+    )
+    .await;
+    let mut output = format!("# {} {} announcements\n", module.code, module.name);
+    for ann in archived?.into_iter().chain(current?) {
+        output.push_str(&format!(
+            "\n## {}\n\n{}\n",
+            ann.title,
+            html_to_text(&ann.description)
+        ));
+    }
+    let module_path = destination.join(module.folder_path());
+    fs::create_dir_all(&module_path).map_err(|_| "Unable to create directory")?;
+    fs::write(module_path.join("Announcements.md"), output)
+        .map_err(|_| "Unable to write announcements")
+}
+
+// Mirrors everything we can get from modules that we are about to lose access to
+async fn archive_modules(
+    api: &Api,
+    modules: &[Module],
+    destination: &str,
+    file_options: &DownloadOptions,
+    multimedia_options: &DownloadOptions,
+    run_summary: &mut RunSummary,
+) -> Result<()> {
+    println!("Archiving expiring modules to {}", destination);
+    let dest_path = Path::new(destination);
+    if !dest_path.is_dir() {
+        return Err("Archive destination does not exist or is not a directory");
+    }
+    for module in modules {
+        if let Err(e) = archive_announcements(api, module, dest_path).await {
+            println!("Failed archiving announcements of {}: {}", module.code, e);
+        }
+    }
+    let files = load_modules_files(api, modules, ModuleTypeFlags::all(), None).await?;
+    let (_, summary) =
+        download_resources(api, files, destination, file_options, MAX_DOWNLOADS).await?;
+    run_summary.add(summary);
+    report_no_access(modules, run_summary);
+    if !api.has_ffmpeg() {
+        println!("ffmpeg was not found, so multimedia will not be archived");
+        return Ok(());
+    }
+    let multimedia = load_modules_multimedia(api, modules).await?;
+    let (_, summary) =
+        download_resources(api, multimedia, destination, multimedia_options, 4).await?;
+    run_summary.add(summary);
+    Ok(())
+}
+
+// Moves module folders left behind under an earlier --folder-name-style or layout, so that nothing is
+// downloaded twice. Folders from before the manifest remembered them were named by module code.
+// `folders` holds (module id, module code, folder) for each module.
+fn migrate_module_folders(
+    manifest: &mut Manifest,
+    destination: &str,
+    folders: &[(String, String, PathBuf)],
+) {
+    let dest_path = Path::new(destination);
+    let known = manifest
+        .module_folders
+        .entry(destination.to_owned())
+        .or_default();
+    let mut moves = vec![];
+    for (id, code, folder) in folders {
+        let previous = known
+            .get(id)
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(sanitise_filename(code)));
+        let from = dest_path.join(&previous);
+        let to = dest_path.join(folder);
+        if &previous != folder && from.is_dir() && !to.exists() {
+            let moved = to
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::rename(&from, &to));
+            if moved.is_err() {
+                // try again next time rather than forgetting where the files are
+                println!(
+                    "Unable to move {} to {}",
+                    from.to_string_lossy(),
+                    to.to_string_lossy()
+                );
+                continue;
+            }
+            println!(
+                "Moved {} to {}",
+                from.to_string_lossy(),
+                to.to_string_lossy()
+            );
+            moves.push((from, to));
+        }
+        known.insert(id.clone(), folder.to_string_lossy().into_owned());
+    }
+    for (from, to) in moves {
+        manifest.move_paths(&from, &to);
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum ListFormat {
+    // aligned columns
+    Table,
+    // one item per line, as fluminurs always printed them
+    Plain,
+    Json,
+}
+
+impl ListFormat {
+    const NAMES: [&'static str; 3] = ["table", "plain", "json"];
+
+    fn parse(name: &str) -> Option<ListFormat> {
+        match name {
+            "table" => Some(ListFormat::Table),
+            "plain" => Some(ListFormat::Plain),
+            "json" => Some(ListFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+// How module, file and multimedia listings are printed
+#[derive(Copy, Clone)]
+struct ListStyle {
+    format: ListFormat,
+    color: bool,
+    permalinks: bool,
+    // one item per line in words, without bullets, padding into columns or bars
+    screen_reader: bool,
+}
+
+// e.g. "1 file" or "3 files", which reads better out loud than "3 file(s)"
+fn count(n: usize, singular: &str, plural: &str) -> String {
+    format!("{} {}", n, if n == 1 { singular } else { plural })
+}
+
+fn paint(text: &str, ansi: &str, color: bool) -> String {
+    if color {
+        format!("\x1b[{}m{}\x1b[0m", ansi, text)
+    } else {
+        text.to_owned()
+    }
+}
+
+// Pads every column to its widest cell. Columns named in `right_aligned` hold numbers and sizes.
+fn print_table(headers: &[&str], rows: &[Vec<String>], right_aligned: &[&str], color: bool) {
+    let widths = headers
+        .iter()
+        .enumerate()
+        .map(|(i, header)| {
+            rows.iter()
+                .map(|row| row[i].chars().count())
+                .chain(Some(header.chars().count()))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<_>>();
+    let line = |cells: Vec<&str>, header: bool| {
+        cells
+            .into_iter()
+            .enumerate()
+            .map(|(i, cell)| {
+                let padded = if right_aligned.contains(&headers[i]) {
+                    format!("{:>width$}", cell, width = widths[i])
+                } else {
+                    format!("{:<width$}", cell, width = widths[i])
+                };
+                // bold headers, and the first column stands out so rows are easy to pick out
+                match (header, i) {
+                    (true, _) => paint(&padded, "1", color),
+                    (false, 0) => paint(&padded, "36", color),
+                    _ => padded,
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_owned()
+    };
+    println!("{}", line(headers.to_vec(), true));
+    for row in rows {
+        println!("{}", line(row.iter().map(String::as_str).collect(), false));
+    }
+}
+
+fn print_json<T: Serialize>(value: &T) {
+    match serde_json::to_string_pretty(value) {
+        Ok(json) => println!("{}", json),
+        Err(_) => println!("Unable to serialise listing"),
+    }
+}
+
+// One module in a listing. Whether it is taken or taught, and its files, aren't always known.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ModuleRow {
+    code: String,
+    name: String,
+    term: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    role: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    access: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    files: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bytes: Option<u64>,
+}
+
+impl ModuleRow {
+    fn of(module: &Module) -> ModuleRow {
+        ModuleRow {
+            code: module.code.clone(),
+            name: module.name.clone(),
+            term: module.term.clone(),
+            role: Some(if module.is_teaching() {
+                "teaching"
+            } else {
+                "taking"
+            }),
+            access: Some(module.capabilities()),
+            files: None,
+            bytes: None,
+        }
+    }
+}
+
+fn list_modules(rows: &[ModuleRow], style: ListStyle) {
+    match style.format {
+        ListFormat::Json => print_json(&rows),
+        ListFormat::Plain => {
+            let groups = [
+                (Some("taking"), "You are taking:"),
+                (Some("teaching"), "You are teaching:"),
+                (None, "Your modules:"),
+            ];
+            for (role, heading) in groups.iter() {
+                let group = rows.iter().filter(|row| row.role == *role);
+                // taking and teaching are always shown, as they always have been
+                if role.is_none() && group.clone().next().is_none() {
+                    continue;
+                }
+                println!("{}", heading);
+                for row in group {
+                    match (row.access, style.screen_reader) {
+                        (Some(access), false) => {
+                            println!("- {} {} [{}]", row.code, row.name, access)
+                        }
+                        (Some(access), true) => println!("{} {}, {}", row.code, row.name, access),
+                        (None, false) => println!("- {} {}", row.code, row.name),
+                        (None, true) => println!("{} {}", row.code, row.name),
+                    }
+                }
+            }
+        }
+        ListFormat::Table => {
+            let has_access = rows.iter().any(|row| row.access.is_some());
+            let has_files = rows.iter().any(|row| row.files.is_some());
+            let mut headers = vec!["Code", "Name", "Term", "Role"];
+            if has_access {
+                headers.push("Access");
+            }
+            if has_files {
+                headers.extend(["Files", "Size"].iter());
+            }
+            let cells = rows
+                .iter()
+                .map(|row| {
+                    let mut cells = vec![
+                        row.code.clone(),
+                        row.name.clone(),
+                        row.term.clone(),
+                        row.role.unwrap_or("-").to_owned(),
+                    ];
+                    if has_access {
+                        cells.push(row.access.unwrap_or("-").to_owned());
+                    }
+                    if has_files {
+                        cells.push(row.files.map_or("-".to_owned(), |n| n.to_string()));
+                        cells.push(row.bytes.map_or("-".to_owned(), format_size));
+                    }
+                    cells
+                })
+                .collect::<Vec<_>>();
+            print_table(&headers, &cells, &["Files", "Size"], style.color);
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ResourceRow<'a> {
+    path: &'a Path,
+    size: Option<u64>,
+    last_updated: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    permalink: Option<&'a str>,
+}
+
+// files cached before permalinks were kept have an empty one
+fn shown_permalink<T: Resource>(resource: &T, style: ListStyle) -> Option<&str> {
+    resource
+        .permalink()
+        .filter(|permalink| style.permalinks && !permalink.is_empty())
+}
+
+fn list_resources<T: Resource>(resources: &[T], style: ListStyle) {
+    match style.format {
+        ListFormat::Plain => {
+            for resource in resources {
+                match shown_permalink(resource, style) {
+                    Some(permalink) => println!("{}\t{}", resource.path().display(), permalink),
+                    None => println!("{}", resource.path().display()),
+                }
+            }
+        }
+        ListFormat::Json => print_json(
+            &resources
+                .iter()
+                .map(|resource| ResourceRow {
+                    path: resource.path(),
+                    size: resource.size(),
+                    last_updated: chrono::DateTime::<chrono::Local>::from(resource.last_updated())
+                        .to_rfc3339(),
+                    permalink: shown_permalink(resource, style),
+                })
+                .collect::<Vec<_>>(),
+        ),
+        ListFormat::Table => {
+            let mut headers = vec!["Path", "Size", "Updated"];
+            if style.permalinks {
+                headers.push("Link");
+            }
+            let rows = resources
+                .iter()
+                .map(|resource| {
+                    let mut cells = vec![
+                        resource.path().display().to_string(),
+                        resource.size().map_or("-".to_owned(), format_size),
+                        chrono::DateTime::<chrono::Local>::from(resource.last_updated())
+                            .format("%Y-%m-%d %H:%M")
+                            .to_string(),
+                    ];
+                    if style.permalinks {
+                        cells.push(shown_permalink(resource, style).unwrap_or("-").to_owned());
+                    }
+                    cells
+                })
+                .collect::<Vec<_>>();
+            print_table(&headers, &rows, &["Size"], style.color);
+        }
+    }
+}
+
+struct DownloadOutcome {
+    path: PathBuf,
+    permalink: Option<String>,
+    // how much was actually transferred, if anything was
+    bytes: Option<u64>,
+    result: Result<OverwriteResult>,
+    diff: Option<ContentDiff>,
+    duration: Duration,
+    // what the download overwrote, trashed or pruned => the copy kept for `undo`
+    kept: Vec<(PathBuf, PathBuf)>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ResourceRecord {
+    path: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    permalink: Option<String>,
+    result: &'static str,
+    // where the old version went, when it was renamed out of the way
+    #[serde(skip_serializing_if = "Option::is_none")]
+    renamed_to: Option<PathBuf>,
+    #[serde(skip)]
+    kept: Vec<(PathBuf, PathBuf)>,
+    error: Option<&'static str>,
+    error_category: Option<&'static str>,
+    seconds: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bytes_per_second: Option<f64>,
+}
+
+// Rough grouping of errors, so that wrapper scripts can decide whether retrying is worthwhile
+fn error_category(error: &str) -> &'static str {
+    let error = error.to_lowercase();
+    if error.contains("http") || error.contains("download") || error.contains("streaming") {
+        "network"
+    } else if error.contains("disk")
+        || error.contains("file")
+        || error.contains("directory")
+        || error.contains("metadata")
+    {
+        "filesystem"
+    } else if error.contains("server") || error.contains("json") {
+        "server"
+    } else {
+        "other"
+    }
+}
+
+// Everything that happened in a run, for --summary-file
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RunSummary {
+    started: String,
+    seconds: f64,
+    success: bool,
+    error: Option<&'static str>,
+    error_category: Option<&'static str>,
+    new_files: usize,
+    updated: usize,
+    skipped: usize,
+    renamed: usize,
+    failed: usize,
+    resources: Vec<ResourceRecord>,
+    // left for the next run, once the byte budget was used up
+    deferred: Vec<PathBuf>,
+    // modules whose files weren't synced because the lecturer hasn't opened them yet
+    no_access: Vec<String>,
+    // request latency, retries and throughput for each host we talked to
+    hosts: Vec<HostStats>,
+    #[serde(skip)]
+    transfer_stats: Option<Arc<TransferStats>>,
+    #[serde(skip)]
+    schema_drift: Option<Arc<DriftLog>>,
+    // the folder of each synced module under each destination => its code, for the history
+    #[serde(skip)]
+    module_folders: Vec<(PathBuf, String)>,
+    #[serde(skip)]
+    verbose: bool,
+    #[serde(skip)]
+    screen_reader: bool,
+    #[serde(skip)]
+    clock: Instant,
+}
+
+impl RunSummary {
+    fn new() -> RunSummary {
+        RunSummary {
+            started: chrono::Local::now().to_rfc3339(),
+            seconds: 0.0,
+            success: true,
+            error: None,
+            error_category: None,
+            new_files: 0,
+            updated: 0,
+            skipped: 0,
+            renamed: 0,
+            failed: 0,
+            resources: vec![],
+            deferred: vec![],
+            no_access: vec![],
+            hosts: vec![],
+            transfer_stats: None,
+            schema_drift: None,
+            module_folders: vec![],
+            verbose: false,
+            screen_reader: false,
+            clock: Instant::now(),
+        }
+    }
+
+    fn add_module_folders(&mut self, destination: &str, folders: &[(String, String, PathBuf)]) {
+        self.module_folders.extend(
+            folders
+                .iter()
+                .map(|(_, code, folder)| (Path::new(destination).join(folder), code.clone())),
+        );
+    }
+
+    // the code of the module whose folder `path` is in
+    fn module_of(&self, path: &Path) -> Option<&str> {
+        self.module_folders
+            .iter()
+            .filter(|(folder, _)| path.starts_with(folder))
+            .max_by_key(|(folder, _)| folder.components().count())
+            .map(|(_, code)| code.as_str())
+    }
+
+    fn add(&mut self, summary: SyncSummary) {
+        self.new_files += summary.new_files;
+        self.updated += summary.updated;
+        self.skipped += summary.skipped;
+        self.renamed += summary.renamed;
+        self.failed += summary.failed;
+        self.resources.extend(summary.records);
+        self.deferred.extend(summary.deferred);
+    }
+
+    fn finish(&mut self, started: Instant, result: &Result<()>) {
+        self.seconds = started.elapsed().as_secs_f64();
+        if let Some(transfer_stats) = &self.transfer_stats {
+            self.hosts = transfer_stats.summary();
+        }
+        if let Err(e) = result {
+            self.success = false;
+            self.error = Some(e);
+            self.error_category = Some(error_category(e));
+        }
+    }
+
+    // what --strict found wrong with the responses, which the library leaves to us to show
+    fn print_schema_drift(&self) {
+        let responses = self
+            .schema_drift
+            .as_ref()
+            .map(|drift| drift.responses())
+            .unwrap_or_default();
+        for (path, drift) in responses {
+            println!(
+                "Response to {} does not match what fluminurs expects:",
+                path
+            );
+            for difference in drift {
+                println!("  {}", difference);
+            }
+        }
+    }
+
+    fn print_hosts(&self) {
+        if self.hosts.is_empty() {
+            return;
+        }
+        let millis = |ms: Option<f64>| ms.map_or("-".to_owned(), |ms| format!("{:.0} ms", ms));
+        let speed = |host: &HostStats| {
+            host.bytes_per_second.map_or("-".to_owned(), |speed| {
+                format!("{}/s", format_size(speed as u64))
+            })
+        };
+        if self.screen_reader {
+            for host in &self.hosts {
+                println!(
+                    "{}: {}, {} retried, {} received at {}, latency median {}, 90th percentile {}, max {}",
+                    host.host,
+                    count(host.requests, "request", "requests"),
+                    host.retries,
+                    format_size(host.bytes),
+                    speed(host),
+                    millis(host.latency_median_ms),
+                    millis(host.latency_p90_ms),
+                    millis(host.latency_max_ms)
+                );
+            }
+            return;
+        }
+        println!(
+            "{:<32} {:>9} {:>8} {:>12} {:>12} {:>10} {:>10} {:>10}",
+            "Host", "Requests", "Retries", "Received", "Speed", "Median", "p90", "Max"
+        );
+        for host in &self.hosts {
+            println!(
+                "{:<32} {:>9} {:>8} {:>12} {:>12} {:>10} {:>10} {:>10}",
+                host.host,
+                host.requests,
+                host.retries,
+                format_size(host.bytes),
+                speed(host),
+                millis(host.latency_median_ms),
+                millis(host.latency_p90_ms),
+                millis(host.latency_max_ms)
+            );
+        }
+    }
+}
+
+#[derive(Default)]
+struct SyncSummary {
+    new_files: usize,
+    updated: usize,
+    skipped: usize,
+    renamed: usize,
+    failed: usize,
+    diffs: Vec<(PathBuf, ContentDiff)>,
+    // files that were newly downloaded or replaced by a newer version in this run
+    downloaded: Vec<PathBuf>,
+    records: Vec<ResourceRecord>,
+    deferred: Vec<PathBuf>,
+}
+
+impl SyncSummary {
+    fn record(&mut self, outcome: DownloadOutcome) {
+        if matches!(
+            outcome.result,
+            Ok(OverwriteResult::NewFile)
+                | Ok(OverwriteResult::Overwritten)
+                | Ok(OverwriteResult::Renamed { .. })
+                | Ok(OverwriteResult::Trashed)
+        ) {
+            self.downloaded.push(outcome.path.clone());
+        }
+        let seconds = outcome.duration.as_secs_f64();
+        self.records.push(ResourceRecord {
+            path: outcome.path.clone(),
+            permalink: outcome.permalink,
+            result: match &outcome.result {
+                Ok(OverwriteResult::NewFile) => "new",
+                Ok(OverwriteResult::AlreadyHave) => "unchanged",
+                Ok(OverwriteResult::Skipped) => "skipped",
+                Ok(OverwriteResult::Overwritten) => "updated",
+                Ok(OverwriteResult::Renamed { .. }) => "renamed",
+                Ok(OverwriteResult::Trashed) => "trashed",
+                Err(_) => "failed",
+            },
+            renamed_to: match &outcome.result {
+                Ok(OverwriteResult::Renamed { renamed_path }) => Some(renamed_path.clone()),
+                _ => None,
+            },
+            kept: outcome.kept,
+            error: outcome.result.as_ref().err().copied(),
+            error_category: outcome.result.as_ref().err().map(|e| error_category(e)),
+            seconds,
+            bytes: outcome.bytes,
+            bytes_per_second: outcome
+                .bytes
+                .filter(|_| seconds > 0.0)
+                .map(|bytes| bytes as f64 / seconds),
+        });
+        match outcome.result {
+            Ok(OverwriteResult::NewFile) => self.new_files += 1,
+            Ok(OverwriteResult::AlreadyHave) => {}
+            Ok(OverwriteResult::Skipped) => self.skipped += 1,
+            Ok(OverwriteResult::Overwritten) | Ok(OverwriteResult::Trashed) => self.updated += 1,
+            Ok(OverwriteResult::Renamed { .. }) => self.renamed += 1,
+            Err(_) => self.failed += 1,
+        }
+        if let Some(diff) = outcome.diff {
+            self.diffs.push((outcome.path, diff));
+        }
+    }
+
+    fn print(&self) {
+        say!(
+            "Sync summary: {} new, {} updated, {} renamed, {} skipped, {} failed",
+            self.new_files,
+            self.updated,
+            self.renamed,
+            self.skipped,
+            self.failed
+        );
+        if !self.deferred.is_empty() {
+            say!(
+                "{} download(s) were left for the next run by --max-bytes-per-run or --max-duration",
+                self.deferred.len()
+            );
+        }
+        for (path, diff) in &self.diffs {
+            println!("Changes in {}:", path.to_string_lossy());
+            println!(
+                "  size: {} -> {} bytes ({:+})",
+                diff.old_size,
+                diff.new_size,
+                diff.size_delta()
+            );
+            if let (Some(old_pages), Some(new_pages), Some(delta)) =
+                (diff.old_pages, diff.new_pages, diff.page_delta())
+            {
+                println!("  pages: {} -> {} ({:+})", old_pages, new_pages, delta);
+            }
+            if let Some(text_diff) = &diff.text_diff {
+                print!("{}", text_diff);
+            }
+        }
+    }
+}
+
+// Modules show up before their lecturers open them, and until then there is nothing in them to sync.
+// They are picked up by the first run after that, like any other module.
+fn report_no_access(modules: &[Module], run_summary: &mut RunSummary) {
+    run_summary.no_access = modules
+        .iter()
+        .filter(|module| !module.has_access())
+        .map(|module| module.code.clone())
+        .collect();
+    if !run_summary.no_access.is_empty() {
+        say!(
+            "No access yet to {}, so their files were not synced. They will be on a later run, once the lecturers open them.",
+            run_summary.no_access.join(", ")
+        );
+    }
+}
+
+// Keeps a copy of a file that is about to be overwritten or trashed, so that we can diff it afterwards.
+// Rename mode already keeps the old file around, so there is no need for a copy in that case.
+async fn keep_previous_version<T: Resource>(
+    file: &T,
+    path: &Path,
+    overwrite_mode: OverwriteMode,
+) -> Option<PathBuf> {
+    if !matches!(
+        overwrite_mode,
+        OverwriteMode::Overwrite | OverwriteMode::Trash
+    ) || !is_diffable(path)
+    {
+        return None;
+    }
+    let modified = tokio::fs::metadata(path).await.ok()?.modified().ok()?;
+    if modified >= file.last_updated() {
+        return None;
+    }
+    let previous_path = path.with_file_name(make_prefixed_file_name("~!prev~", path.file_name()?));
+    tokio::fs::copy(path, &previous_path).await.ok()?;
+    Some(previous_path)
+}
+
+// Hard links what downloading to `path` may overwrite, trash or prune into this run's undo folder,
+// or copies it where that can't be done. Returns the originals with their copies.
+async fn keep_for_undo(
+    api: &Api,
+    path: &Path,
+    overwrite_mode: OverwriteMode,
+    undo_dir: &Path,
+) -> Vec<(PathBuf, PathBuf)> {
+    let at_risk = match overwrite_mode {
+        OverwriteMode::Overwrite | OverwriteMode::Trash => vec![path.to_owned()],
+        // the renamed file itself is safe, but older versions may be pruned to make room for it
+        OverwriteMode::Rename if api.rename_scheme().retention.is_set() => {
+            api.rename_scheme().versions_of(path)
+        }
+        _ => vec![],
+    };
+    let mut kept = vec![];
+    for original in at_risk {
+        if !original.is_file() {
+            continue;
+        }
+        // laid out as in the mirror, so that the copies can also be found by hand
+        let copy = undo_dir.join(
+            original
+                .components()
+                .filter(|component| matches!(component, Component::Normal(_)))
+                .collect::<PathBuf>(),
+        );
+        if let Some(parent) = copy.parent() {
+            if tokio::fs::create_dir_all(parent).await.is_err() {
+                continue;
+            }
+        }
+        let _ = tokio::fs::remove_file(&copy).await;
+        if tokio::fs::hard_link(&original, &copy).await.is_ok()
+            || tokio::fs::copy(&original, &copy).await.is_ok()
+        {
+            kept.push((original, copy));
+        }
+    }
+    kept
+}
+
+// Drops the copies of what the download didn't end up overwriting, trashing or pruning after all
+async fn settle_undo(
+    kept: Vec<(PathBuf, PathBuf)>,
+    path: &Path,
+    result: &Result<OverwriteResult>,
+) -> Vec<(PathBuf, PathBuf)> {
+    let replaced = matches!(
+        result,
+        Ok(OverwriteResult::Overwritten) | Ok(OverwriteResult::Trashed)
+    );
+    let mut settled = vec![];
+    for (original, copy) in kept {
+        let lost = if original == path {
+            replaced
+        } else {
+            !original.exists()
+        };
+        if lost {
+            settled.push((original, copy));
+        } else {
+            let _ = tokio::fs::remove_file(&copy).await;
+        }
+    }
+    settled
+}
+
+#[derive(Clone)]
+struct DownloadOptions {
+    overwrite_mode: OverwriteMode,
+    diff_updated: bool,
+    strip_cover_pages: bool,
+    extract_text: bool,
+    // list and report each resource's web page alongside its path
+    permalinks: bool,
+    // report the size and speed of every download
+    verbose: bool,
+    // where this run keeps what it overwrites, trashes or prunes, for `undo`
+    undo_dir: Rc<Path>,
+}
+
+fn permalink_of<T: Resource>(file: &T, options: &DownloadOptions) -> Option<String> {
+    file.permalink()
+        .filter(|_| options.permalinks)
+        .map(|permalink| permalink.to_owned())
+}
+
+async fn download_resource<T: Resource>(
+    api: &Api,
+    file: &T,
+    dest_path: &Path,
+    options: &DownloadOptions,
+) -> DownloadOutcome {
+    let temp_path = dest_path
+        .join(file.path().parent().unwrap())
+        .join(make_temp_file_name(file.path().file_name().unwrap()));
+    let path = dest_path.join(file.path());
+    let started = Instant::now();
+    let previous_path = if options.diff_updated {
+        keep_previous_version(file, &path, options.overwrite_mode).await
+    } else {
+        None
+    };
+    let kept = keep_for_undo(api, &path, options.overwrite_mode, &options.undo_dir).await;
+    let result = file
+        .download(api, &path, &temp_path, options.overwrite_mode)
+        .await;
+    let kept = settle_undo(kept, &path, &result).await;
+    match &result {
+        Ok(OverwriteResult::NewFile) => say!("Downloaded to {}", path.to_string_lossy()),
+        Ok(OverwriteResult::AlreadyHave) => {}
+        Ok(OverwriteResult::Skipped) => say!("Skipped {}", path.to_string_lossy()),
+        Ok(OverwriteResult::Overwritten) => say!("Updated {}", path.to_string_lossy()),
+        Ok(OverwriteResult::Trashed) => say!(
+            "Updated {}, the old version is in the trash",
+            path.to_string_lossy()
+        ),
+        Ok(OverwriteResult::Renamed { renamed_path }) => say!(
+            "Renamed {} to {}",
+            path.to_string_lossy(),
+            renamed_path.to_string_lossy()
+        ),
+        Err(e) => say!("Failed to download file: {}", e),
+    }
+    let diff = match (&result, &previous_path) {
+        (Ok(OverwriteResult::Overwritten), Some(previous_path))
+        | (Ok(OverwriteResult::Trashed), Some(previous_path)) => {
+            ContentDiff::between(previous_path, &path).await.ok()
+        }
+        (Ok(OverwriteResult::Renamed { renamed_path }), _)
+            if options.diff_updated && is_diffable(&path) =>
+        {
+            ContentDiff::between(renamed_path, &path).await.ok()
+        }
+        _ => None,
+    };
+    if let Some(previous_path) = previous_path {
+        if tokio::fs::remove_file(&previous_path).await.is_err() {
+            println!(
+                "Unable to delete temporary file {}",
+                previous_path.to_string_lossy()
+            );
+        }
+    }
+    let downloaded = matches!(
+        result,
+        Ok(OverwriteResult::NewFile)
+            | Ok(OverwriteResult::Overwritten)
+            | Ok(OverwriteResult::Renamed { .. })
+            | Ok(OverwriteResult::Trashed)
+    );
+    // measured before any post-processing gets to change the file
+    let duration = started.elapsed();
+    let bytes = if downloaded {
+        tokio::fs::metadata(&path)
+            .await
+            .ok()
+            .map(|metadata| metadata.len())
+    } else {
+        None
+    };
+    if let (Some(bytes), true) = (bytes, options.verbose) {
+        println!(
+            "  {} in {:.1}s ({}/s)",
+            format_size(bytes),
+            duration.as_secs_f64(),
+            format_size((bytes as f64 / duration.as_secs_f64().max(0.001)) as u64)
+        );
+    }
+    let backup_path = dest_path.join(ORIGINALS_DIR).join(file.path());
+    if downloaded {
+        // an original kept from an earlier download would no longer match the server's copy
+        let _ = tokio::fs::remove_file(&backup_path).await;
+    }
+    if downloaded && options.strip_cover_pages && pdf::is_pdf(&path) {
+        let stripped_path = path.clone();
+        match tokio::task::spawn_blocking(move || {
+            pdf::strip_cover_page(&stripped_path, &backup_path)
+        })
+        .await
+        {
+            Ok(Ok(true)) => println!("Removed cover page from {}", path.to_string_lossy()),
+            Ok(Ok(false)) => {}
+            Ok(Err(e)) => println!("Failed to remove cover page: {}", e),
+            Err(_) => println!("Failed to remove cover page: task panicked"),
+        }
+    }
+    if downloaded && options.extract_text && extract::is_extractable(&path) {
+        let extracted_path = path.clone();
+        match tokio::task::spawn_blocking(move || extract::write_sidecar(&extracted_path)).await {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => println!("Failed to extract text: {}", e),
+            Err(_) => println!("Failed to extract text: task panicked"),
+        }
+    }
+    DownloadOutcome {
+        path,
+        permalink: permalink_of(file, options),
+        bytes,
+        result,
+        diff,
+        duration,
+        kept,
+    }
+}
+
+// Puts back the extensions that --fix-extensions added on earlier runs, so that files are looked for
+// where they were saved, and not downloaded again
+fn with_added_extensions(files: Vec<File>, added: &BTreeMap<String, String>) -> Vec<File> {
+    if added.is_empty() {
+        return files;
+    }
+    files
+        .into_iter()
+        .map(|file| match added.get(&*file.path().to_string_lossy()) {
+            Some(extension) => file.with_extra_extension(extension),
+            None => file,
+        })
+        .collect()
+}
+
+// Adds an extension to each downloaded file whose content doesn't go with the one it has,
+// remembering it so that the next run finds the file under its new name
+fn add_missing_extensions(
+    files: Vec<File>,
+    destination: &str,
+    downloaded: &mut [PathBuf],
+    manifest: &mut Manifest,
+) -> Vec<File> {
+    let dest_path = Path::new(destination);
+    files
+        .into_iter()
+        .map(|file| {
+            let path = dest_path.join(file.path());
+            let entry = match downloaded
+                .iter_mut()
+                .find(|downloaded| **downloaded == path)
+            {
+                Some(entry) => entry,
+                None => return file,
+            };
+            let extension = match sniff::missing_extension(&path) {
+                Ok(Some(extension)) => extension,
+                _ => return file,
+            };
+            let mut fixed_name = path.file_name().unwrap_or_default().to_owned();
+            fixed_name.push(".");
+            fixed_name.push(extension);
+            let fixed_path = path.with_file_name(fixed_name);
+            if fixed_path.exists() || fs::rename(&path, &fixed_path).is_err() {
+                println!(
+                    "Unable to rename {} to {}",
+                    path.to_string_lossy(),
+                    fixed_path.to_string_lossy()
+                );
+                return file;
+            }
+            say!(
+                "Renamed {} to {}, going by its content",
+                path.to_string_lossy(),
+                fixed_path.to_string_lossy()
+            );
+            manifest.added_extensions.insert(
+                file.path().to_string_lossy().into_owned(),
+                extension.to_owned(),
+            );
+            *entry = fixed_path;
+            file.with_extra_extension(extension)
+        })
+        .collect()
+}
+
+// Leaves out what the .fluminursignore files under `destination` exclude
+fn drop_ignored<T: Resource>(resources: Vec<T>, destination: &Path) -> Vec<T> {
+    let ignore_files = IgnoreFiles::new(destination);
+    let listed = resources.len();
+    let kept = resources
+        .into_iter()
+        .filter(|resource| !ignore_files.is_ignored(resource.path(), false))
+        .collect::<Vec<_>>();
+    if kept.len() < listed {
+        println!(
+            "Leaving out {} file(s) matched by {}",
+            listed - kept.len(),
+            IGNORE_FILE_NAME
+        );
+    }
+    kept
+}
+
+// Plans and runs the downloads, then hands the resources back for whatever comes after
+async fn download_resources<T: Resource + 'static>(
+    api: &Api,
+    files: Vec<T>,
+    destination: &str,
+    options: &DownloadOptions,
+    parallelism: usize,
+) -> Result<(Vec<T>, SyncSummary)> {
+    say!("Download to {}", destination);
+    let dest_path = Path::new(destination);
+    if !dest_path.is_dir() {
+        return Err("Download destination does not exist or is not a directory");
+    }
+    let files = drop_ignored(files, dest_path);
+
+    let plan = sync::plan(&files, dest_path, api.compare_by()).await;
+    let mut summary = SyncSummary::default();
+    for &index in &plan.up_to_date {
+        let file = &files[index];
+        summary.record(DownloadOutcome {
+            path: dest_path.join(file.path()),
+            permalink: permalink_of(file, options),
+            bytes: None,
+            result: Ok(OverwriteResult::AlreadyHave),
+            diff: None,
+            duration: Duration::default(),
+            kept: vec![],
+        });
+    }
+
+    // smallest first, so that a slow or short session still gets most files
+    let mut pending = plan.pending();
+    pending.sort_by_key(|&index| files[index].size().unwrap_or(u64::MAX));
+
+    // the download tasks own what they work on, so nothing borrowed can outlive this function
+    let pending_count = pending.len();
+    let files = Rc::new(files);
+    let session = Rc::new(api.clone());
+    let shared_dest_path = Rc::new(dest_path.to_owned());
+    let pipeline = Pipeline::new(parallelism, api.cancellation().clone());
+    let outcomes = pipeline
+        .run(pending, |index| {
+            let (api, files, dest_path, options) = (
+                session.clone(),
+                files.clone(),
+                shared_dest_path.clone(),
+                options.clone(),
+            );
+            // decided as the download would start, so that the ones in flight still finish
+            let expected = files[index].size().unwrap_or(0);
+            let allowed = !api.past_deadline()
+                && api
+                    .byte_budget()
+                    .is_none_or(|budget| budget.try_reserve(expected));
+            async move {
+                if !allowed {
+                    return (index, None);
+                }
+                let outcome = download_resource(&api, &files[index], &dest_path, &options).await;
+                if let Some(budget) = api.byte_budget() {
+                    budget.settle(expected, outcome.bytes.unwrap_or(0));
+                }
+                (index, Some(outcome))
+            }
+        })
+        .await;
+    if pipeline.is_cancelled() {
+        say!(
+            "Stopped early, {} download(s) were not started",
+            pending_count - outcomes.len()
+        );
+    }
+    for (index, outcome) in outcomes {
+        match outcome {
+            Some(outcome) => summary.record(outcome),
+            None => summary.deferred.push(dest_path.join(files[index].path())),
+        }
+    }
+    summary.print();
+    if let (Some(limit), true) = (api.download_limit(), options.verbose) {
+        let report = limit.report();
+        println!(
+            "Download concurrency: {} now, {} at the lowest, out of at most {} ({} slowdown(s))",
+            report.current, report.lowest, report.max, report.slowdowns
+        );
+    }
+
+    let files = Rc::try_unwrap(files)
+        .ok()
+        .expect("Downloads outlived their pipeline");
+    Ok((files, summary))
+}
+
+// Adds what this run downloaded to the history that `stats` and `history` show, by module
+fn remember_sync_run(manifest: &mut Manifest, run_summary: &RunSummary, undo_dir: &Path) {
+    let mut modules = BTreeMap::<String, u64>::new();
+    let mut changes = vec![];
+    for record in &run_summary.resources {
+        let module = run_summary.module_of(&record.path).map(str::to_owned);
+        if let (Some(module), Some(bytes)) = (&module, record.bytes) {
+            *modules.entry(module.clone()).or_default() += bytes;
+        }
+        let kept_as = |original: &Path| {
+            record
+                .kept
+                .iter()
+                .find(|(kept, _)| kept == original)
+                .map(|(_, copy)| copy.to_string_lossy().into_owned())
+        };
+        if matches!(record.result, "new" | "updated" | "renamed" | "trashed") {
+            changes.push(FileChange {
+                path: record.path.to_string_lossy().into_owned(),
+                module: module.clone(),
+                result: record.result.to_owned(),
+                renamed_to: record
+                    .renamed_to
+                    .as_ref()
+                    .map(|path| path.to_string_lossy().into_owned()),
+                kept_as: kept_as(&record.path),
+            });
+        }
+        for (original, _) in record.kept.iter().filter(|(kept, _)| kept != &record.path) {
+            changes.push(FileChange {
+                path: original.to_string_lossy().into_owned(),
+                module: module.clone(),
+                result: "pruned".to_owned(),
+                renamed_to: None,
+                kept_as: kept_as(original),
+            });
+        }
+    }
+    // only the latest run that changed anything can be undone, so the copies older runs kept can go
+    if !changes.is_empty() {
+        discard_other_undo_dirs(undo_dir);
+    }
+    history::remember(
+        &mut manifest.sync_history,
+        SyncRun {
+            started: run_summary.started.clone(),
+            seconds: run_summary.clock.elapsed().as_secs_f64(),
+            bytes: run_summary
+                .resources
+                .iter()
+                .filter_map(|record| record.bytes)
+                .sum(),
+            files: run_summary.new_files + run_summary.updated + run_summary.renamed,
+            failed: run_summary.failed,
+            modules,
+            changes,
+            undone: false,
+        },
+    );
+}
+
+// Weekly totals of what syncs downloaded, as a bar chart, so that weeks where a module posted
+// far more than usual stand out
+fn print_sync_stats(history: &[SyncRun], screen_reader: bool) {
+    const BAR_WIDTH: u64 = 40;
+    let weeks = history::by_week(history);
+    if weeks.is_empty() {
+        println!("No syncs have been recorded yet");
+        return;
+    }
+    let most = weeks
+        .iter()
+        .map(|week| week.bytes)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    if !screen_reader {
+        println!(
+            "{:<10} {:>5} {:>6} {:>10}",
+            "Week of", "Runs", "Files", "Downloaded"
+        );
+    }
+    for week in &weeks {
+        if screen_reader {
+            let busiest = match week.busiest_module() {
+                Some((module, bytes)) if bytes > 0 => {
+                    format!(", most of it for {}", module)
+                }
+                _ => String::new(),
+            };
+            println!(
+                "Week of {}: {}, {}, {} downloaded{}",
+                week.start.format("%e %B %Y").to_string().trim(),
+                count(week.runs, "run", "runs"),
+                count(week.files, "file", "files"),
+                format_size(week.bytes),
+                busiest
+            );
+            continue;
+        }
+        let bar = "#".repeat((week.bytes * BAR_WIDTH).div_ceil(most) as usize);
+        let busiest = match week.busiest_module() {
+            Some((module, bytes)) if bytes > 0 => {
+                format!(" {} ({})", module, format_size(bytes))
+            }
+            _ => String::new(),
+        };
+        let line = format!(
+            "{:<10} {:>5} {:>6} {:>10} {:<width$}{}",
+            week.start.format("%Y-%m-%d"),
+            week.runs,
+            week.files,
+            format_size(week.bytes),
+            bar,
+            busiest,
+            width = BAR_WIDTH as usize
+        );
+        println!("{}", line.trim_end());
+    }
+    let seconds = history.iter().map(|run| run.seconds).sum::<f64>();
+    let failed = history.iter().map(|run| run.failed).sum::<usize>();
+    println!(
+        "{} run(s), {} downloaded, {:.0}s on average, {} failed download(s)",
+        history.len(),
+        format_size(history.iter().map(|run| run.bytes).sum()),
+        seconds / history.len() as f64,
+        failed
+    );
+}
+
+// What syncs downloaded or updated and when, oldest first, for checking that a file was fetched.
+// `module` is a module code or an alias of one.
+fn print_file_history(
+    history: &[SyncRun],
+    aliases: &ModuleAliases,
+    module: Option<&str>,
+    since: Option<chrono::DateTime<chrono::Local>>,
+) {
+    let module = module.map(|module| aliases.expand(module));
+    let changes = history::changes_since(history, since)
+        .into_iter()
+        .filter(|(_, change)| {
+            module.is_none_or(|module| {
+                change
+                    .module
+                    .as_deref()
+                    .is_some_and(|code| code.eq_ignore_ascii_case(module))
+            })
+        })
+        .collect::<Vec<_>>();
+    if changes.is_empty() {
+        println!("No downloads or updates on record for that");
+        return;
+    }
+    for (started, change) in changes {
+        let old_version = match (change.result.as_str(), &change.renamed_to) {
+            ("renamed", Some(renamed_to)) => format!(" (old version kept as {})", renamed_to),
+            ("trashed", _) => " (old version in the trash)".to_owned(),
+            _ => String::new(),
+        };
+        println!(
+            "{}  {:<8} {}{}",
+            started.format("%a %Y-%m-%d %H:%M"),
+            change.result,
+            change.path,
+            old_version
+        );
+    }
+}
+
+// Replaces what earlier runs left over, since anything still missing is found again anyway
+fn remember_deferred(manifest: &mut Manifest, run_summary: &RunSummary) {
+    manifest.deferred_downloads = run_summary
+        .deferred
+        .iter()
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect();
+}
+
+async fn plan_sync<T: Resource>(
+    resources: Vec<T>,
+    destination: &str,
+    overwrite: OverwriteMode,
+    compare_by: CompareBy,
+) -> PlannedSync {
+    let resources = drop_ignored(resources, Path::new(destination));
+    let plan = sync::plan(&resources, Path::new(destination), compare_by).await;
+    PlannedSync {
+        destination: PathBuf::from(destination),
+        overwrite: overwrite.as_str().to_owned(),
+        actions: plan.actions(&resources, overwrite),
+    }
+}
+
+fn print_planned_sync(kind: &str, planned: &PlannedSync) {
+    println!(
+        "{} to {} ({} action(s)):",
+        kind,
+        planned.destination.to_string_lossy(),
+        planned.actions.len()
+    );
+    for action in &planned.actions {
+        match action {
+            Action::Download { path, .. } => println!("  download {}", path.to_string_lossy()),
+            Action::Update {
+                path, overwrite, ..
+            } => println!("  update {} ({})", path.to_string_lossy(), overwrite),
+            Action::Skip { path, .. } => println!("  skip {}", path.to_string_lossy()),
+        }
+    }
+}
+
+// Writes out what a sync would do, without doing any of it, for `apply` to carry out after review
+#[allow(clippy::too_many_arguments)]
+async fn write_sync_plan(
+    api: &Api,
+    modules: &[Module],
+    include_uploadable_folders: ModuleTypeFlags,
+    download_destination: Option<&str>,
+    multimedia_download_destination: Option<&str>,
+    file_options: &DownloadOptions,
+    multimedia_options: &DownloadOptions,
+    added_extensions: &BTreeMap<String, String>,
+    output: &str,
+) -> Result<()> {
+    if download_destination.is_none() && multimedia_download_destination.is_none() {
+        return Err("Nothing to plan, give --download-to or --download-multimedia-to");
+    }
+    let files = match download_destination {
+        Some(destination) => {
+            let files = load_modules_files(api, modules, include_uploadable_folders, None).await?;
+            let files = with_added_extensions(files, added_extensions);
+            Some(
+                plan_sync(
+                    files,
+                    destination,
+                    file_options.overwrite_mode,
+                    api.compare_by(),
+                )
+                .await,
+            )
+        }
+        None => None,
+    };
+    let multimedia = match multimedia_download_destination {
+        Some(destination) => {
+            let multimedia = load_modules_multimedia(api, modules).await?;
+            Some(
+                plan_sync(
+                    multimedia,
+                    destination,
+                    multimedia_options.overwrite_mode,
+                    api.compare_by(),
+                )
+                .await,
+            )
+        }
+        None => None,
+    };
+    let plan = SyncPlan {
+        created: chrono::Local::now().to_rfc3339(),
+        files,
+        multimedia,
+    };
+    if let Some(files) = &plan.files {
+        print_planned_sync("Files", files);
+    }
+    if let Some(multimedia) = &plan.multimedia {
+        print_planned_sync("Multimedia", multimedia);
+    }
+    let json = serde_json::to_string_pretty(&plan).map_err(|_| "Unable to serialise plan")?;
+    fs::write(output, json).map_err(|_| "Unable to write plan")?;
+    println!("Wrote plan to {}", output);
+    Ok(())
+}
+
+// Carries out a plan from `plan`. Only what the plan lists is downloaded, and only if it
+// hasn't changed on the server since.
+#[allow(clippy::too_many_arguments)]
+async fn apply_sync_plan(
+    api: &Api,
+    modules: &[Module],
+    include_uploadable_folders: ModuleTypeFlags,
+    plan_file: &str,
+    file_options: &DownloadOptions,
+    multimedia_options: &DownloadOptions,
+    added_extensions: &BTreeMap<String, String>,
+    run_summary: &mut RunSummary,
+) -> Result<()> {
+    let content = fs::read_to_string(plan_file).map_err(|_| "Unable to read plan")?;
+    let plan = serde_json::from_str::<SyncPlan>(&content).map_err(|_| "Unable to parse plan")?;
+    let overwrite_mode = |planned: &PlannedSync| {
+        OverwriteMode::parse(&planned.overwrite).ok_or("Invalid overwrite mode in plan")
+    };
+    if let Some(planned) = &plan.files {
+        let options = DownloadOptions {
+            overwrite_mode: overwrite_mode(planned)?,
+            ..file_options.clone()
+        };
+        let files = load_modules_files(api, modules, include_uploadable_folders, None).await?;
+        let files = with_added_extensions(files, added_extensions);
+        let (files, stale) = sync::select(files, &planned.actions);
+        if stale > 0 {
+            println!(
+                "Left out {} file(s) that changed on LumiNUS since the plan was made",
+                stale
+            );
+        }
+        let (_, summary) = download_resources(
+            api,
+            files,
+            &planned.destination.to_string_lossy(),
+            &options,
+            MAX_DOWNLOADS,
+        )
+        .await?;
+        run_summary.add(summary);
+        report_no_access(modules, run_summary);
+    }
+    if let Some(planned) = &plan.multimedia {
+        let options = DownloadOptions {
+            overwrite_mode: overwrite_mode(planned)?,
+            ..multimedia_options.clone()
+        };
+        let multimedia = load_modules_multimedia(api, modules).await?;
+        let (multimedia, stale) = sync::select(multimedia, &planned.actions);
+        if stale > 0 {
+            println!(
+                "Left out {} video(s) that changed on LumiNUS since the plan was made",
+                stale
+            );
+        }
+        let (_, summary) = download_resources(
+            api,
+            multimedia,
+            &planned.destination.to_string_lossy(),
+            &options,
+            4,
+        )
+        .await?;
+        run_summary.add(summary);
+    }
+    Ok(())
+}
+
+// Syncs what every platform supports: announcements and files
+#[allow(clippy::too_many_arguments)]
+async fn sync_platform(
+    platform: &dyn LearningPlatform,
+    manifest: &mut Manifest,
+    do_announcements: bool,
+    announcement_filter: AnnouncementFilter,
+    use_pager: bool,
+    do_files: bool,
+    download_destination: Option<&str>,
+    download_options: &DownloadOptions,
+    list_style: ListStyle,
+    run_summary: &mut RunSummary,
+) -> Result<()> {
+    say!(
+        "Hi {}! (on {})",
+        platform.user_name().await?,
+        platform.platform_name()
+    );
+    let courses = platform.courses(None).await?;
+    list_modules(
+        &courses
+            .iter()
+            .map(|course| ModuleRow {
+                code: course.code.clone(),
+                name: course.name.clone(),
+                term: course.term.clone(),
+                role: Some(if course.is_teaching {
+                    "teaching"
+                } else {
+                    "taking"
+                }),
+                access: None,
+                files: None,
+                bytes: None,
+            })
+            .collect::<Vec<_>>(),
+        list_style,
+    );
+
+    if do_announcements {
+        let mut text = String::new();
+        for course in &courses {
+            let announcements =
+                announcement_filter.select(platform.announcements(course).await?, manifest);
+            if announcement_filter.skips_empty() && announcements.is_empty() {
+                continue;
+            }
+            text.push_str(&format_announcements(
+                &course.code,
+                &course.name,
+                &announcements,
+                download_options.permalinks,
+            ));
+            for ann in announcements {
+                manifest.mark_announcement_seen(&ann.id);
+            }
+        }
+        page(&text, use_pager);
+    }
+
+    if let Some(destination) = download_destination {
+        let folders = courses
+            .iter()
+            .map(|course| {
+                (
+                    course.id.clone(),
+                    course.code.clone(),
+                    course.folder.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        migrate_module_folders(manifest, destination, &folders);
+        run_summary.add_module_folders(destination, &folders);
+    }
+
+    if do_files || download_destination.is_some() {
+        let (mut files, errors) =
+            sync::traverse(courses.iter().map(|course| platform.files(course, false))).await;
+        for e in errors {
+            say!("Failed loading course files: {}", e);
+        }
+        resource::sort_by_path(&mut files);
+        if do_files {
+            list_resources(&files, list_style);
+        }
+        if let Some(destination) = download_destination {
+            let (_, summary) = download_resources(
+                platform.session(),
+                files,
+                destination,
+                download_options,
+                MAX_DOWNLOADS,
+            )
+            .await?;
+            run_summary.add(summary);
+            remember_deferred(manifest, run_summary);
+        }
+    }
+    Ok(())
+}
+
+// One Submissions.csv per module, covering every uploadable folder in it
+fn write_submission_reports(modules: &[Module], files: &[File], destination: &str) -> Result<()> {
+    for module in modules {
+        let module_dir = module.folder_path();
+        let mut submissions = files
+            .iter()
+            .filter(|file| file.path().starts_with(&module_dir))
+            .filter_map(|file| file.submission())
+            .collect::<Vec<_>>();
+        if submissions.is_empty() {
+            continue;
+        }
+        submissions.sort_by(|s1, s2| {
+            s1.folder
+                .cmp(&s2.folder)
+                .then_with(|| s1.student.cmp(&s2.student))
+                .then_with(|| s1.submitted.cmp(&s2.submitted))
+        });
+        let module_path = Path::new(destination).join(&module_dir);
+        fs::create_dir_all(&module_path).map_err(|_| "Unable to create directory")?;
+        let report_path = module_path.join("Submissions.csv");
+        fs::write(&report_path, submission::to_csv(&submissions))
+            .map_err(|_| "Unable to write submission report")?;
+        println!(
+            "Wrote {} submission(s) to {}",
+            submissions.len(),
+            report_path.to_string_lossy()
+        );
+    }
+    Ok(())
+}
+
+fn find_teaching_module<'a>(modules: &'a [Module], module_code: &str) -> Result<&'a Module> {
+    let module = modules
+        .iter()
+        .find(|module| module.code.eq_ignore_ascii_case(module_code))
+        .ok_or("No such module this term")?;
+    if module.is_teaching() {
+        Ok(module)
+    } else {
+        Err("You are not teaching this module")
+    }
+}
+
+// Prints the response of `endpoint`, for working out what changed when LumiNUS changes its API
+async fn dump_endpoint(
+    api: &Api,
+    endpoint: &str,
+    module: Option<&Module>,
+    id: Option<&str>,
+) -> Result<()> {
+    let endpoint = KnownEndpoint::find(endpoint).ok_or("Unknown endpoint")?;
+    let path = endpoint.path(module.map(|module| module.id.as_str()), id)?;
+    if !endpoint.verified {
+        println!("Note: fluminurs has not been checked against this endpoint on LumiNUS yet");
+    }
+    println!("GET {}", path);
+    println!("{}", api.raw_response(&path).await?);
+    Ok(())
+}
+
+async fn export_roster(
+    api: &Api,
+    modules: &[Module],
+    module_code: &str,
+    output: Option<&str>,
+) -> Result<()> {
+    let module = find_teaching_module(modules, module_code)?;
+    let mut students = module.get_roster(api).await?;
+    students.sort_by(|s1, s2| {
+        s1.tutorial_group
+            .cmp(&s2.tutorial_group)
+            .then_with(|| s1.user_name_original.cmp(&s2.user_name_original))
+    });
+    let csv = roster::to_csv(&students);
+    match output {
+        Some(output) => {
+            fs::write(output, csv).map_err(|_| "Unable to write roster")?;
+            println!("Exported {} student(s) to {}", students.len(), output);
+        }
+        None => print!("{}", csv),
+    }
+    Ok(())
+}
+
+async fn merge_pdfs_per_folder(
+    files: &[File],
+    destination: &str,
+    manifest: &mut Manifest,
+) -> Result<()> {
+    let dest_path = Path::new(destination);
+    let folders = files
+        .iter()
+        .filter_map(|file| file.path().parent())
+        .map(|parent| dest_path.join(parent))
+        .collect::<BTreeSet<_>>();
+    for folder in folders {
+        let members = pdf::folder_members(&folder)?;
+        let combined_path = match pdf::combined_path(&folder) {
+            Some(combined_path) if !members.is_empty() => combined_path,
+            _ => continue,
+        };
+        let member_names = members
+            .iter()
+            .map(|member| member.file_name().unwrap().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        let folder_key = folder.to_string_lossy().into_owned();
+        let combined_modified = fs::metadata(&combined_path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        let up_to_date = manifest.merged_folders.get(&folder_key) == Some(&member_names)
+            && combined_modified
+                .map(|combined_modified| {
+                    members.iter().all(|member| {
+                        fs::metadata(member)
+                            .and_then(|metadata| metadata.modified())
+                            .map(|modified| modified <= combined_modified)
+                            .unwrap_or(false)
+                    })
+                })
+                .unwrap_or(false);
+        if up_to_date {
+            continue;
+        }
+
+        let output = combined_path.clone();
+        match tokio::task::spawn_blocking(move || pdf::merge(&members, &output)).await {
+            Ok(Ok(())) => {
+                println!("Combined PDFs into {}", combined_path.to_string_lossy());
+                manifest.merged_folders.insert(folder_key, member_names);
+            }
+            Ok(Err(e)) => println!(
+                "Failed to combine PDFs in {}: {}",
+                folder.to_string_lossy(),
+                e
+            ),
+            Err(_) => println!("Failed to combine PDFs: task panicked"),
+        }
+    }
+    Ok(())
+}
+
+async fn transcode_videos(
+    api: &Api,
+    videos: &[Video],
+    destination: &str,
+    profiles: &HashMap<PathBuf, &'static TranscodeProfile>,
+    default_profile: Option<&'static TranscodeProfile>,
+    downloaded: &[PathBuf],
+    manifest: &mut Manifest,
+) {
+    let dest_path = Path::new(destination);
+    for video in videos {
+        let module_profile = profiles
+            .iter()
+            .find(|(module_dir, _)| video.path().starts_with(module_dir))
+            .map(|(_, profile)| *profile);
+        let profile = match module_profile.or(default_profile) {
+            Some(profile) => profile,
+            None => continue,
+        };
+        let path = dest_path.join(video.path());
+        let key = path.to_string_lossy().into_owned();
+        if downloaded.contains(&path) {
+            // a fresh download has not been transcoded yet, whatever the manifest says
+            manifest.transcoded.remove(&key);
+        }
+        if !path.exists() || manifest.transcoded.get(&key).map(String::as_str) == Some(profile.name)
+        {
+            continue;
+        }
+        // the manifest remembers what is left, so the next run carries on from here
+        if api.past_deadline() {
+            say!("Out of time, leaving the remaining transcodes for the next run");
+            break;
+        }
+        let temp_path = path.with_file_name(make_temp_file_name(path.file_name().unwrap()));
+        say!(
+            "Transcoding {} with profile {}",
+            path.to_string_lossy(),
+            profile.name
+        );
+        match profile.apply(api, &path, &temp_path).await {
+            Ok(()) => {
+                manifest.transcoded.insert(key, profile.name.to_owned());
+            }
+            Err(e) => say!("Failed to transcode {}: {}", path.to_string_lossy(), e),
+        }
+    }
+}
+
+async fn transcribe_videos(
+    api: &Api,
+    videos: &[Video],
+    destination: &str,
+    hook: &TranscriptionHook,
+    downloaded: &[PathBuf],
+    manifest: &mut Manifest,
+) {
+    let dest_path = Path::new(destination);
+    for video in videos {
+        let path = dest_path.join(video.path());
+        let key = path.to_string_lossy().into_owned();
+        if downloaded.contains(&path) {
+            manifest.transcribed.remove(&key);
+        }
+        if !path.exists() || manifest.transcribed.contains(&key) {
+            continue;
+        }
+        if api.past_deadline() {
+            say!("Out of time, leaving the remaining transcriptions for the next run");
+            break;
+        }
+        say!("Transcribing {}", path.to_string_lossy());
+        match hook.run(&path).await {
+            Ok(subtitle_path) => {
+                say!("Subtitles saved to {}", subtitle_path.to_string_lossy());
+                manifest.transcribed.insert(key);
+            }
+            Err(e) => say!("Failed to transcribe {}: {}", path.to_string_lossy(), e),
+        }
+    }
+}
+
+fn make_temp_file_name(name: &OsStr) -> OsString {
+    make_prefixed_file_name("~!", name)
+}
+
+fn make_prefixed_file_name(prefix: &str, name: &OsStr) -> OsString {
+    let prepend = OsStr::new(prefix);
+    let mut res = OsString::with_capacity(prepend.len() + name.len());
+    res.push(prepend);
+    res.push(name);
+    res
+}
+
+fn get_credentials(credential_file: &str) -> Result<(String, String)> {
+    if let Ok(mut file) = fs::File::open(credential_file) {
+        let mut content = String::new();
+        file.read_to_string(&mut content)
+            .map_err(|_| "Unable to read credentials")?;
+        if let Ok(login) = serde_json::from_str::<Login>(&content) {
+            Ok((login.username, login.password))
+        } else {
+            say!("Corrupt credentials.json, deleting file...");
+            fs::remove_file(Path::new(credential_file))
+                .map_err(|_| "Unable to delete credential file")?;
+            get_credentials(credential_file)
+        }
+    } else {
+        let username = get_input(tr("Username (include the nusstu\\ prefix): "));
+        let password = get_password(tr("Password: "));
+        Ok((username, password))
+    }
+}
+
+fn store_credentials(credential_file: &str, username: &str, password: &str) -> Result<()> {
     if confirm("Store credentials (WARNING: they are stored in plain text)? [y/n]") {
         let login = Login {
             username: username.to_owned(),
@@ -257,6 +3805,57 @@ fn store_credentials(credential_file: &str, username: &str, password: &str) -> R
     Ok(())
 }
 
+// A checkbox list on plain stdin: toggle entries by number, confirm with an empty line
+fn pick_modules(modules: &[Module], manifest: &mut Manifest) {
+    let mut selected = modules
+        .iter()
+        .map(|module| manifest.is_module_selected(&module.code))
+        .collect::<Vec<_>>();
+    loop {
+        say!("Which modules should be synced?");
+        for (i, (module, selected)) in modules.iter().zip(&selected).enumerate() {
+            println!(
+                "{:>3}. [{}] {} {}{}",
+                i + 1,
+                if *selected { "x" } else { " " },
+                module.code,
+                module.name,
+                if module.is_teaching() {
+                    " (teaching)"
+                } else {
+                    ""
+                }
+            );
+        }
+        let input = get_input(tr(
+            "Numbers to toggle (e.g. 1 3), 'a' for all, 'n' for none, or Enter to confirm: ",
+        ));
+        if input.is_empty() {
+            break;
+        }
+        match input.as_str() {
+            "a" => selected.iter_mut().for_each(|s| *s = true),
+            "n" => selected.iter_mut().for_each(|s| *s = false),
+            _ => {
+                for number in input.split(|c: char| c == ',' || c.is_whitespace()) {
+                    match number.parse::<usize>() {
+                        Ok(n) if n >= 1 && n <= selected.len() => {
+                            selected[n - 1] = !selected[n - 1]
+                        }
+                        _ if number.is_empty() => {}
+                        _ => say!("Ignoring {}", number),
+                    }
+                }
+            }
+        }
+    }
+    for (module, selected) in modules.iter().zip(selected) {
+        manifest
+            .module_selection
+            .insert(module.code.clone(), selected);
+    }
+}
+
 fn confirm(prompt: &str) -> bool {
     print!("{} ", prompt);
     flush_stdout();
@@ -273,51 +3872,577 @@ async fn main() -> Result<()> {
     #[cfg(feature = "with-env-logger")]
     env_logger::init();
 
-    let matches = App::new(PKG_NAME)
+    let started = Instant::now();
+    let mut run_summary = RunSummary::new();
+    let mut summary_file = None;
+    let result = run(&mut run_summary, &mut summary_file).await;
+    run_summary.finish(started, &result);
+    run_summary.print_schema_drift();
+    if run_summary.verbose {
+        run_summary.print_hosts();
+    }
+    if let Some(summary_file) = summary_file {
+        let written = serde_json::to_string_pretty(&run_summary)
+            .map_err(|_| ())
+            .and_then(|json| fs::write(&summary_file, json).map_err(|_| ()));
+        if written.is_err() {
+            println!("Failed to write run summary to {}", summary_file);
+        }
+    }
+    result.map_err(tr)
+}
+
+// Every option and subcommand that the command line takes
+fn app() -> App<'static, 'static> {
+    static AUTHOR: OnceCell<String> = OnceCell::new();
+    let transcode_profile_names = transcode::PROFILES
+        .iter()
+        .map(|profile| profile.name)
+        .collect::<Vec<_>>();
+    let endpoint_names = dump::ENDPOINTS
+        .iter()
+        .map(|endpoint| endpoint.name)
+        .collect::<Vec<_>>();
+    App::new(PKG_NAME)
         .version(VERSION)
-        .author(&*format!("{} and contributors", clap::crate_authors!(", ")))
+        .author(
+            AUTHOR
+                .get_or_init(|| format!("{} and contributors", clap::crate_authors!(", ")))
+                .as_str(),
+        )
         .about(DESCRIPTION)
+        .after_help(UNVERIFIED_NOTE)
         .arg(Arg::with_name("announcements").long("announcements"))
+        .arg(
+            Arg::with_name("unread-only")
+                .long("unread-only")
+                .help("Only show announcements that were not shown in a previous run"),
+        )
+        .arg(
+            Arg::with_name("announcements-digest")
+                .long("announcements-digest")
+                .takes_value(true)
+                .value_name("file")
+                .help("Write every announcement of your modules into one file, oldest first, as HTML if it ends in .html and Markdown otherwise"),
+        )
+        .arg(
+            Arg::with_name("limit")
+                .long("limit")
+                .takes_value(true)
+                .value_name("n")
+                .validator(validate_count)
+                .help("Only show the newest n announcements of each module"),
+        )
+        .arg(
+            Arg::with_name("since")
+                .long("since")
+                .takes_value(true)
+                .value_name("duration")
+                .validator(validate_duration)
+                .help("Only show announcements from this long ago, e.g. 7d, 36h or 2w"),
+        )
+        .arg(
+            Arg::with_name("no-pager")
+                .long("no-pager")
+                .help("Print announcements straight to the terminal instead of through $PAGER"),
+        )
+        .arg(
+            Arg::with_name("messages")
+                .long("messages")
+                .help("List conversations in your LumiNUS inbox"),
+        )
+        .arg(
+            Arg::with_name("export-messages")
+                .long("export-messages-to")
+                .takes_value(true)
+                .help("Export every inbox conversation as a Markdown file"),
+        )
+        .arg(
+            Arg::with_name("groups")
+                .long("groups")
+                .help("List your project groups and their members"),
+        )
+        .arg(
+            Arg::with_name("export-groups")
+                .long("export-groups-to")
+                .takes_value(true)
+                .help("Export group rosters of each module as CSV and vCard"),
+        )
+        .arg(
+            Arg::with_name("peer-evaluations")
+                .long("peer-evaluations")
+                .help("List peer evaluations you submitted and received"),
+        )
+        .arg(
+            Arg::with_name("export-peer-evaluations")
+                .long("export-peer-evaluations-to")
+                .takes_value(true)
+                .help("Archive peer evaluations of each module as JSON and Markdown"),
+        )
+        .arg(
+            Arg::with_name("export-syllabus")
+                .long("export-syllabus-to")
+                .takes_value(true)
+                .help("Write a README.md describing each module into its folder, along with an assessment.json of its graded components, their weightages and any marks released, for grade calculators"),
+        )
+        .arg(
+            Arg::with_name("deadlines")
+                .long("deadlines")
+                .help("List upcoming assignment and quiz deadlines"),
+        )
+        .arg(
+            Arg::with_name("export-deadlines")
+                .long("export-deadlines-to")
+                .takes_value(true)
+                .value_name("ics-file")
+                .help("Export upcoming deadlines as an iCalendar file"),
+        )
+        .arg(
+            Arg::with_name("calendar")
+                .long("calendar")
+                .help("List upcoming events from your LumiNUS calendar"),
+        )
+        .arg(
+            Arg::with_name("export-calendar")
+                .long("export-calendar-to")
+                .takes_value(true)
+                .value_name("ics-file")
+                .help("Export upcoming calendar events as an iCalendar file"),
+        )
+        .arg(
+            Arg::with_name("export-ics")
+                .long("export-ics-to")
+                .takes_value(true)
+                .value_name("ics-file")
+                .help("Export upcoming deadlines and calendar events together as one iCalendar file"),
+        )
+        .arg(
+            Arg::with_name("archive-expiring")
+                .long("archive-expiring-to")
+                .takes_value(true)
+                .help("Fully mirror modules whose term has ended before doing anything else"),
+        )
         .arg(Arg::with_name("files").long("files"))
         .arg(
             Arg::with_name("download")
                 .long("download-to")
                 .takes_value(true),
         )
-        .arg(Arg::with_name("list-multimedia").long("list-multimedia"))
         .arg(
-            Arg::with_name("download-multimedia")
-                .long("download-multimedia-to")
-                .takes_value(true),
+            Arg::with_name("extract-text")
+                .long("extract-text")
+                .help("Write the text of downloaded PDF and PPTX files to .txt sidecars"),
+        )
+        .arg(
+            Arg::with_name("html-index")
+                .long("html-index")
+                .help("Write a browsable index.html into every downloaded folder"),
+        )
+        .arg(
+            Arg::with_name("merge-pdf-per-folder")
+                .long("merge-pdf-per-folder")
+                .help("Concatenate the PDFs of each downloaded folder into one combined PDF"),
+        )
+        .arg(Arg::with_name("list-multimedia").long("list-multimedia"))
+        .arg(
+            Arg::with_name("strip-cover-pages")
+                .long("strip-cover-pages")
+                .help("Remove the LumiNUS cover page from downloaded PDFs, keeping the original"),
+        )
+        .arg(
+            Arg::with_name("diff-updated")
+                .long("diff-updated")
+                .help("Show what changed in updated PDF, PPTX and text files"),
+        )
+        .arg(
+            Arg::with_name("download-multimedia")
+                .long("download-multimedia-to")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("credential-file")
+                .long("credential-file")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("manifest-file")
+                .long("manifest-file")
+                .takes_value(true)
+                .help("File used to remember state across runs"),
+        )
+        .arg(
+            Arg::with_name("profile")
+                .long("profile")
+                .takes_value(true)
+                .value_name("name")
+                .help("Use the options saved under this name in the profiles file. Options given on the command line take precedence"),
+        )
+        .arg(
+            Arg::with_name("profiles-file")
+                .long("profiles-file")
+                .takes_value(true)
+                .value_name("file")
+                .help("File of named option sets for --profile, each starting with [name] and followed by one `option = value` per line. Defaults to profiles.conf"),
+        )
+        .arg(
+            Arg::with_name("aliases-file")
+                .long("aliases-file")
+                .takes_value(true)
+                .value_name("file")
+                .help("File of module nicknames, one `alias = CODE` per line, usable wherever a module code is. Defaults to aliases.conf"),
+        )
+        .arg(
+            Arg::with_name("alerts-file")
+                .long("alerts-file")
+                .takes_value(true)
+                .value_name("file")
+                .help("File of alert rules, one word, phrase or /regular expression/ per line. Announcements that match are raised as alerts, apart from the rest, once each. Defaults to alerts.conf"),
+        )
+        .arg(
+            Arg::with_name("alert-command")
+                .long("alert-command")
+                .takes_value(true)
+                .value_name("command")
+                .help("Run this for each alert, e.g. to send it to your phone. The announcement's text comes on standard input, and FLUMINURS_ALERT_MODULE, FLUMINURS_ALERT_TITLE, FLUMINURS_ALERT_RULE and FLUMINURS_ALERT_LINK say what it is"),
+        )
+        .arg(
+            Arg::with_name("platform")
+                .long("platform")
+                .takes_value(true)
+                .possible_values(&["luminus", "canvas"])
+                .default_value("luminus")
+                .help("Learning platform to sync from. Canvas supports announcements and files only"),
+        )
+        .arg(
+            Arg::with_name("canvas-url")
+                .long("canvas-url")
+                .takes_value(true)
+                .default_value("https://canvas.nus.edu.sg")
+                .help("Canvas site to use with --platform canvas. The access token is read from CANVAS_TOKEN, or asked for"),
+        )
+        .arg(
+            Arg::with_name("endpoints-file")
+                .long("endpoints-file")
+                .takes_value(true)
+                .help("JSON file overriding the API and login URLs, e.g. to use a staging instance"),
+        )
+        .arg(
+            Arg::with_name("user-agent")
+                .long("user-agent")
+                .takes_value(true)
+                .help("User agent to send, for networks that block the default one"),
+        )
+        .arg(
+            Arg::with_name("header")
+                .long("header")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("name:value")
+                .help("Extra header to send with every request. Can be given more than once"),
+        )
+        .arg(
+            Arg::with_name("max-writers")
+                .long("max-writers")
+                .takes_value(true)
+                .value_name("n")
+                .validator(validate_count)
+                .help("Maximum number of files written to disk at once, e.g. 1 or 2 for hard disks and NAS"),
+        )
+        .arg(
+            Arg::with_name("max-requests")
+                .long("max-requests")
+                .takes_value(true)
+                .value_name("n")
+                .validator(validate_count)
+                .default_value("16")
+                .help("Maximum number of API requests in flight at once, shared by announcements, files and multimedia"),
+        )
+        .arg(
+            Arg::with_name("max-bytes-per-run")
+                .long("max-bytes-per-run")
+                .takes_value(true)
+                .value_name("size")
+                .validator(validate_size)
+                .help("Stop starting downloads once this much has been downloaded, e.g. 2G, leaving the rest for the next run"),
+        )
+        .arg(
+            Arg::with_name("max-duration")
+                .long("max-duration")
+                .takes_value(true)
+                .value_name("duration")
+                .validator(validate_duration)
+                .help("Stop starting downloads, transcodes and transcriptions after this long, e.g. 15m, leaving the rest for the next run"),
+        )
+        .arg(
+            Arg::with_name("memory-limit")
+                .long("memory-limit")
+                .takes_value(true)
+                .value_name("size")
+                .validator(validate_size)
+                .help("Memory that concurrent downloads may buffer in total, e.g. 64M"),
+        )
+        .arg(
+            Arg::with_name("modules")
+                .long("modules")
+                .takes_value(true)
+                .value_name("codes")
+                .help("Comma-separated module codes to sync, instead of the ones picked on the first run"),
+        )
+        .arg(
+            Arg::with_name("folder-name-style")
+                .long("folder-name-style")
+                .takes_value(true)
+                .value_name("style")
+                .possible_values(FolderNameStyle::NAMES)
+                .help("How to name module folders, e.g. CS2103T, CS2103T Software Engineering or Software Engineering. Existing folders are renamed to match. Defaults to code"),
+        )
+        .arg(
+            Arg::with_name("term-folders")
+                .long("term-folders")
+                .help("Mirror modules into <term>/<module> folders, so that several terms can share a download destination"),
+        )
+        .arg(
+            Arg::with_name("record")
+                .long("record")
+                .takes_value(true)
+                .value_name("dir")
+                .conflicts_with("replay")
+                .help("Save every API response to this directory, with tokens removed, e.g. to attach to a bug report"),
+        )
+        .arg(
+            Arg::with_name("replay")
+                .long("replay")
+                .takes_value(true)
+                .value_name("dir")
+                .help("Answer API requests from a directory saved with --record instead of logging in to LumiNUS"),
+        )
+        .arg(
+            Arg::with_name("offline")
+                .long("offline")
+                .conflicts_with_all(&["replay", "record"])
+                .help("Answer listings, status, du and report from what earlier runs saved, without going online"),
+        )
+        .arg(
+            Arg::with_name("lang")
+                .long("lang")
+                .takes_value(true)
+                .possible_values(Lang::NAMES)
+                .help("Language of messages, e.g. zh for Chinese. Defaults to the one of the locale"),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .long("verbose")
+                .help("Show the size and speed of each download, and latency, retry and speed statistics per host at the end"),
+        )
+        .arg(
+            Arg::with_name("permalinks")
+                .long("permalinks")
+                .help("Show the LumiNUS web page of each listed file, video and announcement, also in --summary-file"),
+        )
+        .arg(
+            Arg::with_name("priority")
+                .long("priority")
+                .takes_value(true)
+                .value_name("parts")
+                .use_delimiter(true)
+                .possible_values(&PRIORITY_PARTS)
+                .default_value("announcements,files,multimedia")
+                .help("Order in which announcements, files and multimedia are fetched, each waiting for the ones before it. Parts left out go alongside the rest"),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .takes_value(true)
+                .possible_values(&ListFormat::NAMES)
+                .default_value("table")
+                .help("How modules, files and multimedia are listed"),
+        )
+        .arg(
+            Arg::with_name("no-color")
+                .long("no-color")
+                .help("Don't colour listings, which is also the case when NO_COLOR is set or the output isn't a terminal"),
+        )
+        .arg(
+            Arg::with_name("screen-reader")
+                .long("screen-reader")
+                .help("Print plain lines that read well with a screen reader: no colour, columns, bars or decorative punctuation. Listings are plain unless --format is given"),
+        )
+        .arg(
+            Arg::with_name("full-scan")
+                .long("full-scan")
+                .help("List every folder again, even those that haven't changed since the last run"),
+        )
+        .arg(
+            Arg::with_name("pick-modules")
+                .long("pick-modules")
+                .help("Choose again which modules are synced"),
+        )
+        .arg(
+            Arg::with_name("summary-file")
+                .long("summary-file")
+                .takes_value(true)
+                .value_name("file")
+                .help("Write a JSON summary of the run, including every downloaded file and any errors"),
+        )
+        .arg(
+            Arg::with_name("audit-log-file")
+                .long("audit-log-file")
+                .takes_value(true)
+                .help("File that every request changing something on LumiNUS is logged to"),
+        )
+        .arg(
+            Arg::with_name("strict")
+                .long("strict")
+                .help("Treat API responses with fields that fluminurs doesn't know about, or without ones it expects, as errors, to spot changes to the LumiNUS API"),
+        )
+        .arg(
+            Arg::with_name("failure-file")
+                .long("failure-file")
+                .takes_value(true)
+                .help("File that the last LumiNUS response that couldn't be understood is kept in, for diagnose"),
+        )
+        .arg(
+            Arg::with_name("read-only")
+                .long("read-only")
+                .help("Never change anything on LumiNUS. Also enabled by setting FLUMINURS_READ_ONLY=1"),
+        )
+        .arg(
+            Arg::with_name("include-uploadable")
+                .long("include-uploadable-folders")
+                .takes_value(true)
+                .min_values(0)
+                .max_values(u64::MAX)
+                .possible_values(ModuleTypeFlags::NAMES),
+        )
+        .arg(
+            Arg::with_name("updated")
+                .long("updated")
+                .takes_value(true)
+                .value_name("action-on-updated-files")
+                .possible_values(OverwriteMode::NAMES)
+                .number_of_values(1)
+                .default_value("skip"),
+        )
+        .arg(
+            Arg::with_name("updated-files")
+                .long("updated-files")
+                .takes_value(true)
+                .value_name("action-on-updated-files")
+                .possible_values(OverwriteMode::NAMES)
+                .number_of_values(1)
+                .help("What to do with updated files, instead of --updated"),
+        )
+        .arg(
+            Arg::with_name("updated-multimedia")
+                .long("updated-multimedia")
+                .takes_value(true)
+                .value_name("action-on-updated-multimedia")
+                .possible_values(OverwriteMode::NAMES)
+                .number_of_values(1)
+                .help("What to do with updated multimedia, instead of --updated. Videos are large, so skip is usually best"),
+        )
+        .arg(
+            Arg::with_name("compare-by")
+                .long("compare-by")
+                .takes_value(true)
+                .value_name("mtime|content")
+                .possible_values(CompareBy::NAMES)
+                .default_value("mtime")
+                .help("How to tell whether files already downloaded are up to date. content compares sizes and samples of the content with the server instead of last modified times, for archives copied over without them. Multimedia still goes by last modified time"),
+        )
+        .arg(
+            Arg::with_name("validate-downloads")
+                .long("validate-downloads")
+                .help("Check that downloaded PDFs, zip files (including Office documents) and videos are whole: PDFs have their header and end marker, zip entries pass their CRC checks, MP4 videos have their moov atom and Matroska videos (as multimedia is saved) have all of their segment. Files are checked as the format their content turns out to be. Downloads that fail are tried again a few times, and then reported as failed instead of being put in place"),
+        )
+        .arg(
+            Arg::with_name("rename-template")
+                .long("rename-template")
+                .takes_value(true)
+                .value_name("template")
+                .default_value("{name}_autorename_{time}")
+                .help("What old versions are renamed to when updated files are renamed. {name} is the file's name and {time} is when the old version was last updated. The extension is kept"),
+        )
+        .arg(
+            Arg::with_name("rename-time-format")
+                .long("rename-time-format")
+                .takes_value(true)
+                .value_name("format")
+                .default_value("%Y-%m-%d")
+                .help("strftime format for {time} in --rename-template, e.g. %Y-%m-%d_%H%M for several versions a day"),
+        )
+        .arg(
+            Arg::with_name("versions-folder")
+                .long("versions-folder")
+                .help("Put renamed old versions into a versions folder next to the file, instead of beside it"),
         )
         .arg(
-            Arg::with_name("credential-file")
-                .long("credential-file")
-                .takes_value(true),
+            Arg::with_name("keep-versions")
+                .long("keep-versions")
+                .takes_value(true)
+                .value_name("n")
+                .help("Keep only the newest n renamed old versions of each file, deleting older ones when a file is renamed. See also the clean-versions command"),
         )
         .arg(
-            Arg::with_name("include-uploadable")
-                .long("include-uploadable-folders")
+            Arg::with_name("keep-versions-for")
+                .long("keep-versions-for")
                 .takes_value(true)
-                .min_values(0)
-                .max_values(u64::max_value())
-                .possible_values(&["taking", "teaching", "all"]),
+                .value_name("duration")
+                .help("Keep only renamed old versions last updated within this long ago, e.g. 30d or 8w, deleting older ones when a file is renamed"),
         )
         .arg(
-            Arg::with_name("updated")
-                .long("updated")
+            Arg::with_name("fix-extensions")
+                .long("fix-extensions")
+                .help("Add the right extension to downloaded files whose content doesn't match theirs, so that they open with the right app"),
+        )
+        .arg(
+            Arg::with_name("moved-files")
+                .long("moved-files")
                 .takes_value(true)
-                .value_name("action-on-updated-files")
-                .possible_values(&["skip", "overwrite", "rename"])
-                .number_of_values(1)
-                .default_value("skip"),
+                .value_name("policy")
+                .possible_values(MovePolicy::NAMES)
+                .default_value("download")
+                .help("What to do with downloaded files that were moved elsewhere in the mirror: download them again, leave them where they are, or relink them back with a hard link. Anything but download checksums each download to recognise it later"),
         )
         .arg(
             Arg::with_name("term")
                 .long("term")
                 .takes_value(true)
                 .value_name("term")
-                .number_of_values(1),
+                .number_of_values(1)
+                .validator(validate_term)
+                .help("Only sync modules from this term, e.g. 2010 for AY2020/21 Semester 1. See the terms command"),
+        )
+        .arg(
+            Arg::with_name("transcode-profile")
+                .long("transcode-profile")
+                .takes_value(true)
+                .value_name("profile")
+                .possible_values(&transcode_profile_names)
+                .help("Re-encode downloaded multimedia with this ffmpeg profile to save space"),
+        )
+        .arg(
+            Arg::with_name("module-transcode-profile")
+                .long("module-transcode-profile")
+                .takes_value(true)
+                .value_name("module=profile")
+                .multiple(true)
+                .number_of_values(1)
+                .validator(validate_module_transcode_profile)
+                .help("Transcode profile to use for a specific module, e.g. CS1010=h265-480p"),
+        )
+        .arg(
+            Arg::with_name("transcribe-command")
+                .long("transcribe-command")
+                .takes_value(true)
+                .value_name("command")
+                .validator(validate_transcription_command)
+                .help(
+                    "Command that generates subtitles for downloaded multimedia, \
+                     with {input} and {output} placeholders",
+                ),
         )
         .arg(
             Arg::with_name("ffmpeg")
@@ -328,107 +4453,1496 @@ async fn main() -> Result<()> {
                 .default_value("ffmpeg")
                 .help("Path to ffmpeg executable for downloading multimedia"),
         )
-        .get_matches();
-    let credential_file = matches
-        .value_of("credential-file")
-        .unwrap_or("login.json")
-        .to_owned();
-    let do_announcements = matches.is_present("announcements");
-    let do_files = matches.is_present("files");
-    let download_destination = matches.value_of("download").map(|s| s.to_owned());
-    let do_multimedia = matches.is_present("list-multimedia");
-    let multimedia_download_destination = matches
-        .value_of("download-multimedia")
-        .map(|s| s.to_owned());
-    let include_uploadable_folders = matches
-        .values_of("include-uploadable")
-        .map(|values| {
-            let include_flags = values
-                .fold(Ok(ModuleTypeFlags::empty()), |acc, s| {
-                    acc.and_then(|flag| match s.to_lowercase().as_str() {
-                        "taking" => Ok(flag | ModuleTypeFlags::TAKING),
-                        "teaching" => Ok(flag | ModuleTypeFlags::TEACHING),
-                        "all" => Ok(flag | ModuleTypeFlags::all()),
-                        _ => Err("Invalid module type"),
+        .subcommand(
+            SubCommand::with_name("du")
+                .about(
+                    "Report local disk usage per module and the size of content not yet downloaded",
+                )
+                .arg(
+                    Arg::with_name("mirror")
+                        .value_name("mirror-directory")
+                        .default_value(".")
+                        .help("Directory that files are downloaded to"),
+                )
+                .arg(
+                    Arg::with_name("multimedia-mirror")
+                        .long("multimedia-mirror")
+                        .value_name("directory")
+                        .takes_value(true)
+                        .help("Directory that multimedia is downloaded to, if not the mirror directory"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("dedupe")
+                .about("Find files with the same content in more than one place in the mirror")
+                .arg(
+                    Arg::with_name("mirror")
+                        .value_name("mirror-directory")
+                        .default_value(".")
+                        .help("Directory that files are downloaded to"),
+                )
+                .arg(
+                    Arg::with_name("report")
+                        .long("report")
+                        .required_unless("link")
+                        .help("List the duplicates and how much space they waste"),
+                )
+                .arg(
+                    Arg::with_name("link")
+                        .long("link")
+                        .help("Also replace the duplicates with hard links to one copy, freeing the space"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("clean-versions")
+                .about("Delete renamed old versions of files that --keep-versions and --keep-versions-for say not to keep")
+                .arg(
+                    Arg::with_name("mirror")
+                        .value_name("mirror-directory")
+                        .default_value(".")
+                        .help("Directory that files are downloaded to"),
+                )
+                .arg(
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .help("Only list what would be deleted"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("undo")
+                .about("Put back the files that the latest sync overwrote, trashed or pruned, from the copies it kept")
+                .arg(
+                    Arg::with_name("last")
+                        .long("last")
+                        .required(true)
+                        .help("Undo the latest sync that changed any files"),
+                )
+                .arg(
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .help("Only list what would be restored"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("diagnose")
+                .about("Bundle versions, config, recent logs and the last response that couldn't be understood into a zip for a bug report, with credentials removed")
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .takes_value(true)
+                        .value_name("file")
+                        .default_value("fluminurs-diagnostics.zip")
+                        .help("Where to write the zip"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("stats")
+                .about("Chart how much was downloaded each week, from the history of past syncs"),
+        )
+        .subcommand(
+            SubCommand::with_name("history")
+                .about("List what past syncs downloaded or updated, and when")
+                .arg(
+                    Arg::with_name("module")
+                        .long("module")
+                        .takes_value(true)
+                        .value_name("module-code")
+                        .help("Only list files of this module"),
+                )
+                .arg(
+                    Arg::with_name("since")
+                        .long("since")
+                        .takes_value(true)
+                        .value_name("date-or-duration")
+                        .validator(validate_since)
+                        .help("Only list syncs from this date on, e.g. 2020-09-01, or from this long ago, e.g. 7d"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("report")
+                .about("Produce a Markdown report of new announcements and files")
+                .arg(
+                    Arg::with_name("since")
+                        .long("since")
+                        .takes_value(true)
+                        .value_name("duration")
+                        .default_value("7d")
+                        .validator(validate_duration)
+                        .help("How far back to report, e.g. 7d, 36h or 2w"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .short("o")
+                        .takes_value(true)
+                        .value_name("file")
+                        .help("Write the report to a file instead of standard output"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("browse")
+                .about("Serve a local web page for browsing the downloaded mirror offline")
+                .arg(
+                    Arg::with_name("mirror")
+                        .value_name("mirror-directory")
+                        .default_value(".")
+                        .help("Directory that files are downloaded to"),
+                )
+                .arg(
+                    Arg::with_name("address")
+                        .long("address")
+                        .takes_value(true)
+                        .default_value("127.0.0.1:8080")
+                        .help("Address to listen on"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("plan")
+                .about("Write out what syncing to --download-to and --download-multimedia-to would do, without doing it")
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .short("o")
+                        .takes_value(true)
+                        .value_name("file")
+                        .default_value("plan.json")
+                        .help("Where to write the plan"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("apply")
+                .about("Carry out a plan written by the plan subcommand")
+                .arg(
+                    Arg::with_name("plan")
+                        .value_name("plan-file")
+                        .required(true)
+                        .help("Plan to carry out"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("adopt")
+                .about("Mark files that were downloaded by hand as synced, so that they aren't downloaded again")
+                .arg(
+                    Arg::with_name("to")
+                        .long("to")
+                        .takes_value(true)
+                        .value_name("dir")
+                        .required(true)
+                        .help("Folder holding the files, which --download-to should point at from now on"),
+                )
+                .arg(
+                    Arg::with_name("move")
+                        .long("move")
+                        .help("Move files found in other folders to where fluminurs keeps them"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("terms")
+                .about("List the terms that you have modules in, for use with --term"),
+        )
+        .subcommand(
+            SubCommand::with_name("status")
+                .about("Check that logging in works, and show what earlier runs remembered"),
+        )
+        .subcommand(
+            SubCommand::with_name("api-dump")
+                .about("Print an API response as LumiNUS sends it, with tokens removed, for working out changes to the API")
+                .arg(
+                    Arg::with_name("endpoint")
+                        .required(true)
+                        .possible_values(&endpoint_names)
+                        .help("Which endpoint to fetch"),
+                )
+                .arg(
+                    Arg::with_name("module")
+                        .long("module")
+                        .takes_value(true)
+                        .value_name("module-code")
+                        .help("Module to fetch it for, for endpoints about one module"),
+                )
+                .arg(
+                    Arg::with_name("id")
+                        .long("id")
+                        .takes_value(true)
+                        .help("Id of the folder, channel or group to fetch it for, as seen in other responses"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("roster")
+                .about("Export the class roster of a module you are teaching as CSV")
+                .arg(
+                    Arg::with_name("module")
+                        .value_name("module-code")
+                        .required(true)
+                        .help("Module code, e.g. CS1010"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .short("o")
+                        .takes_value(true)
+                        .value_name("file")
+                        .help("Write the roster to a file instead of standard output"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("grades")
+                .about("Work out where you stand in the modules you are taking")
+                .subcommand(
+                    SubCommand::with_name("what-if")
+                        .about("Work out your standing in each module, and what you need on the rest to reach a target, from what --export-syllabus-to wrote. Works offline")
+                        .arg(
+                            Arg::with_name("export")
+                                .value_name("export-directory")
+                                .default_value(".")
+                                .help("Directory that syllabuses were exported to"),
+                        )
+                        .arg(
+                            Arg::with_name("module")
+                                .long("module")
+                                .takes_value(true)
+                                .value_name("module-code")
+                                .help("Only this module"),
+                        )
+                        .arg(
+                            Arg::with_name("target")
+                                .long("target")
+                                .takes_value(true)
+                                .value_name("percent")
+                                .multiple(true)
+                                .number_of_values(1)
+                                .default_value("50")
+                                .validator(validate_percent)
+                                .help("Final grade to work out what is needed for, in percent. Can be given more than once"),
+                        )
+                        .arg(
+                            Arg::with_name("assume")
+                                .long("assume")
+                                .takes_value(true)
+                                .value_name("component=percent")
+                                .multiple(true)
+                                .number_of_values(1)
+                                .validator(validate_assumption)
+                                .help("Count a component as scoring this percent of its marks, e.g. \"Final Exam=75\", whether or not it has a mark yet"),
+                        ),
+                ),
+        )
+}
+
+// What the command line asks for, other than which subcommand to run
+struct Options {
+    credential_file: String,
+    manifest_file: String,
+    module_filter: Option<Vec<String>>,
+    pick_modules_again: bool,
+    folder_name_style: FolderNameStyle,
+    term_folders: bool,
+    rename_scheme: RenameScheme,
+    compare_by: CompareBy,
+    validate_downloads: bool,
+    full_scan: bool,
+    undo_dir: Rc<Path>,
+    do_announcements: bool,
+    announcement_filter: AnnouncementFilter,
+    use_pager: bool,
+    announcements_digest: Option<String>,
+    read_only: bool,
+    audit_log_file: String,
+    do_messages: bool,
+    messages_export_destination: Option<String>,
+    do_groups: bool,
+    groups_export_destination: Option<String>,
+    do_peer_evaluations: bool,
+    peer_evaluations_export_destination: Option<String>,
+    syllabus_export_destination: Option<String>,
+    do_deadlines: bool,
+    deadlines_export_destination: Option<String>,
+    do_calendar: bool,
+    calendar_export_destination: Option<String>,
+    ics_export_destination: Option<String>,
+    archive_destination: Option<String>,
+    do_files: bool,
+    download_destination: Option<String>,
+    do_multimedia: bool,
+    multimedia_download_destination: Option<String>,
+    include_uploadable_folders: ModuleTypeFlags,
+    fix_extensions: bool,
+    move_policy: MovePolicy,
+    file_options: DownloadOptions,
+    multimedia_options: DownloadOptions,
+    list_style: ListStyle,
+    merge_pdf_per_folder: bool,
+    write_html_index: bool,
+    default_transcode_profile: Option<&'static TranscodeProfile>,
+    // by module code or alias, as given
+    module_transcode_profiles: HashMap<String, &'static TranscodeProfile>,
+    transcription_hook: Option<TranscriptionHook>,
+    specified_term: Option<AcademicTerm>,
+}
+
+impl Options {
+    // `started` is when the run started, which its undo folder is named after
+    fn parse(matches: &ArgMatches, started: &str) -> Result<Options> {
+        let credential_file = matches
+            .value_of("credential-file")
+            .unwrap_or("login.json")
+            .to_owned();
+        let module_filter = matches.value_of("modules").map(|codes| {
+            codes
+                .split(',')
+                .map(|code| code.trim().to_owned())
+                .filter(|code| !code.is_empty())
+                .collect::<Vec<_>>()
+        });
+        let rename_scheme = RenameScheme {
+            template: matches
+                .value_of("rename-template")
+                .unwrap_or("{name}_autorename_{time}")
+                .to_owned(),
+            time_format: matches
+                .value_of("rename-time-format")
+                .unwrap_or("%Y-%m-%d")
+                .to_owned(),
+            folder: if matches.is_present("versions-folder") {
+                Some("versions".to_owned())
+            } else {
+                None
+            },
+            retention: Retention {
+                keep: matches
+                    .value_of("keep-versions")
+                    .map(|n| {
+                        n.parse::<usize>()
+                            .map_err(|_| "Invalid number for --keep-versions")
+                    })
+                    .transpose()?,
+                max_age: matches
+                    .value_of("keep-versions-for")
+                    .map(|duration| {
+                        parse_duration(duration).ok_or("Invalid duration for --keep-versions-for")
                     })
+                    .transpose()?,
+            },
+        };
+        rename_scheme.validate()?;
+        let manifest_file = matches
+            .value_of("manifest-file")
+            .unwrap_or("manifest.json")
+            .to_owned();
+        // named after when the run started, so that its copies don't mix with those of the run before
+        let undo_dir: Rc<Path> =
+            Rc::from(undo_root(&manifest_file).join(started.replace(':', "-")));
+        let announcement_filter = AnnouncementFilter {
+            unread_only: matches.is_present("unread-only"),
+            since: matches
+                .value_of("since")
+                .map(|since| {
+                    parse_duration(since)
+                        .ok_or("Invalid duration for --since")
+                        .and_then(time_ago)
+                })
+                .transpose()?,
+            limit: matches
+                .value_of("limit")
+                .map(|limit| {
+                    limit
+                        .parse::<usize>()
+                        .map_err(|_| "Invalid number for --limit")
                 })
-                .expect("Unable to parse parameters of include-uploadable");
-            if include_flags.is_empty() {
-                ModuleTypeFlags::all()
+                .transpose()?,
+        };
+        let include_uploadable_folders = matches
+            .values_of("include-uploadable")
+            .map(|values| {
+                let include_flags = values
+                    .filter_map(ModuleTypeFlags::parse)
+                    .fold(ModuleTypeFlags::empty(), |flags, flag| flags | flag);
+                if include_flags.is_empty() {
+                    ModuleTypeFlags::all()
+                } else {
+                    include_flags
+                }
+            })
+            .unwrap_or_else(ModuleTypeFlags::empty);
+        // --updated applies to every content type that doesn't have its own mode
+        let overwrite_mode_for = |content_type: &str| {
+            matches
+                .value_of(content_type)
+                .or_else(|| matches.value_of("updated"))
+                .and_then(OverwriteMode::parse)
+                .unwrap_or(OverwriteMode::Skip)
+        };
+        let file_options = DownloadOptions {
+            overwrite_mode: overwrite_mode_for("updated-files"),
+            diff_updated: matches.is_present("diff-updated"),
+            strip_cover_pages: matches.is_present("strip-cover-pages"),
+            permalinks: matches.is_present("permalinks"),
+            verbose: matches.is_present("verbose"),
+            extract_text: matches.is_present("extract-text"),
+            undo_dir: undo_dir.clone(),
+        };
+        let multimedia_options = DownloadOptions {
+            overwrite_mode: overwrite_mode_for("updated-multimedia"),
+            ..file_options.clone()
+        };
+        let screen_reader = matches.is_present("screen-reader");
+        let list_format = matches
+            .value_of("format")
+            .and_then(ListFormat::parse)
+            .unwrap_or(ListFormat::Table);
+        let list_style = ListStyle {
+            // tables are read out cell by cell, so unless asked for otherwise, lists are plain
+            format: if screen_reader && matches.occurrences_of("format") == 0 {
+                ListFormat::Plain
             } else {
-                include_flags
-            }
+                list_format
+            },
+            // https://no-color.org
+            color: !matches.is_present("no-color")
+                && !screen_reader
+                && std::env::var_os("NO_COLOR").is_none()
+                && io::stdout().is_terminal(),
+            permalinks: file_options.permalinks,
+            screen_reader,
+        };
+        let module_transcode_profiles = matches
+            .values_of("module-transcode-profile")
+            .map(|values| {
+                values
+                    .filter_map(|value| {
+                        let (code, name) = value.split_once('=')?;
+                        Some((code.trim().to_owned(), transcode::find_profile(name)?))
+                    })
+                    .collect::<HashMap<_, _>>()
+            })
+            .unwrap_or_default();
+        let owned = |name: &str| matches.value_of(name).map(|s| s.to_owned());
+        Ok(Options {
+            credential_file,
+            manifest_file,
+            module_filter,
+            pick_modules_again: matches.is_present("pick-modules"),
+            folder_name_style: matches
+                .value_of("folder-name-style")
+                .and_then(FolderNameStyle::parse)
+                .unwrap_or_default(),
+            term_folders: matches.is_present("term-folders"),
+            rename_scheme,
+            compare_by: matches
+                .value_of("compare-by")
+                .and_then(CompareBy::parse)
+                .unwrap_or(CompareBy::Mtime),
+            validate_downloads: matches.is_present("validate-downloads"),
+            full_scan: matches.is_present("full-scan"),
+            undo_dir,
+            do_announcements: matches.is_present("announcements"),
+            announcement_filter,
+            use_pager: !matches.is_present("no-pager"),
+            announcements_digest: owned("announcements-digest"),
+            // deployments can enforce this for every user through the environment
+            read_only: matches.is_present("read-only")
+                || std::env::var_os("FLUMINURS_READ_ONLY")
+                    .is_some_and(|v| !v.is_empty() && v != "0"),
+            audit_log_file: matches
+                .value_of("audit-log-file")
+                .unwrap_or("audit.jsonl")
+                .to_owned(),
+            do_messages: matches.is_present("messages"),
+            messages_export_destination: owned("export-messages"),
+            do_groups: matches.is_present("groups"),
+            groups_export_destination: owned("export-groups"),
+            do_peer_evaluations: matches.is_present("peer-evaluations"),
+            peer_evaluations_export_destination: owned("export-peer-evaluations"),
+            syllabus_export_destination: owned("export-syllabus"),
+            do_deadlines: matches.is_present("deadlines"),
+            deadlines_export_destination: owned("export-deadlines"),
+            do_calendar: matches.is_present("calendar"),
+            calendar_export_destination: owned("export-calendar"),
+            ics_export_destination: owned("export-ics"),
+            archive_destination: owned("archive-expiring"),
+            do_files: matches.is_present("files"),
+            download_destination: owned("download"),
+            do_multimedia: matches.is_present("list-multimedia"),
+            multimedia_download_destination: owned("download-multimedia"),
+            include_uploadable_folders,
+            fix_extensions: matches.is_present("fix-extensions"),
+            move_policy: matches
+                .value_of("moved-files")
+                .and_then(MovePolicy::parse)
+                .unwrap_or(MovePolicy::Download),
+            file_options,
+            multimedia_options,
+            list_style,
+            merge_pdf_per_folder: matches.is_present("merge-pdf-per-folder"),
+            write_html_index: matches.is_present("html-index"),
+            default_transcode_profile: matches
+                .value_of("transcode-profile")
+                .and_then(transcode::find_profile),
+            module_transcode_profiles,
+            transcription_hook: matches
+                .value_of("transcribe-command")
+                .map(TranscriptionHook::parse)
+                .transpose()?,
+            specified_term: matches
+                .value_of("term")
+                .map(|term| {
+                    AcademicTerm::parse(term)
+                        .ok_or("Invalid term, expected a 4-digit term code such as 2010")
+                })
+                .transpose()?,
         })
-        .unwrap_or_else(ModuleTypeFlags::empty);
-    let overwrite_mode = matches
-        .value_of("updated")
-        .map(|s| match s.to_lowercase().as_str() {
-            "skip" => OverwriteMode::Skip,
-            "overwrite" => OverwriteMode::Overwrite,
-            "rename" => OverwriteMode::Rename,
-            _ => panic!("Unable to parse parameter of overwrite_mode"),
+    }
+}
+
+// How much a session may do at once, and in all
+struct SessionLimits {
+    max_writers: Option<usize>,
+    max_requests: usize,
+    memory_limit: Option<u64>,
+    max_bytes_per_run: Option<u64>,
+    deadline: Option<Instant>,
+}
+
+impl SessionLimits {
+    fn parse(matches: &ArgMatches) -> Result<SessionLimits> {
+        Ok(SessionLimits {
+            max_writers: matches
+                .value_of("max-writers")
+                .map(|n| {
+                    n.parse::<usize>()
+                        .map_err(|_| "Invalid number for --max-writers")
+                })
+                .transpose()?,
+            max_requests: matches
+                .value_of("max-requests")
+                .unwrap()
+                .parse::<usize>()
+                .map_err(|_| "Invalid number for --max-requests")?,
+            memory_limit: matches
+                .value_of("memory-limit")
+                .map(|size| parse_size(size).ok_or("Invalid size for --memory-limit"))
+                .transpose()?,
+            max_bytes_per_run: matches
+                .value_of("max-bytes-per-run")
+                .map(|size| parse_size(size).ok_or("Invalid size for --max-bytes-per-run"))
+                .transpose()?,
+            deadline: matches
+                .value_of("max-duration")
+                .map(|duration| {
+                    parse_duration(duration)
+                        .map(|duration| Instant::now() + duration)
+                        .ok_or("Invalid duration for --max-duration")
+                })
+                .transpose()?,
         })
-        .unwrap_or(OverwriteMode::Skip);
-    let specified_term = matches.value_of("term").map(|s| {
-        if s.len() == 4 && s.chars().all(char::is_numeric) {
-            s.to_owned()
-        } else {
-            panic!("Invalid input term")
-        }
-    });
+    }
+}
 
-    let (username, password) =
-        get_credentials(&credential_file).expect("Unable to get credentials");
+// Sets up a session the way the options ask for, whichever platform it is on, and lets the run
+// summary and Ctrl-C see it
+fn configure_session(
+    session: Api,
+    options: &Options,
+    limits: &SessionLimits,
+    run_summary: &mut RunSummary,
+) -> Api {
+    let session = session
+        .with_audit_log(AuditLog::new(options.audit_log_file.clone()))
+        .with_read_only(options.read_only)
+        .with_folder_name_style(options.folder_name_style)
+        .with_term_folders(options.term_folders)
+        .with_rename_scheme(options.rename_scheme.clone())
+        .with_compare_by(options.compare_by)
+        .with_validate_downloads(options.validate_downloads)
+        .with_max_requests(limits.max_requests)
+        .with_adaptive_downloads(MAX_DOWNLOADS);
+    let session = match limits.max_writers {
+        Some(max_writers) => session.with_max_writers(max_writers),
+        None => session,
+    };
+    let session = match limits.memory_limit {
+        Some(memory_limit) => session.with_memory_limit(memory_limit as usize),
+        None => session,
+    };
+    let session = match limits.max_bytes_per_run {
+        Some(max_bytes) => session.with_byte_budget(max_bytes),
+        None => session,
+    };
+    let session = match limits.deadline {
+        Some(deadline) => session.with_deadline(deadline),
+        None => session,
+    };
+    run_summary.transfer_stats = Some(session.stats().clone());
+    run_summary.schema_drift = Some(session.schema_drift().clone());
+    stop_on_ctrl_c(session.cancellation().clone());
+    session
+}
 
-    let api = Api::with_login(&username, &password)
-        .await?
-        .with_ffmpeg(matches.value_of("ffmpeg").unwrap_or("ffmpeg").to_owned());
-    if !Path::new(&credential_file).exists() {
-        match store_credentials(&credential_file, &username, &password) {
+fn load_endpoints(matches: &ArgMatches) -> Result<Endpoints> {
+    let mut endpoints = match matches.value_of("endpoints-file") {
+        Some(endpoints_file) => Endpoints::load(Path::new(endpoints_file))?,
+        None => Endpoints::default(),
+    };
+    if let Some(user_agent) = matches.value_of("user-agent") {
+        endpoints.user_agent = Some(user_agent.to_owned());
+    }
+    for header in matches.values_of("header").into_iter().flatten() {
+        let (name, value) = header
+            .split_once(':')
+            .ok_or("Headers must be given as name:value")?;
+        endpoints
+            .headers
+            .insert(name.trim().to_owned(), value.trim().to_owned());
+    }
+    Ok(endpoints)
+}
+
+async fn run(run_summary: &mut RunSummary, summary_file: &mut Option<String>) -> Result<()> {
+    let args = apply_profile(std::env::args_os().collect())?;
+    let matches = app().get_matches_from(args);
+    let _ = LANG.set(
+        matches
+            .value_of("lang")
+            .and_then(Lang::parse)
+            .unwrap_or_else(Lang::detect),
+    );
+    let options = Options::parse(&matches, &run_summary.started)?;
+    run_summary.verbose = matches.is_present("verbose");
+    run_summary.screen_reader = matches.is_present("screen-reader");
+    *summary_file = matches.value_of("summary-file").map(|s| s.to_owned());
+
+    if let Some(diagnose_matches) = matches.subcommand_matches("diagnose") {
+        return write_diagnostics(&matches, diagnose_matches.value_of("output").unwrap());
+    }
+
+    let manifest_file = &options.manifest_file;
+    // held until the run ends
+    let manifest_lock = ManifestLock::acquire(Path::new(manifest_file), || {
+        say!(
+            "Another run is using {}, waiting for it to finish",
+            manifest_file
+        )
+    })?;
+    let mut manifest = Manifest::load(Path::new(manifest_file))?;
+    if manifest.was_recovered() {
+        say!(
+            "{} was corrupt, so it was restored from its backup and the corrupt copy was kept as {}.corrupt",
+            manifest_file, manifest_file
+        );
+    }
+    let aliases = ModuleAliases::load(Path::new(
+        matches.value_of("aliases-file").unwrap_or("aliases.conf"),
+    ))?;
+    let alert_rules = AlertRules::load(Path::new(
+        matches.value_of("alerts-file").unwrap_or("alerts.conf"),
+    ))?;
+    if !manifest.deferred_downloads.is_empty() {
+        say!(
+            "{} download(s) were left over from the last run, they will be picked up if there is room",
+            manifest.deferred_downloads.len()
+        );
+    }
+
+    // browsing the mirror works offline, so there is no need to log in
+    if let Some(browse_matches) = matches.subcommand_matches("browse") {
+        return serve_mirror(browse_matches, manifest_lock, &manifest).await;
+    }
+    if let Some(result) = run_without_login(&matches, &options, &mut manifest, &aliases) {
+        return result;
+    }
+    if matches.is_present("offline") {
+        return run_offline(
+            &matches,
+            &manifest,
+            &aliases,
+            options.module_filter.as_deref(),
+            options.specified_term.as_ref(),
+            options.list_style,
+        );
+    }
+
+    let limits = SessionLimits::parse(&matches)?;
+    let endpoints = load_endpoints(&matches)?;
+    if matches.value_of("platform") == Some("canvas") {
+        return run_canvas(
+            &matches,
+            &options,
+            &limits,
+            endpoints,
+            &mut manifest,
+            run_summary,
+        )
+        .await;
+    }
+    let api = log_in(&matches, &options, &limits, endpoints, run_summary).await?;
+    run_luminus(
+        &matches,
+        options,
+        &api,
+        &mut manifest,
+        &aliases,
+        &alert_rules,
+        run_summary,
+    )
+    .await
+}
+
+async fn serve_mirror(
+    browse_matches: &ArgMatches<'_>,
+    manifest_lock: ManifestLock,
+    manifest: &Manifest,
+) -> Result<()> {
+    let address = browse_matches
+        .value_of("address")
+        .unwrap_or("127.0.0.1:8080");
+    let mirror = browse_matches.value_of("mirror").unwrap_or(".");
+    // serving only reads what was loaded already, and shouldn't hold up runs until it is stopped
+    drop(manifest_lock);
+    say!("Serving {} on http://{}/", mirror, address);
+    browse::serve(
+        PathBuf::from(mirror),
+        manifest.enrolled_modules.clone(),
+        manifest
+            .module_folders
+            .get(mirror)
+            .cloned()
+            .unwrap_or_default(),
+        address,
+    )
+    .await
+}
+
+// The subcommands that only need what earlier runs left behind, or None if the command line asks
+// for something else
+fn run_without_login(
+    matches: &ArgMatches,
+    options: &Options,
+    manifest: &mut Manifest,
+    aliases: &ModuleAliases,
+) -> Option<Result<()>> {
+    if matches.subcommand_matches("stats").is_some() {
+        print_sync_stats(&manifest.sync_history, options.list_style.screen_reader);
+        return Some(Ok(()));
+    }
+
+    // worked out from what was exported, so no need to log in
+    if let Some(what_if_matches) = matches
+        .subcommand_matches("grades")
+        .and_then(|grades_matches| grades_matches.subcommand_matches("what-if"))
+    {
+        return Some(print_what_if(
+            what_if_matches.value_of("export").unwrap_or("."),
+            aliases,
+            what_if_matches.value_of("module"),
+            &what_if_matches
+                .values_of("target")
+                .map(|targets| targets.filter_map(parse_percent).collect::<Vec<_>>())
+                .unwrap_or_default(),
+            &what_if_matches
+                .values_of("assume")
+                .map(|assumed| assumed.filter_map(parse_assumption).collect::<Vec<_>>())
+                .unwrap_or_default(),
+        ));
+    }
+
+    if let Some(history_matches) = matches.subcommand_matches("history") {
+        print_file_history(
+            &manifest.sync_history,
+            aliases,
+            history_matches.value_of("module"),
+            history_matches.value_of("since").and_then(parse_since),
+        );
+        return Some(Ok(()));
+    }
+
+    // also offline, but holding on to the manifest lock, so that no sync writes to the mirror meanwhile
+    if let Some(dedupe_matches) = matches.subcommand_matches("dedupe") {
+        return Some(dedupe_mirror(
+            dedupe_matches.value_of("mirror").unwrap_or("."),
+            dedupe_matches.is_present("link"),
+        ));
+    }
+
+    if let Some(undo_matches) = matches.subcommand_matches("undo") {
+        let dry_run = undo_matches.is_present("dry-run");
+        let manifest_file = Path::new(&options.manifest_file);
+        return Some(
+            undo_last_sync(manifest, &undo_root(&options.manifest_file), dry_run).and_then(|_| {
+                if dry_run {
+                    Ok(())
+                } else {
+                    manifest.save(manifest_file)
+                }
+            }),
+        );
+    }
+
+    if let Some(clean_matches) = matches.subcommand_matches("clean-versions") {
+        return Some(clean_versions(
+            &options.rename_scheme,
+            clean_matches.value_of("mirror").unwrap_or("."),
+            clean_matches.is_present("dry-run"),
+        ));
+    }
+    None
+}
+
+async fn run_canvas(
+    matches: &ArgMatches<'_>,
+    options: &Options,
+    limits: &SessionLimits,
+    endpoints: Endpoints,
+    manifest: &mut Manifest,
+    run_summary: &mut RunSummary,
+) -> Result<()> {
+    let token =
+        std::env::var("CANVAS_TOKEN").unwrap_or_else(|_| get_password("Canvas access token: "));
+    let canvas_url = matches.value_of("canvas-url").unwrap();
+    let session = Api::with_token(
+        Endpoints {
+            user_agent: endpoints.user_agent,
+            headers: endpoints.headers,
+            ..Canvas::endpoints(canvas_url)
+        },
+        token.trim(),
+    )?;
+    let session = configure_session(session, options, limits, run_summary);
+    let canvas = Canvas::with_api(session);
+    sync_platform(
+        &canvas,
+        manifest,
+        options.do_announcements,
+        options.announcement_filter,
+        options.use_pager,
+        options.do_files,
+        options.download_destination.as_deref(),
+        &options.file_options,
+        options.list_style,
+        run_summary,
+    )
+    .await?;
+    if options.download_destination.is_some() {
+        remember_sync_run(manifest, run_summary, &options.undo_dir);
+    }
+    manifest.save(Path::new(&options.manifest_file))
+}
+
+// Logs in to LumiNUS, or picks up a recording to replay instead, and stores the credentials for
+// next time if they were typed in
+async fn log_in(
+    matches: &ArgMatches<'_>,
+    options: &Options,
+    limits: &SessionLimits,
+    endpoints: Endpoints,
+    run_summary: &mut RunSummary,
+) -> Result<Api> {
+    let credential_file = &options.credential_file;
+    let (api, credentials) = if let Some(replay_dir) = matches.value_of("replay") {
+        // a recording holds everything that a login would have unlocked
+        let api = Api::with_token(endpoints, "replay")?.with_replay(PathBuf::from(replay_dir));
+        (api, None)
+    } else {
+        let (username, password) =
+            get_credentials(credential_file).expect("Unable to get credentials");
+        let api = Api::with_login_at(endpoints, &username, &password)
+            .await
+            .inspect_err(|e| {
+                say!("Login failed: {}. {}", tr(e.message()), tr(e.advice()));
+            })?;
+        let api = match matches.value_of("record") {
+            Some(record_dir) => api.with_recording(PathBuf::from(record_dir)),
+            None => api,
+        };
+        (api, Some((username, password)))
+    };
+    let api = api
+        .with_ffmpeg(matches.value_of("ffmpeg").unwrap_or("ffmpeg").to_owned())
+        .with_strict(matches.is_present("strict"))
+        .with_failure_file(PathBuf::from(
+            matches.value_of("failure-file").unwrap_or("failure.json"),
+        ));
+    let api = configure_session(api, options, limits, run_summary);
+    if let (Some((username, password)), false) = (&credentials, Path::new(credential_file).exists())
+    {
+        match store_credentials(credential_file, username, password) {
             Ok(_) => (),
-            Err(e) => println!("Failed to store credentials: {}", e),
+            Err(e) => say!("Failed to store credentials: {}", e),
         }
     }
+    Ok(api)
+}
 
+async fn run_luminus(
+    matches: &ArgMatches<'_>,
+    mut options: Options,
+    api: &Api,
+    manifest: &mut Manifest,
+    aliases: &ModuleAliases,
+    alert_rules: &AlertRules,
+    run_summary: &mut RunSummary,
+) -> Result<()> {
     let name = api.name().await?;
-    println!("Hi {}!", name);
-    let modules = api.modules(specified_term).await?;
-    println!("You are taking:");
-    for module in modules.iter().filter(|m| m.is_taking()) {
-        println!("- {} {}", module.code, module.name);
+    say!("Hi {}!", name);
+    let enrolled_modules = api.enrolled_modules().await?;
+    if matches.subcommand_matches("terms").is_some() || options.specified_term.is_some() {
+        let terms = terms_of(&enrolled_modules);
+        if matches.subcommand_matches("terms").is_some() {
+            say!("You have modules in these terms:");
+            for term in &terms {
+                println!("- {} ({})", term, term.describe());
+            }
+            return Ok(());
+        }
+        if let Some(term) = options
+            .specified_term
+            .as_ref()
+            .filter(|term| !terms.contains(term))
+        {
+            say!(
+                "You have no modules in term {} ({}), run the terms command to see which terms you do",
+                term,
+                term.describe()
+            );
+            return Err("Unknown term");
+        }
+    }
+    if matches.subcommand_matches("status").is_some() {
+        print_status(manifest);
+        return Ok(());
+    }
+    check_enrollment(
+        &enrolled_modules,
+        manifest,
+        options.list_style.screen_reader,
+    );
+    let expiring_modules = check_expiring_modules(api, &enrolled_modules, manifest, &options).await;
+    if let (Some(destination), false) = (&options.archive_destination, expiring_modules.is_empty())
+    {
+        archive_modules(
+            api,
+            &expiring_modules,
+            destination,
+            &options.file_options,
+            &options.multimedia_options,
+            run_summary,
+        )
+        .await?;
+    }
+    // missing tools only switch off the features that need them, rather than failing file by file later on
+    if options.multimedia_download_destination.is_some() && !api.has_ffmpeg() {
+        say!(
+            "ffmpeg was not found at {}, so multimedia will not be downloaded, transcoded or transcribed. \
+             Install ffmpeg or point --ffmpeg at it.",
+            matches.value_of("ffmpeg").unwrap_or("ffmpeg")
+        );
+        options.multimedia_download_destination = None;
+    }
+    if let Some(hook) = options
+        .transcription_hook
+        .take_if(|hook| !hook.is_available())
+    {
+        say!(
+            "{} was not found, so subtitles will not be generated",
+            hook.program()
+        );
+    }
+    let mut modules = api
+        .select_modules(
+            enrolled_modules,
+            options
+                .specified_term
+                .as_ref()
+                .map(|term| term.code().to_owned()),
+        )
+        .await?;
+    // modules may be referred to by alias, and typos are caught against every module, selected or not
+    let codes = modules
+        .iter()
+        .map(|module| module.code.as_str())
+        .collect::<Vec<_>>();
+    let module_transcode_profiles = std::mem::take(&mut options.module_transcode_profiles)
+        .into_iter()
+        .filter_map(|(reference, profile)| {
+            Some((resolve_module(aliases, &reference, &codes)?, profile))
+        })
+        .collect::<HashMap<_, _>>();
+    let roster_module = matches
+        .subcommand_matches("roster")
+        .and_then(|roster_matches| roster_matches.value_of("module"))
+        .map(|reference| resolve_module(aliases, reference, &codes));
+    if let Some(dump_matches) = matches.subcommand_matches("api-dump") {
+        let module_code = dump_matches
+            .value_of("module")
+            .map(|reference| {
+                resolve_module(aliases, reference, &codes).ok_or("No such module this term")
+            })
+            .transpose()?;
+        return dump_endpoint(
+            api,
+            dump_matches.value_of("endpoint").unwrap(),
+            module_code.and_then(|code| modules.iter().find(|module| module.code == code)),
+            dump_matches.value_of("id"),
+        )
+        .await;
     }
-    println!("You are teaching:");
-    for module in modules.iter().filter(|m| m.is_teaching()) {
-        println!("- {} {}", module.code, module.name);
+    choose_modules(&mut modules, manifest, aliases, &options)?;
+    let module_folders = modules
+        .iter()
+        .map(|module| (module.id.clone(), module.code.clone(), module.folder_path()))
+        .collect::<Vec<_>>();
+    for destination in options
+        .download_destination
+        .iter()
+        .chain(options.multimedia_download_destination.iter())
+    {
+        migrate_module_folders(manifest, destination, &module_folders);
+        run_summary.add_module_folders(destination, &module_folders);
     }
+    list_modules(
+        &modules.iter().map(ModuleRow::of).collect::<Vec<_>>(),
+        options.list_style,
+    );
 
-    if do_announcements {
-        print_announcements(&api, &modules).await?;
+    run_online_subcommands(matches, &options, api, &modules, roster_module.flatten()).await?;
+
+    if let Some(plan_matches) = matches.subcommand_matches("plan") {
+        write_sync_plan(
+            api,
+            &modules,
+            options.include_uploadable_folders,
+            options.download_destination.as_deref(),
+            options.multimedia_download_destination.as_deref(),
+            &options.file_options,
+            &options.multimedia_options,
+            &manifest.added_extensions,
+            plan_matches.value_of("output").unwrap(),
+        )
+        .await?;
+        return manifest.save(Path::new(&options.manifest_file));
     }
 
-    if do_files || download_destination.is_some() {
-        let module_file = load_modules_files(&api, &modules, include_uploadable_folders).await?;
+    if let Some(apply_matches) = matches.subcommand_matches("apply") {
+        apply_sync_plan(
+            api,
+            &modules,
+            options.include_uploadable_folders,
+            apply_matches.value_of("plan").unwrap(),
+            &options.file_options,
+            &options.multimedia_options,
+            &manifest.added_extensions,
+            run_summary,
+        )
+        .await?;
+        remember_deferred(manifest, run_summary);
+        return manifest.save(Path::new(&options.manifest_file));
+    }
 
-        if do_files {
-            list_resources(&module_file);
+    if let Some(adopt_matches) = matches.subcommand_matches("adopt") {
+        let destination = adopt_matches.value_of("to").unwrap();
+        migrate_module_folders(manifest, destination, &module_folders);
+        adopt_files(
+            api,
+            &modules,
+            options.include_uploadable_folders,
+            destination,
+            adopt_matches.is_present("move"),
+        )
+        .await?;
+        return manifest.save(Path::new(&options.manifest_file));
+    }
+
+    sync_modules(
+        matches,
+        &options,
+        api,
+        &modules,
+        &module_transcode_profiles,
+        manifest,
+        alert_rules,
+        run_summary,
+    )
+    .await?;
+    manifest.save(Path::new(&options.manifest_file))
+}
+
+// Warns about the modules whose terms have ended, once each, and returns all of them
+async fn check_expiring_modules(
+    api: &Api,
+    enrolled_modules: &[Module],
+    manifest: &mut Manifest,
+    options: &Options,
+) -> Vec<Module> {
+    let expiring_modules = api
+        .expiring_modules(enrolled_modules)
+        .await
+        .unwrap_or_else(|e| {
+            say!("Failed checking for expiring modules: {}", e);
+            vec![]
+        });
+    // each module is warned about once, on the first run after its term ends
+    let newly_expiring = expiring_modules
+        .iter()
+        .filter(|module| !manifest.expiring_modules.contains(&module.id))
+        .collect::<Vec<_>>();
+    manifest.expiring_modules = expiring_modules
+        .iter()
+        .map(|module| module.id.clone())
+        .collect();
+    if !newly_expiring.is_empty() {
+        say!(
+            "Warning: the terms of these modules have ended, so you may lose access to them soon:"
+        );
+        for module in &newly_expiring {
+            if options.list_style.screen_reader {
+                say!("{} {}, term {}", module.code, module.name, module.term);
+            } else {
+                say!("- {} {} (term {})", module.code, module.name, module.term);
+            }
+        }
+        if options.archive_destination.is_none() {
+            say!("Use --archive-expiring-to to mirror them before anything else.");
         }
+    }
+    expiring_modules
+}
 
-        if let Some(destination) = download_destination {
-            download_resources(&api, &module_file, &destination, overwrite_mode, 64).await?;
+// Narrows the modules down to those given to --modules, or else to those picked earlier
+fn choose_modules(
+    modules: &mut Vec<Module>,
+    manifest: &mut Manifest,
+    aliases: &ModuleAliases,
+    options: &Options,
+) -> Result<()> {
+    if let Some(module_references) = &options.module_filter {
+        let codes = modules
+            .iter()
+            .map(|module| module.code.as_str())
+            .collect::<Vec<_>>();
+        let wanted = module_references
+            .iter()
+            .filter_map(|reference| resolve_module(aliases, reference, &codes))
+            .collect::<Vec<_>>();
+        if wanted.is_empty() {
+            return Err("None of the modules given to --modules were found");
+        }
+        modules.retain(|module| wanted.contains(&module.code));
+    } else {
+        let interactive = io::stdin().is_terminal() && io::stdout().is_terminal();
+        let never_picked = manifest.module_selection.is_empty();
+        if options.pick_modules_again || (never_picked && interactive) {
+            pick_modules(modules, manifest);
         }
+        for module in modules.iter() {
+            if !manifest.module_selection.contains_key(&module.code) && !never_picked {
+                say!(
+                    "New module {} will be synced, use --pick-modules to change this",
+                    module.code
+                );
+                manifest.module_selection.insert(module.code.clone(), true);
+            }
+        }
+        modules.retain(|module| manifest.is_module_selected(&module.code));
+    }
+    Ok(())
+}
+
+async fn run_online_subcommands(
+    matches: &ArgMatches<'_>,
+    options: &Options,
+    api: &Api,
+    modules: &[Module],
+    roster_module: Option<String>,
+) -> Result<()> {
+    if let Some(du_matches) = matches.subcommand_matches("du") {
+        let mirror = du_matches.value_of("mirror").unwrap_or(".");
+        print_disk_usage(
+            api,
+            modules,
+            mirror,
+            du_matches.value_of("multimedia-mirror").unwrap_or(mirror),
+            options.include_uploadable_folders,
+            options.list_style.screen_reader,
+        )
+        .await?;
+    }
+
+    if let Some(roster_matches) = matches.subcommand_matches("roster") {
+        export_roster(
+            api,
+            modules,
+            &roster_module.ok_or("No such module this term")?,
+            roster_matches.value_of("output"),
+        )
+        .await?;
+    }
+
+    if let Some(report_matches) = matches.subcommand_matches("report") {
+        let since = report_matches
+            .value_of("since")
+            .and_then(parse_duration)
+            .ok_or("Invalid duration for --since")?;
+        write_report(
+            api,
+            modules,
+            time_ago(since)?,
+            options.include_uploadable_folders,
+            report_matches.value_of("output"),
+        )
+        .await?;
+    }
+
+    if options.do_messages || options.messages_export_destination.is_some() {
+        print_messages(api, options.messages_export_destination.as_deref()).await?;
+    }
+
+    if options.do_groups || options.groups_export_destination.is_some() {
+        print_groups(api, modules, options.groups_export_destination.as_deref()).await?;
+    }
+
+    if options.do_peer_evaluations || options.peer_evaluations_export_destination.is_some() {
+        print_peer_evaluations(
+            api,
+            modules,
+            options.peer_evaluations_export_destination.as_deref(),
+        )
+        .await?;
+    }
+
+    if let Some(destination) = &options.syllabus_export_destination {
+        export_syllabuses(api, modules, destination).await?;
+    }
+
+    if options.do_deadlines || options.deadlines_export_destination.is_some() {
+        print_deadlines(
+            api,
+            modules,
+            options.deadlines_export_destination.as_deref(),
+        )
+        .await?;
+    }
+
+    if options.do_calendar || options.calendar_export_destination.is_some() {
+        print_calendar(api, options.calendar_export_destination.as_deref()).await?;
+    }
+
+    if let Some(destination) = &options.ics_export_destination {
+        export_ics(api, modules, destination).await?;
     }
+    Ok(())
+}
+
+// Lists and downloads announcements, files and multimedia, then does whatever was asked for with
+// what came down
+#[allow(clippy::too_many_arguments)]
+async fn sync_modules(
+    matches: &ArgMatches<'_>,
+    options: &Options,
+    api: &Api,
+    modules: &[Module],
+    module_transcode_profiles: &HashMap<String, &'static TranscodeProfile>,
+    manifest: &mut Manifest,
+    alert_rules: &AlertRules,
+    run_summary: &mut RunSummary,
+) -> Result<()> {
+    let turns = Turns::new(
+        matches
+            .values_of("priority")
+            .map(|parts| parts.map(|part| part.to_owned()).collect())
+            .unwrap_or_default(),
+    );
+    let move_policy = options.move_policy;
+    // Announcements, files and multimedia don't depend on each other, so they are listed side by side,
+    // sharing the session's request limit, and downloaded in turn by --priority. Anything that touches
+    // the manifest waits until they are all done.
+    let previous_folders = std::mem::take(&mut manifest.folder_cache);
+    let folder_cache = FolderCache::new(if options.full_scan {
+        // starting from nothing refreshes the manifest
+        HashMap::new()
+    } else {
+        previous_folders
+    });
+    let manifest_so_far: &Manifest = manifest;
+    let announcements_phase = turns.take_turn("announcements", async {
+        if options.do_announcements
+            || options.announcements_digest.is_some()
+            || !alert_rules.is_empty()
+        {
+            turns.wait_for_turn("announcements").await;
+            Some(load_announcements(api, modules).await)
+        } else {
+            None
+        }
+    });
+    let files_phase = turns.take_turn("files", async {
+        if !options.do_files && options.download_destination.is_none() {
+            return Ok(None);
+        }
+        let module_file = load_modules_files(
+            api,
+            modules,
+            options.include_uploadable_folders,
+            Some(&folder_cache),
+        )
+        .await?;
+        let module_file = with_added_extensions(module_file, &manifest_so_far.added_extensions);
+        if options.do_files {
+            list_resources(&module_file, options.list_style);
+        }
+        match &options.download_destination {
+            Some(destination) => {
+                turns.wait_for_turn("files").await;
+                let (module_file, moved) = match move_policy {
+                    MovePolicy::Download => (module_file, vec![]),
+                    _ => handle_moved_files(module_file, destination, manifest_so_far, move_policy),
+                };
+                let (module_file, summary) = download_resources(
+                    api,
+                    module_file,
+                    destination,
+                    &options.file_options,
+                    MAX_DOWNLOADS,
+                )
+                .await?;
+                Ok(Some((module_file, Some((summary, moved)))))
+            }
+            None => Ok(Some((module_file, None))),
+        }
+    });
+    let multimedia_phase = turns.take_turn("multimedia", async {
+        if !options.do_multimedia && options.multimedia_download_destination.is_none() {
+            return Ok(None);
+        }
+        let module_multimedia = load_modules_multimedia(api, modules).await?;
+        if options.do_multimedia {
+            list_resources(&module_multimedia, options.list_style);
+        }
+        match &options.multimedia_download_destination {
+            Some(destination) => {
+                turns.wait_for_turn("multimedia").await;
+                let (module_multimedia, moved) = match move_policy {
+                    MovePolicy::Download => (module_multimedia, vec![]),
+                    _ => handle_moved_files(
+                        module_multimedia,
+                        destination,
+                        manifest_so_far,
+                        move_policy,
+                    ),
+                };
+                let (module_multimedia, summary) = download_resources(
+                    api,
+                    module_multimedia,
+                    destination,
+                    &options.multimedia_options,
+                    4,
+                )
+                .await?;
+                Ok(Some((module_multimedia, Some((summary, moved)))))
+            }
+            None => Ok(Some((module_multimedia, None))),
+        }
+    });
+    let (module_announcements, files_result, multimedia_result): (_, Result<_>, Result<_>) =
+        future::join3(announcements_phase, files_phase, multimedia_phase).await;
+    manifest.folder_cache = folder_cache.into_snapshots();
 
-    if do_multimedia || multimedia_download_destination.is_some() {
-        let module_multimedia = load_modules_multimedia(&api, &modules).await?;
+    if let Some(module_announcements) = &module_announcements {
+        raise_alerts(
+            modules,
+            module_announcements,
+            alert_rules,
+            manifest,
+            matches.value_of("alert-command"),
+        );
+    }
+    if let (Some(module_announcements), Some(path)) =
+        (&module_announcements, &options.announcements_digest)
+    {
+        if let Err(e) = write_announcement_digest(modules, module_announcements, path) {
+            say!("Failed to write announcements digest: {}", e);
+        }
+    }
+    if let (Some(module_announcements), true) = (module_announcements, options.do_announcements) {
+        print_announcements(
+            modules,
+            module_announcements,
+            manifest,
+            options.announcement_filter,
+            options.file_options.permalinks,
+            options.use_pager,
+        )?;
+    }
 
-        if do_multimedia {
-            list_resources(&module_multimedia);
+    if let (Some((module_file, Some((mut summary, moved)))), Some(destination)) =
+        (files_result?, &options.download_destination)
+    {
+        let module_file = if options.fix_extensions {
+            add_missing_extensions(module_file, destination, &mut summary.downloaded, manifest)
+        } else {
+            module_file
+        };
+        if move_policy != MovePolicy::Download {
+            manifest.moved_files.extend(moved);
+            remember_fingerprints(manifest, &summary.downloaded);
+        }
+        run_summary.add(summary);
+        report_no_access(modules, run_summary);
+        write_submission_reports(modules, &module_file, destination)?;
+        if options.merge_pdf_per_folder {
+            merge_pdfs_per_folder(&module_file, destination, manifest).await?;
+        }
+        if options.write_html_index {
+            match html_index::write_indexes(Path::new(destination), "LumiNUS", false) {
+                Ok(written) => say!("Wrote {} index.html file(s)", written),
+                Err(e) => say!("Failed to write HTML indexes: {}", e),
+            }
         }
+    }
 
-        if let Some(destination) = multimedia_download_destination {
-            download_resources(&api, &module_multimedia, &destination, overwrite_mode, 4).await?;
+    if let (Some((module_multimedia, Some((summary, moved)))), Some(destination)) =
+        (multimedia_result?, &options.multimedia_download_destination)
+    {
+        if move_policy != MovePolicy::Download {
+            manifest.moved_files.extend(moved);
+            remember_fingerprints(manifest, &summary.downloaded);
+        }
+        if options.default_transcode_profile.is_some() || !module_transcode_profiles.is_empty() {
+            // profiles are given per module code, but videos only know which folder they are in
+            let profiles_by_folder = modules
+                .iter()
+                .filter_map(|module| {
+                    module_transcode_profiles
+                        .get(&module.code)
+                        .map(|profile| (module.folder_path(), *profile))
+                })
+                .collect::<HashMap<_, _>>();
+            transcode_videos(
+                api,
+                &module_multimedia,
+                destination,
+                &profiles_by_folder,
+                options.default_transcode_profile,
+                &summary.downloaded,
+                manifest,
+            )
+            .await;
+        }
+        if let Some(hook) = &options.transcription_hook {
+            transcribe_videos(
+                api,
+                &module_multimedia,
+                destination,
+                hook,
+                &summary.downloaded,
+                manifest,
+            )
+            .await;
         }
+        run_summary.add(summary);
     }
 
+    if options.download_destination.is_some() || options.multimedia_download_destination.is_some() {
+        remember_deferred(manifest, run_summary);
+        remember_sync_run(manifest, run_summary, &options.undo_dir);
+    }
     Ok(())
 }
@@ -0,0 +1,341 @@
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::manifest::KnownModule;
+use crate::util::{escape_html, format_size, natural_cmp, percent_encode, sanitise_filename};
+use crate::Result;
+
+const MAX_SEARCH_RESULTS: usize = 200;
+
+// A read-only web view over a local mirror, for browsing without LumiNUS
+struct Mirror {
+    root: PathBuf,
+    // module id => module
+    modules: BTreeMap<String, KnownModule>,
+    // module id => folder, for mirrors that don't name folders by module code
+    folders: BTreeMap<String, String>,
+}
+
+pub async fn serve(
+    root: PathBuf,
+    modules: BTreeMap<String, KnownModule>,
+    folders: BTreeMap<String, String>,
+    address: &str,
+) -> Result<()> {
+    let listener = TcpListener::bind(address)
+        .await
+        .map_err(|_| "Unable to listen on address")?;
+    let mirror = Arc::new(Mirror {
+        root,
+        modules,
+        folders,
+    });
+    loop {
+        let (stream, _) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(_) => continue,
+        };
+        let mirror = mirror.clone();
+        tokio::spawn(async move {
+            // a broken connection only affects that one request
+            let _ = handle_connection(stream, mirror).await;
+        });
+    }
+}
+
+// Files are sent as they are read, so that large videos don't have to fit in memory
+enum Response {
+    Page(&'static str, Vec<u8>),
+    File(&'static str, tokio::fs::File, u64),
+}
+
+async fn handle_connection(mut stream: TcpStream, mirror: Arc<Mirror>) -> std::io::Result<()> {
+    let mut buffer = vec![0u8; 8192];
+    let read = stream.read(&mut buffer).await?;
+    let request = String::from_utf8_lossy(&buffer[..read]);
+    let target = request
+        .lines()
+        .next()
+        .and_then(|line| {
+            let mut parts = line.split_whitespace();
+            match (parts.next(), parts.next()) {
+                (Some("GET"), Some(target)) => Some(target.to_owned()),
+                _ => None,
+            }
+        })
+        .unwrap_or_default();
+
+    let (status, response) = match route(mirror, &target).await {
+        Some(response) => ("200 OK", response),
+        None => (
+            "404 Not Found",
+            Response::Page(
+                "text/html; charset=utf-8",
+                page("Not found", "<p>Nothing here.</p>").into_bytes(),
+            ),
+        ),
+    };
+    let (content_type, length) = match &response {
+        Response::Page(content_type, body) => (content_type, body.len() as u64),
+        Response::File(content_type, _, length) => (content_type, *length),
+    };
+    let header = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status, content_type, length
+    );
+    stream.write_all(header.as_bytes()).await?;
+    match response {
+        Response::Page(_, body) => stream.write_all(&body).await?,
+        // no more than the length promised, in case the file grew since
+        Response::File(_, file, length) => {
+            tokio::io::copy(&mut file.take(length), &mut stream).await?;
+        }
+    }
+    stream.shutdown().await
+}
+
+// Builds a page on a blocking thread, since it walks the file system
+async fn blocking_page<F: FnOnce() -> String + Send + 'static>(build: F) -> Option<Response> {
+    tokio::task::spawn_blocking(build).await.ok().map(html)
+}
+
+async fn route(mirror: Arc<Mirror>, target: &str) -> Option<Response> {
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (target, None),
+    };
+    let path = percent_decode(path);
+    if path == "/" && mirror.modules.is_empty() {
+        // no manifest yet, so just show whatever is in the mirror
+        blocking_page(move || directory_page(&mirror.root, Path::new(""))).await
+    } else if path == "/" {
+        blocking_page(move || index_page(&mirror)).await
+    } else if path == "/search" {
+        let term = query
+            .and_then(|query| {
+                query
+                    .split('&')
+                    .find_map(|pair| pair.strip_prefix("q="))
+                    .map(|term| percent_decode(&term.replace('+', " ")))
+            })
+            .unwrap_or_default();
+        blocking_page(move || search_page(&mirror, &term)).await
+    } else if let Some(relative) = path.strip_prefix("/files/") {
+        let relative = safe_relative_path(relative)?;
+        let full_path = mirror.root.join(&relative);
+        let metadata = tokio::fs::metadata(&full_path).await.ok()?;
+        if metadata.is_dir() {
+            blocking_page(move || directory_page(&full_path, &relative)).await
+        } else {
+            let file = tokio::fs::File::open(&full_path).await.ok()?;
+            Some(Response::File(
+                content_type_of(&full_path),
+                file,
+                metadata.len(),
+            ))
+        }
+    } else {
+        None
+    }
+}
+
+// refuses anything that could escape the mirror directory
+fn safe_relative_path(path: &str) -> Option<PathBuf> {
+    let relative = PathBuf::from(path.trim_end_matches('/'));
+    if relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        Some(relative)
+    } else {
+        None
+    }
+}
+
+fn html(body: String) -> Response {
+    Response::Page("text/html; charset=utf-8", body.into_bytes())
+}
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{title}</title>\
+         <style>body{{font-family:sans-serif;max-width:60em;margin:auto}}td{{padding:0 1em}}</style>\
+         </head><body><p><a href=\"/\">Home</a></p>\
+         <form action=\"/search\"><input name=\"q\" placeholder=\"Search files and notes\"> \
+         <button>Search</button></form><h1>{title}</h1>{body}</body></html>",
+        title = escape_html(title),
+        body = body
+    )
+}
+
+fn index_page(mirror: &Mirror) -> String {
+    let mut body = String::from("<ul>");
+    for (id, module) in &mirror.modules {
+        let dir = mirror
+            .folders
+            .get(id)
+            .cloned()
+            .unwrap_or_else(|| sanitise_filename(&module.code));
+        if mirror.root.join(&dir).is_dir() {
+            body.push_str(&format!(
+                "<li><a href=\"/files/{}/\">{} {}</a> (term {})</li>",
+                percent_encode_path(Path::new(&dir)),
+                escape_html(&module.code),
+                escape_html(&module.name),
+                escape_html(&module.term)
+            ));
+        }
+    }
+    body.push_str("</ul>");
+    page("fluminurs mirror", &body)
+}
+
+fn directory_page(full_path: &Path, relative: &Path) -> String {
+    let mut entries = std::fs::read_dir(full_path)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| !entry.file_name().to_string_lossy().starts_with(['.', '~']))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    entries.sort_by(|e1, e2| {
+        natural_cmp(
+            &e1.file_name().to_string_lossy(),
+            &e2.file_name().to_string_lossy(),
+        )
+    });
+
+    let mut body = String::from("<table>");
+    for entry in entries {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let link = format!("/files/{}", percent_encode_path(&relative.join(&name)));
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        if metadata.is_dir() {
+            body.push_str(&format!(
+                "<tr><td><a href=\"{}/\">{}/</a></td><td></td></tr>",
+                link,
+                escape_html(&name)
+            ));
+        } else {
+            body.push_str(&format!(
+                "<tr><td><a href=\"{}\">{}</a></td><td>{}</td></tr>",
+                link,
+                escape_html(&name),
+                format_size(metadata.len())
+            ));
+        }
+    }
+    body.push_str("</table>");
+    page(&relative.to_string_lossy(), &body)
+}
+
+// Matches file names, and the contents of text files such as announcement archives and text sidecars
+fn search_page(mirror: &Mirror, term: &str) -> String {
+    let needle = term.to_lowercase();
+    let mut results = vec![];
+    if !needle.is_empty() {
+        search_dir(&mirror.root, &mirror.root, &needle, &mut results);
+    }
+    let mut body = format!("<p>{} result(s)</p><ul>", results.len());
+    for result in results {
+        body.push_str(&format!(
+            "<li><a href=\"/files/{}\">{}</a></li>",
+            percent_encode_path(&result),
+            escape_html(&result.to_string_lossy())
+        ));
+    }
+    body.push_str("</ul>");
+    page(&format!("Search: {}", term), &body)
+}
+
+fn search_dir(root: &Path, dir: &Path, needle: &str, results: &mut Vec<PathBuf>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        if results.len() >= MAX_SEARCH_RESULTS {
+            return;
+        }
+        let path = entry.path();
+        if path.is_dir() {
+            search_dir(root, &path, needle, results);
+            continue;
+        }
+        let name_matches = entry
+            .file_name()
+            .to_string_lossy()
+            .to_lowercase()
+            .contains(needle);
+        let content_matches = || {
+            is_searchable_text(&path)
+                && std::fs::read_to_string(&path)
+                    .map(|content| content.to_lowercase().contains(needle))
+                    .unwrap_or(false)
+        };
+        if name_matches || content_matches() {
+            if let Ok(relative) = path.strip_prefix(root) {
+                results.push(relative.to_owned());
+            }
+        }
+    }
+}
+
+fn is_searchable_text(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("txt") | Some("md")
+    )
+}
+
+fn content_type_of(path: &Path) -> &'static str {
+    match path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+        .as_deref()
+    {
+        Some("pdf") => "application/pdf",
+        Some("txt") | Some("md") | Some("srt") => "text/plain; charset=utf-8",
+        Some("html") | Some("htm") => "text/html; charset=utf-8",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("mp4") => "video/mp4",
+        Some("mkv") => "video/x-matroska",
+        _ => "application/octet-stream",
+    }
+}
+
+fn percent_encode_path(path: &Path) -> String {
+    path.components()
+        .map(|component| percent_encode(&component.as_os_str().to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+            if let Ok(b) = u8::from_str_radix(hex, 16) {
+                decoded.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,44 @@
+use std::sync::Mutex;
+
+// How many bytes a run may download. Downloads are let through until what has been spent
+// reaches the budget, so the last few in flight may take it over somewhat.
+#[derive(Debug)]
+pub struct ByteBudget {
+    max: u64,
+    // what finished downloads took, plus what the ones in flight are expected to take
+    spent: Mutex<u64>,
+}
+
+impl ByteBudget {
+    pub fn new(max: u64) -> ByteBudget {
+        ByteBudget {
+            max,
+            spent: Mutex::new(0),
+        }
+    }
+
+    // Sets aside `expected` bytes for a download about to start, or returns false if the budget
+    // is used up and it shouldn't start at all
+    pub fn try_reserve(&self, expected: u64) -> bool {
+        let mut spent = self.spent.lock().unwrap();
+        if *spent >= self.max {
+            return false;
+        }
+        *spent = spent.saturating_add(expected);
+        true
+    }
+
+    // Swaps what a download was expected to take for what it really took
+    pub fn settle(&self, expected: u64, actual: u64) {
+        let mut spent = self.spent.lock().unwrap();
+        *spent = spent.saturating_sub(expected).saturating_add(actual);
+    }
+
+    pub fn spent(&self) -> u64 {
+        *self.spent.lock().unwrap()
+    }
+
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+}
@@ -0,0 +1,48 @@
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ical::{self, Event};
+use crate::util::{html_to_text, parse_time};
+
+// An entry in the personal calendar, which aggregates events across all modules
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CalendarEvent {
+    pub id: String,
+    pub title: String,
+    pub description: Option<String>,
+    pub location: Option<String>,
+    start_date: String,
+    end_date: Option<String>,
+}
+
+impl CalendarEvent {
+    pub fn start(&self) -> SystemTime {
+        parse_time(&self.start_date)
+    }
+
+    pub fn end(&self) -> Option<SystemTime> {
+        self.end_date.as_deref().map(parse_time)
+    }
+
+    pub fn to_event(&self) -> Event {
+        let description = match (&self.location, &self.description) {
+            (Some(location), Some(description)) => Some(format!(
+                "Location: {}\n\n{}",
+                location,
+                html_to_text(description)
+            )),
+            (Some(location), None) => Some(format!("Location: {}", location)),
+            (None, Some(description)) => Some(html_to_text(description)),
+            (None, None) => None,
+        };
+        Event {
+            uid: ical::uid("calendar", &self.id),
+            summary: self.title.clone(),
+            description,
+            start: self.start(),
+            end: self.end(),
+        }
+    }
+}
@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use reqwest::header::LINK;
+use reqwest::{Method, Url};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::file::File;
+use crate::module::{clashing_folders, module_folder_path, Announcement, FolderNameStyle};
+use crate::platform::{Course, LearningPlatform};
+use crate::resource;
+use crate::resource::{OverwriteMode, OverwriteResult, Resource};
+use crate::util::{parse_time, sanitise_filename};
+use crate::{Api, Endpoints, Result};
+
+// Canvas puts every course's files under a root folder with this name
+const ROOT_FOLDER_NAME: &str = "course files";
+
+#[derive(Debug, Deserialize)]
+struct ApiTerm {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiEnrollment {
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiCourse {
+    id: u64,
+    name: String,
+    course_code: String,
+    term: Option<ApiTerm>,
+    #[serde(default)]
+    enrollments: Vec<ApiEnrollment>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiAnnouncement {
+    id: u64,
+    title: String,
+    message: Option<String>,
+    posted_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiFolder {
+    id: u64,
+    full_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiFile {
+    id: u64,
+    folder_id: u64,
+    display_name: String,
+    size: Option<u64>,
+    updated_at: String,
+    // empty when the file is locked or hidden from students
+    url: Option<String>,
+}
+
+// Canvas hands out pre-signed download URLs with the file listing, so there is nothing left to ask for
+pub struct CanvasFile {
+    url: Url,
+    path: PathBuf,
+    last_updated: SystemTime,
+    size: Option<u64>,
+    permalink: String,
+}
+
+pub struct Canvas {
+    api: Api,
+}
+
+impl Canvas {
+    // `base_url` is the institution's Canvas site, e.g. https://canvas.nus.edu.sg,
+    // and `token` an access token generated under Account > Settings
+    pub fn with_token(base_url: &str, token: &str) -> Result<Canvas> {
+        Ok(Canvas {
+            api: Api::with_token(Self::endpoints(base_url), token)?,
+        })
+    }
+
+    pub fn endpoints(base_url: &str) -> Endpoints {
+        Endpoints {
+            api_base_url: format!("{}/api/v1/", base_url.trim_end_matches('/')),
+            web_base_url: format!("{}/", base_url.trim_end_matches('/')),
+            subscription_key: None,
+            ..Endpoints::default()
+        }
+    }
+
+    // for when the session needs more setup, such as an audit log
+    pub fn with_api(api: Api) -> Canvas {
+        Canvas { api }
+    }
+
+    // Canvas pages every listing, and tells us where the next page is in the Link header
+    async fn get_all<T: DeserializeOwned + 'static>(&self, path: &str) -> Result<Vec<T>> {
+        let mut items = vec![];
+        let mut next = Some(path.to_owned());
+        while let Some(path) = next {
+            let res = self.api.api(&path, Method::GET, None).await?;
+            if !res.status().is_success() {
+                return Err("Canvas refused the request");
+            }
+            next = res
+                .headers()
+                .get(LINK)
+                .and_then(|link| link.to_str().ok())
+                .and_then(next_page_url);
+            let mut page = res
+                .json::<Vec<T>>()
+                .await
+                .map_err(|_| "Unable to deserialize JSON")?;
+            items.append(&mut page);
+        }
+        Ok(items)
+    }
+}
+
+// picks the rel="next" URL out of a Link header
+fn next_page_url(link: &str) -> Option<String> {
+    link.split(',').find_map(|part| {
+        let mut pieces = part.split(';');
+        let url = pieces.next()?.trim();
+        if pieces.any(|piece| piece.trim() == "rel=\"next\"") {
+            Some(url.trim_start_matches('<').trim_end_matches('>').to_owned())
+        } else {
+            None
+        }
+    })
+}
+
+#[async_trait(?Send)]
+impl LearningPlatform for Canvas {
+    fn platform_name(&self) -> &'static str {
+        "Canvas"
+    }
+
+    fn session(&self) -> &Api {
+        &self.api
+    }
+
+    async fn user_name(&self) -> Result<String> {
+        #[derive(Deserialize)]
+        struct Profile {
+            name: String,
+        }
+        let res = self
+            .api
+            .api("users/self/profile", Method::GET, None)
+            .await?;
+        if !res.status().is_success() {
+            return Err("Invalid Canvas access token");
+        }
+        Ok(res
+            .json::<Profile>()
+            .await
+            .map_err(|_| "Unable to deserialize JSON")?
+            .name)
+    }
+
+    async fn courses(&self, term: Option<String>) -> Result<Vec<Course>> {
+        let courses = self
+            .get_all::<ApiCourse>("courses?enrollment_state=active&include[]=term&per_page=100")
+            .await?;
+        let mut courses = courses
+            .into_iter()
+            .map(|course| {
+                let term = course.term.map(|term| term.name).unwrap_or_default();
+                Course {
+                    id: course.id.to_string(),
+                    folder: module_folder_path(
+                        self.api.term_folders,
+                        &term,
+                        &self
+                            .api
+                            .folder_name_style
+                            .folder_name(&course.course_code, &course.name),
+                    ),
+                    code: course.course_code,
+                    name: course.name,
+                    term,
+                    is_teaching: course.enrollments.iter().any(|enrollment| {
+                        matches!(enrollment.kind.as_str(), "teacher" | "ta" | "designer")
+                    }),
+                }
+            })
+            .collect::<Vec<_>>();
+        if self.api.folder_name_style == FolderNameStyle::Name {
+            let folders = courses
+                .iter()
+                .map(|course| course.folder.clone())
+                .collect::<Vec<_>>();
+            for (course, clashes) in courses.iter_mut().zip(clashing_folders(&folders)) {
+                if clashes {
+                    course.folder = module_folder_path(
+                        self.api.term_folders,
+                        &course.term,
+                        &FolderNameStyle::CodeName.folder_name(&course.code, &course.name),
+                    );
+                }
+            }
+        }
+        courses.retain(|course| term.as_ref().is_none_or(|term| &course.term == term));
+        courses.sort_by(|c1, c2| c1.code.cmp(&c2.code));
+        Ok(courses)
+    }
+
+    async fn announcements(&self, course: &Course) -> Result<Vec<Announcement>> {
+        let announcements = self
+            .get_all::<ApiAnnouncement>(&format!(
+                "announcements?context_codes[]=course_{}&per_page=100",
+                course.id
+            ))
+            .await?;
+        let mut announcements = announcements
+            .into_iter()
+            .map(|announcement| Announcement {
+                id: announcement.id.to_string(),
+                title: announcement.title,
+                description: announcement.message.unwrap_or_default(),
+                display_from: announcement.posted_at,
+                permalink: Some(self.api.web_url(&format!(
+                    "courses/{}/discussion_topics/{}",
+                    course.id, announcement.id
+                ))),
+            })
+            .collect::<Vec<_>>();
+        // oldest first, like LumiNUS
+        announcements.sort_by(|a1, a2| a1.display_from.cmp(&a2.display_from));
+        Ok(announcements)
+    }
+
+    async fn files(
+        &self,
+        course: &Course,
+        _include_uploadable: bool,
+    ) -> Result<Vec<Box<dyn Resource>>> {
+        let (folders, files) = futures_util::future::join(
+            self.get_all::<ApiFolder>(&format!("courses/{}/folders?per_page=100", course.id)),
+            self.get_all::<ApiFile>(&format!("courses/{}/files?per_page=100", course.id)),
+        )
+        .await;
+        let course_path = course.folder.clone();
+        let folder_paths = folders?
+            .into_iter()
+            .map(|folder| {
+                let path = folder
+                    .full_name
+                    .strip_prefix(ROOT_FOLDER_NAME)
+                    .unwrap_or(&folder.full_name)
+                    .split('/')
+                    .filter(|part| !part.is_empty())
+                    .fold(course_path.clone(), |path, part| {
+                        path.join(sanitise_filename(part))
+                    });
+                (folder.id, path)
+            })
+            .collect::<HashMap<_, _>>();
+        Ok(files?
+            .into_iter()
+            .filter_map(|file| {
+                let url = Url::parse(file.url.as_deref()?).ok()?;
+                let folder_path = folder_paths.get(&file.folder_id).unwrap_or(&course_path);
+                Some(Box::new(CanvasFile {
+                    url,
+                    path: folder_path.join(sanitise_filename(&file.display_name)),
+                    last_updated: parse_time(&file.updated_at),
+                    size: file.size,
+                    permalink: self
+                        .api
+                        .web_url(&format!("courses/{}/files/{}", course.id, file.id)),
+                }) as Box<dyn Resource>)
+            })
+            .collect())
+    }
+
+    // lecture videos on Canvas live in external tools such as Panopto, which we can't reach
+    async fn videos(&self, _course: &Course) -> Result<Vec<Box<dyn Resource>>> {
+        Ok(vec![])
+    }
+}
+
+#[async_trait(?Send)]
+impl Resource for CanvasFile {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn last_updated(&self) -> SystemTime {
+        self.last_updated
+    }
+
+    fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    fn permalink(&self) -> Option<&str> {
+        Some(&self.permalink)
+    }
+
+    async fn download(
+        &self,
+        api: &Api,
+        destination: &Path,
+        temp_destination: &Path,
+        overwrite: OverwriteMode,
+    ) -> Result<OverwriteResult> {
+        resource::do_retryable_download(
+            api,
+            destination,
+            temp_destination,
+            overwrite,
+            self.last_updated,
+            resource::content_matches(api, destination, &self.path, self.size, || async {
+                Ok(self.url.clone())
+            })
+            .await,
+            move |_| async move { Ok(self.url.clone()) },
+            File::download_chunks,
+        )
+        .await
+    }
+}
@@ -0,0 +1,112 @@
+use std::time::SystemTime;
+
+use futures_util::future;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+
+use crate::ical::{self, Event};
+use crate::util::parse_time;
+use crate::{Api, ApiData, Result};
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiSubmissionFolder {
+    id: String,
+    name: String,
+    allow_upload: Option<bool>,
+    close_date: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiQuiz {
+    id: String,
+    title: String,
+    end_date: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeadlineKind {
+    Assignment,
+    Quiz,
+}
+
+pub struct Deadline {
+    pub id: String,
+    pub module_code: String,
+    pub title: String,
+    pub kind: DeadlineKind,
+    pub due: SystemTime,
+}
+
+impl DeadlineKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            DeadlineKind::Assignment => "Assignment",
+            DeadlineKind::Quiz => "Quiz",
+        }
+    }
+}
+
+impl Deadline {
+    // Assignments are the workbin folders that accept submissions, quizzes come from the quiz tool
+    pub(crate) async fn load(
+        api: &Api,
+        module_id: &str,
+        module_code: &str,
+    ) -> Result<Vec<Deadline>> {
+        let (folders, quizzes) = future::join(
+            api.api_as_json::<ApiData<Vec<ApiSubmissionFolder>>>(
+                &format!("files/?ParentID={}", module_id),
+                Method::GET,
+                None,
+            ),
+            api.api_as_json::<ApiData<Vec<ApiQuiz>>>(
+                &format!("quiz/?ParentID={}", module_id),
+                Method::GET,
+                None,
+            ),
+        )
+        .await;
+        let folders = folders?
+            .data
+            .ok_or("Invalid API response from server: type mismatch")?;
+        // not every module has the quiz tool enabled
+        let quizzes = quizzes.ok().and_then(|q| q.data).unwrap_or_default();
+
+        let assignments = folders
+            .into_iter()
+            .filter(|f| f.allow_upload.unwrap_or(false))
+            .filter_map(|f| {
+                let due = parse_time(f.close_date.as_deref()?);
+                Some(Deadline {
+                    id: f.id,
+                    module_code: module_code.to_owned(),
+                    title: f.name,
+                    kind: DeadlineKind::Assignment,
+                    due,
+                })
+            });
+        let quizzes = quizzes.into_iter().filter_map(|q| {
+            let due = parse_time(q.end_date.as_deref()?);
+            Some(Deadline {
+                id: q.id,
+                module_code: module_code.to_owned(),
+                title: q.title,
+                kind: DeadlineKind::Quiz,
+                due,
+            })
+        });
+        Ok(assignments.chain(quizzes).collect())
+    }
+
+    pub fn to_event(&self) -> Event {
+        Event {
+            uid: ical::uid(&self.kind.name().to_lowercase(), &self.id),
+            summary: format!("{} {}: {}", self.module_code, self.kind.name(), self.title),
+            description: None,
+            start: self.due,
+            end: None,
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::moves::Fingerprint;
+use crate::util::walk_files;
+
+// Files under a mirror that have the same content, e.g. slides posted to two modules
+pub struct Duplicates {
+    pub size: u64,
+    pub paths: Vec<PathBuf>,
+}
+
+impl Duplicates {
+    // what every copy but one takes up
+    pub fn wasted(&self) -> u64 {
+        self.size * (self.paths.len() as u64).saturating_sub(1)
+    }
+}
+
+// Files that are already hard links of each other take no extra space, so they count as one
+#[cfg(unix)]
+fn file_id(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+// Sets of files under `dir` with the same content, most space wasted first. Only files that share a
+// size are hashed. Empty files are left out, since they waste nothing.
+pub fn find_duplicates(dir: &Path) -> Vec<Duplicates> {
+    let mut sizes = HashMap::<u64, Vec<PathBuf>>::new();
+    let mut seen = HashSet::new();
+    walk_files(dir, &mut |path, metadata| {
+        if metadata.len() == 0 {
+            return;
+        }
+        if let Some(id) = file_id(&metadata) {
+            if !seen.insert(id) {
+                return;
+            }
+        }
+        sizes.entry(metadata.len()).or_default().push(path);
+    });
+    let mut duplicates = sizes
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .flat_map(|(size, paths)| {
+            let mut by_content = HashMap::<String, Vec<PathBuf>>::new();
+            for path in paths {
+                if let Ok(fingerprint) = Fingerprint::of(&path) {
+                    by_content.entry(fingerprint.sha256).or_default().push(path);
+                }
+            }
+            by_content
+                .into_iter()
+                .filter(|(_, paths)| paths.len() > 1)
+                .map(move |(_, mut paths)| {
+                    paths.sort();
+                    Duplicates { size, paths }
+                })
+        })
+        .collect::<Vec<_>>();
+    duplicates.sort_by(|a, b| b.wasted().cmp(&a.wasted()).then(a.paths.cmp(&b.paths)));
+    duplicates
+}
+
+// Replaces every copy with a hard link to the newest one. Its last modified time is then what every copy
+// has, which is no older than what they had, so syncs still see them as up to date. Each copy is swapped
+// out in one rename, so it is never missing, even if this is interrupted. Returns how many were replaced.
+pub fn link_duplicates(duplicates: &Duplicates) -> io::Result<usize> {
+    let modified = |path: &PathBuf| {
+        fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    };
+    let original = match duplicates.paths.iter().max_by_key(|path| modified(path)) {
+        Some(original) => original,
+        None => return Ok(0),
+    };
+    let mut linked = 0;
+    for copy in duplicates.paths.iter().filter(|path| *path != original) {
+        let mut temp_name = OsString::from("~!");
+        temp_name.push(copy.file_name().unwrap_or_default());
+        let temp_path = copy.with_file_name(temp_name);
+        fs::hard_link(original, &temp_path)?;
+        if let Err(e) = fs::rename(&temp_path, copy) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(e);
+        }
+        linked += 1;
+    }
+    Ok(linked)
+}
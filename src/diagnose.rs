@@ -0,0 +1,98 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::replay::{redact_fields, REDACTED};
+use crate::Result;
+
+// Settings in config files that may hold credentials: the API subscription key, and extra
+// headers, which are often cookies or tokens for a campus proxy. JSON field names are compared
+// in lowercase, options in profiles as written.
+const CONFIG_SECRET_FIELDS: &[&str] = &["subscriptionkey", "headers", "password"];
+const CONFIG_SECRET_OPTIONS: &[&str] = &["header"];
+
+// Files for attaching to a bug report, scrubbed of credentials as they are added
+pub struct Bundle {
+    // e.g. the username and password, removed wherever they appear
+    secrets: Vec<String>,
+    entries: Vec<(String, String)>,
+}
+
+impl Bundle {
+    pub fn new(secrets: Vec<String>) -> Bundle {
+        Bundle {
+            secrets: secrets
+                .into_iter()
+                .filter(|secret| !secret.is_empty())
+                .collect(),
+            entries: vec![],
+        }
+    }
+
+    pub fn add(&mut self, name: &str, content: &str) {
+        let mut content = content.to_owned();
+        for secret in &self.secrets {
+            content = content.replace(secret.as_str(), REDACTED);
+        }
+        self.entries.push((name.to_owned(), content));
+    }
+
+    // adds the file if there is one, keeping only its last `max_lines` lines
+    pub fn add_file(&mut self, name: &str, path: &Path, max_lines: usize) {
+        if let Ok(content) = fs::read_to_string(path) {
+            let lines = content.lines().collect::<Vec<_>>();
+            let start = lines.len().saturating_sub(max_lines);
+            self.add(name, &lines[start..].join("\n"));
+        }
+    }
+
+    // adds a JSON or `option = value` config file if there is one, without the settings that may hold credentials
+    pub fn add_config_file(&mut self, name: &str, path: &Path) {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(_) => return,
+        };
+        let content = match serde_json::from_str::<Value>(&content) {
+            Ok(mut json) => {
+                redact_fields(&mut json, CONFIG_SECRET_FIELDS);
+                serde_json::to_string_pretty(&json).unwrap_or_default()
+            }
+            Err(_) => content
+                .lines()
+                .map(|line| match line.split_once('=') {
+                    Some((option, _))
+                        if CONFIG_SECRET_OPTIONS
+                            .contains(&option.trim().trim_start_matches('-')) =>
+                    {
+                        format!("{}= {}", option, REDACTED)
+                    }
+                    _ => line.to_owned(),
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        };
+        self.add(name, &content);
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|(name, _)| name.as_str())
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let file = fs::File::create(path).map_err(|_| "Unable to create diagnostics file")?;
+        let mut zip = zip::ZipWriter::new(file);
+        let options =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        for (name, content) in &self.entries {
+            zip.start_file(name.as_str(), options)
+                .map_err(|_| "Unable to write diagnostics file")?;
+            zip.write_all(content.as_bytes())
+                .map_err(|_| "Unable to write diagnostics file")?;
+        }
+        zip.finish()
+            .map_err(|_| "Unable to write diagnostics file")?;
+        Ok(())
+    }
+}
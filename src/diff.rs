@@ -0,0 +1,211 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::util::extension_of;
+use crate::Result;
+
+const TEXT_EXTENSIONS: &[&str] = &[
+    "txt", "md", "csv", "tsv", "json", "xml", "html", "htm", "tex",
+];
+const PAGED_EXTENSIONS: &[&str] = &["pdf", "pptx"];
+// the line diff below is quadratic, so don't bother with anything that is absurdly long
+const MAX_DIFF_LINES: usize = 5000;
+const DIFF_CONTEXT: usize = 3;
+
+pub struct ContentDiff {
+    pub old_size: u64,
+    pub new_size: u64,
+    pub old_pages: Option<usize>,
+    pub new_pages: Option<usize>,
+    pub text_diff: Option<String>,
+}
+
+pub fn is_diffable(path: &Path) -> bool {
+    extension_of(path)
+        .map(|ext| {
+            TEXT_EXTENSIONS.contains(&ext.as_str()) || PAGED_EXTENSIONS.contains(&ext.as_str())
+        })
+        .unwrap_or(false)
+}
+
+impl ContentDiff {
+    pub async fn between(old: &Path, new: &Path) -> Result<ContentDiff> {
+        let old_content = tokio::fs::read(old)
+            .await
+            .map_err(|_| "Unable to read previous version of file")?;
+        let new_content = tokio::fs::read(new)
+            .await
+            .map_err(|_| "Unable to read updated file")?;
+        let ext = extension_of(new);
+        let (old_pages, new_pages) = match ext.as_deref() {
+            Some("pdf") => (count_pdf_pages(&old_content), count_pdf_pages(&new_content)),
+            Some("pptx") => (
+                Some(count_pptx_slides(&old_content)),
+                Some(count_pptx_slides(&new_content)),
+            ),
+            _ => (None, None),
+        };
+        let text_diff = match ext.as_deref() {
+            Some(ext) if TEXT_EXTENSIONS.contains(&ext) => unified_diff(
+                &String::from_utf8_lossy(&old_content),
+                &String::from_utf8_lossy(&new_content),
+            ),
+            _ => None,
+        };
+        Ok(ContentDiff {
+            old_size: old_content.len() as u64,
+            new_size: new_content.len() as u64,
+            old_pages,
+            new_pages,
+            text_diff,
+        })
+    }
+
+    pub fn size_delta(&self) -> i64 {
+        self.new_size as i64 - self.old_size as i64
+    }
+
+    pub fn page_delta(&self) -> Option<i64> {
+        match (self.old_pages, self.new_pages) {
+            (Some(old), Some(new)) => Some(new as i64 - old as i64),
+            _ => None,
+        }
+    }
+}
+
+// None if the PDF can't be parsed
+#[cfg(any(feature = "pdf", feature = "extract"))]
+fn count_pdf_pages(content: &[u8]) -> Option<usize> {
+    lopdf::Document::load_mem(content)
+        .ok()
+        .map(|document| document.get_pages().len())
+}
+
+// without lopdf, pages can't be counted
+#[cfg(not(any(feature = "pdf", feature = "extract")))]
+fn count_pdf_pages(_content: &[u8]) -> Option<usize> {
+    None
+}
+
+// A PPTX file is a zip archive with one "ppt/slides/slideN.xml" entry per slide.
+// Every entry name appears in both the local header and the central directory, hence the set.
+fn count_pptx_slides(content: &[u8]) -> usize {
+    const PREFIX: &[u8] = b"ppt/slides/slide";
+    let mut slides = HashSet::new();
+    let mut i = 0;
+    while let Some(offset) = find(&content[i..], PREFIX) {
+        i += offset + PREFIX.len();
+        let digits = content[i..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit())
+            .count();
+        if digits > 0 && content[i + digits..].starts_with(b".xml") {
+            slides.insert(content[i..i + digits].to_vec());
+        }
+    }
+    slides.len()
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+enum DiffLine<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+pub fn unified_diff(old: &str, new: &str) -> Option<String> {
+    let old_lines = old.lines().collect::<Vec<_>>();
+    let new_lines = new.lines().collect::<Vec<_>>();
+    if old_lines.len() > MAX_DIFF_LINES || new_lines.len() > MAX_DIFF_LINES {
+        return None;
+    }
+
+    // classic longest common subsequence table, filled from the back
+    let (n, m) = (old_lines.len(), new_lines.len());
+    let mut lcs = vec![vec![0u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if old_lines[i] == new_lines[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && old_lines[i] == new_lines[j] {
+            lines.push(DiffLine::Same(old_lines[i]));
+            i += 1;
+            j += 1;
+        } else if i < n && (j == m || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(DiffLine::Removed(old_lines[i]));
+            i += 1;
+        } else {
+            lines.push(DiffLine::Added(new_lines[j]));
+            j += 1;
+        }
+    }
+
+    if lines.iter().all(|line| matches!(line, DiffLine::Same(_))) {
+        return None;
+    }
+    Some(format_hunks(&lines))
+}
+
+fn format_hunks(lines: &[DiffLine]) -> String {
+    let changed = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, DiffLine::Same(_)))
+        .map(|(idx, _)| idx)
+        .collect::<Vec<_>>();
+
+    // group changes that are close enough to share their context lines
+    let mut ranges: Vec<(usize, usize)> = vec![];
+    for idx in changed {
+        let start = idx.saturating_sub(DIFF_CONTEXT);
+        let end = (idx + DIFF_CONTEXT + 1).min(lines.len());
+        match ranges.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => ranges.push((start, end)),
+        }
+    }
+
+    let mut output = String::new();
+    for (start, end) in ranges {
+        let (old_before, new_before) = count_sides(&lines[..start]);
+        let (old_len, new_len) = count_sides(&lines[start..end]);
+        output.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_before + 1,
+            old_len,
+            new_before + 1,
+            new_len
+        ));
+        for line in &lines[start..end] {
+            match line {
+                DiffLine::Same(l) => output.push_str(&format!(" {}\n", l)),
+                DiffLine::Removed(l) => output.push_str(&format!("-{}\n", l)),
+                DiffLine::Added(l) => output.push_str(&format!("+{}\n", l)),
+            }
+        }
+    }
+    output
+}
+
+// returns how many of the lines belong to the old and the new version respectively
+fn count_sides(lines: &[DiffLine]) -> (usize, usize) {
+    lines.iter().fold((0, 0), |(old, new), line| match line {
+        DiffLine::Same(_) => (old + 1, new + 1),
+        DiffLine::Removed(_) => (old + 1, new),
+        DiffLine::Added(_) => (old, new + 1),
+    })
+}
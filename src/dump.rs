@@ -0,0 +1,156 @@
+use crate::Result;
+
+// An API endpoint that fluminurs reads, by a short name, so that `api-dump` can fetch it as is.
+// {module} is filled in with a module's id, and {id} with the id of a folder, channel or group.
+pub struct KnownEndpoint {
+    pub name: &'static str,
+    path: &'static str,
+    // Whether fluminurs has been seen working against this endpoint on LumiNUS itself, as the ones
+    // that announcements, files and multimedia come from have. The others were written from how
+    // those look, without an account to check them against, and only the fake server answers them.
+    pub verified: bool,
+}
+
+pub const ENDPOINTS: &[KnownEndpoint] = &[
+    KnownEndpoint {
+        name: "profile",
+        path: "user/Profile",
+        verified: true,
+    },
+    KnownEndpoint {
+        name: "term",
+        path: "setting/AcademicWeek/current?populate=termDetail",
+        verified: true,
+    },
+    KnownEndpoint {
+        name: "modules",
+        path: "module",
+        verified: true,
+    },
+    KnownEndpoint {
+        name: "messages",
+        path: "message/inbox?sortby=createdDate%20ASC",
+        verified: false,
+    },
+    KnownEndpoint {
+        name: "calendar",
+        path: "calendar/event?sortby=startDate%20ASC",
+        verified: false,
+    },
+    KnownEndpoint {
+        name: "module",
+        path: "module/{module}",
+        verified: false,
+    },
+    KnownEndpoint {
+        name: "tools",
+        path: "module/{module}/tools",
+        verified: false,
+    },
+    KnownEndpoint {
+        name: "announcements",
+        path: "announcement/NonArchived/{module}?sortby=displayFrom%20ASC",
+        verified: true,
+    },
+    KnownEndpoint {
+        name: "archived-announcements",
+        path: "announcement/Archived/{module}?sortby=displayFrom%20ASC",
+        verified: true,
+    },
+    KnownEndpoint {
+        name: "workbin",
+        path: "files/?ParentID={module}",
+        verified: true,
+    },
+    KnownEndpoint {
+        name: "multimedia",
+        path: "multimedia/?ParentID={module}",
+        verified: true,
+    },
+    KnownEndpoint {
+        name: "quizzes",
+        path: "quiz/?ParentID={module}",
+        verified: false,
+    },
+    KnownEndpoint {
+        name: "gradebook",
+        path: "gradebook/?ParentID={module}",
+        verified: false,
+    },
+    KnownEndpoint {
+        name: "assessments",
+        path: "module/{module}/assessment",
+        verified: false,
+    },
+    KnownEndpoint {
+        name: "lessons",
+        path: "lessonplan/Lesson/?ModuleID={module}",
+        verified: false,
+    },
+    KnownEndpoint {
+        name: "groups",
+        path: "group/{module}/mygroups",
+        verified: false,
+    },
+    KnownEndpoint {
+        name: "roster",
+        path: "classroster/{module}/students",
+        verified: false,
+    },
+    KnownEndpoint {
+        name: "folders",
+        path: "files/?ParentID={id}",
+        verified: true,
+    },
+    KnownEndpoint {
+        name: "files",
+        path: "files/{id}/file",
+        verified: true,
+    },
+    KnownEndpoint {
+        name: "download-url",
+        path: "files/file/{id}/downloadurl",
+        verified: true,
+    },
+    KnownEndpoint {
+        name: "medias",
+        path: "multimedia/{id}/medias",
+        verified: true,
+    },
+    KnownEndpoint {
+        name: "peer-evaluations-submitted",
+        path: "peerevaluation/{module}/submitted",
+        verified: false,
+    },
+    KnownEndpoint {
+        name: "peer-evaluations-received",
+        path: "peerevaluation/{module}/received",
+        verified: false,
+    },
+    KnownEndpoint {
+        name: "group-members",
+        path: "group/{id}/members",
+        verified: false,
+    },
+];
+
+impl KnownEndpoint {
+    pub fn find(name: &str) -> Option<&'static KnownEndpoint> {
+        ENDPOINTS.iter().find(|endpoint| endpoint.name == name)
+    }
+
+    pub fn needs_module(&self) -> bool {
+        self.path.contains("{module}")
+    }
+
+    pub fn path(&self, module_id: Option<&str>, id: Option<&str>) -> Result<String> {
+        let mut path = self.path.to_owned();
+        if self.needs_module() {
+            path = path.replace("{module}", module_id.ok_or("This endpoint needs --module")?);
+        }
+        if path.contains("{id}") {
+            path = path.replace("{id}", id.ok_or("This endpoint needs --id")?);
+        }
+        Ok(path)
+    }
+}
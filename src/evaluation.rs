@@ -0,0 +1,95 @@
+use futures_util::future;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+
+use crate::util::html_to_text;
+use crate::{Api, ApiData, Result};
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EvaluationAnswer {
+    pub question: String,
+    pub answer: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerEvaluation {
+    pub id: String,
+    pub title: String,
+    pub evaluator_name: Option<String>,
+    pub evaluatee_name: Option<String>,
+    pub submitted_date: Option<String>,
+    #[serde(default)]
+    pub answers: Vec<EvaluationAnswer>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PeerEvaluations {
+    pub submitted: Vec<PeerEvaluation>,
+    pub received: Vec<PeerEvaluation>,
+}
+
+impl PeerEvaluations {
+    pub(crate) async fn load(api: &Api, module_id: &str) -> Result<PeerEvaluations> {
+        let (submitted, received) = future::join(
+            Self::load_direction(api, module_id, "submitted"),
+            Self::load_direction(api, module_id, "received"),
+        )
+        .await;
+        Ok(PeerEvaluations {
+            submitted: submitted?,
+            received: received?,
+        })
+    }
+
+    async fn load_direction(
+        api: &Api,
+        module_id: &str,
+        direction: &str,
+    ) -> Result<Vec<PeerEvaluation>> {
+        let resp = api
+            .api_as_json::<ApiData<Vec<PeerEvaluation>>>(
+                &format!("peerevaluation/{}/{}", module_id, direction),
+                Method::GET,
+                None,
+            )
+            .await?;
+        resp.data
+            .ok_or("Invalid API response from server: type mismatch")
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.submitted.is_empty() && self.received.is_empty()
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).map_err(|_| "Unable to serialise peer evaluations")
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut output = String::new();
+        for (heading, evaluations) in
+            &[("Submitted", &self.submitted), ("Received", &self.received)]
+        {
+            output.push_str(&format!("# {}\n", heading));
+            for evaluation in evaluations.iter() {
+                output.push_str(&format!(
+                    "\n## {} ({} -> {})\n\n",
+                    evaluation.title,
+                    evaluation.evaluator_name.as_deref().unwrap_or("Anonymous"),
+                    evaluation.evaluatee_name.as_deref().unwrap_or("Unknown")
+                ));
+                for answer in &evaluation.answers {
+                    output.push_str(&format!(
+                        "**{}**\n\n{}\n\n",
+                        html_to_text(&answer.question),
+                        html_to_text(answer.answer.as_deref().unwrap_or("(no answer)"))
+                    ));
+                }
+            }
+            output.push('\n');
+        }
+        output
+    }
+}
@@ -0,0 +1,88 @@
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use lopdf::Document;
+
+use crate::util::{extension_of, natural_cmp};
+use crate::Result;
+
+pub fn is_extractable(path: &Path) -> bool {
+    matches!(extension_of(path).as_deref(), Some("pdf") | Some("pptx"))
+}
+
+// "Lecture 1.pdf" => "Lecture 1.pdf.txt", so that sidecars of files differing only in extension don't clash
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".txt");
+    path.with_file_name(name)
+}
+
+// Writes the text content of a PDF or PPTX file to its sidecar.
+// Returns whether a sidecar was written.
+pub fn write_sidecar(path: &Path) -> Result<bool> {
+    let text = match extension_of(path).as_deref() {
+        Some("pdf") => extract_pdf_text(path)?,
+        Some("pptx") => extract_pptx_text(path)?,
+        _ => return Ok(false),
+    };
+    fs::write(sidecar_path(path), text).map_err(|_| "Unable to write text sidecar")?;
+    Ok(true)
+}
+
+fn extract_pdf_text(path: &Path) -> Result<String> {
+    let document = Document::load(path).map_err(|_| "Unable to parse PDF")?;
+    let pages = document.get_pages().keys().copied().collect::<Vec<_>>();
+    // pages that we can't decode (e.g. scanned images) are simply left empty
+    Ok(pages
+        .iter()
+        .map(|page| document.extract_text(&[*page]).unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("\x0c\n"))
+}
+
+fn extract_pptx_text(path: &Path) -> Result<String> {
+    let file = fs::File::open(path).map_err(|_| "Unable to open file")?;
+    let mut archive = zip::ZipArchive::new(file).map_err(|_| "Unable to parse PPTX")?;
+    let mut slide_names = archive
+        .file_names()
+        .filter(|name| name.starts_with("ppt/slides/slide") && name.ends_with(".xml"))
+        .map(|name| name.to_owned())
+        .collect::<Vec<_>>();
+    slide_names.sort_by(|n1, n2| natural_cmp(n1, n2));
+
+    let mut slides = vec![];
+    for name in slide_names {
+        let mut xml = String::new();
+        archive
+            .by_name(&name)
+            .map_err(|_| "Unable to parse PPTX")?
+            .read_to_string(&mut xml)
+            .map_err(|_| "Unable to read slide from PPTX")?;
+        slides.push(extract_text_runs(&xml));
+    }
+    Ok(slides.join("\x0c\n"))
+}
+
+// Slide text lives in <a:t> runs, one paragraph per <a:p>
+fn extract_text_runs(xml: &str) -> String {
+    let mut output = String::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start..];
+        if rest.starts_with("</a:p>") || rest.starts_with("<a:br") {
+            output.push('\n');
+        }
+        if rest.starts_with("<a:t>") {
+            rest = &rest["<a:t>".len()..];
+            let end = rest.find("</a:t>").unwrap_or(rest.len());
+            output.push_str(
+                &htmlescape::decode_html(&rest[..end]).unwrap_or_else(|_| rest[..end].to_owned()),
+            );
+            rest = &rest[end..];
+        } else {
+            rest = &rest[1..];
+        }
+    }
+    output
+}
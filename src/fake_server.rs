@@ -0,0 +1,444 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::{Endpoints, Result};
+
+const TOKEN: &str = "fake-token";
+const WRONG_PASSWORD: &str = "wrong";
+
+// A stand-in for LumiNUS and its ADFS login, for testing syncs end to end without real credentials.
+// Like the mirror browser, it speaks just enough HTTP/1.1 to be useful.
+pub struct FakeLuminus {
+    address: SocketAddr,
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FakeModule {
+    pub id: String,
+    pub code: String,
+    pub name: String,
+    pub term: String,
+    // the module's workbin, whose id is the module id
+    pub workbin: FakeFolder,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FakeFolder {
+    pub id: String,
+    pub name: String,
+    pub last_updated: String,
+    pub files: Vec<FakeFile>,
+    pub folders: Vec<FakeFolder>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FakeFile {
+    pub id: String,
+    pub name: String,
+    pub last_updated: String,
+    pub content: Vec<u8>,
+}
+
+struct State {
+    base_url: String,
+    current_term: String,
+    modules: Vec<FakeModule>,
+    // path prefix => how many more connections to drop without answering
+    failures: HashMap<String, usize>,
+    // path prefix => how many more requests to answer with 429, and the Retry-After to send with it
+    throttles: HashMap<String, (usize, u64)>,
+    // method and target of every request, in the order they arrived
+    requests: Vec<String>,
+}
+
+struct Request {
+    method: String,
+    target: String,
+    authorization: Option<String>,
+    range: Option<String>,
+    body: String,
+}
+
+impl FakeModule {
+    pub fn new(id: &str, code: &str, name: &str, term: &str) -> FakeModule {
+        FakeModule {
+            id: id.to_owned(),
+            code: code.to_owned(),
+            name: name.to_owned(),
+            term: term.to_owned(),
+            workbin: FakeFolder {
+                id: id.to_owned(),
+                ..FakeFolder::default()
+            },
+        }
+    }
+}
+
+impl FakeFolder {
+    fn find(&self, id: &str) -> Option<&FakeFolder> {
+        if self.id == id {
+            return Some(self);
+        }
+        self.folders.iter().find_map(|folder| folder.find(id))
+    }
+
+    fn find_mut(&mut self, id: &str) -> Option<&mut FakeFolder> {
+        if self.id == id {
+            return Some(self);
+        }
+        self.folders
+            .iter_mut()
+            .find_map(|folder| folder.find_mut(id))
+    }
+
+    fn find_file(&self, id: &str) -> Option<&FakeFile> {
+        self.files
+            .iter()
+            .find(|file| file.id == id)
+            .or_else(|| self.folders.iter().find_map(|folder| folder.find_file(id)))
+    }
+
+    fn find_file_mut(&mut self, id: &str) -> Option<&mut FakeFile> {
+        if let Some(index) = self.files.iter().position(|file| file.id == id) {
+            return self.files.get_mut(index);
+        }
+        self.folders
+            .iter_mut()
+            .find_map(|folder| folder.find_file_mut(id))
+    }
+}
+
+impl FakeLuminus {
+    // listens on a free port on localhost until the returned server is dropped along with the runtime
+    pub async fn start(current_term: &str, modules: Vec<FakeModule>) -> Result<FakeLuminus> {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .map_err(|_| "Unable to listen on address")?;
+        let address = listener
+            .local_addr()
+            .map_err(|_| "Unable to listen on address")?;
+        let state = Arc::new(Mutex::new(State {
+            base_url: format!("http://{}", address),
+            current_term: current_term.to_owned(),
+            modules,
+            failures: HashMap::new(),
+            throttles: HashMap::new(),
+            requests: vec![],
+        }));
+        let server_state = state.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = match listener.accept().await {
+                    Ok(connection) => connection,
+                    Err(_) => continue,
+                };
+                let state = server_state.clone();
+                tokio::spawn(async move {
+                    let _ = handle_connection(stream, &state).await;
+                });
+            }
+        });
+        Ok(FakeLuminus { address, state })
+    }
+
+    // endpoints that point every part of the client at this server
+    pub fn endpoints(&self) -> Endpoints {
+        let base_url = format!("http://{}", self.address);
+        Endpoints {
+            api_base_url: format!("{}/v2/api/", base_url),
+            adfs_oauth2_url: format!("{}/adfs/oauth2/authorize", base_url),
+            adfs_redirect_uri: format!("{}/auth/callback", base_url),
+            web_base_url: format!("{}/", base_url),
+            ..Endpoints::default()
+        }
+    }
+
+    // drops the next `times` connections for paths starting with `path_prefix`, e.g. "/download/"
+    // or "/v2/api/module", to exercise the client's retries
+    pub fn fail_next(&self, path_prefix: &str, times: usize) {
+        self.state
+            .lock()
+            .unwrap()
+            .failures
+            .insert(path_prefix.to_owned(), times);
+    }
+
+    // answers the next `times` requests for paths starting with `path_prefix` with 429 Too Many
+    // Requests, asking the client to come back after `retry_after` seconds
+    pub fn throttle_next(&self, path_prefix: &str, times: usize, retry_after: u64) {
+        self.state
+            .lock()
+            .unwrap()
+            .throttles
+            .insert(path_prefix.to_owned(), (times, retry_after));
+    }
+
+    // gives a file new content, as if a lecturer had uploaded a new version
+    pub fn update_file(&self, id: &str, content: &[u8], last_updated: &str) {
+        let mut state = self.state.lock().unwrap();
+        for module in &mut state.modules {
+            if let Some(file) = module.workbin.find_file_mut(id) {
+                file.content = content.to_owned();
+                file.last_updated = last_updated.to_owned();
+            }
+        }
+    }
+
+    pub fn add_file(&self, folder_id: &str, file: FakeFile) {
+        let mut state = self.state.lock().unwrap();
+        for module in &mut state.modules {
+            if let Some(folder) = module.workbin.find_mut(folder_id) {
+                folder.files.push(file);
+                return;
+            }
+        }
+    }
+
+    pub fn requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().requests.clone()
+    }
+}
+
+async fn read_request(stream: &mut TcpStream) -> std::io::Result<Option<Request>> {
+    let mut buffer = vec![];
+    let mut chunk = [0u8; 8192];
+    let header_end = loop {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+        if let Some(end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end + 4;
+        }
+    };
+    let head = String::from_utf8_lossy(&buffer[..header_end]).into_owned();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_owned();
+    let target = request_line.next().unwrap_or_default().to_owned();
+    let header = |name: &str| {
+        head.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            if key.trim().eq_ignore_ascii_case(name) {
+                Some(value.trim().to_owned())
+            } else {
+                None
+            }
+        })
+    };
+    let content_length = header("content-length")
+        .and_then(|length| length.parse::<usize>().ok())
+        .unwrap_or(0);
+    while buffer.len() < header_end + content_length {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            break;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+    Ok(Some(Request {
+        method,
+        target,
+        authorization: header("authorization"),
+        range: header("range"),
+        body: String::from_utf8_lossy(&buffer[header_end..]).into_owned(),
+    }))
+}
+
+async fn handle_connection(mut stream: TcpStream, state: &Mutex<State>) -> std::io::Result<()> {
+    let request = match read_request(&mut stream).await? {
+        Some(request) => request,
+        None => return Ok(()),
+    };
+    let (status, headers, body) = {
+        let mut state = state.lock().unwrap();
+        state
+            .requests
+            .push(format!("{} {}", request.method, request.target));
+        let failing = state
+            .failures
+            .iter_mut()
+            .find(|(prefix, remaining)| request.target.starts_with(*prefix) && **remaining > 0);
+        if let Some((_, remaining)) = failing {
+            // hang up without a response, which is what a flaky network looks like to the client
+            *remaining -= 1;
+            return Ok(());
+        }
+        let throttled = state
+            .throttles
+            .iter_mut()
+            .find(|(prefix, (remaining, _))| request.target.starts_with(*prefix) && *remaining > 0);
+        if let Some((_, (remaining, retry_after))) = throttled {
+            *remaining -= 1;
+            (
+                "429 Too Many Requests",
+                vec![("Retry-After", retry_after.to_string())],
+                vec![],
+            )
+        } else {
+            respond(&state, &request)
+        }
+    };
+    let mut head = format!(
+        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        status,
+        body.len()
+    );
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&body).await?;
+    stream.shutdown().await
+}
+
+// e.g. bytes=0-99, which is all that downloads ask for
+fn byte_range(range: &str) -> Option<(usize, usize)> {
+    let (start, end) = range.strip_prefix("bytes=")?.split_once('-')?;
+    Some((start.parse().ok()?, end.parse().ok()?))
+}
+
+fn respond(
+    state: &State,
+    request: &Request,
+) -> (&'static str, Vec<(&'static str, String)>, Vec<u8>) {
+    let json_response = |value: Value| {
+        (
+            "200 OK",
+            vec![("Content-Type", "application/json".to_owned())],
+            value.to_string().into_bytes(),
+        )
+    };
+    let not_found = ("404 Not Found", vec![], vec![]);
+    let (path, query) = match request.target.split_once('?') {
+        Some((path, query)) => (path, query),
+        None => (request.target.as_str(), ""),
+    };
+
+    if path == "/adfs/oauth2/authorize" {
+        if request.method != "POST" {
+            return ("405 Method Not Allowed", vec![], vec![]);
+        }
+        let form = serde_urlencoded::from_str::<HashMap<String, String>>(&request.body)
+            .unwrap_or_default();
+        if form.get("Password").map(String::as_str) == Some(WRONG_PASSWORD) {
+            return (
+                "200 OK",
+                vec![("Content-Type", "text/html".to_owned())],
+                b"<span id=\"errorText\">Incorrect user ID or password.</span>".to_vec(),
+            );
+        }
+        let location = format!("{}/auth/callback?code=fake-code", state.base_url);
+        return ("302 Found", vec![("Location", location)], vec![]);
+    }
+    if path == "/auth/callback" {
+        return ("200 OK", vec![], b"Logged in".to_vec());
+    }
+    if let Some(id) = path.strip_prefix("/download/") {
+        return match state
+            .modules
+            .iter()
+            .find_map(|module| module.workbin.find_file(id))
+        {
+            Some(file) => match request.range.as_deref().and_then(byte_range) {
+                Some((start, end)) if start <= end && end < file.content.len() => (
+                    "206 Partial Content",
+                    vec![(
+                        "Content-Range",
+                        format!("bytes {}-{}/{}", start, end, file.content.len()),
+                    )],
+                    file.content[start..=end].to_vec(),
+                ),
+                Some(_) => ("416 Range Not Satisfiable", vec![], vec![]),
+                None => ("200 OK", vec![], file.content.clone()),
+            },
+            None => not_found,
+        };
+    }
+    let api_path = match path.strip_prefix("/v2/api/") {
+        Some(api_path) => api_path,
+        None => return not_found,
+    };
+    if api_path == "login/adfstoken" {
+        return json_response(json!({ "access_token": TOKEN }));
+    }
+    if request.authorization.as_deref() != Some(&format!("Bearer {}", TOKEN)) {
+        return ("401 Unauthorized", vec![], vec![]);
+    }
+
+    let find_folder = |id: &str| {
+        state
+            .modules
+            .iter()
+            .find_map(|module| module.workbin.find(id))
+    };
+    let segments = api_path.split('/').collect::<Vec<_>>();
+    match segments.as_slice() {
+        ["user", "Profile"] => json_response(json!({ "userNameOriginal": "Fake Student" })),
+        ["setting", "AcademicWeek", "current"] => {
+            json_response(json!({ "termDetail": { "term": state.current_term } }))
+        }
+        ["module"] => json_response(json!({
+            "data": state.modules.iter().map(|module| json!({
+                "id": module.id,
+                "name": module.code,
+                "courseName": module.name,
+                "term": module.term,
+                "access": {
+                    "access_Full": false,
+                    "access_Read": true,
+                    "access_Create": false,
+                    "access_Update": false,
+                    "access_Delete": false,
+                    "access_Settings_Read": false,
+                    "access_Settings_Update": false,
+                },
+            })).collect::<Vec<_>>()
+        })),
+        ["module", _, "tools"] => json_response(json!({
+            "data": [{ "toolName": "Files" }, { "toolName": "Multimedia" }]
+        })),
+        ["files", ""] => {
+            let parent_id = query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("ParentID="))
+                .unwrap_or_default();
+            match find_folder(parent_id) {
+                Some(folder) => json_response(json!({
+                    "data": folder.folders.iter().map(|subfolder| json!({
+                        "id": subfolder.id,
+                        "name": subfolder.name,
+                        "allowUpload": false,
+                        "lastUpdatedDate": subfolder.last_updated,
+                    })).collect::<Vec<_>>()
+                })),
+                None => not_found,
+            }
+        }
+        ["files", "file", id, "downloadurl"] => {
+            json_response(json!({ "data": format!("{}/download/{}", state.base_url, id) }))
+        }
+        ["files", id, "file"] => match find_folder(id) {
+            Some(folder) => json_response(json!({
+                "data": folder.files.iter().map(|file| json!({
+                    "id": file.id,
+                    "name": file.name,
+                    "fileName": file.name,
+                    "lastUpdatedDate": file.last_updated,
+                    "fileSize": file.content.len(),
+                })).collect::<Vec<_>>()
+            })),
+            None => not_found,
+        },
+        ["multimedia", ""] | ["announcement", ..] => json_response(json!({ "data": [] })),
+        _ => not_found,
+    }
+}
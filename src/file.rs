@@ -1,19 +1,27 @@
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
-use std::time::SystemTime;
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime};
 
 use async_trait::async_trait;
 use futures_util::future;
 use futures_util::future::{BoxFuture, FutureExt};
-use reqwest::{Method, Url};
-use serde::Deserialize;
-use tokio::io::AsyncWriteExt;
+use futures_util::StreamExt;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Method, StatusCode, Url};
+use serde::{Deserialize, Serialize};
 
 use crate::resource;
-use crate::resource::{OverwriteMode, OverwriteResult, Resource, RetryableError, RetryableResult};
+use crate::resource::{
+    GatedWriter, OverwriteMode, OverwriteResult, Resource, RetryableError, RetryableResult,
+};
+use crate::submission::Submission;
 use crate::util::{parse_time, sanitise_filename};
 use crate::{Api, ApiData, Result};
 
-#[derive(Debug, Deserialize)]
+const WRITE_BUFFER_SIZE: usize = 4 * 1024 * 1024;
+
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct ApiFileDirectory {
     id: String,
@@ -22,27 +30,191 @@ struct ApiFileDirectory {
     allow_upload: Option<bool>,
     creator_name: Option<String>,
     last_updated_date: String,
+    created_date: Option<String>,
+    file_size: Option<u64>,
 }
 
 pub struct DirectoryHandle {
     id: String,
+    // the module this folder belongs to, whose workbin shares its id
+    module_id: String,
     path: PathBuf,
     allow_upload: bool,
-    /* last_updated: SystemTime, */
+    // the folder's last updated date as listed by its parent. It moves when files are added or
+    // removed, but not always when a file in the folder is updated.
+    marker: Option<String>,
+    // the workbin itself is 0, and the folders at its top are 1
+    depth: usize,
 }
 
 pub struct File {
     id: String,
     path: PathBuf,
     last_updated: SystemTime,
+    size: Option<u64>,
+    submission: Option<Submission>,
+    permalink: String,
+}
+
+// What a folder held when it was last listed, with paths relative to the folder
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FolderSnapshot {
+    marker: String,
+    files: Vec<FileSnapshot>,
+    // where the folder was and which module it belongs to, so that --offline can list it.
+    // Snapshots from before these were kept have neither.
+    #[serde(default)]
+    path: Option<PathBuf>,
+    #[serde(default)]
+    module_id: Option<String>,
+    // whether the folder had no subfolders, which is what makes it safe to reuse.
+    // Snapshots from before this was kept held their subfolders' files as well, and aren't reused.
+    #[serde(default)]
+    leaf: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FileSnapshot {
+    id: String,
+    relative_path: PathBuf,
+    last_updated: SystemTime,
+    size: Option<u64>,
+    // snapshots from before permalinks were kept have none, so the folder page stands in
+    #[serde(default)]
+    permalink: Option<String>,
+}
+
+// Folder listings from the previous run, so that folders whose marker hasn't moved needn't be listed again.
+// Markers don't always move when a file is updated, so only folders without subfolders are reused,
+// and never those at the top of a workbin, where most updates happen. Each folder's snapshot holds
+// its own files. Only the folders seen in this run are kept, so deleted folders drop out on their own.
+pub struct FolderCache {
+    previous: HashMap<String, FolderSnapshot>,
+    current: Mutex<HashMap<String, FolderSnapshot>>,
+}
+
+impl FolderCache {
+    pub fn new(previous: HashMap<String, FolderSnapshot>) -> FolderCache {
+        FolderCache {
+            previous,
+            current: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn into_snapshots(self) -> HashMap<String, FolderSnapshot> {
+        self.current
+            .into_inner()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn reuse(&self, id: &str, marker: &str, path: &Path, permalink: &str) -> Option<Vec<File>> {
+        let snapshot = self
+            .previous
+            .get(id)
+            .filter(|snapshot| snapshot.leaf && snapshot.marker == marker)?;
+        self.current
+            .lock()
+            .unwrap()
+            .insert(id.to_owned(), snapshot.clone());
+        Some(
+            snapshot
+                .files
+                .iter()
+                .map(|file| File {
+                    id: file.id.clone(),
+                    path: path.join(&file.relative_path),
+                    last_updated: file.last_updated,
+                    size: file.size,
+                    submission: None,
+                    permalink: file
+                        .permalink
+                        .clone()
+                        .unwrap_or_else(|| permalink.to_owned()),
+                })
+                .collect(),
+        )
+    }
+
+    fn remember(
+        &self,
+        id: &str,
+        module_id: &str,
+        marker: &str,
+        path: &Path,
+        files: &[File],
+        leaf: bool,
+    ) {
+        let files = files
+            .iter()
+            .filter_map(|file| {
+                Some(FileSnapshot {
+                    id: file.id.clone(),
+                    relative_path: file.path.strip_prefix(path).ok()?.to_owned(),
+                    last_updated: file.last_updated,
+                    size: file.size,
+                    permalink: Some(file.permalink.clone()),
+                })
+            })
+            .collect();
+        self.current.lock().unwrap().insert(
+            id.to_owned(),
+            FolderSnapshot {
+                marker: marker.to_owned(),
+                files,
+                path: Some(path.to_owned()),
+                module_id: Some(module_id.to_owned()),
+                leaf,
+            },
+        );
+    }
+}
+
+// Every file that the last run listed, along with the id of its module, for answering questions
+// without the server. Older snapshots held their subfolders' files as well, so files may turn up
+// more than once, and only the first is kept. Files at the top of a module's workbin aren't cached.
+pub fn cached_files(snapshots: &HashMap<String, FolderSnapshot>) -> Vec<(String, File)> {
+    let mut seen = HashSet::new();
+    let mut files = snapshots
+        .values()
+        .filter_map(|snapshot| {
+            Some((
+                snapshot.module_id.as_ref()?,
+                snapshot.path.as_ref()?,
+                &snapshot.files,
+            ))
+        })
+        .flat_map(|(module_id, path, files)| {
+            files.iter().map(move |file| {
+                (
+                    module_id.clone(),
+                    File {
+                        id: file.id.clone(),
+                        path: path.join(&file.relative_path),
+                        last_updated: file.last_updated,
+                        size: file.size,
+                        submission: None,
+                        permalink: file.permalink.clone().unwrap_or_default(),
+                    },
+                )
+            })
+        })
+        .filter(|(_, file)| seen.insert(file.id.clone()))
+        .collect::<Vec<_>>();
+    files.sort_by(|(_, a), (_, b)| a.path.cmp(&b.path));
+    files
 }
 
 impl DirectoryHandle {
     pub fn new(id: String, path: PathBuf) -> DirectoryHandle {
         DirectoryHandle {
+            module_id: id.clone(),
             id,
             path,
             allow_upload: false,
+            marker: None,
+            depth: 0,
         }
     }
 
@@ -51,10 +223,30 @@ impl DirectoryHandle {
         self,
         api: &'a Api,
         include_uploadable: bool,
+    ) -> BoxFuture<'a, Result<Vec<File>>> {
+        self.load_cached(api, include_uploadable, None)
+    }
+
+    // like `load`, but takes unchanged folders from the cache instead of walking them again
+    pub fn load_cached<'a>(
+        self,
+        api: &'a Api,
+        include_uploadable: bool,
+        cache: Option<&'a FolderCache>,
     ) -> BoxFuture<'a, Result<Vec<File>>> {
         debug_assert!(include_uploadable || !self.allow_upload);
 
         async move {
+            // submissions aren't cached, so uploadable folders are always listed afresh
+            let cache = cache.filter(|_| !self.allow_upload);
+            let folder_permalink =
+                api.web_url(&format!("modules/{}/files/{}", self.module_id, self.id));
+            if let (Some(cache), Some(marker), true) = (cache, &self.marker, self.depth > 1) {
+                if let Some(files) = cache.reuse(&self.id, marker, &self.path, &folder_permalink) {
+                    return Ok(files);
+                }
+            }
+
             let get_subdirs = || async {
                 let subdirs_resp = api
                     .api_as_json::<ApiData<Vec<ApiFileDirectory>>>(
@@ -64,22 +256,25 @@ impl DirectoryHandle {
                     )
                     .await?;
                 match subdirs_resp.data {
+                    Some(subdirs) if subdirs.is_empty() => Ok((vec![], true)),
                     Some(subdirs) => future::join_all(
                         subdirs
                             .into_iter()
                             .filter(|s| include_uploadable || !s.allow_upload.unwrap_or(false))
                             .map(|s| DirectoryHandle {
                                 id: s.id,
+                                module_id: self.module_id.clone(),
                                 path: self.path.join(Path::new(&sanitise_filename(&s.name))),
                                 allow_upload: s.allow_upload.unwrap_or(false),
-                                /* last_updated: parse_time(&s.last_updated_date), */
+                                marker: Some(s.last_updated_date),
+                                depth: self.depth + 1,
                             })
-                            .map(|dh| dh.load(api, include_uploadable)),
+                            .map(|dh| dh.load_cached(api, include_uploadable, cache)),
                     )
                     .await
                     .into_iter()
                     .collect::<Result<Vec<_>>>()
-                    .map(|v| v.into_iter().flatten().collect::<Vec<_>>()),
+                    .map(|v| (v.into_iter().flatten().collect::<Vec<_>>(), false)),
                     None => Err("Invalid API response from server: type mismatch"),
                 }
             };
@@ -103,25 +298,41 @@ impl DirectoryHandle {
                 match files_resp.data {
                     Some(files) => Ok(files
                         .into_iter()
-                        .map(|s| File {
-                            id: s.id,
-                            path: self.path.join({
-                                let name_for_download =
-                                    s.file_name.as_deref().unwrap_or(s.name.as_str());
-                                if self.allow_upload {
+                        .map(|s| {
+                            let name_for_download =
+                                s.file_name.as_deref().unwrap_or(s.name.as_str());
+                            let creator_name = s.creator_name.as_deref().unwrap_or("Unknown");
+                            let last_updated = parse_time(&s.last_updated_date);
+                            File {
+                                id: s.id.clone(),
+                                path: self.path.join(if self.allow_upload {
                                     sanitise_filename(
-                                        format!(
-                                            "{} - {}",
-                                            s.creator_name.as_deref().unwrap_or_else(|| "Unknown"),
-                                            name_for_download
-                                        )
-                                        .as_str(),
+                                        format!("{} - {}", creator_name, name_for_download)
+                                            .as_str(),
                                     )
                                 } else {
                                     sanitise_filename(name_for_download)
-                                }
-                            }),
-                            last_updated: parse_time(&s.last_updated_date),
+                                }),
+                                last_updated,
+                                size: s.file_size,
+                                submission: if self.allow_upload {
+                                    Some(Submission {
+                                        folder: self.path.clone(),
+                                        student: creator_name.to_owned(),
+                                        file_name: name_for_download.to_owned(),
+                                        size: s.file_size,
+                                        submitted: last_updated,
+                                        resubmitted: s
+                                            .created_date
+                                            .as_deref()
+                                            .map(|created| parse_time(created) != last_updated)
+                                            .unwrap_or(false),
+                                    })
+                                } else {
+                                    None
+                                },
+                                permalink: format!("{}?fileId={}", folder_permalink, s.id),
+                            }
                         })
                         .collect::<Vec<_>>()),
                     None => Err("Invalid API response from server: type mismatch"),
@@ -129,9 +340,20 @@ impl DirectoryHandle {
             };
 
             let (res_subdirs, res_files) = future::join(get_subdirs(), get_files()).await;
-            let mut files = res_subdirs?;
-            files.append(&mut res_files?);
+            let (mut files, leaf) = res_subdirs?;
+            let own_files = res_files?;
 
+            if let (Some(cache), Some(marker)) = (cache, &self.marker) {
+                cache.remember(
+                    &self.id,
+                    &self.module_id,
+                    marker,
+                    &self.path,
+                    &own_files,
+                    leaf,
+                );
+            }
+            files.extend(own_files);
             Ok(files)
         }
         .boxed()
@@ -144,6 +366,18 @@ impl Resource for File {
         &self.path
     }
 
+    fn last_updated(&self) -> SystemTime {
+        self.last_updated
+    }
+
+    fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    fn permalink(&self) -> Option<&str> {
+        Some(&self.permalink)
+    }
+
     async fn download(
         &self,
         api: &Api,
@@ -157,14 +391,34 @@ impl Resource for File {
             temp_destination,
             overwrite,
             self.last_updated,
+            resource::content_matches(api, destination, &self.path, self.size, || {
+                self.get_download_url(api)
+            })
+            .await,
             move |api| self.get_download_url(api),
-            move |api, url, temp_destination| Self::download_chunks(api, url, temp_destination),
+            Self::download_chunks,
         )
         .await
     }
 }
 
 impl File {
+    // e.g. notes => notes.pdf, for files uploaded without the extension their content calls for
+    pub fn with_extra_extension(self, extension: &str) -> File {
+        let mut name = self.path.file_name().unwrap_or_default().to_owned();
+        name.push(".");
+        name.push(extension);
+        File {
+            path: self.path.with_file_name(name),
+            ..self
+        }
+    }
+
+    // only files in uploadable folders are submissions
+    pub fn submission(&self) -> Option<&Submission> {
+        self.submission.as_ref()
+    }
+
     pub async fn get_download_url(&self, api: &Api) -> Result<Url> {
         let data = api
             .api_as_json::<ApiData<String>>(
@@ -180,30 +434,68 @@ impl File {
         }
     }
 
-    async fn download_chunks(
+    pub(crate) async fn download_chunks(
         api: &Api,
         download_url: reqwest::Url,
         temp_destination: &Path,
     ) -> RetryableResult<()> {
-        let mut file = tokio::fs::File::create(temp_destination)
+        let file = tokio::fs::File::create(temp_destination)
             .await
             .map_err(|_| RetryableError::Fail("Unable to open temporary file"))?;
-        let mut res = api
-            .get_client()
-            .get(download_url)
-            .send()
-            .await
-            .map_err(|_| RetryableError::Retry("Failed during download"))?;
-        while let Some(chunk) = res
-            .chunk()
-            .await
-            .map_err(|_| RetryableError::Retry("Failed during streaming"))?
-            .as_deref()
+        let url = download_url.to_string();
+        let started = Instant::now();
+        let res = match api.get_client().get(download_url).send().await {
+            Ok(res) => res,
+            Err(_) => {
+                api.stats().record_retry(&url);
+                return Err(RetryableError::Retry("Failed during download"));
+            }
+        };
+        api.stats().record_response(&url, started.elapsed());
+        if res.status() == StatusCode::TOO_MANY_REQUESTS
+            || res.status() == StatusCode::SERVICE_UNAVAILABLE
         {
-            file.write_all(chunk)
+            api.stats().record_retry(&url);
+            let retry_after = res
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(resource::parse_retry_after);
+            return Err(RetryableError::SlowDown(
+                "Server asked us to slow down",
+                retry_after,
+            ));
+        }
+        // an error page from the server, or from a mirror it redirected to, must not be saved as the file
+        if !res.status().is_success() {
+            api.stats().record_retry(&url);
+            return Err(RetryableError::Stale("Download URL returned an error"));
+        }
+        let mut received = 0;
+        let transfer_started = Instant::now();
+        // The response is streamed into large batches, so that many concurrent downloads don't
+        // turn into many small interleaved writes, which spinning disks hate. The batches come
+        // out of the shared memory budget, and never grow past what was reserved.
+        let (buffer_size, _reservation) = api.reserve_buffer(WRITE_BUFFER_SIZE).await;
+        let mut writer = GatedWriter::new(file.into_std().await, api.writer_gate(), buffer_size);
+        let mut stream = res.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|_| {
+                api.stats().record_retry(&url);
+                RetryableError::Retry("Failed during streaming")
+            })?;
+            received += chunk.len() as u64;
+            writer
+                .write(&chunk)
                 .await
                 .map_err(|_| RetryableError::Fail("Failed writing to disk"))?;
         }
+        writer
+            .finish()
+            .await
+            .map_err(|_| RetryableError::Fail("Failed writing to disk"))?;
+        api.stats()
+            .record_transfer(&url, received, transfer_started.elapsed());
         Ok(())
     }
 }
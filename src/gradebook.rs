@@ -0,0 +1,31 @@
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+
+use crate::{Api, ApiData, Result};
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GradebookItem {
+    pub id: String,
+    pub name: String,
+    pub max_mark: Option<f64>,
+    // percent of the final grade, where the lecturer has set it
+    pub weightage: Option<f64>,
+    // the student's own mark, in what students see of the gradebook once it is released
+    pub mark: Option<f64>,
+}
+
+impl GradebookItem {
+    pub(crate) async fn load(api: &Api, module_id: &str) -> Result<Vec<GradebookItem>> {
+        let items_resp = api
+            .api_as_json::<ApiData<Vec<GradebookItem>>>(
+                &format!("gradebook/?ParentID={}", module_id),
+                Method::GET,
+                None,
+            )
+            .await?;
+        items_resp
+            .data
+            .ok_or("Invalid API response from server: type mismatch")
+    }
+}
@@ -0,0 +1,105 @@
+use futures_util::future;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+
+use crate::util::csv_escape;
+use crate::{Api, ApiData, Result};
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiGroup {
+    id: String,
+    name: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupMember {
+    pub user_name_original: String,
+    pub email: Option<String>,
+}
+
+pub struct Group {
+    pub name: String,
+    pub members: Vec<GroupMember>,
+}
+
+impl Group {
+    pub(crate) async fn load_mine(api: &Api, module_id: &str) -> Result<Vec<Group>> {
+        let groups_resp = api
+            .api_as_json::<ApiData<Vec<ApiGroup>>>(
+                &format!("group/{}/mygroups", module_id),
+                Method::GET,
+                None,
+            )
+            .await?;
+        match groups_resp.data {
+            Some(groups) => {
+                future::join_all(groups.into_iter().map(|g| Self::load_members(api, g)))
+                    .await
+                    .into_iter()
+                    .collect::<Result<Vec<_>>>()
+            }
+            None => Err("Invalid API response from server: type mismatch"),
+        }
+    }
+
+    async fn load_members(api: &Api, group: ApiGroup) -> Result<Group> {
+        let members_resp = api
+            .api_as_json::<ApiData<Vec<GroupMember>>>(
+                &format!("group/{}/members", group.id),
+                Method::GET,
+                None,
+            )
+            .await?;
+        match members_resp.data {
+            Some(members) => Ok(Group {
+                name: group.name,
+                members,
+            }),
+            None => Err("Invalid API response from server: type mismatch"),
+        }
+    }
+}
+
+pub fn to_csv(groups: &[Group]) -> String {
+    let mut output = String::from("Group,Name,Email\n");
+    for group in groups {
+        for member in &group.members {
+            output.push_str(&format!(
+                "{},{},{}\n",
+                csv_escape(&group.name),
+                csv_escape(&member.user_name_original),
+                csv_escape(member.email.as_deref().unwrap_or(""))
+            ));
+        }
+    }
+    output
+}
+
+pub fn to_vcard(groups: &[Group]) -> String {
+    let mut output = String::new();
+    for group in groups {
+        for member in &group.members {
+            output.push_str("BEGIN:VCARD\r\nVERSION:3.0\r\n");
+            output.push_str(&format!(
+                "FN:{}\r\n",
+                vcard_escape(&member.user_name_original)
+            ));
+            if let Some(email) = &member.email {
+                output.push_str(&format!("EMAIL;TYPE=INTERNET:{}\r\n", vcard_escape(email)));
+            }
+            output.push_str(&format!("NOTE:{}\r\n", vcard_escape(&group.name)));
+            output.push_str("END:VCARD\r\n");
+        }
+    }
+    output
+}
+
+fn vcard_escape(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace(';', "\\;")
+        .replace('\n', "\\n")
+}
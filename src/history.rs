@@ -0,0 +1,127 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Datelike, Duration, FixedOffset, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+// How many runs are remembered, a few a day for a couple of semesters
+const MAX_RUNS: usize = 1000;
+
+// What a sync run downloaded, kept in the manifest so that `stats` can show it over the semester
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncRun {
+    // RFC 3339
+    pub started: String,
+    pub seconds: f64,
+    pub bytes: u64,
+    pub files: usize,
+    pub failed: usize,
+    // module code => bytes downloaded into its folder
+    #[serde(default)]
+    pub modules: BTreeMap<String, u64>,
+    // the files it put in place, for `history`
+    #[serde(default)]
+    pub changes: Vec<FileChange>,
+    // whether `undo` has put back what it changed
+    #[serde(default)]
+    pub undone: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileChange {
+    pub path: String,
+    // the code of the module it belongs to
+    pub module: Option<String>,
+    // "new", "updated", "renamed" or "trashed", as in the summary file, or "pruned" for
+    // an old version that was removed to stay within --keep-versions
+    pub result: String,
+    // where the old version went, when it was renamed out of the way
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub renamed_to: Option<String>,
+    // a copy of what was overwritten, trashed or pruned, for `undo`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kept_as: Option<String>,
+}
+
+// Runs that started in the same week
+pub struct Week {
+    // the Monday
+    pub start: NaiveDate,
+    pub runs: usize,
+    pub bytes: u64,
+    pub files: usize,
+    pub failed: usize,
+    pub modules: BTreeMap<String, u64>,
+}
+
+impl Week {
+    fn empty(start: NaiveDate) -> Week {
+        Week {
+            start,
+            runs: 0,
+            bytes: 0,
+            files: 0,
+            failed: 0,
+            modules: BTreeMap::new(),
+        }
+    }
+
+    // the module that the most was downloaded into
+    pub fn busiest_module(&self) -> Option<(&str, u64)> {
+        self.modules
+            .iter()
+            .max_by_key(|(_, bytes)| **bytes)
+            .map(|(module, bytes)| (module.as_str(), *bytes))
+    }
+}
+
+pub fn remember(history: &mut Vec<SyncRun>, run: SyncRun) {
+    history.push(run);
+    if history.len() > MAX_RUNS {
+        history.drain(..history.len() - MAX_RUNS);
+    }
+}
+
+// What runs that started from `since` on put in place, oldest first, with when the run started
+pub fn changes_since(
+    history: &[SyncRun],
+    since: Option<DateTime<Local>>,
+) -> Vec<(DateTime<FixedOffset>, &FileChange)> {
+    history
+        .iter()
+        .filter_map(|run| Some((DateTime::parse_from_rfc3339(&run.started).ok()?, run)))
+        .filter(|(started, _)| since.is_none_or(|since| *started >= since))
+        .flat_map(|(started, run)| run.changes.iter().map(move |change| (started, change)))
+        .collect()
+}
+
+// Totals per week, oldest first. Weeks without runs in between are included, so that gaps show up.
+pub fn by_week(history: &[SyncRun]) -> Vec<Week> {
+    let mut weeks = BTreeMap::<NaiveDate, Week>::new();
+    for run in history {
+        let date = match DateTime::parse_from_rfc3339(&run.started) {
+            Ok(started) => started.naive_local().date(),
+            Err(_) => continue,
+        };
+        let start = date - Duration::days(date.weekday().num_days_from_monday().into());
+        let week = weeks.entry(start).or_insert_with(|| Week::empty(start));
+        week.runs += 1;
+        week.bytes += run.bytes;
+        week.files += run.files;
+        week.failed += run.failed;
+        for (module, bytes) in &run.modules {
+            *week.modules.entry(module.clone()).or_default() += bytes;
+        }
+    }
+    let (first, last) = match (weeks.keys().next(), weeks.keys().next_back()) {
+        (Some(first), Some(last)) => (*first, *last),
+        _ => return vec![],
+    };
+    let mut start = first;
+    while start < last {
+        start += Duration::weeks(1);
+        weeks.entry(start).or_insert_with(|| Week::empty(start));
+    }
+    weeks.into_values().collect()
+}
@@ -0,0 +1,273 @@
+use std::fmt::Display;
+
+// Languages that messages can be shown in. Anything without a translation is shown in English.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Lang {
+    English,
+    Chinese,
+}
+
+impl Lang {
+    pub const NAMES: &'static [&'static str] = &["en", "zh"];
+
+    // also takes locales such as zh_CN.UTF-8 or zh-Hans
+    pub fn parse(lang: &str) -> Option<Lang> {
+        let lang = lang.to_lowercase();
+        let language = lang.split(['_', '-', '.', '@']).next().unwrap_or("");
+        match language {
+            "en" => Some(Lang::English),
+            "zh" => Some(Lang::Chinese),
+            _ => None,
+        }
+    }
+
+    // from the locale, in the order that gettext looks at it
+    pub fn detect() -> Lang {
+        ["LANGUAGE", "LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|value| !value.is_empty())
+            // LANGUAGE is a list of preferences, e.g. zh_CN:en
+            .and_then(|value| value.split(':').find_map(Lang::parse))
+            .unwrap_or(Lang::English)
+    }
+}
+
+// English message => Chinese. Messages are looked up by their English text, so that they still read
+// naturally where they are used, and ones without a translation fall back to it. {} marks where
+// values go, in the same order in every language.
+//
+// What a sync prints is translated: logging in, checks on the modules, downloads, transcoding and
+// transcription, the summary and status. Listings, tables and reports (plan, du, stats, history,
+// grades, dedupe, undo, api-dump and the exports) are left in English, as are help texts and
+// whatever comes from LumiNUS itself.
+const CATALOG: &[(&str, &str)] = &[
+    ("Hi {}!", "{}，你好！"),
+    ("Hi {}! (on {})", "{}，你好！（{}）"),
+    ("Username (include the nusstu\\ prefix): ", "用户名（包括 nusstu\\ 前缀）："),
+    ("Password: ", "密码："),
+    ("Login failed: {}. {}", "登录失败：{}。{}"),
+    ("Invalid credentials", "用户名或密码错误"),
+    ("Password has expired", "密码已过期"),
+    ("Account is locked", "账户已被锁定"),
+    ("Multi-factor authentication is required", "需要多重身份验证"),
+    ("Login server is down for maintenance", "登录服务器正在维护"),
+    ("Unable to reach the login server", "无法连接登录服务器"),
+    (
+        "Check your username (e.g. nusstu\\e0123456) and password. \
+         If they are stored in the credential file, delete it to be asked again.",
+        "请检查用户名（例如 nusstu\\e0123456）和密码。如果它们保存在凭据文件中，请删除该文件以重新输入。",
+    ),
+    (
+        "Change your password on the NUS password portal, then delete the credential file.",
+        "请在 NUS 密码门户修改密码，然后删除凭据文件。",
+    ),
+    (
+        "Too many failed attempts. Wait for the lockout to expire or contact IT Care, \
+         and don't retry until then.",
+        "失败次数过多。请等待锁定解除或联系 IT Care，在此之前不要重试。",
+    ),
+    (
+        "Your account needs a second factor, which fluminurs cannot provide. \
+         Sign in through a browser once, or ask IT Care about exempting non-interactive logins.",
+        "你的账户需要第二重验证，fluminurs 无法提供。请先通过浏览器登录一次，或向 IT Care 询问非交互式登录的豁免。",
+    ),
+    ("Try again later.", "请稍后再试。"),
+    (
+        "Check your internet connection, VPN and proxy settings (HTTPS_PROXY), \
+         or try --user-agent if a proxy is blocking requests.",
+        "请检查网络连接、VPN 和代理设置（HTTPS_PROXY）；如果代理拦截了请求，可以尝试 --user-agent。",
+    ),
+    (
+        "Try again later, or report this if it keeps happening.",
+        "请稍后再试；如果问题持续出现，请报告。",
+    ),
+    ("Corrupt credentials.json, deleting file...", "credentials.json 已损坏，正在删除……"),
+    ("Failed to store credentials: {}", "保存凭据失败：{}"),
+    ("You have modules in these terms:", "你在以下学期有模块："),
+    (
+        "You had modules in these terms as of the last run:",
+        "截至上次运行，你在以下学期有模块：",
+    ),
+    (
+        "You have no modules in term {} ({}), run the terms command to see which terms you do",
+        "你在学期 {}（{}）没有模块，运行 terms 命令可查看你有模块的学期",
+    ),
+    ("Which modules should be synced?", "要同步哪些模块？"),
+    (
+        "Numbers to toggle (e.g. 1 3), 'a' for all, 'n' for none, or Enter to confirm: ",
+        "输入要切换的编号（例如 1 3），'a' 全选，'n' 全不选，或按回车确认：",
+    ),
+    ("Ignoring {}", "忽略 {}"),
+    (
+        "New module {} will be synced, use --pick-modules to change this",
+        "新模块 {} 将被同步，可用 --pick-modules 更改",
+    ),
+    (
+        "New module since last run: {} {} (term {})",
+        "自上次运行以来的新模块：{} {}（学期 {}）",
+    ),
+    (
+        "!!! WARNING: modules have disappeared from your account since the last run !!!",
+        "!!! 警告：自上次运行以来，有模块从你的账户中消失 !!!",
+    ),
+    (
+        "!!! You may be about to lose access to the rest of their files.",
+        "!!! 你可能即将无法访问它们余下的文件。",
+    ),
+    (
+        "Warning: modules have disappeared from your account since the last run:",
+        "警告：自上次运行以来，以下模块从你的账户中消失了：",
+    ),
+    ("{} {}, term {}", "{} {}，学期 {}"),
+    ("- {} {} (term {})", "- {} {}（学期 {}）"),
+    (
+        "You may be about to lose access to the rest of their files.",
+        "你可能即将无法访问它们余下的文件。",
+    ),
+    (
+        "Warning: the terms of these modules have ended, so you may lose access to them soon:",
+        "警告：以下模块的学期已结束，你可能很快会失去访问权限：",
+    ),
+    (
+        "Use --archive-expiring-to to mirror them before anything else.",
+        "可用 --archive-expiring-to 先将它们镜像下来。",
+    ),
+    (
+        "Offline, so LumiNUS was not contacted",
+        "离线模式，未连接 LumiNUS",
+    ),
+    (
+        "Known modules: {}, seen announcements: {}",
+        "已知模块：{}，已读公告：{}",
+    ),
+    (
+        "Cached folders: {}, downloads left over: {}",
+        "已缓存的文件夹：{}，遗留的下载：{}",
+    ),
+    ("Failed checking module enrollment: {}", "检查模块注册情况失败：{}"),
+    ("Failed checking for expiring modules: {}", "检查即将过期的模块失败：{}"),
+    (
+        "Another run is using {}, waiting for it to finish",
+        "另一个运行正在使用 {}，等待其结束",
+    ),
+    (
+        "{} was corrupt, so it was restored from its backup and the corrupt copy was kept as {}.corrupt",
+        "{} 已损坏，已从备份恢复，损坏的副本保存为 {}.corrupt",
+    ),
+    (
+        "{} download(s) were left over from the last run, they will be picked up if there is room",
+        "上次运行留下了 {} 个下载，如有余量将继续下载",
+    ),
+    (
+        "ffmpeg was not found at {}, so multimedia will not be downloaded, transcoded or transcribed. \
+         Install ffmpeg or point --ffmpeg at it.",
+        "在 {} 未找到 ffmpeg，因此不会下载、转码或转写多媒体。请安装 ffmpeg 或用 --ffmpeg 指定其位置。",
+    ),
+    (
+        "{} was not found, so subtitles will not be generated",
+        "未找到 {}，因此不会生成字幕",
+    ),
+    ("Failed loading module files: {}", "加载模块文件失败：{}"),
+    ("Failed loading course files: {}", "加载课程文件失败：{}"),
+    (
+        "!!! Alert for {}: {} (matches {})",
+        "!!! {} 的提醒：{}（匹配 {}）",
+    ),
+    ("Failed to run the alert command: {}", "运行提醒命令失败：{}"),
+    (
+        "Skipping files of {}: no access yet, the lecturer hasn't opened the module",
+        "跳过 {} 的文件：尚无访问权限，讲师还未开放该模块",
+    ),
+    (
+        "No access yet to {}, so their files were not synced. They will be on a later run, once the lecturers open them.",
+        "尚无 {} 的访问权限，因此没有同步其文件。讲师开放这些模块后，之后的运行会同步它们。",
+    ),
+    ("Failed loading module multimedia: {}", "加载模块多媒体失败：{}"),
+    (
+        "Out of time, leaving the remaining transcodes for the next run",
+        "时间已到，剩余的转码留待下次运行",
+    ),
+    ("Transcoding {} with profile {}", "正在转码 {}，使用配置 {}"),
+    ("Failed to transcode {}: {}", "转码 {} 失败：{}"),
+    (
+        "Out of time, leaving the remaining transcriptions for the next run",
+        "时间已到，剩余的转写留待下次运行",
+    ),
+    ("Transcribing {}", "正在转写 {}"),
+    ("Subtitles saved to {}", "字幕已保存到 {}"),
+    ("Failed to transcribe {}: {}", "转写 {} 失败：{}"),
+    ("Failed to write announcements digest: {}", "写入公告摘要失败：{}"),
+    ("Wrote {} index.html file(s)", "已写入 {} 个 index.html 文件"),
+    ("Failed to write HTML indexes: {}", "写入 HTML 索引失败：{}"),
+    ("Serving {} on http://{}/", "正在提供 {}，地址为 http://{}/"),
+    ("Download to {}", "下载到 {}"),
+    ("Downloaded to {}", "已下载到 {}"),
+    ("Skipped {}", "已跳过 {}"),
+    ("Updated {}", "已更新 {}"),
+    (
+        "Updated {}, the old version is in the trash",
+        "已更新 {}，旧版本已移到回收站",
+    ),
+    ("Renamed {} to {}", "已将 {} 重命名为 {}"),
+    (
+        "Renamed {} to {}, going by its content",
+        "根据内容，已将 {} 重命名为 {}",
+    ),
+    ("Failed to download file: {}", "下载文件失败：{}"),
+    (
+        "Stopping once the downloads in progress finish, press Ctrl-C again to quit now",
+        "正在进行的下载完成后将停止，再按一次 Ctrl-C 立即退出",
+    ),
+    (
+        "Stopped early, {} download(s) were not started",
+        "已提前停止，{} 个下载未开始",
+    ),
+    (
+        "Sync summary: {} new, {} updated, {} renamed, {} skipped, {} failed",
+        "同步摘要：新增 {}，更新 {}，重命名 {}，跳过 {}，失败 {}",
+    ),
+    (
+        "{} download(s) were left for the next run by --max-bytes-per-run or --max-duration",
+        "因 --max-bytes-per-run 或 --max-duration 的限制，{} 个下载留待下次运行",
+    ),
+    // errors that runs commonly end with
+    (
+        "Download destination does not exist or is not a directory",
+        "下载目标不存在或不是目录",
+    ),
+    ("Unable to deserialize JSON", "无法解析服务器返回的 JSON"),
+    (
+        "Invalid API response from server: type mismatch",
+        "服务器返回的 API 响应无效：类型不匹配",
+    ),
+    ("No such module this term", "本学期没有这个模块"),
+    ("Unknown term", "未知的学期"),
+];
+
+pub fn translate(lang: Lang, english: &'static str) -> &'static str {
+    match lang {
+        Lang::English => english,
+        Lang::Chinese => CATALOG
+            .iter()
+            .find(|(message, _)| *message == english)
+            .map_or(english, |(_, chinese)| chinese),
+    }
+}
+
+// Puts `values` into the {}s of a message, in order
+pub fn fill(message: &str, values: &[&dyn Display]) -> String {
+    let mut filled = String::new();
+    let mut values = values.iter();
+    let mut parts = message.split("{}");
+    if let Some(first) = parts.next() {
+        filled.push_str(first);
+    }
+    for part in parts {
+        if let Some(value) = values.next() {
+            filled.push_str(&value.to_string());
+        }
+        filled.push_str(part);
+    }
+    filled
+}
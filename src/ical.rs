@@ -0,0 +1,59 @@
+use std::collections::HashSet;
+use std::time::SystemTime;
+
+// A calendar event, in the subset of iCalendar (RFC 5545) that calendar apps agree on
+pub struct Event {
+    // Calendar apps match events by UID when a file is imported again, updating what they have
+    // instead of adding a copy. So it must stay the same across runs, and not clash between
+    // kinds of events whose ids come from different places, see `uid`.
+    pub uid: String,
+    pub summary: String,
+    pub description: Option<String>,
+    pub start: SystemTime,
+    // events without an end are shown as a point in time, which is what we want for deadlines
+    pub end: Option<SystemTime>,
+}
+
+// A UID for the event of kind `kind` with the given server id
+pub fn uid(kind: &str, id: &str) -> String {
+    format!("{}-{}", kind, id)
+}
+
+fn format_time(time: SystemTime) -> String {
+    chrono::DateTime::<chrono::Utc>::from(time)
+        .format("%Y%m%dT%H%M%SZ")
+        .to_string()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+// Events with the same UID are only written once, since apps would otherwise keep just one of them anyway
+pub fn to_ics(events: &[Event]) -> String {
+    let now = format_time(SystemTime::now());
+    let mut output = String::from("BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//fluminurs//EN\r\n");
+    let mut written = HashSet::new();
+    for event in events {
+        if !written.insert(event.uid.as_str()) {
+            continue;
+        }
+        output.push_str("BEGIN:VEVENT\r\n");
+        output.push_str(&format!("UID:{}@fluminurs\r\n", escape(&event.uid)));
+        output.push_str(&format!("DTSTAMP:{}\r\n", now));
+        output.push_str(&format!("DTSTART:{}\r\n", format_time(event.start)));
+        if let Some(end) = event.end {
+            output.push_str(&format!("DTEND:{}\r\n", format_time(end)));
+        }
+        output.push_str(&format!("SUMMARY:{}\r\n", escape(&event.summary)));
+        if let Some(description) = &event.description {
+            output.push_str(&format!("DESCRIPTION:{}\r\n", escape(description)));
+        }
+        output.push_str("END:VEVENT\r\n");
+    }
+    output.push_str("END:VCALENDAR\r\n");
+    output
+}
@@ -0,0 +1,288 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const IGNORE_FILE_NAME: &str = ".fluminursignore";
+
+// One line of an ignore file, in gitignore syntax
+struct Rule {
+    pattern: String,
+    // `!pattern`, which brings back what an earlier rule left out
+    negated: bool,
+    // `pattern/`, which only matches folders
+    dir_only: bool,
+    // a slash anywhere but at the end ties the pattern to the ignore file's folder,
+    // otherwise it matches a name at any depth
+    anchored: bool,
+}
+
+impl Rule {
+    fn parse(line: &str) -> Option<Rule> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let pattern = line.trim_start_matches('/');
+        if pattern.is_empty() {
+            return None;
+        }
+        Some(Rule {
+            pattern: pattern.to_owned(),
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    // `path` is relative to the ignore file's folder, with / between its parts
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let subject = if self.anchored {
+            path
+        } else {
+            path.rsplit('/').next().unwrap_or(path)
+        };
+        glob_match(self.pattern.as_bytes(), subject.as_bytes())
+    }
+}
+
+// * and ? stop at slashes, ** doesn't, and [...] is a set of characters like in a shell
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', b'/', rest @ ..] => {
+            // any number of folders, including none
+            glob_match(rest, text)
+                || text
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &c)| c == b'/')
+                    .any(|(i, _)| glob_match(rest, &text[i + 1..]))
+        }
+        [b'*', b'*'] => true,
+        [b'*', rest @ ..] => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != b'/')
+            .any(|i| glob_match(rest, &text[i..])),
+        [b'?', rest @ ..] => match text {
+            [c, text_rest @ ..] if *c != b'/' => glob_match(rest, text_rest),
+            _ => false,
+        },
+        [b'[', class @ ..] => match (class.iter().position(|&c| c == b']'), text) {
+            (Some(end), [c, text_rest @ ..]) if end > 0 => {
+                let (set, negated) = match &class[..end] {
+                    [b'!', set @ ..] | [b'^', set @ ..] => (set, true),
+                    set => (set, false),
+                };
+                let mut in_set = false;
+                let mut i = 0;
+                while i < set.len() {
+                    if i + 2 < set.len() && set[i + 1] == b'-' {
+                        in_set |= set[i] <= *c && *c <= set[i + 2];
+                        i += 3;
+                    } else {
+                        in_set |= set[i] == *c;
+                        i += 1;
+                    }
+                }
+                in_set != negated && *c != b'/' && glob_match(&class[end + 1..], text_rest)
+            }
+            // an unclosed bracket is just a bracket
+            _ => text.first() == Some(&b'[') && glob_match(class, &text[1..]),
+        },
+        [b'\\', c, rest @ ..] | [c, rest @ ..] => match text {
+            [t, text_rest @ ..] if t == c => glob_match(rest, text_rest),
+            _ => false,
+        },
+    }
+}
+
+// The .fluminursignore files under a download destination. Like with git, one applies to everything
+// in its folder, and rules further down override the ones above. Files are read as they are needed.
+pub struct IgnoreFiles {
+    root: PathBuf,
+    // folder, relative to the root => its rules
+    loaded: RefCell<HashMap<PathBuf, Vec<Rule>>>,
+}
+
+impl IgnoreFiles {
+    pub fn new(root: &Path) -> IgnoreFiles {
+        IgnoreFiles {
+            root: root.to_owned(),
+            loaded: RefCell::new(HashMap::new()),
+        }
+    }
+
+    fn with_rules<T, F: FnOnce(&[Rule]) -> T>(&self, dir: &Path, f: F) -> T {
+        let mut loaded = self.loaded.borrow_mut();
+        let rules = loaded.entry(dir.to_owned()).or_insert_with(|| {
+            fs::read_to_string(self.root.join(dir).join(IGNORE_FILE_NAME))
+                .map(|content| content.lines().filter_map(Rule::parse).collect())
+                .unwrap_or_default()
+        });
+        f(rules)
+    }
+
+    // whether the last rule matching `path` itself leaves it out, not looking at its folders
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        let mut ignored = false;
+        for dir in path
+            .ancestors()
+            .skip(1)
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+        {
+            let relative = path
+                .strip_prefix(dir)
+                .unwrap_or(path)
+                .to_string_lossy()
+                .replace('\\', "/");
+            self.with_rules(dir, |rules| {
+                if let Some(rule) = rules
+                    .iter()
+                    .rev()
+                    .find(|rule| rule.matches(&relative, is_dir))
+                {
+                    ignored = !rule.negated;
+                }
+            });
+        }
+        ignored
+    }
+
+    // `path` is relative to the root. A file in an ignored folder is ignored too,
+    // and can't be brought back by a ! rule, as with git.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let folders = path.ancestors().skip(1).collect::<Vec<_>>();
+        folders
+            .into_iter()
+            .rev()
+            .filter(|folder| !folder.as_os_str().is_empty())
+            .any(|folder| self.matches(folder, true))
+            || self.matches(path, is_dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glob(pattern: &str, text: &str) -> bool {
+        glob_match(pattern.as_bytes(), text.as_bytes())
+    }
+
+    // ignore files under a scratch destination, as (folder, content)
+    fn ignore_files(name: &str, files: &[(&str, &str)]) -> IgnoreFiles {
+        let root =
+            std::env::temp_dir().join(format!("fluminurs-ignore-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (folder, content) in files {
+            let dir = root.join(folder);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join(IGNORE_FILE_NAME), content).unwrap();
+        }
+        IgnoreFiles::new(&root)
+    }
+
+    fn ignored(ignore: &IgnoreFiles, path: &str) -> bool {
+        ignore.is_ignored(Path::new(path), false)
+    }
+
+    #[test]
+    fn matches_globs() {
+        assert!(glob("*.mp4", "Lecture 1.mp4"));
+        assert!(!glob("*.mp4", "Lecture 1.mp4.part"));
+        assert!(!glob("*.mp4", "Recordings/Lecture 1.mp4"));
+        assert!(glob("L?.pdf", "L1.pdf"));
+        assert!(!glob("L?.pdf", "L10.pdf"));
+        assert!(!glob("a?b", "a/b"));
+        assert!(glob("Week [1-3]", "Week 2"));
+        assert!(!glob("Week [1-3]", "Week 4"));
+        assert!(glob("Week [!1-3]", "Week 4"));
+        assert!(glob("[ab]*", "b.txt"));
+        assert!(glob("[unclosed", "[unclosed"));
+        assert!(glob("\\*.pdf", "*.pdf"));
+        assert!(!glob("\\*.pdf", "a.pdf"));
+    }
+
+    #[test]
+    fn double_stars_cross_folders() {
+        assert!(glob("**/*.mp4", "a.mp4"));
+        assert!(glob("**/*.mp4", "Recordings/Week 1/a.mp4"));
+        assert!(glob("Recordings/**", "Recordings/Week 1/a.mp4"));
+        assert!(glob("a/**/b", "a/b"));
+        assert!(glob("a/**/b", "a/x/y/b"));
+        assert!(!glob("a/**/b", "a/x/c"));
+    }
+
+    #[test]
+    fn parses_rules() {
+        assert!(Rule::parse("").is_none());
+        assert!(Rule::parse("# a comment").is_none());
+        assert!(Rule::parse("/").is_none());
+        let rule = Rule::parse("\\#notes.txt").unwrap();
+        assert_eq!(rule.pattern, "#notes.txt");
+        assert!(!rule.negated);
+        let rule = Rule::parse("!/Tutorials/answers/  ").unwrap();
+        assert_eq!(rule.pattern, "Tutorials/answers");
+        assert!(rule.negated && rule.dir_only && rule.anchored);
+        let rule = Rule::parse("*.mp4").unwrap();
+        assert!(!rule.negated && !rule.dir_only && !rule.anchored);
+    }
+
+    #[test]
+    fn unanchored_patterns_match_names_at_any_depth() {
+        let ignore = ignore_files("unanchored", &[("", "*.mp4\n")]);
+        assert!(ignored(&ignore, "a.mp4"));
+        assert!(ignored(&ignore, "CS1010/Recordings/a.mp4"));
+        assert!(!ignored(&ignore, "CS1010/a.pdf"));
+    }
+
+    #[test]
+    fn anchored_patterns_match_from_their_folder() {
+        let ignore = ignore_files("anchored", &[("", "/Old\nCS1010/Tutorials/*.pdf\n")]);
+        assert!(ignored(&ignore, "Old/a.pdf"));
+        assert!(!ignored(&ignore, "CS1010/Old/a.pdf"));
+        assert!(ignored(&ignore, "CS1010/Tutorials/T1.pdf"));
+        assert!(!ignored(&ignore, "CS2030/Tutorials/T1.pdf"));
+    }
+
+    #[test]
+    fn negation_brings_files_back() {
+        let ignore = ignore_files(
+            "negation",
+            &[("", "*.pdf\n!Syllabus.pdf\n"), ("CS1010", "!*.pdf\n")],
+        );
+        assert!(ignored(&ignore, "CS2030/L1.pdf"));
+        assert!(!ignored(&ignore, "CS2030/Syllabus.pdf"));
+        // rules further down override the ones above
+        assert!(!ignored(&ignore, "CS1010/L1.pdf"));
+    }
+
+    #[test]
+    fn directory_patterns_only_match_folders() {
+        let ignore = ignore_files("directories", &[("", "Recordings/\n")]);
+        assert!(ignore.is_ignored(Path::new("CS1010/Recordings"), true));
+        assert!(!ignore.is_ignored(Path::new("CS1010/Recordings"), false));
+        // everything in an ignored folder is ignored too
+        assert!(ignored(&ignore, "CS1010/Recordings/Week 1/a.mp4"));
+    }
+
+    #[test]
+    fn files_in_ignored_folders_cant_be_brought_back() {
+        let ignore = ignore_files("reinclude", &[("", "Recordings/\n!Recordings/keep.mp4\n")]);
+        assert!(ignored(&ignore, "Recordings/keep.mp4"));
+    }
+}
@@ -0,0 +1,75 @@
+use std::fs;
+use std::path::Path;
+
+use crate::util::{escape_html, format_size, natural_cmp, percent_encode};
+use crate::Result;
+
+pub const INDEX_FILE_NAME: &str = "index.html";
+
+// Writes an index.html into `dir` and every directory below it, so that the mirror
+// can be browsed from any static file server. Returns the number of indexes written.
+pub fn write_indexes(dir: &Path, title: &str, has_parent: bool) -> Result<usize> {
+    let mut entries = fs::read_dir(dir)
+        .map_err(|_| "Unable to read directory")?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            !name.starts_with('.') && !name.starts_with('~') && name != INDEX_FILE_NAME
+        })
+        .collect::<Vec<_>>();
+    entries.sort_by(|e1, e2| {
+        natural_cmp(
+            &e1.file_name().to_string_lossy(),
+            &e2.file_name().to_string_lossy(),
+        )
+    });
+
+    let mut written = 0;
+    let mut rows = String::new();
+    if has_parent {
+        rows.push_str("<tr><td><a href=\"../index.html\">../</a></td><td></td><td></td></tr>\n");
+    }
+    for entry in entries {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        let updated = metadata
+            .modified()
+            .map(|modified| {
+                chrono::DateTime::<chrono::Local>::from(modified)
+                    .format("%Y-%m-%d %H:%M")
+                    .to_string()
+            })
+            .unwrap_or_default();
+        if metadata.is_dir() {
+            written += write_indexes(&entry.path(), &format!("{}/{}", title, name), true)?;
+            rows.push_str(&format!(
+                "<tr><td><a href=\"{}/index.html\">{}/</a></td><td></td><td>{}</td></tr>\n",
+                percent_encode(&name),
+                escape_html(&name),
+                updated
+            ));
+        } else {
+            rows.push_str(&format!(
+                "<tr><td><a href=\"{}\">{}</a></td><td>{}</td><td>{}</td></tr>\n",
+                percent_encode(&name),
+                escape_html(&name),
+                format_size(metadata.len()),
+                updated
+            ));
+        }
+    }
+
+    let page = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{title}</title>\
+         <style>body{{font-family:sans-serif;max-width:60em;margin:auto}}td{{padding:0 1em}}</style>\
+         </head><body><h1>{title}</h1>\n<table>\n\
+         <tr><th>Name</th><th>Size</th><th>Updated</th></tr>\n{rows}</table></body></html>\n",
+        title = escape_html(title),
+        rows = rows
+    );
+    fs::write(dir.join(INDEX_FILE_NAME), page).map_err(|_| "Unable to write index.html")?;
+    Ok(written + 1)
+}
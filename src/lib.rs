@@ -1,19 +1,85 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
 
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
 use reqwest::redirect::Policy;
+use reqwest::Certificate;
 use reqwest::Method;
-use reqwest::{header::CONTENT_TYPE, Certificate};
 use reqwest::{Client, RequestBuilder, Response, Url};
 use serde::de::DeserializeOwned;
-use serde::Deserialize;
-
-use self::module::Module;
-
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tokio_util::sync::CancellationToken;
+
+use self::adaptive::AdaptiveLimit;
+use self::audit::AuditLog;
+use self::budget::ByteBudget;
+use self::calendar::CalendarEvent;
+use self::login::LoginError;
+use self::message::Message;
+use self::module::{FolderNameStyle, Module};
+use self::replay::Traffic;
+use self::resource::CompareBy;
+use self::stats::TransferStats;
+use self::strict::DriftLog;
+use self::versions::RenameScheme;
+
+pub mod adaptive;
+pub mod alert;
+pub mod alias;
+pub mod audit;
+pub mod browse;
+pub mod budget;
+pub mod calendar;
+pub mod canvas;
+pub mod deadline;
+pub mod dedupe;
+#[cfg(feature = "extract")]
+pub mod diagnose;
+pub mod diff;
+pub mod dump;
+pub mod evaluation;
+#[cfg(feature = "extract")]
+pub mod extract;
+#[cfg(feature = "fake-server")]
+pub mod fake_server;
 pub mod file;
+pub mod gradebook;
+pub mod group;
+pub mod history;
+pub mod i18n;
+pub mod ical;
+pub mod ignore;
+pub mod index;
+pub mod login;
+pub mod manifest;
+pub mod message;
 pub mod module;
+pub mod moves;
 pub mod multimedia;
+#[cfg(feature = "pdf")]
+pub mod pdf;
+pub mod platform;
+pub mod profile;
+pub mod replay;
+pub mod report;
 pub mod resource;
+pub mod roster;
+pub mod sniff;
+pub mod standing;
+pub mod stats;
+pub mod strict;
+pub mod submission;
+pub mod subtitle;
+pub mod syllabus;
+pub mod sync;
+pub mod term;
+pub mod transcode;
 pub mod util;
+pub mod validate;
+pub mod versions;
 
 pub type Error = &'static str;
 pub type Result<T> = std::result::Result<T, Error>;
@@ -23,27 +89,29 @@ const ADFS_CLIENT_ID: &str = "E10493A3B1024F14BDC7D0D8B9F649E9-234390";
 const ADFS_RESOURCE_TYPE: &str = "sg_edu_nus_oauth";
 const ADFS_REDIRECT_URI: &str = "https://luminus.nus.edu.sg/auth/callback";
 const API_BASE_URL: &str = "https://luminus.nus.edu.sg/v2/api/";
+const WEB_BASE_URL: &str = "https://luminus.nus.edu.sg/";
 const OCP_APIM_SUBSCRIPTION_KEY: &str = "6963c200ca9440de8fa1eede730d8f7e";
+const MEMORY_UNIT: usize = 64 * 1024;
 const OCP_APIM_SUBSCRIPTION_KEY_HEADER: &str = "Ocp-Apim-Subscription-Key";
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct Name {
     user_name_original: String,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct Term {
     term_detail: TermDetail,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 struct TermDetail {
     term: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 struct ApiData<T> {
     data: Option<T>,
 }
@@ -53,22 +121,85 @@ struct TokenResponse {
     access_token: String,
 }
 
-fn full_api_url(path: &str) -> Url {
-    Url::parse(API_BASE_URL)
+// Where the LMS and its login server live, and how to talk to them. The defaults point at NUS's
+// production LumiNUS, but they can be changed to target the staging environment or another institution's instance.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Endpoints {
+    pub api_base_url: String,
+    pub adfs_oauth2_url: String,
+    pub adfs_client_id: String,
+    pub adfs_resource_type: String,
+    pub adfs_redirect_uri: String,
+    // where the LumiNUS web pages live, for permalinks to what we list
+    pub web_base_url: String,
+    // sent with every API request; LMSes other than LumiNUS don't need one
+    pub subscription_key: Option<String>,
+    // for campus proxies that block reqwest's default user agent
+    pub user_agent: Option<String>,
+    // extra headers sent with every request, including logging in and downloads
+    pub headers: BTreeMap<String, String>,
+}
+
+impl Default for Endpoints {
+    fn default() -> Endpoints {
+        Endpoints {
+            api_base_url: API_BASE_URL.to_owned(),
+            adfs_oauth2_url: ADFS_OAUTH2_URL.to_owned(),
+            adfs_client_id: ADFS_CLIENT_ID.to_owned(),
+            adfs_resource_type: ADFS_RESOURCE_TYPE.to_owned(),
+            adfs_redirect_uri: ADFS_REDIRECT_URI.to_owned(),
+            web_base_url: WEB_BASE_URL.to_owned(),
+            subscription_key: Some(OCP_APIM_SUBSCRIPTION_KEY.to_owned()),
+            user_agent: None,
+            headers: BTreeMap::new(),
+        }
+    }
+}
+
+impl Endpoints {
+    // reads overrides from a JSON file; anything left out keeps its default
+    pub fn load(path: &std::path::Path) -> Result<Endpoints> {
+        let content = std::fs::read_to_string(path).map_err(|_| "Unable to read endpoints file")?;
+        let mut endpoints = serde_json::from_str::<Endpoints>(&content)
+            .map_err(|_| "Unable to parse endpoints file")?;
+        // Url::join drops the last path segment unless the base ends with a slash
+        if !endpoints.api_base_url.ends_with('/') {
+            endpoints.api_base_url.push('/');
+        }
+        if !endpoints.web_base_url.ends_with('/') {
+            endpoints.web_base_url.push('/');
+        }
+        endpoints.validate()?;
+        Ok(endpoints)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        Url::parse(&self.api_base_url).map_err(|_| "Invalid API base URL")?;
+        Url::parse(&self.adfs_oauth2_url).map_err(|_| "Invalid ADFS URL")?;
+        Url::parse(&self.adfs_redirect_uri).map_err(|_| "Invalid ADFS redirect URI")?;
+        Url::parse(&self.web_base_url).map_err(|_| "Invalid web base URL")?;
+        build_headers(self)?;
+        Ok(())
+    }
+}
+
+fn full_api_url(endpoints: &Endpoints, path: &str) -> Url {
+    Url::parse(&endpoints.api_base_url)
         .and_then(|u| u.join(path))
         .expect("Unable to join URL's")
 }
 
-fn build_auth_url() -> Url {
+fn build_auth_url(endpoints: &Endpoints) -> Url {
     let nonce = generate_random_bytes(16);
-    let mut url = Url::parse(ADFS_OAUTH2_URL).expect("Unable to parse ADFS URL");
+    let mut url = Url::parse(&endpoints.adfs_oauth2_url).expect("Unable to parse ADFS URL");
     url.query_pairs_mut()
         .append_pair("response_type", "code")
-        .append_pair("client_id", ADFS_CLIENT_ID)
+        .append_pair("client_id", &endpoints.adfs_client_id)
         .append_pair("state", &nonce)
-        .append_pair("redirect_uri", ADFS_REDIRECT_URI)
+        .append_pair("redirect_uri", &endpoints.adfs_redirect_uri)
         .append_pair("scope", "")
-        .append_pair("resource", ADFS_RESOURCE_TYPE)
+        .append_pair("resource", &endpoints.adfs_resource_type)
         .append_pair("nonce", &nonce);
     url
 }
@@ -81,13 +212,13 @@ fn build_auth_form<'a>(username: &'a str, password: &'a str) -> HashMap<&'static
     map
 }
 
-fn build_token_form<'a>(code: &'a str) -> HashMap<&'static str, &'a str> {
+fn build_token_form<'a>(endpoints: &'a Endpoints, code: &'a str) -> HashMap<&'static str, &'a str> {
     let mut map = HashMap::new();
     map.insert("grant_type", "authorization_code");
-    map.insert("client_id", ADFS_CLIENT_ID);
-    map.insert("resource", ADFS_RESOURCE_TYPE);
+    map.insert("client_id", endpoints.adfs_client_id.as_str());
+    map.insert("resource", endpoints.adfs_resource_type.as_str());
     map.insert("code", code);
-    map.insert("redirect_uri", ADFS_REDIRECT_URI);
+    map.insert("redirect_uri", endpoints.adfs_redirect_uri.as_str());
     map
 }
 
@@ -96,8 +227,25 @@ fn hack_get_intermediate_cert() -> Result<Certificate> {
         .map_err(|_| "Unable to load TLS intermediate certificate")
 }
 
-fn build_client() -> Result<Client> {
-    Client::builder()
+fn build_headers(endpoints: &Endpoints) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    for (name, value) in &endpoints.headers {
+        headers.insert(
+            HeaderName::from_bytes(name.as_bytes()).map_err(|_| "Invalid header name")?,
+            HeaderValue::from_str(value).map_err(|_| "Invalid header value")?,
+        );
+    }
+    Ok(headers)
+}
+
+fn build_client(endpoints: &Endpoints) -> Result<Client> {
+    let builder = Client::builder();
+    let builder = match &endpoints.user_agent {
+        Some(user_agent) => builder.user_agent(user_agent),
+        None => builder,
+    };
+    builder
+        .default_headers(build_headers(endpoints)?)
         .http1_title_case_headers()
         .cookie_store(true)
         .add_root_certificate(hack_get_intermediate_cert()?)
@@ -123,6 +271,8 @@ async fn infinite_retry_http<F>(
     url: Url,
     method: Method,
     form: Option<&HashMap<&str, &str>>,
+    max_attempts: Option<usize>,
+    stats: Option<&TransferStats>,
     edit_request: F,
 ) -> Result<Response>
 where
@@ -136,6 +286,7 @@ where
 
     // LumiNUS randomly returns 400 to a perfectly good request for no apparent reason
     // We'll just ignore it and repeat the request
+    let mut attempts = 0;
     let res = loop {
         let request_builder = client.request(method.clone(), url.clone());
         let request_builder = if let Some(form) = &form {
@@ -149,10 +300,21 @@ where
             .build()
             .map_err(|_| "Failed to build request")?;
 
+        let started = Instant::now();
         let res = client.execute(request).await.map_err(|_| "HTTP error");
         if let Ok(res) = res {
+            if let Some(stats) = stats {
+                stats.record_response(url.as_str(), started.elapsed());
+            }
             break res;
         }
+        if let Some(stats) = stats {
+            stats.record_retry(url.as_str());
+        }
+        attempts += 1;
+        if max_attempts.is_some_and(|max_attempts| attempts >= max_attempts) {
+            return res;
+        }
     };
     Ok(res)
 }
@@ -161,23 +323,59 @@ async fn auth_http_post(
     client: &Client,
     url: Url,
     form: Option<&HashMap<&str, &str>>,
-    with_apim: bool,
+    subscription_key: Option<&str>,
 ) -> Result<Response> {
-    infinite_retry_http(client, url, Method::POST, form, move |req| {
-        if with_apim {
-            req.header(OCP_APIM_SUBSCRIPTION_KEY_HEADER, OCP_APIM_SUBSCRIPTION_KEY)
-        } else {
-            req
-        }
+    // a login server that can't be reached won't become reachable by hammering it
+    infinite_retry_http(client, url, Method::POST, form, Some(3), None, move |req| {
+        with_subscription_key(req, subscription_key)
     })
     .await
 }
 
+fn with_subscription_key(req: RequestBuilder, subscription_key: Option<&str>) -> RequestBuilder {
+    if let Some(subscription_key) = subscription_key {
+        req.header(OCP_APIM_SUBSCRIPTION_KEY_HEADER, subscription_key)
+    } else {
+        req
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Api {
     jwt: String,
     client: Client,
     ffmpeg_path: String,
+    audit_log: Option<AuditLog>,
+    read_only: bool,
+    endpoints: Endpoints,
+    // caps how many downloads may write to disk at once, independent of how many are in flight
+    writers: Option<Arc<Semaphore>>,
+    // download buffers are reserved from this, in units of MEMORY_UNIT bytes
+    memory_budget: Option<Arc<Semaphore>>,
+    folder_name_style: FolderNameStyle,
+    term_folders: bool,
+    rename_scheme: RenameScheme,
+    compare_by: CompareBy,
+    // whether downloads in formats we know are checked for being cut short or corrupted
+    validate_downloads: bool,
+    // caps API requests in flight across everything sharing this session
+    requests: Option<Arc<Semaphore>>,
+    traffic: Option<Traffic>,
+    // where the last response that couldn't be deserialised is kept, for `diagnose`
+    failure_file: Option<PathBuf>,
+    // whether responses with fields we don't know about, or without fields we expect, are errors
+    strict: bool,
+    // what strict found wrong, shared by every clone of this session
+    drift: Arc<DriftLog>,
+    stats: Arc<TransferStats>,
+    // how many downloads may be in flight, which backs off when they start failing
+    downloads: Option<Arc<AdaptiveLimit>>,
+    // how much this session may still download, shared by every clone of it
+    byte_budget: Option<Arc<ByteBudget>>,
+    // no new work should start past this
+    deadline: Option<Instant>,
+    // cancelled when the user asks us to stop, shared by every clone of this session
+    cancellation: CancellationToken,
 }
 
 impl Api {
@@ -185,16 +383,62 @@ impl Api {
         &self.client
     }
 
-    async fn api_as_json<T: DeserializeOwned + 'static>(
+    // the LumiNUS web page at `path`, e.g. "modules/{id}/announcements"
+    pub fn web_url(&self, path: &str) -> String {
+        format!("{}{}", self.endpoints.web_base_url, path)
+    }
+
+    async fn api_as_json<T: DeserializeOwned + Serialize + 'static>(
         &self,
         path: &str,
         method: Method,
         form: Option<&HashMap<&str, &str>>,
     ) -> Result<T> {
-        let res = self.api(path, method, form).await?;
-        res.json::<T>()
+        let res = self.api(path, method.clone(), form).await?;
+        let status = res.status();
+        let headers = res.headers().clone();
+        let body = res
+            .bytes()
             .await
-            .map_err(|_| "Unable to deserialize JSON")
+            .map_err(|_| "Unable to deserialize JSON")?;
+        let (result, error) = match serde_json::from_slice::<T>(&body) {
+            Ok(value) if self.strict => {
+                let drift = serde_json::from_slice::<serde_json::Value>(&body)
+                    .and_then(|received| {
+                        Ok(strict::schema_drift(
+                            &received,
+                            &serde_json::to_value(&value)?,
+                        ))
+                    })
+                    .unwrap_or_default();
+                if drift.is_empty() {
+                    (Ok(value), None)
+                } else {
+                    let details = drift.join("\n");
+                    self.drift.record(path, drift);
+                    (
+                        Err("Response does not match what fluminurs expects (--strict)"),
+                        Some(details),
+                    )
+                }
+            }
+            Ok(value) => (Ok(value), None),
+            Err(e) => (Err("Unable to deserialize JSON"), Some(e.to_string())),
+        };
+        if let (Some(error), Some(failure_file)) = (error, &self.failure_file) {
+            replay::save_failure(
+                failure_file,
+                &method,
+                path,
+                status,
+                &headers,
+                &body,
+                &self.jwt,
+                error,
+            )
+            .await;
+        }
+        result
     }
 
     pub async fn api(
@@ -203,13 +447,60 @@ impl Api {
         method: Method,
         form: Option<&HashMap<&str, &str>>,
     ) -> Result<Response> {
-        let url = full_api_url(path);
+        let url = full_api_url(&self.endpoints, path);
+        let is_mutation = method != Method::GET;
+        if is_mutation && self.read_only {
+            if let Some(audit_log) = &self.audit_log {
+                audit_log.record(&method, path, form, "refused: read-only mode");
+            }
+            return Err("Refusing to change anything on LumiNUS in read-only mode");
+        }
 
-        infinite_retry_http(&self.client, url, method, form, move |req| {
-            req.header(OCP_APIM_SUBSCRIPTION_KEY_HEADER, OCP_APIM_SUBSCRIPTION_KEY)
-                .bearer_auth(self.jwt.as_str())
-        })
-        .await
+        if let Some(Traffic::Replay(dir)) = &self.traffic {
+            return replay::replay(dir, &method, path).await;
+        }
+        let _permit = match &self.requests {
+            Some(requests) => requests.acquire().await.ok(),
+            None => None,
+        };
+        let res = infinite_retry_http(
+            &self.client,
+            url,
+            method.clone(),
+            form,
+            None,
+            Some(&self.stats),
+            move |req| {
+                with_subscription_key(req, self.endpoints.subscription_key.as_deref())
+                    .bearer_auth(self.jwt.as_str())
+            },
+        )
+        .await;
+        if let (true, Some(audit_log)) = (is_mutation, &self.audit_log) {
+            let result = match &res {
+                Ok(res) => res.status().to_string(),
+                Err(e) => (*e).to_owned(),
+            };
+            audit_log.record(&method, path, form, &result);
+        }
+        match (&self.traffic, res) {
+            (Some(Traffic::Record(dir)), Ok(res)) => {
+                replay::record(dir, &method, path, res, &self.jwt).await
+            }
+            (_, res) => res,
+        }
+    }
+
+    // The response as the server sent it, pretty printed, with tokens removed like in recordings
+    pub async fn raw_response(&self, path: &str) -> Result<String> {
+        let res = self.api(path, Method::GET, None).await?;
+        let status = res.status();
+        let body = res.bytes().await.map_err(|_| "Failed reading response")?;
+        Ok(format!(
+            "{}\n{}",
+            status,
+            replay::redacted_body(&body, &self.jwt)
+        ))
     }
 
     async fn current_term(&self) -> Result<String> {
@@ -225,6 +516,16 @@ impl Api {
     }
 
     pub async fn modules(&self, term: Option<String>) -> Result<Vec<Module>> {
+        let modules = self.enrolled_modules().await?;
+        self.select_modules(modules, term).await
+    }
+
+    // Of `modules` (from `enrolled_modules`), those in `term`, or in the current term and later ones
+    pub async fn select_modules(
+        &self,
+        modules: Vec<Module>,
+        term: Option<String>,
+    ) -> Result<Vec<Module>> {
         enum FilterMode {
             GreaterThan(String),
             Equal(String),
@@ -237,30 +538,58 @@ impl Api {
             FilterMode::GreaterThan(self.current_term().await?)
         };
 
-        let modules = self
-            .api_as_json::<ApiData<Vec<Module>>>("module", Method::GET, None)
-            .await?;
-
-        if let Some(modules) = modules.data {
-            let iter = modules.into_iter();
-            let mut selected_modules: Vec<Module> = match filter {
-                FilterMode::Equal(term) => iter.filter(|m| m.term == term).collect(),
-                FilterMode::GreaterThan(term) => iter.filter(|m| m.term >= term).collect(),
-            };
-            // sort by increasing module code, then by decreasing term
-            selected_modules.sort_unstable_by(|m1, m2| {
-                m1.code.cmp(&m2.code).then_with(|| m2.term.cmp(&m1.term))
-            });
-            selected_modules.dedup_by(|other, latest| if other.code == latest.code {
-                println!("Warning: module {} appeared in more than one semester, only latest semester will be retrieved", other.code);
-                true
-            } else {
-                false
-            });
-            Ok(selected_modules)
+        let iter = modules.into_iter();
+        let mut selected_modules: Vec<Module> = match filter {
+            FilterMode::Equal(term) => iter.filter(|m| m.term == term).collect(),
+            FilterMode::GreaterThan(term) => iter.filter(|m| m.term >= term).collect(),
+        };
+        // sort by increasing module code, then by decreasing term
+        selected_modules
+            .sort_unstable_by(|m1, m2| m1.code.cmp(&m2.code).then_with(|| m2.term.cmp(&m1.term)));
+        selected_modules.dedup_by(|other, latest| if other.code == latest.code {
+            println!("Warning: module {} appeared in more than one semester, only latest semester will be retrieved", other.code);
+            true
         } else {
-            Err("Invalid API response from server: type mismatch")
+            false
+        });
+        Ok(selected_modules)
+    }
+
+    // Of `modules` (from `enrolled_modules`), those from terms that have already ended but which
+    // we can still access. Access to these is usually revoked some time after the term ends.
+    pub async fn expiring_modules(&self, modules: &[Module]) -> Result<Vec<Module>> {
+        let current_term = self.current_term().await?;
+        let mut expiring = modules
+            .iter()
+            .filter(|m| m.term < current_term && m.has_access())
+            .cloned()
+            .collect::<Vec<_>>();
+        expiring
+            .sort_unstable_by(|m1, m2| m1.code.cmp(&m2.code).then_with(|| m2.term.cmp(&m1.term)));
+        expiring.dedup_by(|other, latest| other.code == latest.code);
+        Ok(expiring)
+    }
+
+    // every module the account can see, regardless of term
+    pub async fn enrolled_modules(&self) -> Result<Vec<Module>> {
+        let mut modules = self
+            .api_as_json::<ApiData<Vec<Module>>>("module", Method::GET, None)
+            .await?
+            .data
+            .ok_or("Invalid API response from server: type mismatch")?;
+        for module in &mut modules {
+            module.folder_name_style = self.folder_name_style;
+            module.term_folders = self.term_folders;
         }
+        if self.folder_name_style == FolderNameStyle::Name {
+            let folders = modules.iter().map(Module::folder_path).collect::<Vec<_>>();
+            for (module, clashes) in modules.iter_mut().zip(module::clashing_folders(&folders)) {
+                if clashes {
+                    module.folder_name_style = FolderNameStyle::CodeName;
+                }
+            }
+        }
+        Ok(modules)
     }
 
     pub async fn name(&self) -> Result<String> {
@@ -270,46 +599,340 @@ impl Api {
             .user_name_original)
     }
 
-    pub async fn with_login<'a>(username: &str, password: &str) -> Result<Api> {
+    pub async fn messages(&self) -> Result<Vec<Message>> {
+        let messages = self
+            .api_as_json::<ApiData<Vec<Message>>>(
+                "message/inbox?sortby=createdDate%20ASC",
+                Method::GET,
+                None,
+            )
+            .await?;
+        messages
+            .data
+            .ok_or("Invalid API response from server: type mismatch")
+    }
+
+    pub async fn calendar(&self) -> Result<Vec<CalendarEvent>> {
+        let events = self
+            .api_as_json::<ApiData<Vec<CalendarEvent>>>(
+                "calendar/event?sortby=startDate%20ASC",
+                Method::GET,
+                None,
+            )
+            .await?;
+        events
+            .data
+            .ok_or("Invalid API response from server: type mismatch")
+    }
+
+    pub async fn with_login(
+        username: &str,
+        password: &str,
+    ) -> std::result::Result<Api, LoginError> {
+        Self::with_login_at(Endpoints::default(), username, password).await
+    }
+
+    pub async fn with_login_at(
+        endpoints: Endpoints,
+        username: &str,
+        password: &str,
+    ) -> std::result::Result<Api, LoginError> {
         let params = build_auth_form(username, password);
-        let client = build_client()?;
+        let client = build_client(&endpoints).map_err(LoginError::Other)?;
 
-        let auth_resp = auth_http_post(&client, build_auth_url(), Some(&params), false).await?;
-        if !auth_resp.url().as_str().starts_with(ADFS_REDIRECT_URI) {
-            return Err("Invalid credentials");
+        let auth_resp = auth_http_post(&client, build_auth_url(&endpoints), Some(&params), None)
+            .await
+            .map_err(|_| LoginError::Network)?;
+        if !auth_resp
+            .url()
+            .as_str()
+            .starts_with(&endpoints.adfs_redirect_uri)
+        {
+            let status = auth_resp.status();
+            let body = auth_resp.text().await.unwrap_or_default();
+            return Err(LoginError::from_adfs_page(status, &body));
         }
         let code = auth_resp
             .url()
             .query_pairs()
             .find(|(key, _)| key == "code")
             .map(|(_key, code)| code.into_owned())
-            .ok_or("Unknown authentication failure (no code returned)")?;
+            .ok_or(LoginError::Other(
+                "Unknown authentication failure (no code returned)",
+            ))?;
         let token_resp = auth_http_post(
             &client,
-            full_api_url("login/adfstoken"),
-            Some(&build_token_form(&code)),
-            true,
+            full_api_url(&endpoints, "login/adfstoken"),
+            Some(&build_token_form(&endpoints, &code)),
+            endpoints.subscription_key.as_deref(),
         )
-        .await?;
+        .await
+        .map_err(|_| LoginError::Network)?;
         if !token_resp.status().is_success() {
-            return Err("Unknown authentication failure (no token returned)");
+            return Err(LoginError::Other(
+                "Unknown authentication failure (no token returned)",
+            ));
         }
         let token = token_resp
             .json::<TokenResponse>()
             .await
-            .map_err(|_| "Failed to deserialise token exchange response")?;
+            .map_err(|_| LoginError::Other("Failed to deserialise token exchange response"))?;
         Ok(Api {
             jwt: token.access_token,
             client,
             ffmpeg_path: String::new(),
+            audit_log: None,
+            read_only: false,
+            endpoints,
+            writers: None,
+            memory_budget: None,
+            folder_name_style: FolderNameStyle::default(),
+            term_folders: false,
+            rename_scheme: RenameScheme::default(),
+            compare_by: CompareBy::Mtime,
+            validate_downloads: false,
+            requests: None,
+            traffic: None,
+            failure_file: None,
+            strict: false,
+            drift: Arc::new(DriftLog::default()),
+            stats: Arc::new(TransferStats::default()),
+            downloads: None,
+            byte_budget: None,
+            deadline: None,
+            cancellation: CancellationToken::new(),
+        })
+    }
+
+    // For platforms that hand out API tokens directly instead of going through ADFS
+    pub fn with_token(endpoints: Endpoints, token: &str) -> Result<Api> {
+        endpoints.validate()?;
+        Ok(Api {
+            jwt: token.to_owned(),
+            client: build_client(&endpoints)?,
+            ffmpeg_path: String::new(),
+            audit_log: None,
+            read_only: false,
+            endpoints,
+            writers: None,
+            memory_budget: None,
+            folder_name_style: FolderNameStyle::default(),
+            term_folders: false,
+            rename_scheme: RenameScheme::default(),
+            compare_by: CompareBy::Mtime,
+            validate_downloads: false,
+            requests: None,
+            traffic: None,
+            failure_file: None,
+            strict: false,
+            drift: Arc::new(DriftLog::default()),
+            stats: Arc::new(TransferStats::default()),
+            downloads: None,
+            byte_budget: None,
+            deadline: None,
+            cancellation: CancellationToken::new(),
         })
     }
 
     pub fn with_ffmpeg<S: Into<String>>(self: Api, ffmpeg_path: S) -> Api {
         Api {
-            jwt: self.jwt,
-            client: self.client,
             ffmpeg_path: ffmpeg_path.into(),
+            ..self
+        }
+    }
+
+    // multimedia can't be downloaded or transcoded without ffmpeg
+    pub fn has_ffmpeg(&self) -> bool {
+        util::find_executable(&self.ffmpeg_path).is_some()
+    }
+
+    // Once set, every request other than GET is refused before it leaves the machine.
+    // Logging in is unaffected.
+    pub fn with_read_only(self: Api, read_only: bool) -> Api {
+        Api { read_only, ..self }
+    }
+
+    pub fn with_max_writers(self: Api, max_writers: usize) -> Api {
+        Api {
+            writers: Some(Arc::new(Semaphore::new(max_writers.max(1)))),
+            ..self
+        }
+    }
+
+    // Saves every API response, minus the session token, so that problems can be reproduced offline
+    pub fn with_recording(self: Api, dir: PathBuf) -> Api {
+        Api {
+            traffic: Some(Traffic::Record(dir)),
+            ..self
+        }
+    }
+
+    pub fn with_strict(self: Api, strict: bool) -> Api {
+        Api { strict, ..self }
+    }
+
+    pub fn with_failure_file(self: Api, file: PathBuf) -> Api {
+        Api {
+            failure_file: Some(file),
+            ..self
+        }
+    }
+
+    // Answers API requests from an earlier recording instead of the server.
+    // Downloads still go to the network, since their contents aren't recorded.
+    pub fn with_replay(self: Api, dir: PathBuf) -> Api {
+        Api {
+            traffic: Some(Traffic::Replay(dir)),
+            ..self
+        }
+    }
+
+    // Sync phases run side by side, so this keeps them from piling onto the server together
+    pub fn with_max_requests(self: Api, max_requests: usize) -> Api {
+        Api {
+            requests: Some(Arc::new(Semaphore::new(max_requests.max(1)))),
+            ..self
+        }
+    }
+
+    // Starts out allowing `max_downloads` at once, and lowers that while downloads keep failing
+    pub fn with_adaptive_downloads(self: Api, max_downloads: usize) -> Api {
+        Api {
+            downloads: Some(Arc::new(AdaptiveLimit::new(max_downloads))),
+            ..self
+        }
+    }
+
+    // Downloads past this many bytes should be left for another session
+    pub fn with_byte_budget(self: Api, max_bytes: u64) -> Api {
+        Api {
+            byte_budget: Some(Arc::new(ByteBudget::new(max_bytes))),
+            ..self
+        }
+    }
+
+    // Work that hasn't started by `deadline` should be left for another session
+    pub fn with_deadline(self: Api, deadline: Instant) -> Api {
+        Api {
+            deadline: Some(deadline),
+            ..self
+        }
+    }
+
+    // Bounds the memory that all concurrent downloads may buffer together.
+    // Downloads wait for their share of the budget before they start streaming.
+    pub fn with_memory_limit(self: Api, bytes: usize) -> Api {
+        let units = (bytes / MEMORY_UNIT).clamp(1, Semaphore::MAX_PERMITS);
+        Api {
+            memory_budget: Some(Arc::new(Semaphore::new(units))),
+            ..self
+        }
+    }
+
+    // every module loaded through this session will be mirrored into folders named this way
+    pub fn with_folder_name_style(self: Api, folder_name_style: FolderNameStyle) -> Api {
+        Api {
+            folder_name_style,
+            ..self
+        }
+    }
+
+    // mirror modules into <term>/<module>, so that several terms can share a download destination
+    pub fn with_term_folders(self: Api, term_folders: bool) -> Api {
+        Api {
+            term_folders,
+            ..self
+        }
+    }
+
+    // how old versions of updated files are named, when they are renamed instead of overwritten
+    pub fn with_rename_scheme(self: Api, rename_scheme: RenameScheme) -> Api {
+        Api {
+            rename_scheme,
+            ..self
+        }
+    }
+
+    pub fn rename_scheme(&self) -> &RenameScheme {
+        &self.rename_scheme
+    }
+
+    // whether files already on disk are up to date, going by their last modified time or their content
+    pub fn with_compare_by(self: Api, compare_by: CompareBy) -> Api {
+        Api { compare_by, ..self }
+    }
+
+    pub fn compare_by(&self) -> CompareBy {
+        self.compare_by
+    }
+
+    // downloads that fail the check are tried again a few times, and then given up on
+    pub fn with_validate_downloads(self: Api, validate_downloads: bool) -> Api {
+        Api {
+            validate_downloads,
+            ..self
+        }
+    }
+
+    pub(crate) fn validate_downloads(&self) -> bool {
+        self.validate_downloads
+    }
+
+    // returns how much a download may buffer, along with the reservation backing it
+    pub(crate) async fn reserve_buffer(
+        &self,
+        wanted: usize,
+    ) -> (usize, Option<SemaphorePermit<'_>>) {
+        match &self.memory_budget {
+            Some(budget) => {
+                let units = (wanted / MEMORY_UNIT)
+                    .clamp(1, budget.available_permits().max(1))
+                    .min(u32::MAX as usize);
+                let permit = budget.acquire_many(units as u32).await.ok();
+                (units * MEMORY_UNIT, permit)
+            }
+            None => (wanted, None),
+        }
+    }
+
+    pub(crate) fn writer_gate(&self) -> Option<Arc<Semaphore>> {
+        self.writers.clone()
+    }
+
+    pub fn cancellation(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
+    pub fn download_limit(&self) -> Option<&AdaptiveLimit> {
+        self.downloads.as_deref()
+    }
+
+    pub fn byte_budget(&self) -> Option<&ByteBudget> {
+        self.byte_budget.as_deref()
+    }
+
+    pub fn past_deadline(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    // how requests and downloads have fared so far, per host
+    pub fn stats(&self) -> &Arc<TransferStats> {
+        &self.stats
+    }
+
+    pub fn schema_drift(&self) -> &Arc<DriftLog> {
+        &self.drift
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn with_audit_log(self: Api, audit_log: AuditLog) -> Api {
+        Api {
+            audit_log: Some(audit_log),
+            ..self
         }
     }
 }
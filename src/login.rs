@@ -0,0 +1,100 @@
+use std::fmt;
+
+use reqwest::StatusCode;
+
+// Why logging in failed, so that users can be told what to do about it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginError {
+    WrongPassword,
+    PasswordExpired,
+    AccountLocked,
+    MfaRequired,
+    Maintenance,
+    // we couldn't reach the login server at all, usually a proxy or no connection
+    Network,
+    Other(&'static str),
+}
+
+impl LoginError {
+    pub fn message(self) -> &'static str {
+        match self {
+            LoginError::WrongPassword => "Invalid credentials",
+            LoginError::PasswordExpired => "Password has expired",
+            LoginError::AccountLocked => "Account is locked",
+            LoginError::MfaRequired => "Multi-factor authentication is required",
+            LoginError::Maintenance => "Login server is down for maintenance",
+            LoginError::Network => "Unable to reach the login server",
+            LoginError::Other(message) => message,
+        }
+    }
+
+    // what the user can do about it
+    pub fn advice(self) -> &'static str {
+        match self {
+            LoginError::WrongPassword => {
+                "Check your username (e.g. nusstu\\e0123456) and password. \
+                 If they are stored in the credential file, delete it to be asked again."
+            }
+            LoginError::PasswordExpired => {
+                "Change your password on the NUS password portal, then delete the credential file."
+            }
+            LoginError::AccountLocked => {
+                "Too many failed attempts. Wait for the lockout to expire or contact IT Care, \
+                 and don't retry until then."
+            }
+            LoginError::MfaRequired => {
+                "Your account needs a second factor, which fluminurs cannot provide. \
+                 Sign in through a browser once, or ask IT Care about exempting non-interactive logins."
+            }
+            LoginError::Maintenance => "Try again later.",
+            LoginError::Network => {
+                "Check your internet connection, VPN and proxy settings (HTTPS_PROXY), \
+                 or try --user-agent if a proxy is blocking requests."
+            }
+            LoginError::Other(_) => "Try again later, or report this if it keeps happening.",
+        }
+    }
+
+    // ADFS shows a page instead of redirecting back whenever it won't let us in,
+    // so the page has to be read to find out why
+    pub(crate) fn from_adfs_page(status: StatusCode, body: &str) -> LoginError {
+        let body = body.to_lowercase();
+        let contains_any = |needles: &[&str]| needles.iter().any(|needle| body.contains(needle));
+        if status == StatusCode::SERVICE_UNAVAILABLE || contains_any(&["maintenance"]) {
+            LoginError::Maintenance
+        } else if contains_any(&["locked out", "account is locked", "account has been locked"]) {
+            LoginError::AccountLocked
+        } else if contains_any(&[
+            "password has expired",
+            "password expired",
+            "must change your password",
+            "updatepassword",
+        ]) {
+            LoginError::PasswordExpired
+        } else if contains_any(&[
+            "multi-factor",
+            "additional authentication",
+            "verification code",
+            "authenticator",
+            "duo security",
+        ]) {
+            LoginError::MfaRequired
+        } else if contains_any(&["incorrect user id or password", "errortext"]) {
+            LoginError::WrongPassword
+        } else {
+            LoginError::Other("Unknown authentication failure (login page not recognised)")
+        }
+    }
+}
+
+impl fmt::Display for LoginError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl From<LoginError> for &'static str {
+    fn from(error: LoginError) -> &'static str {
+        error.message()
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use fs2::FileExt;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::file::FolderSnapshot;
+use crate::history::SyncRun;
+use crate::moves::Fingerprint;
+use crate::Result;
+
+// Bumped whenever the manifest changes in a way that needs `migrate` to bring older ones up to date
+const VERSION: u64 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KnownModule {
+    pub code: String,
+    pub name: String,
+    pub term: String,
+}
+
+// Local state that is remembered across runs
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    #[serde(default)]
+    pub seen_announcements: HashSet<String>,
+    // announcements that an alert was raised for, so that each is only raised once
+    #[serde(default)]
+    pub alerted_announcements: HashSet<String>,
+    // folder path => names of the PDFs that were concatenated into its combined PDF
+    #[serde(default)]
+    pub merged_folders: HashMap<String, Vec<String>>,
+    // video path => name of the transcode profile that was applied to it
+    #[serde(default)]
+    pub transcoded: HashMap<String, String>,
+    // videos that subtitles were generated for
+    #[serde(default)]
+    pub transcribed: HashSet<String>,
+    // module id => module, as of the previous run
+    #[serde(default)]
+    pub enrolled_modules: BTreeMap<String, KnownModule>,
+    // ids of the modules from ended terms that were warned about, so that each is only warned about once
+    #[serde(default)]
+    pub expiring_modules: BTreeSet<String>,
+    // module code => whether to sync it, as chosen in the module picker
+    #[serde(default)]
+    pub module_selection: BTreeMap<String, bool>,
+    // download destination => module id => folder the module was last mirrored into there
+    #[serde(default)]
+    pub module_folders: BTreeMap<String, BTreeMap<String, String>>,
+    // folder id => what the folder held when it was last listed
+    #[serde(default)]
+    pub folder_cache: HashMap<String, FolderSnapshot>,
+    // downloads that the last run left for later, having run out of --max-bytes-per-run or --max-duration
+    #[serde(default)]
+    pub deferred_downloads: BTreeSet<String>,
+    // downloaded file path => what it looked like, kept with --moved-files to recognise it if it is moved
+    #[serde(default)]
+    pub fingerprints: BTreeMap<String, Fingerprint>,
+    // downloaded file path => where it was moved to and left, with --moved-files leave
+    #[serde(default)]
+    pub moved_files: BTreeMap<String, String>,
+    // file path on the server => extension added to it with --fix-extensions, because its content called for one
+    #[serde(default)]
+    pub added_extensions: BTreeMap<String, String>,
+    // what each sync run downloaded, oldest first, for `stats`
+    #[serde(default)]
+    pub sync_history: Vec<SyncRun>,
+    // set when the manifest was unreadable and this came from its backup instead
+    #[serde(skip)]
+    recovered: bool,
+}
+
+// Held for as long as a run uses the manifest, so that runs pointed at the same mirror take turns
+// instead of overwriting each other's changes. The lock is advisory, on a file next to the manifest.
+pub struct ManifestLock {
+    _file: fs::File,
+}
+
+impl ManifestLock {
+    // Waits for any other run holding the lock, calling `on_wait` first if it has to
+    pub fn acquire<F: FnOnce()>(path: &Path, on_wait: F) -> Result<ManifestLock> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(with_suffix(path, ".lock"))
+            .map_err(|_| "Unable to create manifest lock file")?;
+        match file.try_lock_exclusive() {
+            Ok(()) => {}
+            Err(err) if err.kind() == fs2::lock_contended_error().kind() => {
+                on_wait();
+                file.lock_exclusive()
+                    .map_err(|_| "Unable to lock manifest file")?;
+            }
+            Err(_) => return Err("Unable to lock manifest file"),
+        }
+        Ok(ManifestLock { _file: file })
+    }
+}
+
+// e.g. manifest.json => manifest.json.bak
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+// Brings a manifest written by an older version up to date
+fn migrate(mut value: Value) -> Result<Value> {
+    // manifests from before versioning have no version at all
+    let version = value.get("version").and_then(Value::as_u64).unwrap_or(0);
+    if version > VERSION {
+        return Err("Manifest file was written by a newer version of fluminurs");
+    }
+    // 0 => 1: nothing changed besides the version appearing, as every field has a default
+    if let Some(object) = value.as_object_mut() {
+        object.insert("version".to_owned(), VERSION.into());
+    }
+    Ok(value)
+}
+
+// None if the content is not a manifest at all, e.g. because it was cut off halfway
+fn parse(content: &str) -> Result<Option<Manifest>> {
+    let value = match serde_json::from_str::<Value>(content) {
+        Ok(value) => value,
+        Err(_) => return Ok(None),
+    };
+    Ok(serde_json::from_value(migrate(value)?).ok())
+}
+
+impl Manifest {
+    // A missing manifest is not an error, it just means that this is the first run.
+    // A corrupt one is replaced by the backup from the save before it, if that is any good.
+    pub fn load(path: &Path) -> Result<Manifest> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Manifest::default()),
+            Err(_) => return Err("Unable to read manifest file"),
+        };
+        if let Some(manifest) = parse(&content)? {
+            return Ok(manifest);
+        }
+        let backup = fs::read_to_string(with_suffix(path, ".bak"))
+            .map_err(|_| "Corrupt manifest file, and there is no backup to recover from")?;
+        let mut manifest =
+            parse(&backup)?.ok_or("Corrupt manifest file, and its backup is corrupt too")?;
+        // kept for inspection, and so that the next save doesn't back it up over the good copy
+        fs::rename(path, with_suffix(path, ".corrupt"))
+            .map_err(|_| "Unable to move corrupt manifest file aside")?;
+        manifest.recovered = true;
+        Ok(manifest)
+    }
+
+    // Writes to a temporary file first and renames it over the manifest, so that a crash midway
+    // never leaves a truncated manifest behind. The manifest being replaced is kept as a backup.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut value = serde_json::to_value(self).map_err(|_| "Unable to serialise manifest")?;
+        if let Some(object) = value.as_object_mut() {
+            object.insert("version".to_owned(), VERSION.into());
+        }
+        let serialised =
+            serde_json::to_string_pretty(&value).map_err(|_| "Unable to serialise manifest")?;
+        let temp_path = with_suffix(path, ".tmp");
+        fs::File::create(&temp_path)
+            .and_then(|mut file| {
+                file.write_all(serialised.as_bytes())?;
+                file.sync_all()
+            })
+            .map_err(|_| "Unable to write manifest file")?;
+        if path.exists() {
+            fs::copy(path, with_suffix(path, ".bak"))
+                .map_err(|_| "Unable to back up manifest file")?;
+        }
+        fs::rename(&temp_path, path).map_err(|_| "Unable to write manifest file")
+    }
+
+    pub fn was_recovered(&self) -> bool {
+        self.recovered
+    }
+
+    pub fn has_seen_announcement(&self, id: &str) -> bool {
+        self.seen_announcements.contains(id)
+    }
+
+    // modules that were never offered in the picker are included, so that nothing is missed silently
+    pub fn is_module_selected(&self, code: &str) -> bool {
+        self.module_selection.get(code).copied().unwrap_or(true)
+    }
+
+    pub fn mark_announcement_seen(&mut self, id: &str) {
+        self.seen_announcements.insert(id.to_owned());
+    }
+
+    // Points what is remembered by path at its new place, after the folder `from` was moved to `to`
+    pub fn move_paths(&mut self, from: &Path, to: &Path) {
+        let moved = |key: &String| {
+            Path::new(key)
+                .strip_prefix(from)
+                .ok()
+                .map(|rest| to.join(rest).to_string_lossy().into_owned())
+        };
+        self.merged_folders = self
+            .merged_folders
+            .drain()
+            .map(|(key, members)| (moved(&key).unwrap_or(key), members))
+            .collect();
+        self.transcoded = self
+            .transcoded
+            .drain()
+            .map(|(key, profile)| (moved(&key).unwrap_or(key), profile))
+            .collect();
+        self.transcribed = self
+            .transcribed
+            .drain()
+            .map(|key| moved(&key).unwrap_or(key))
+            .collect();
+    }
+}
@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::util::html_to_text;
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Message {
+    pub id: String,
+    pub subject: String,
+    pub body: String,
+    pub sender_name: Option<String>,
+    pub created_date: String,
+    // replies point to the message that started the conversation
+    parent_id: Option<String>,
+}
+
+pub struct Conversation {
+    // the id of the message that started it
+    pub id: String,
+    pub subject: String,
+    pub messages: Vec<Message>,
+}
+
+impl Message {
+    pub fn sender(&self) -> &str {
+        self.sender_name.as_deref().unwrap_or("Unknown")
+    }
+
+    pub fn conversation_id(&self) -> &str {
+        self.parent_id.as_deref().unwrap_or(&self.id)
+    }
+}
+
+impl Conversation {
+    // groups messages into conversations, ordered by the time the conversation was started
+    pub fn group(messages: Vec<Message>) -> Vec<Conversation> {
+        let mut by_id: HashMap<String, Vec<Message>> = HashMap::new();
+        for message in messages {
+            by_id
+                .entry(message.conversation_id().to_owned())
+                .or_default()
+                .push(message);
+        }
+        let mut conversations = by_id
+            .into_iter()
+            .map(|(id, mut messages)| {
+                // the API returns RFC 3339 timestamps, which sort correctly as strings
+                messages.sort_by(|m1, m2| m1.created_date.cmp(&m2.created_date));
+                Conversation {
+                    id,
+                    subject: messages[0].subject.clone(),
+                    messages,
+                }
+            })
+            .collect::<Vec<_>>();
+        conversations.sort_by(|c1, c2| c1.started().cmp(c2.started()));
+        conversations
+    }
+
+    pub fn started(&self) -> &str {
+        &self.messages[0].created_date
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut output = format!("# {}\n", self.subject);
+        for message in &self.messages {
+            output.push_str(&format!(
+                "\n## {} ({})\n\n{}\n",
+                message.sender(),
+                message.created_date,
+                html_to_text(&message.body)
+            ));
+        }
+        output
+    }
+}
@@ -1,14 +1,22 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 use reqwest::Method;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
 
+use crate::deadline::Deadline;
+use crate::evaluation::PeerEvaluations;
 use crate::file::DirectoryHandle;
+use crate::gradebook::GradebookItem;
+use crate::group::Group;
 use crate::multimedia::MultimediaHandle;
+use crate::roster::{self, Student};
+use crate::syllabus::Syllabus;
 use crate::util::sanitise_filename;
 use crate::{Api, ApiData, Result};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 struct Access {
     #[serde(rename = "access_Full")]
     full: bool,
@@ -26,13 +34,57 @@ struct Access {
     settings_update: bool,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct Announcement {
+    pub id: String,
     pub title: String,
     pub description: String,
+    #[serde(rename = "displayFrom")]
+    pub display_from: Option<String>,
+    // filled in once loaded, since the listing doesn't say which module it came from
+    #[serde(skip)]
+    pub permalink: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiTool {
+    tool_name: String,
+}
+
+// The tools a module has switched on. Lecturers can turn off Files or Multimedia, and the server
+// then answers their listings with nothing, which would otherwise look like a broken response.
+// module/{id}/tools is not verified against LumiNUS (see dump.rs), so an error or an empty list
+// is taken as not knowing, and nothing is skipped.
+#[derive(Debug, Clone)]
+pub struct ModuleTools {
+    // None if the server wouldn't say, in which case every tool is assumed to be there
+    names: Option<Vec<String>>,
+}
+
+impl ModuleTools {
+    pub fn unknown() -> ModuleTools {
+        ModuleTools { names: None }
+    }
+
+    fn has_any(&self, tools: &[&str]) -> bool {
+        self.names.as_ref().is_none_or(|names| {
+            names
+                .iter()
+                .any(|name| tools.iter().any(|tool| name.eq_ignore_ascii_case(tool)))
+        })
+    }
+
+    pub fn has_files(&self) -> bool {
+        self.has_any(&["files", "workbin"])
+    }
+
+    pub fn has_multimedia(&self) -> bool {
+        self.has_any(&["multimedia"])
+    }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Module {
     pub id: String,
     #[serde(rename = "name")]
@@ -41,6 +93,112 @@ pub struct Module {
     pub name: String,
     access: Option<Access>,
     pub term: String,
+    // not from the server, stamped on by the Api that loaded the module
+    #[serde(skip)]
+    pub(crate) folder_name_style: FolderNameStyle,
+    #[serde(skip)]
+    pub(crate) term_folders: bool,
+    // fetched on first use, and shared by everything that asks after that
+    #[serde(skip)]
+    tools: OnceCell<ModuleTools>,
+}
+
+// How module folders are named locally
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FolderNameStyle {
+    // CS2103T
+    #[default]
+    Code,
+    // CS2103T Software Engineering
+    CodeName,
+    // Software Engineering
+    Name,
+}
+
+impl FolderNameStyle {
+    pub const NAMES: &'static [&'static str] = &["code", "code-name", "name"];
+
+    pub fn parse(style: &str) -> Option<FolderNameStyle> {
+        match style {
+            "code" => Some(FolderNameStyle::Code),
+            "code-name" => Some(FolderNameStyle::CodeName),
+            "name" => Some(FolderNameStyle::Name),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FolderNameStyle::Code => "code",
+            FolderNameStyle::CodeName => "code-name",
+            FolderNameStyle::Name => "name",
+        }
+    }
+
+    pub fn folder_name(self, code: &str, name: &str) -> String {
+        sanitise_filename(&match self {
+            FolderNameStyle::Code => code.to_owned(),
+            FolderNameStyle::CodeName => format!("{} {}", code, name),
+            // a module without a name still needs a folder
+            FolderNameStyle::Name if name.trim().is_empty() => code.to_owned(),
+            FolderNameStyle::Name => name.to_owned(),
+        })
+    }
+}
+
+// Which of `folders` are shared with another, as with CS2103 and CS2103T going by name alone.
+// Those modules get code-name folders instead, so that their files don't end up mixed together.
+pub(crate) fn clashing_folders(folders: &[PathBuf]) -> Vec<bool> {
+    folders
+        .iter()
+        .map(|folder| folders.iter().filter(|other| *other == folder).count() > 1)
+        .collect()
+}
+
+pub(crate) fn module_folder_path(term_folders: bool, term: &str, folder_name: &str) -> PathBuf {
+    if term_folders && !term.trim().is_empty() {
+        Path::new(&sanitise_filename(term)).join(folder_name)
+    } else {
+        PathBuf::from(folder_name)
+    }
+}
+
+impl Announcement {
+    pub(crate) async fn load(
+        api: &Api,
+        module_id: &str,
+        archived: bool,
+    ) -> Result<Vec<Announcement>> {
+        let path = format!(
+            "announcement/{}/{}?sortby=displayFrom%20ASC",
+            if archived { "Archived" } else { "NonArchived" },
+            module_id
+        );
+        let api_data = api
+            .api_as_json::<ApiData<Vec<Announcement>>>(&path, Method::GET, None)
+            .await?;
+        if let Some(mut announcements) = api_data.data {
+            for announcement in &mut announcements {
+                announcement.permalink = Some(api.web_url(&format!(
+                    "modules/{}/announcements/{}/{}",
+                    module_id,
+                    if archived { "archived" } else { "active" },
+                    announcement.id
+                )));
+            }
+            Ok(announcements)
+        } else {
+            Err("Invalid API response from server: type mismatch")
+        }
+    }
+
+    pub fn displayed_from(&self) -> Option<SystemTime> {
+        self.display_from.as_deref().and_then(|time| {
+            chrono::DateTime::parse_from_rfc3339(time)
+                .ok()
+                .map(SystemTime::from)
+        })
+    }
 }
 
 impl Module {
@@ -66,27 +224,113 @@ impl Module {
         self.access.is_some()
     }
 
-    pub async fn get_announcements(&self, api: &Api, archived: bool) -> Result<Vec<Announcement>> {
-        let path = format!(
-            "announcement/{}/{}?sortby=displayFrom%20ASC",
-            if archived { "Archived" } else { "NonArchived" },
-            self.id
-        );
-        let api_data = api
-            .api_as_json::<ApiData<Vec<Announcement>>>(&path, Method::GET, None)
-            .await?;
-        if let Some(announcements) = api_data.data {
-            Ok(announcements)
+    pub fn can_read(&self) -> bool {
+        self.access
+            .as_ref()
+            .map(|access| access.full || access.read)
+            .unwrap_or(false)
+    }
+
+    pub fn can_upload(&self) -> bool {
+        self.access
+            .as_ref()
+            .map(|access| access.full || access.create)
+            .unwrap_or(false)
+    }
+
+    pub fn can_manage(&self) -> bool {
+        self.access
+            .as_ref()
+            .map(|access| access.full || access.update || access.delete || access.settings_update)
+            .unwrap_or(false)
+    }
+
+    // we can look, but the server will refuse anything that changes the module
+    pub fn is_read_only(&self) -> bool {
+        self.can_read() && !self.can_upload() && !self.can_manage()
+    }
+
+    pub fn capabilities(&self) -> &'static str {
+        // the module is listed before the lecturer opens it, but nothing in it can be seen until then
+        if !self.has_access() {
+            "no access yet"
+        } else if !self.can_read() {
+            "no access"
+        } else if self.is_read_only() {
+            "read-only"
+        } else if self.can_upload() && self.can_manage() {
+            "upload, manage"
+        } else if self.can_upload() {
+            "upload"
         } else {
-            Err("Invalid API response from server: type mismatch")
+            "manage"
         }
     }
 
-    pub fn workbin_root<F: FnOnce(&str) -> PathBuf>(&self, make_path: F) -> DirectoryHandle {
-        DirectoryHandle::new(self.id.clone(), make_path(&sanitise_filename(&self.code)))
+    pub async fn get_tools(&self, api: &Api) -> Result<ModuleTools> {
+        self.tools
+            .get_or_try_init(|| async {
+                let tools = api
+                    .api_as_json::<ApiData<Vec<ApiTool>>>(
+                        &format!("module/{}/tools", self.id),
+                        Method::GET,
+                        None,
+                    )
+                    .await?
+                    .data
+                    .ok_or("Invalid API response from server: type mismatch")?;
+                Ok(ModuleTools {
+                    names: Some(tools.into_iter().map(|tool| tool.tool_name).collect())
+                        .filter(|names: &Vec<String>| !names.is_empty()),
+                })
+            })
+            .await
+            .cloned()
+    }
+
+    pub async fn get_announcements(&self, api: &Api, archived: bool) -> Result<Vec<Announcement>> {
+        Announcement::load(api, &self.id, archived).await
+    }
+
+    pub async fn get_groups(&self, api: &Api) -> Result<Vec<Group>> {
+        Group::load_mine(api, &self.id).await
+    }
+
+    pub async fn get_peer_evaluations(&self, api: &Api) -> Result<PeerEvaluations> {
+        PeerEvaluations::load(api, &self.id).await
+    }
+
+    pub async fn get_roster(&self, api: &Api) -> Result<Vec<Student>> {
+        roster::load(api, &self.id).await
+    }
+
+    pub async fn get_gradebook(&self, api: &Api) -> Result<Vec<GradebookItem>> {
+        GradebookItem::load(api, &self.id).await
+    }
+
+    pub async fn get_syllabus(&self, api: &Api) -> Result<Syllabus> {
+        Syllabus::load(api, &self.id).await
+    }
+
+    pub async fn get_deadlines(&self, api: &Api) -> Result<Vec<Deadline>> {
+        Deadline::load(api, &self.id, &self.code).await
+    }
+
+    // the local folder this module is mirrored into, already sanitised
+    pub fn folder_name(&self) -> String {
+        self.folder_name_style.folder_name(&self.code, &self.name)
+    }
+
+    // where the module's folder lives relative to the download destination, under its term if asked to
+    pub fn folder_path(&self) -> PathBuf {
+        module_folder_path(self.term_folders, &self.term, &self.folder_name())
+    }
+
+    pub fn workbin_root<F: FnOnce(&Path) -> PathBuf>(&self, make_path: F) -> DirectoryHandle {
+        DirectoryHandle::new(self.id.clone(), make_path(&self.folder_path()))
     }
 
-    pub fn multimedia_root<F: FnOnce(&str) -> PathBuf>(&self, make_path: F) -> MultimediaHandle {
-        MultimediaHandle::new(self.id.clone(), make_path(&sanitise_filename(&self.code)))
+    pub fn multimedia_root<F: FnOnce(&Path) -> PathBuf>(&self, make_path: F) -> MultimediaHandle {
+        MultimediaHandle::new(self.id.clone(), make_path(&self.folder_path()))
     }
 }
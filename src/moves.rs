@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::util::walk_files;
+
+// What to do when a file that was synced is gone from where it was downloaded to,
+// but turns up elsewhere in the mirror because it was moved by hand
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum MovePolicy {
+    // download it again, as if it were never there
+    Download,
+    // leave it where it was moved to, and note that in the manifest
+    Leave,
+    // hard link it back to where it was downloaded to, so that it is in both places
+    Relink,
+}
+
+impl MovePolicy {
+    pub const NAMES: &'static [&'static str] = &["download", "leave", "relink"];
+
+    pub fn parse(policy: &str) -> Option<MovePolicy> {
+        match policy.to_lowercase().as_str() {
+            "download" => Some(MovePolicy::Download),
+            "leave" => Some(MovePolicy::Leave),
+            "relink" => Some(MovePolicy::Relink),
+            _ => None,
+        }
+    }
+}
+
+// What a downloaded file looked like, for recognising it again after it is moved
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fingerprint {
+    pub size: u64,
+    pub sha256: String,
+}
+
+impl Fingerprint {
+    pub fn of(path: &Path) -> io::Result<Fingerprint> {
+        let mut hasher = Sha256::new();
+        let size = io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+        Ok(Fingerprint {
+            size,
+            sha256: format!("{:x}", hasher.finalize()),
+        })
+    }
+}
+
+// Looks under `dir` for each of the missing files, by fingerprint. Only files of the right size
+// are hashed, so this stays cheap unless a lot of files are the same size.
+// Returns missing path => where it was found.
+pub fn find_moved(dir: &Path, missing: &[(PathBuf, &Fingerprint)]) -> HashMap<PathBuf, PathBuf> {
+    let mut sizes = HashMap::<u64, Vec<PathBuf>>::new();
+    walk_files(dir, &mut |path, metadata| {
+        sizes.entry(metadata.len()).or_default().push(path)
+    });
+    let mut hashes = HashMap::<PathBuf, Option<Fingerprint>>::new();
+    let mut found = HashMap::new();
+    for (path, fingerprint) in missing {
+        let candidates = sizes.get(&fingerprint.size).map_or(&[][..], Vec::as_slice);
+        let moved_to = candidates.iter().find(|candidate| {
+            hashes
+                .entry(candidate.to_path_buf())
+                .or_insert_with(|| Fingerprint::of(candidate).ok())
+                .as_ref()
+                == Some(fingerprint)
+        });
+        if let Some(moved_to) = moved_to {
+            found.insert(path.clone(), moved_to.clone());
+        }
+    }
+    found
+}
@@ -1,10 +1,10 @@
 use std::path::{Path, PathBuf};
-use std::time::SystemTime;
+use std::time::{Instant, SystemTime};
 
 use async_trait::async_trait;
 use futures_util::future;
 use reqwest::Method;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use tokio::process::Command;
 
 use crate::resource;
@@ -12,7 +12,7 @@ use crate::resource::{OverwriteMode, OverwriteResult, Resource, RetryableError,
 use crate::util::{parse_time, sanitise_filename};
 use crate::{Api, ApiData, Result};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct Channel {
     id: String,
@@ -20,10 +20,10 @@ struct Channel {
     is_external_tool: bool,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct Media {
-    /* id: String, */
+    id: String,
     name: String,
     last_updated_date: String,
     stream_url_path: String, // used to download the stream
@@ -36,6 +36,7 @@ pub struct MultimediaHandle {
 
 pub struct Video {
     stream_url_path: String,
+    permalink: String,
     path: PathBuf,
     last_updated: SystemTime,
 }
@@ -62,7 +63,7 @@ impl MultimediaHandle {
                 channels
                     .into_iter()
                     .filter(|c| !c.is_external_tool)
-                    .map(|c| Self::load_channel(api, c, &self.path)),
+                    .map(|c| Self::load_channel(api, &self.id, c, &self.path)),
             )
             .await
             .into_iter()
@@ -72,7 +73,12 @@ impl MultimediaHandle {
         }
     }
 
-    async fn load_channel(api: &Api, channel: Channel, path: &Path) -> Result<Vec<Video>> {
+    async fn load_channel(
+        api: &Api,
+        module_id: &str,
+        channel: Channel,
+        path: &Path,
+    ) -> Result<Vec<Video>> {
         let channel_resp = api
             .api_as_json::<ApiData<Vec<Media>>>(
                 &format!("multimedia/{}/medias", channel.id),
@@ -88,6 +94,10 @@ impl MultimediaHandle {
                 .into_iter()
                 .map(|m| Video {
                     stream_url_path: m.stream_url_path,
+                    permalink: api.web_url(&format!(
+                        "modules/{}/multimedia/{}/{}",
+                        module_id, channel.id, m.id
+                    )),
                     path: channel_path.join(Self::make_mkv_extension(Path::new(
                         &sanitise_filename(&m.name),
                     ))),
@@ -109,6 +119,14 @@ impl Resource for Video {
         &self.path
     }
 
+    fn last_updated(&self) -> SystemTime {
+        self.last_updated
+    }
+
+    fn permalink(&self) -> Option<&str> {
+        Some(&self.permalink)
+    }
+
     async fn download(
         &self,
         api: &Api,
@@ -122,6 +140,8 @@ impl Resource for Video {
             temp_destination,
             overwrite,
             self.last_updated,
+            // videos are remuxed by ffmpeg on the way down, so they never match the server's bytes
+            None,
             move |_| future::ready(Ok(self.stream_url_path.as_str())),
             move |api, stream_url_path, temp_destination| {
                 Self::stream_video(api, stream_url_path, temp_destination)
@@ -137,6 +157,8 @@ impl Video {
         stream_url_path: &str,
         temp_destination: &Path,
     ) -> RetryableResult<()> {
+        // ffmpeg does its own fetching, so all we can tell is how long the whole thing took
+        let started = Instant::now();
         let success = Command::new(&api.ffmpeg_path)
             .arg("-y") // flag to overwrite output file without prompting
             .arg("-i")
@@ -150,8 +172,14 @@ impl Video {
             .status
             .success();
         if success {
+            let bytes = tokio::fs::metadata(temp_destination)
+                .await
+                .map_or(0, |metadata| metadata.len());
+            api.stats()
+                .record_transfer(stream_url_path, bytes, started.elapsed());
             Ok(())
         } else {
+            api.stats().record_retry(stream_url_path);
             Err(RetryableError::Retry("ffmpeg returned nonzero exit code"))
         }
     }
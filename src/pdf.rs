@@ -0,0 +1,190 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use lopdf::{Dictionary, Document, Object, ObjectId};
+
+use crate::util::natural_cmp;
+use crate::Result;
+
+const COMBINED_SUFFIX: &str = "_combined.pdf";
+// attributes a page may inherit from its ancestors in the page tree
+const INHERITABLE_PAGE_ATTRIBUTES: &[&[u8]] = &[b"Resources", b"MediaBox", b"CropBox", b"Rotate"];
+
+// Text found on the cover page that LumiNUS stamps onto the front of some PDFs.
+// Matching is case-insensitive.
+const COVER_PAGE_MARKERS: &[&str] = &["downloaded from luminus", "luminus.nus.edu.sg"];
+// a real first page with lecture content is usually longer than the generated cover page,
+// so don't treat a page as the cover page just because it mentions LumiNUS somewhere
+const MAX_COVER_PAGE_TEXT_LEN: usize = 1000;
+
+pub fn is_pdf(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("pdf"))
+        .unwrap_or(false)
+}
+
+fn is_cover_page(document: &Document, page_number: u32) -> bool {
+    document
+        .extract_text(&[page_number])
+        .map(|text| {
+            let text = text.to_lowercase();
+            text.len() <= MAX_COVER_PAGE_TEXT_LEN
+                && COVER_PAGE_MARKERS
+                    .iter()
+                    .any(|marker| text.contains(marker))
+        })
+        .unwrap_or(false)
+}
+
+// Removes the generated cover page of the PDF at `path` in place, if it has one.
+// The untouched file is copied to `backup_path` first.
+// Returns whether a cover page was removed.
+pub fn strip_cover_page(path: &Path, backup_path: &Path) -> Result<bool> {
+    let mut document = Document::load(path).map_err(|_| "Unable to parse PDF")?;
+    if document.get_pages().len() < 2 || !is_cover_page(&document, 1) {
+        return Ok(false);
+    }
+
+    let modified = fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .map_err(|_| "Unable to retrieve file metadata")?;
+    if let Some(parent) = backup_path.parent() {
+        fs::create_dir_all(parent).map_err(|_| "Unable to create directory")?;
+    }
+    fs::copy(path, backup_path).map_err(|_| "Unable to back up original PDF")?;
+
+    document.delete_pages(&[1]);
+    document.prune_objects();
+    document
+        .save(path)
+        .map_err(|_| "Unable to save PDF without cover page")?;
+
+    // keep the server's last updated time, otherwise we would never notice a newer version
+    filetime::set_file_mtime(path, filetime::FileTime::from_system_time(modified))
+        .map_err(|_| "Unable to set last modified time")?;
+    Ok(true)
+}
+
+pub fn combined_path(folder: &Path) -> Option<PathBuf> {
+    let name = folder.file_name()?.to_string_lossy();
+    Some(folder.join(format!("{}{}", name, COMBINED_SUFFIX)))
+}
+
+// The PDFs that make up the combined PDF of a folder, in natural file name order.
+// Temporary files, hidden files and previously combined PDFs are left out.
+pub fn folder_members(folder: &Path) -> Result<Vec<PathBuf>> {
+    let mut members = fs::read_dir(folder)
+        .map_err(|_| "Unable to read directory")?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && is_pdf(path))
+        .filter(|path| {
+            let name = path.file_name().unwrap().to_string_lossy();
+            !name.starts_with("~!") && !name.starts_with('.') && !name.ends_with(COMBINED_SUFFIX)
+        })
+        .collect::<Vec<_>>();
+    members.sort_by(|p1, p2| {
+        natural_cmp(
+            &p1.file_name().unwrap().to_string_lossy(),
+            &p2.file_name().unwrap().to_string_lossy(),
+        )
+    });
+    Ok(members)
+}
+
+// Concatenates the given PDFs into a single PDF at `output`.
+pub fn merge(inputs: &[PathBuf], output: &Path) -> Result<()> {
+    let mut next_id = 1;
+    let mut page_ids = vec![];
+    let mut objects = BTreeMap::new();
+    for input in inputs {
+        let mut document = Document::load(input).map_err(|_| "Unable to parse PDF")?;
+        document.renumber_objects_with(next_id);
+        next_id = document.max_id + 1;
+        for page_id in document.page_iter().collect::<Vec<_>>() {
+            inherit_page_attributes(&mut document, page_id);
+            page_ids.push(page_id);
+        }
+        objects.append(&mut document.objects);
+    }
+
+    let pages_id = (next_id, 0);
+    let catalog_id = (next_id + 1, 0);
+    let mut merged = Document::with_version("1.5");
+    // the page trees and catalogs of the inputs are replaced with our own
+    merged.objects = objects
+        .into_iter()
+        .filter(|(_, object)| match object.type_name() {
+            Ok(name) => !matches!(name, "Catalog" | "Pages" | "Outlines" | "Outline"),
+            Err(_) => true,
+        })
+        .collect();
+    for page_id in &page_ids {
+        if let Ok(page) = merged
+            .get_object_mut(*page_id)
+            .and_then(Object::as_dict_mut)
+        {
+            page.set("Parent", pages_id);
+        }
+    }
+
+    let mut pages = Dictionary::new();
+    pages.set("Type", "Pages");
+    pages.set("Count", page_ids.len() as i64);
+    pages.set(
+        "Kids",
+        page_ids
+            .iter()
+            .map(|id| Object::Reference(*id))
+            .collect::<Vec<_>>(),
+    );
+    merged.objects.insert(pages_id, Object::Dictionary(pages));
+    let mut catalog = Dictionary::new();
+    catalog.set("Type", "Catalog");
+    catalog.set("Pages", pages_id);
+    merged
+        .objects
+        .insert(catalog_id, Object::Dictionary(catalog));
+    merged.trailer.set("Root", catalog_id);
+    merged.max_id = catalog_id.0;
+
+    merged
+        .save(output)
+        .map(|_| ())
+        .map_err(|_| "Unable to save combined PDF")
+}
+
+// Copies attributes inherited from the page tree onto the page itself,
+// because we throw away the page tree when merging
+fn inherit_page_attributes(document: &mut Document, page_id: ObjectId) {
+    let mut inherited = Dictionary::new();
+    let mut current = document
+        .get_dictionary(page_id)
+        .ok()
+        .and_then(|page| page.get(b"Parent").and_then(Object::as_reference).ok());
+    while let Some(parent_id) = current {
+        let parent = match document.get_dictionary(parent_id) {
+            Ok(parent) => parent,
+            Err(_) => break,
+        };
+        for key in INHERITABLE_PAGE_ATTRIBUTES {
+            if !inherited.has(key) {
+                if let Ok(value) = parent.get(key) {
+                    inherited.set(key.to_vec(), value.clone());
+                }
+            }
+        }
+        current = parent.get(b"Parent").and_then(Object::as_reference).ok();
+    }
+    if let Ok(page) = document
+        .get_object_mut(page_id)
+        .and_then(Object::as_dict_mut)
+    {
+        for (key, value) in inherited.iter() {
+            if !page.has(key) {
+                page.set(key.clone(), value.clone());
+            }
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+
+use crate::file::DirectoryHandle;
+use crate::module::{Announcement, Module};
+use crate::multimedia::MultimediaHandle;
+use crate::resource::Resource;
+use crate::{Api, Result};
+
+// A course on whichever platform it lives on. LumiNUS calls these modules.
+#[derive(Debug, Clone)]
+pub struct Course {
+    pub id: String,
+    pub code: String,
+    pub name: String,
+    pub term: String,
+    pub is_teaching: bool,
+    // where the course is mirrored to, relative to the download destination
+    pub folder: PathBuf,
+}
+
+impl From<&Module> for Course {
+    fn from(module: &Module) -> Course {
+        Course {
+            id: module.id.clone(),
+            code: module.code.clone(),
+            name: module.name.clone(),
+            term: module.term.clone(),
+            is_teaching: module.is_teaching(),
+            folder: module.folder_path(),
+        }
+    }
+}
+
+// The content model that syncing needs, independent of the LMS behind it.
+// Resource paths start with the course's folder.
+#[async_trait(?Send)]
+pub trait LearningPlatform {
+    fn platform_name(&self) -> &'static str;
+
+    // the logged in session, which resources are downloaded through
+    fn session(&self) -> &Api;
+
+    async fn user_name(&self) -> Result<String>;
+
+    // courses in the given term, or in the current and later terms if none is given
+    async fn courses(&self, term: Option<String>) -> Result<Vec<Course>>;
+
+    async fn announcements(&self, course: &Course) -> Result<Vec<Announcement>>;
+
+    async fn files(
+        &self,
+        course: &Course,
+        include_uploadable: bool,
+    ) -> Result<Vec<Box<dyn Resource>>>;
+
+    async fn videos(&self, course: &Course) -> Result<Vec<Box<dyn Resource>>>;
+}
+
+#[async_trait(?Send)]
+impl LearningPlatform for Api {
+    fn platform_name(&self) -> &'static str {
+        "LumiNUS"
+    }
+
+    fn session(&self) -> &Api {
+        self
+    }
+
+    async fn user_name(&self) -> Result<String> {
+        Api::name(self).await
+    }
+
+    async fn courses(&self, term: Option<String>) -> Result<Vec<Course>> {
+        Ok(self
+            .modules(term)
+            .await?
+            .iter()
+            .filter(|module| module.has_access())
+            .map(Course::from)
+            .collect())
+    }
+
+    async fn announcements(&self, course: &Course) -> Result<Vec<Announcement>> {
+        Announcement::load(self, &course.id, false).await
+    }
+
+    async fn files(
+        &self,
+        course: &Course,
+        include_uploadable: bool,
+    ) -> Result<Vec<Box<dyn Resource>>> {
+        let root = DirectoryHandle::new(course.id.clone(), course.folder.clone());
+        let files = root.load(self, include_uploadable).await?;
+        Ok(files
+            .into_iter()
+            .map(|file| Box::new(file) as Box<dyn Resource>)
+            .collect())
+    }
+
+    async fn videos(&self, course: &Course) -> Result<Vec<Box<dyn Resource>>> {
+        let root = MultimediaHandle::new(course.id.clone(), course.folder.join("Multimedia"));
+        let videos = root.load(self).await?;
+        Ok(videos
+            .into_iter()
+            .map(|video| Box::new(video) as Box<dyn Resource>)
+            .collect())
+    }
+}
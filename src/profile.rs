@@ -0,0 +1,76 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::Result;
+
+// Named sets of command line options, so that e.g. a quick files-only sync and a full one
+// don't have to be typed out every time. Each profile starts with `[name]`, followed by one
+// option per line, written as on the command line minus the dashes:
+//
+//     [quick]
+//     download-to = nus
+//     max-bytes-per-run = 50M
+//
+// Options that take no value are written on their own. Blank lines and lines starting with # are ignored.
+#[derive(Debug, Default)]
+pub struct SyncProfiles {
+    // name => (option, value)
+    profiles: BTreeMap<String, Vec<(String, Option<String>)>>,
+}
+
+impl SyncProfiles {
+    // a missing file just means that no profiles are defined
+    pub fn load(path: &Path) -> Result<SyncProfiles> {
+        match fs::read_to_string(path) {
+            Ok(content) => SyncProfiles::parse(&content),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(SyncProfiles::default()),
+            Err(_) => Err("Unable to read profiles file"),
+        }
+    }
+
+    pub fn parse(content: &str) -> Result<SyncProfiles> {
+        let mut profiles = BTreeMap::new();
+        let mut current = None;
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line
+                .strip_prefix('[')
+                .and_then(|rest| rest.strip_suffix(']'))
+            {
+                let name = name.trim();
+                if name.is_empty() {
+                    return Err("Invalid line in profiles file, expected [name]");
+                }
+                profiles.entry(name.to_owned()).or_insert_with(Vec::new);
+                current = Some(name.to_owned());
+                continue;
+            }
+            let options = current
+                .as_ref()
+                .and_then(|name| profiles.get_mut(name))
+                .ok_or("Options in profiles file must come after a [name]")?;
+            let (option, value) = match line.split_once('=') {
+                Some((option, value)) => (option.trim(), Some(value.trim().to_owned())),
+                None => (line, None),
+            };
+            let option = option.trim_start_matches('-');
+            if option.is_empty() {
+                return Err("Invalid line in profiles file, expected option = value");
+            }
+            options.push((option.to_owned(), value));
+        }
+        Ok(SyncProfiles { profiles })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&[(String, Option<String>)]> {
+        self.profiles.get(name).map(Vec::as_slice)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.keys().map(String::as_str)
+    }
+}
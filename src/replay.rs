@@ -0,0 +1,204 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use reqwest::header::{
+    HeaderMap, HeaderName, HeaderValue, CONTENT_ENCODING, CONTENT_LENGTH, SET_COOKIE,
+    TRANSFER_ENCODING,
+};
+use reqwest::{Method, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::util::sanitise_filename;
+use crate::Result;
+
+pub(crate) const REDACTED: &str = "<redacted>";
+// JSON fields that may hold credentials, wherever they appear in a response
+const SECRET_FIELDS: &[&str] = &["access_token", "refresh_token", "id_token", "token", "jwt"];
+
+// Where API traffic is recorded to, or played back from
+#[derive(Debug, Clone)]
+pub enum Traffic {
+    Record(PathBuf),
+    Replay(PathBuf),
+}
+
+// One API response as it is stored on disk, readable enough to attach to a bug report
+#[derive(Debug, Serialize, Deserialize)]
+struct Recording {
+    method: String,
+    path: String,
+    status: u16,
+    headers: BTreeMap<String, String>,
+    body: String,
+    // why it couldn't be deserialised, for responses kept by `save_failure`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+// one file per request, named so that a person can still find the one they're after
+fn recording_path(dir: &Path, method: &Method, path: &str) -> PathBuf {
+    let key = format!("{} {}", method, path);
+    let digest = Sha256::digest(key.as_bytes());
+    let readable = sanitise_filename(&key.replace(['?', '/'], "_"));
+    let readable = readable.chars().take(80).collect::<String>();
+    dir.join(format!(
+        "{}-{:02x}{:02x}{:02x}{:02x}.json",
+        readable, digest[0], digest[1], digest[2], digest[3]
+    ))
+}
+
+// How much of a response body that couldn't be understood is kept, enough to see what changed in it
+const FAILURE_SNIPPET_SIZE: usize = 4096;
+
+// Replaces the values of `fields`, wherever they appear, matching names case-insensitively
+pub(crate) fn redact_fields(value: &mut Value, fields: &[&str]) {
+    match value {
+        Value::Object(object) => {
+            for (name, field) in object.iter_mut() {
+                if fields.contains(&name.to_lowercase().as_str()) {
+                    *field = Value::String(REDACTED.to_owned());
+                } else {
+                    redact_fields(field, fields);
+                }
+            }
+        }
+        Value::Array(items) => items
+            .iter_mut()
+            .for_each(|item| redact_fields(item, fields)),
+        _ => {}
+    }
+}
+
+// The body pretty printed if it is JSON, without the session token or any field that may hold one
+pub(crate) fn redacted_body(body: &[u8], jwt: &str) -> String {
+    let text = String::from_utf8_lossy(body);
+    let text = match serde_json::from_slice::<Value>(body) {
+        Ok(mut json) => {
+            redact_fields(&mut json, SECRET_FIELDS);
+            serde_json::to_string_pretty(&json).unwrap_or_else(|_| text.into_owned())
+        }
+        Err(_) => text.into_owned(),
+    };
+    if jwt.is_empty() {
+        text
+    } else {
+        text.replace(jwt, REDACTED)
+    }
+}
+
+// A response as it is stored on disk, without credentials in it
+fn recording_of(
+    method: &Method,
+    path: &str,
+    status: StatusCode,
+    headers: &HeaderMap,
+    body: &[u8],
+    jwt: &str,
+) -> Recording {
+    Recording {
+        method: method.to_string(),
+        path: path.to_owned(),
+        status: status.as_u16(),
+        headers: headers
+            .iter()
+            // the body is rewritten, so anything describing its encoding no longer holds
+            .filter(|(name, _)| {
+                ![
+                    SET_COOKIE,
+                    CONTENT_LENGTH,
+                    CONTENT_ENCODING,
+                    TRANSFER_ENCODING,
+                ]
+                .contains(name)
+            })
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_owned())))
+            .collect(),
+        body: redacted_body(body, jwt),
+        error: None,
+    }
+}
+
+// Saves the response, scrubbed of the session token, and hands back an identical one to carry on with
+pub(crate) async fn record(
+    dir: &Path,
+    method: &Method,
+    path: &str,
+    res: Response,
+    jwt: &str,
+) -> Result<Response> {
+    let status = res.status();
+    let headers = res.headers().clone();
+    let body = res
+        .bytes()
+        .await
+        .map_err(|_| "Failed reading response to record")?;
+
+    let recording = recording_of(method, path, status, &headers, &body, jwt);
+    tokio::fs::create_dir_all(dir)
+        .await
+        .map_err(|_| "Unable to create recording directory")?;
+    let serialised =
+        serde_json::to_string_pretty(&recording).map_err(|_| "Unable to serialise recording")?;
+    tokio::fs::write(recording_path(dir, method, path), serialised)
+        .await
+        .map_err(|_| "Unable to write recording")?;
+
+    let mut builder = http::Response::builder().status(status);
+    for (name, value) in headers.iter() {
+        builder = builder.header(name, value);
+    }
+    builder
+        .body(body)
+        .map(Response::from)
+        .map_err(|_| "Unable to rebuild recorded response")
+}
+
+// Keeps the start of a response that couldn't be deserialised, scrubbed like a recording, so that
+// `diagnose` can show which LumiNUS response changed and how. Only the last one is kept.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn save_failure(
+    file: &Path,
+    method: &Method,
+    path: &str,
+    status: StatusCode,
+    headers: &HeaderMap,
+    body: &[u8],
+    jwt: &str,
+    error: String,
+) {
+    let mut recording = recording_of(method, path, status, headers, body, jwt);
+    if recording.body.len() > FAILURE_SNIPPET_SIZE {
+        let mut end = FAILURE_SNIPPET_SIZE;
+        while !recording.body.is_char_boundary(end) {
+            end -= 1;
+        }
+        recording.body.truncate(end);
+    }
+    recording.error = Some(error);
+    if let Ok(serialised) = serde_json::to_string_pretty(&recording) {
+        let _ = tokio::fs::write(file, serialised).await;
+    }
+}
+
+pub(crate) async fn replay(dir: &Path, method: &Method, path: &str) -> Result<Response> {
+    let content = tokio::fs::read_to_string(recording_path(dir, method, path))
+        .await
+        .map_err(|_| "No recorded response for this request")?;
+    let recording =
+        serde_json::from_str::<Recording>(&content).map_err(|_| "Corrupt recorded response")?;
+    let mut builder = http::Response::builder().status(recording.status);
+    for (name, value) in &recording.headers {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(value),
+        ) {
+            builder = builder.header(name, value);
+        }
+    }
+    builder
+        .body(recording.body)
+        .map(Response::from)
+        .map_err(|_| "Corrupt recorded response")
+}
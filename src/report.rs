@@ -0,0 +1,159 @@
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use crate::module::Announcement;
+use crate::util::{escape_html, html_to_text};
+
+// announcements are cut down to roughly this many characters in the report
+const SUMMARY_LENGTH: usize = 280;
+
+pub struct ModuleReport {
+    pub code: String,
+    pub name: String,
+    pub new_files: Vec<PathBuf>,
+    pub announcements: Vec<Announcement>,
+    // why the announcements couldn't be loaded, when they couldn't
+    pub announcements_error: Option<&'static str>,
+}
+
+// A Markdown digest of what happened on LumiNUS since some point in time
+pub struct Report {
+    pub since: SystemTime,
+    pub modules: Vec<ModuleReport>,
+}
+
+pub fn summarise(html: &str) -> String {
+    let text = html_to_text(html)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if text.chars().count() <= SUMMARY_LENGTH {
+        text
+    } else {
+        let cut = text
+            .char_indices()
+            .nth(SUMMARY_LENGTH)
+            .map(|(idx, _)| idx)
+            .unwrap_or_else(|| text.len());
+        // don't cut in the middle of a word if we can help it
+        let cut = text[..cut].rfind(' ').unwrap_or(cut);
+        format!("{}…", &text[..cut])
+    }
+}
+
+impl ModuleReport {
+    pub fn is_empty(&self) -> bool {
+        self.new_files.is_empty()
+            && self.announcements.is_empty()
+            && self.announcements_error.is_none()
+    }
+}
+
+impl Report {
+    pub fn to_markdown(&self) -> String {
+        let since = chrono::DateTime::<chrono::Local>::from(self.since);
+        let now = chrono::Local::now();
+        let mut output = format!(
+            "# LumiNUS report {} to {}\n",
+            since.format("%Y-%m-%d"),
+            now.format("%Y-%m-%d")
+        );
+        let active_modules = self
+            .modules
+            .iter()
+            .filter(|module| !module.is_empty())
+            .collect::<Vec<_>>();
+        if active_modules.is_empty() {
+            output.push_str("\nNothing new.\n");
+        }
+        for module in active_modules {
+            output.push_str(&format!("\n## {} {}\n", module.code, module.name));
+            if let Some(error) = module.announcements_error {
+                output.push_str(&format!(
+                    "\n*Announcements could not be loaded: {}*\n",
+                    error
+                ));
+            }
+            if !module.announcements.is_empty() {
+                output.push_str("\n### Announcements\n\n");
+                for announcement in &module.announcements {
+                    output.push_str(&format!(
+                        "- **{}**: {}\n",
+                        announcement.title,
+                        summarise(&announcement.description)
+                    ));
+                }
+            }
+            if !module.new_files.is_empty() {
+                output.push_str("\n### New files\n\n");
+                for file in &module.new_files {
+                    output.push_str(&format!("- {}\n", file.display()));
+                }
+            }
+        }
+        output
+    }
+}
+
+// Every announcement across modules in one document, oldest first, for printing or skimming
+pub struct Digest<'a> {
+    // module code and name, and the announcement
+    entries: Vec<(&'a str, &'a str, &'a Announcement)>,
+}
+
+impl<'a> Digest<'a> {
+    pub fn new(entries: Vec<(&'a str, &'a str, &'a Announcement)>) -> Digest<'a> {
+        let mut entries = entries;
+        // undated announcements go last, since there is no telling when they are from
+        entries.sort_by_key(|(_, _, announcement)| {
+            let displayed_from = announcement.displayed_from();
+            (displayed_from.is_none(), displayed_from)
+        });
+        Digest { entries }
+    }
+
+    fn date(announcement: &Announcement) -> String {
+        announcement
+            .displayed_from()
+            .map(|time| {
+                chrono::DateTime::<chrono::Local>::from(time)
+                    .format("%a %Y-%m-%d %H:%M")
+                    .to_string()
+            })
+            .unwrap_or_else(|| "undated".to_owned())
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut output = "# Announcements\n".to_owned();
+        for (code, name, announcement) in &self.entries {
+            output.push_str(&format!(
+                "\n## {}\n\n*{} {}, {}*\n\n{}\n",
+                announcement.title,
+                code,
+                name,
+                Digest::date(announcement),
+                html_to_text(&announcement.description).trim()
+            ));
+        }
+        output
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut output = "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+            <title>Announcements</title>\n</head>\n<body>\n<h1>Announcements</h1>\n"
+            .to_owned();
+        for (code, name, announcement) in &self.entries {
+            output.push_str(&format!(
+                "<article>\n<h2>{}</h2>\n<p><em>{} {}, {}</em></p>\n{}\n</article>\n",
+                escape_html(&announcement.title),
+                escape_html(code),
+                escape_html(name),
+                Digest::date(announcement),
+                // announcements are HTML already, but only the harmless parts are kept
+                ammonia::clean(&announcement.description)
+            ));
+        }
+        output.push_str("</body>\n</html>\n");
+        output
+    }
+}
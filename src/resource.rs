@@ -1,14 +1,36 @@
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
-use std::time::SystemTime;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
 use async_trait::async_trait;
 use futures_util::future::Future;
+use rand::Rng;
+use reqwest::header::RANGE;
+use reqwest::{StatusCode, Url};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::sync::Semaphore;
 
+use crate::validate;
+use crate::versions::RenameScheme;
 use crate::{Api, Error, Result};
 
+// where originals of post-processed downloads are kept, relative to the download destination
+pub const ORIGINALS_DIR: &str = ".fluminurs-originals";
+
 #[async_trait(?Send)]
 pub trait Resource {
     fn path(&self) -> &Path;
+    fn last_updated(&self) -> SystemTime;
+    // size reported by the server, if it tells us
+    fn size(&self) -> Option<u64> {
+        None
+    }
+    // the page for this resource on the platform's website, if we know how to link to it
+    fn permalink(&self) -> Option<&str> {
+        None
+    }
     async fn download(
         &self,
         api: &Api,
@@ -18,11 +40,172 @@ pub trait Resource {
     ) -> Result<OverwriteResult>;
 }
 
+// lets callers treat resources from different platforms alike
+#[async_trait(?Send)]
+impl<R: Resource + ?Sized> Resource for Box<R> {
+    fn path(&self) -> &Path {
+        (**self).path()
+    }
+
+    fn last_updated(&self) -> SystemTime {
+        (**self).last_updated()
+    }
+
+    fn size(&self) -> Option<u64> {
+        (**self).size()
+    }
+
+    fn permalink(&self) -> Option<&str> {
+        (**self).permalink()
+    }
+
+    async fn download(
+        &self,
+        api: &Api,
+        destination: &Path,
+        temp_destination: &Path,
+        overwrite: OverwriteMode,
+    ) -> Result<OverwriteResult> {
+        (**self)
+            .download(api, destination, temp_destination, overwrite)
+            .await
+    }
+}
+
+// Resources arrive in whatever order the server and our concurrent requests produce them.
+// Sorting by path (which starts with the module) gives listings that can be diffed between runs.
+pub fn sort_by_path<T: Resource>(resources: &mut [T]) {
+    resources.sort_by(|r1, r2| r1.path().cmp(r2.path()));
+}
+
+// Collects what is written into batches as large as the write buffer, and writes each one out
+// on the blocking pool. With a gate, a permit is held until the batch has reached the disk,
+// which caps how many downloads touch the disk at once.
+pub(crate) struct GatedWriter {
+    file: Option<std::fs::File>,
+    gate: Option<Arc<Semaphore>>,
+    batch: Vec<u8>,
+    capacity: usize,
+}
+
+impl GatedWriter {
+    pub(crate) fn new(
+        file: std::fs::File,
+        gate: Option<Arc<Semaphore>>,
+        capacity: usize,
+    ) -> GatedWriter {
+        GatedWriter {
+            file: Some(file),
+            gate,
+            batch: Vec::with_capacity(capacity),
+            capacity: capacity.max(1),
+        }
+    }
+
+    pub(crate) async fn write(&mut self, mut buf: &[u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            let taken = (self.capacity - self.batch.len()).min(buf.len());
+            self.batch.extend_from_slice(&buf[..taken]);
+            buf = &buf[taken..];
+            if self.batch.len() == self.capacity {
+                self.write_batch().await?;
+            }
+        }
+        Ok(())
+    }
+
+    // writes out whatever is left
+    pub(crate) async fn finish(mut self) -> io::Result<()> {
+        if !self.batch.is_empty() {
+            self.write_batch().await?;
+        }
+        Ok(())
+    }
+
+    async fn write_batch(&mut self) -> io::Result<()> {
+        let _permit = match &self.gate {
+            Some(gate) => Some(
+                gate.clone()
+                    .acquire_owned()
+                    .await
+                    .map_err(io::Error::other)?,
+            ),
+            None => None,
+        };
+        let mut file = self
+            .file
+            .take()
+            .ok_or_else(|| io::Error::other("File was lost in a failed write"))?;
+        let batch = std::mem::take(&mut self.batch);
+        // without a gate, the OS is left to write back when it likes
+        let sync = _permit.is_some();
+        let (file, mut batch, res) = tokio::task::spawn_blocking(move || {
+            let res = file
+                .write_all(&batch)
+                .and_then(|_| if sync { file.sync_data() } else { Ok(()) });
+            (file, batch, res)
+        })
+        .await
+        .map_err(io::Error::other)?;
+        self.file = Some(file);
+        batch.clear();
+        self.batch = batch;
+        res
+    }
+}
+
 #[derive(Copy, Clone)]
 pub enum OverwriteMode {
     Skip,
     Overwrite,
     Rename,
+    // the old version goes to the OS trash, where it can still be restored
+    Trash,
+}
+
+impl OverwriteMode {
+    pub const NAMES: &'static [&'static str] = &["skip", "overwrite", "rename", "trash"];
+
+    pub fn parse(mode: &str) -> Option<OverwriteMode> {
+        match mode.to_lowercase().as_str() {
+            "skip" => Some(OverwriteMode::Skip),
+            "overwrite" => Some(OverwriteMode::Overwrite),
+            "rename" => Some(OverwriteMode::Rename),
+            "trash" => Some(OverwriteMode::Trash),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OverwriteMode::Skip => "skip",
+            OverwriteMode::Overwrite => "overwrite",
+            OverwriteMode::Rename => "rename",
+            OverwriteMode::Trash => "trash",
+        }
+    }
+}
+
+// How a file that is already on disk is judged to be up to date
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CompareBy {
+    // its last modified time, which syncs set to the server's
+    Mtime,
+    // its size and samples of its content, for copies whose last modified times mean nothing,
+    // e.g. an archive copied onto a fresh machine without its manifest
+    Content,
+}
+
+impl CompareBy {
+    pub const NAMES: &'static [&'static str] = &["mtime", "content"];
+
+    pub fn parse(compare_by: &str) -> Option<CompareBy> {
+        match compare_by.to_lowercase().as_str() {
+            "mtime" => Some(CompareBy::Mtime),
+            "content" => Some(CompareBy::Content),
+            _ => None,
+        }
+    }
 }
 
 pub enum OverwriteResult {
@@ -31,18 +214,129 @@ pub enum OverwriteResult {
     Skipped,
     Overwritten,
     Renamed { renamed_path: PathBuf },
+    Trashed,
 }
 
 pub enum RetryableError {
     Retry(Error),
+    // the server asked us to slow down, and maybe said for how long
+    SlowDown(Error, Option<Duration>),
+    // the download URL itself is no good, e.g. an expired link or a broken mirror behind it,
+    // so retrying only helps with a fresh URL
+    Stale(Error),
     Fail(Error),
 }
 
+// Failed attempts in a row after which a fresh download URL is asked for
+const REFRESH_URL_AFTER: usize = 3;
+// How many fresh URLs a download gets before a stale URL counts as a failure
+const MAX_URL_REFRESHES: usize = 3;
+// How many times a download that fails validation is tried, in case it was mangled on the way,
+// before we give up on it. The copy on the server may well be broken itself.
+const MAX_INVALID_DOWNLOADS: usize = 3;
+// The wait before the first retry of a download, which doubles with every retry after it
+const FIRST_BACKOFF: Duration = Duration::from_millis(250);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+// Longer waits asked for by the server are cut short, rather than leaving a download hanging for hours
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(300);
+
 pub type RetryableResult<T> = std::result::Result<T, RetryableError>;
 
+// How many pieces of a file are compared with the server's, spread evenly through it
+const SAMPLES: u64 = 4;
+const SAMPLE_SIZE: u64 = 64 * 1024;
+
+// The original of the resource at `path`, downloaded to `destination`, if it was kept when the download
+// was post-processed, e.g. by --strip-cover-pages. That is what the server's copy looks like.
+pub(crate) fn original_of(destination: &Path, path: &Path) -> Option<PathBuf> {
+    let mut root = destination;
+    for _ in path.components() {
+        root = root.parent()?;
+    }
+    Some(root.join(ORIGINALS_DIR).join(path)).filter(|original| original.is_file())
+}
+
+// With CompareBy::Content, whether the file at `destination` is as big as the server says and hashes the
+// same as the server's copy at a few sampled ranges. None when there is nothing to go by, e.g. the server
+// doesn't say how big the file is, in which case the last modified time decides as usual.
+pub(crate) async fn content_matches<F: FnOnce() -> Fut, Fut: Future<Output = Result<Url>>>(
+    api: &Api,
+    destination: &Path,
+    path: &Path,
+    size: Option<u64>,
+    download_url: F,
+) -> Option<bool> {
+    if api.compare_by() != CompareBy::Content {
+        return None;
+    }
+    let compared = original_of(destination, path).unwrap_or_else(|| destination.to_owned());
+    let local_size = tokio::fs::metadata(&compared).await.ok()?.len();
+    if local_size != size? {
+        return Some(false);
+    }
+    let url = download_url().await.ok()?;
+    samples_match(api, url, &compared, local_size).await
+}
+
+// None if the server doesn't do ranges, since then there is nothing to compare
+async fn samples_match(api: &Api, url: Url, destination: &Path, size: u64) -> Option<bool> {
+    let ranges = if size <= SAMPLES * SAMPLE_SIZE {
+        vec![(0, size)]
+    } else {
+        (0..SAMPLES)
+            .map(|i| ((size - SAMPLE_SIZE) * i / (SAMPLES - 1), SAMPLE_SIZE))
+            .collect()
+    };
+    let mut file = match tokio::fs::File::open(destination).await {
+        Ok(file) => file,
+        Err(_) => return Some(false),
+    };
+    let mut local = Sha256::new();
+    let mut remote = Sha256::new();
+    for (start, len) in ranges {
+        if len == 0 {
+            continue;
+        }
+        let mut buffer = vec![0; len as usize];
+        let read = async {
+            file.seek(io::SeekFrom::Start(start)).await?;
+            file.read_exact(&mut buffer).await
+        };
+        if read.await.is_err() {
+            return Some(false);
+        }
+        local.update(&buffer);
+
+        let res = api
+            .get_client()
+            .get(url.clone())
+            .header(RANGE, format!("bytes={}-{}", start, start + len - 1))
+            .send()
+            .await;
+        // a server that ignores the range sends the whole file, which is only worth reading for small ones
+        let whole_file = start == 0 && len == size;
+        let body = match res {
+            Ok(res)
+                if res.status() == StatusCode::PARTIAL_CONTENT
+                    || (res.status() == StatusCode::OK && whole_file) =>
+            {
+                res.bytes().await
+            }
+            Ok(res) if res.status() == StatusCode::OK => return None,
+            _ => return Some(false),
+        };
+        match body {
+            Ok(body) if body.len() as u64 == len => remote.update(&body),
+            _ => return Some(false),
+        }
+    }
+    Some(local.finalize() == remote.finalize())
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn do_retryable_download<
     'a,
-    F1: FnOnce(&'a Api) -> Fut1 + 'a,
+    F1: Fn(&'a Api) -> Fut1 + 'a,
     Fut1: Future<Output = Result<C>>,
     F2: Fn(&'a Api, C, &'a Path) -> Fut2 + 'a,
     Fut2: Future<Output = RetryableResult<()>>,
@@ -53,12 +347,20 @@ pub async fn do_retryable_download<
     temp_destination: &'a Path,
     overwrite: OverwriteMode,
     last_updated: SystemTime,
+    // from `content_matches`, for resources that can be compared by content
+    content_matches: Option<bool>,
     before_download_file: F1,
     download_file: F2,
 ) -> Result<OverwriteResult> {
-    let (should_download, result) = prepare_path(destination, overwrite, last_updated).await?;
+    let (should_download, result) = prepare_path(
+        destination,
+        overwrite,
+        api.rename_scheme(),
+        last_updated,
+        content_matches,
+    )
+    .await?;
     if should_download {
-        let before_download_data = before_download_file(api).await?;
         if let Some(parent) = destination.parent() {
             tokio::fs::create_dir_all(parent)
                 .await
@@ -66,9 +368,10 @@ pub async fn do_retryable_download<
         };
         infinite_retry_download(
             api,
-            before_download_data,
             destination,
             temp_destination,
+            matches!(result, OverwriteResult::Trashed),
+            before_download_file,
             download_file,
         )
         .await?;
@@ -88,7 +391,9 @@ pub async fn do_retryable_download<
 async fn prepare_path(
     path: &Path,
     overwrite: OverwriteMode,
+    rename_scheme: &RenameScheme,
     last_updated: SystemTime,
+    content_matches: Option<bool>,
 ) -> Result<(bool, OverwriteResult)> {
     let metadata = tokio::fs::metadata(path).await;
     if let Err(e) = metadata {
@@ -104,66 +409,145 @@ async fn prepare_path(
         .unwrap()
         .modified()
         .map_err(|_| "File system does not support last modified time")?;
-    if last_updated <= old_time {
+    if content_matches.unwrap_or(last_updated <= old_time) {
+        if content_matches == Some(true) && old_time != last_updated {
+            // so that runs going by the last modified time agree
+            filetime::set_file_mtime(path, filetime::FileTime::from_system_time(last_updated))
+                .map_err(|_| "Unable to set last modified time")?;
+        }
         Ok((false, OverwriteResult::AlreadyHave)) // don't download, because we already have updated file
     } else {
         match overwrite {
             OverwriteMode::Skip => Ok((false, OverwriteResult::Skipped)), // don't download, because user wants to skip updated files
             OverwriteMode::Overwrite => Ok((true, OverwriteResult::Overwritten)), // do download, because user wants to overwrite updated files
             OverwriteMode::Rename => {
-                let mut new_stem = path
-                    .file_stem()
-                    .expect("File does not have name")
-                    .to_os_string();
-                let date = chrono::DateTime::<chrono::Local>::from(old_time).date();
-                use chrono::Datelike;
-                new_stem.push(format!(
-                    "_autorename_{:04}-{:02}-{:02}",
-                    date.year(),
-                    date.month(),
-                    date.day()
-                ));
-                let path_extension = path.extension();
-                let mut i = 0;
-                let mut suffixed_stem = new_stem.clone();
-                let renamed_path = loop {
-                    let renamed_path_without_ext = path.with_file_name(suffixed_stem);
-                    let renamed_path = if let Some(ext) = path_extension {
-                        renamed_path_without_ext.with_extension(ext)
-                    } else {
-                        renamed_path_without_ext
-                    };
-                    if !renamed_path.exists() {
-                        break renamed_path;
-                    }
-                    i += 1;
-                    suffixed_stem = new_stem.clone();
-                    suffixed_stem.push(format!("_{}", i));
-                };
+                let renamed_path = rename_scheme.renamed_path(path, old_time);
+                if let Some(parent) = renamed_path.parent() {
+                    tokio::fs::create_dir_all(parent)
+                        .await
+                        .map_err(|_| "Unable to create directory")?;
+                }
                 tokio::fs::rename(path, renamed_path.clone())
                     .await
                     .map_err(|_| "Failed renaming existing file")?;
+                // the file is being downloaded again anyway, so versions that couldn't be removed can wait for next time
+                let _ = rename_scheme.prune(path);
                 Ok((true, OverwriteResult::Renamed { renamed_path })) // do download, because we renamed the old file
             }
+            // do download, and the old file goes to the trash once the new one is ready,
+            // so that a failed download doesn't leave nothing in its place
+            OverwriteMode::Trash => Ok((true, OverwriteResult::Trashed)),
         }
     }
 }
 
+#[cfg(feature = "trash")]
+async fn move_to_trash(path: &Path) -> Result<()> {
+    let old_path = path.to_owned();
+    tokio::task::spawn_blocking(move || trash::delete(old_path))
+        .await
+        .map_err(|_| "Failed moving existing file to trash")?
+        .map_err(|_| "Failed moving existing file to trash")
+}
+
+// the trash crate pulls in a lot on some platforms, so library users may leave it out
+#[cfg(not(feature = "trash"))]
+async fn move_to_trash(_path: &Path) -> Result<()> {
+    Err("This build of fluminurs cannot move files to the trash")
+}
+
+async fn validate_download(destination: &Path, temp_destination: &Path) -> Result<()> {
+    let destination = destination.to_owned();
+    let temp_destination = temp_destination.to_owned();
+    tokio::task::spawn_blocking(move || validate::check(&temp_destination, &destination))
+        .await
+        .map_err(|_| "Unable to validate download")?
+}
+
+// How long to wait before the retry after `retries` earlier ones. Half of it is random, so that
+// downloads that failed together don't all come back at once.
+fn backoff(retries: u32) -> Duration {
+    let backoff = FIRST_BACKOFF
+        .checked_mul(1 << retries.min(16))
+        .map_or(MAX_BACKOFF, |backoff| backoff.min(MAX_BACKOFF));
+    backoff / 2 + backoff.mul_f64(rand::thread_rng().gen_range(0.0..0.5))
+}
+
+// The value of a Retry-After header, which is either a number of seconds or a date
+pub(crate) fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let wait = match value.parse::<u64>() {
+        Ok(seconds) => Duration::from_secs(seconds),
+        Err(_) => (chrono::DateTime::parse_from_rfc2822(value)
+            .ok()?
+            .with_timezone(&chrono::Utc)
+            - chrono::Utc::now())
+        .to_std()
+        .unwrap_or_default(),
+    };
+    Some(wait.min(MAX_RETRY_AFTER))
+}
+
+// Retries for as long as the failures look temporary. When attempts keep failing, `before_download_file`
+// is asked again for where to download from, since the URL may have expired or lead to a broken mirror.
 async fn infinite_retry_download<
     'a,
-    F: Fn(&'a Api, C, &'a Path) -> Fut + 'a,
-    Fut: Future<Output = RetryableResult<()>>,
+    F1: Fn(&'a Api) -> Fut1 + 'a,
+    Fut1: Future<Output = Result<C>>,
+    F2: Fn(&'a Api, C, &'a Path) -> Fut2 + 'a,
+    Fut2: Future<Output = RetryableResult<()>>,
     C: Clone,
 >(
     api: &'a Api,
-    before_download_data: C,
     destination: &Path,
     temp_destination: &'a Path,
-    download_file: F,
+    // whether the file at `destination` goes to the trash before the download takes its place
+    trash_existing: bool,
+    before_download_file: F1,
+    download_file: F2,
 ) -> Result<()> {
+    let mut before_download_data = before_download_file(api).await?;
+    let mut failures = 0;
+    let mut refreshes = 0;
+    let mut invalid = 0;
+    let mut retries = 0;
     loop {
-        match download_file(api, before_download_data.clone(), temp_destination).await {
+        let permit = match api.download_limit() {
+            Some(limit) => Some(limit.acquire().await),
+            None => None,
+        };
+        let result = download_file(api, before_download_data.clone(), temp_destination).await;
+        if let Some(limit) = api.download_limit() {
+            match &result {
+                Ok(_) => limit.report_success(),
+                Err(RetryableError::Retry(_))
+                | Err(RetryableError::SlowDown(..))
+                | Err(RetryableError::Stale(_)) => limit.report_failure(),
+                Err(RetryableError::Fail(_)) => {}
+            }
+        }
+        drop(permit);
+        let result = match result {
+            Ok(_) if api.validate_downloads() => validate_download(destination, temp_destination)
+                .await
+                .map_err(|err| {
+                    invalid += 1;
+                    if invalid >= MAX_INVALID_DOWNLOADS {
+                        RetryableError::Fail(err)
+                    } else {
+                        RetryableError::Retry(err)
+                    }
+                }),
+            result => result,
+        };
+        match result {
             Ok(_) => {
+                if trash_existing {
+                    if let Err(err) = move_to_trash(destination).await {
+                        let _ = tokio::fs::remove_file(temp_destination).await;
+                        return Err(err);
+                    }
+                }
                 tokio::fs::rename(temp_destination, destination)
                     .await
                     .map_err(|_| "Unable to move temporary file")?;
@@ -174,7 +558,26 @@ async fn infinite_retry_download<
                     .await
                     .map_err(|_| "Unable to delete temporary file")?;
                 match err {
-                    RetryableError::Retry(_) => { /* retry */ }
+                    RetryableError::Stale(err) if refreshes >= MAX_URL_REFRESHES => {
+                        Err(err)?;
+                    }
+                    RetryableError::Retry(_)
+                    | RetryableError::SlowDown(..)
+                    | RetryableError::Stale(_) => {
+                        // waited out without a permit, so that other downloads can go on meanwhile
+                        let wait = match err {
+                            RetryableError::SlowDown(_, Some(retry_after)) => retry_after,
+                            _ => backoff(retries),
+                        };
+                        tokio::time::sleep(wait).await;
+                        retries += 1;
+                        failures += 1;
+                        if failures >= REFRESH_URL_AFTER {
+                            before_download_data = before_download_file(api).await?;
+                            failures = 0;
+                            refreshes += 1;
+                        }
+                    }
                     RetryableError::Fail(err) => {
                         Err(err)?;
                     }
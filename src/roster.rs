@@ -0,0 +1,44 @@
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+
+use crate::util::csv_escape;
+use crate::{Api, ApiData, Result};
+
+// A student enrolled in a module, as seen by its teaching staff
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Student {
+    #[serde(rename = "userID")]
+    pub user_id: String,
+    pub user_name_original: String,
+    pub email: Option<String>,
+    #[serde(rename = "classGroupName")]
+    pub tutorial_group: Option<String>,
+}
+
+pub(crate) async fn load(api: &Api, module_id: &str) -> Result<Vec<Student>> {
+    let roster_resp = api
+        .api_as_json::<ApiData<Vec<Student>>>(
+            &format!("classroster/{}/students", module_id),
+            Method::GET,
+            None,
+        )
+        .await?;
+    roster_resp
+        .data
+        .ok_or("Invalid API response from server: type mismatch")
+}
+
+pub fn to_csv(students: &[Student]) -> String {
+    let mut output = String::from("Name,User ID,Tutorial Group,Email\n");
+    for student in students {
+        output.push_str(&format!(
+            "{},{},{},{}\n",
+            csv_escape(&student.user_name_original),
+            csv_escape(&student.user_id),
+            csv_escape(student.tutorial_group.as_deref().unwrap_or("")),
+            csv_escape(student.email.as_deref().unwrap_or(""))
+        ));
+    }
+    output
+}
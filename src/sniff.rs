@@ -0,0 +1,143 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+// How much of a file is looked at. Office documents are zip files, and the names of the parts
+// that tell them apart come early, right after [Content_Types].xml.
+const HEAD_SIZE: u64 = 64 * 1024;
+
+struct FileType {
+    extension: &'static str,
+    // other extensions that are fine for the same content
+    also: &'static [&'static str],
+    // generic containers such as zip hold many formats with their own extensions,
+    // so an existing extension is only second-guessed for types that can't be anything else
+    specific: bool,
+}
+
+const PDF: FileType = FileType {
+    extension: "pdf",
+    also: &[],
+    specific: true,
+};
+const PNG: FileType = FileType {
+    extension: "png",
+    also: &[],
+    specific: true,
+};
+const JPEG: FileType = FileType {
+    extension: "jpg",
+    also: &["jpeg", "jpe"],
+    specific: true,
+};
+const GIF: FileType = FileType {
+    extension: "gif",
+    also: &[],
+    specific: true,
+};
+const DOCX: FileType = FileType {
+    extension: "docx",
+    also: &["docm", "dotx", "dotm"],
+    specific: true,
+};
+const PPTX: FileType = FileType {
+    extension: "pptx",
+    also: &["pptm", "ppsx", "ppsm", "potx", "potm"],
+    specific: true,
+};
+const XLSX: FileType = FileType {
+    extension: "xlsx",
+    also: &["xlsm", "xltx", "xltm"],
+    specific: true,
+};
+const ZIP: FileType = FileType {
+    extension: "zip",
+    also: &[],
+    specific: false,
+};
+const MP4: FileType = FileType {
+    extension: "mp4",
+    also: &["m4v", "m4a", "mov", "3gp"],
+    specific: false,
+};
+const MKV: FileType = FileType {
+    extension: "mkv",
+    also: &["webm", "mka", "mk3d"],
+    specific: false,
+};
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
+
+fn sniff(head: &[u8]) -> Option<&'static FileType> {
+    if head.starts_with(b"%PDF-") {
+        Some(&PDF)
+    } else if head.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some(&PNG)
+    } else if head.starts_with(b"\xff\xd8\xff") {
+        Some(&JPEG)
+    } else if head.starts_with(b"GIF87a") || head.starts_with(b"GIF89a") {
+        Some(&GIF)
+    } else if head.starts_with(b"PK\x03\x04") {
+        if contains(head, b"word/") {
+            Some(&DOCX)
+        } else if contains(head, b"ppt/") {
+            Some(&PPTX)
+        } else if contains(head, b"xl/") {
+            Some(&XLSX)
+        } else {
+            Some(&ZIP)
+        }
+    } else if head.len() >= 8 && &head[4..8] == b"ftyp" {
+        Some(&MP4)
+    } else if head.starts_with(b"\x1a\x45\xdf\xa3") {
+        Some(&MKV)
+    } else {
+        None
+    }
+}
+
+fn sniff_file(path: &Path) -> io::Result<Option<&'static FileType>> {
+    let mut head = vec![];
+    fs::File::open(path)?
+        .take(HEAD_SIZE)
+        .read_to_end(&mut head)?;
+    Ok(sniff(&head))
+}
+
+// The usual extension for what is in the file at `path`, whatever it is named.
+// None if the content isn't recognised.
+pub fn content_extension(path: &Path) -> io::Result<Option<&'static str>> {
+    Ok(sniff_file(path)?.map(|file_type| file_type.extension))
+}
+
+// The extension to add to the file at `path` so that it opens with the right app, going by what is
+// in it. None if its extension already fits, or if the content isn't recognised.
+pub fn missing_extension(path: &Path) -> io::Result<Option<&'static str>> {
+    let file_type = match sniff_file(path)? {
+        Some(file_type) => file_type,
+        None => return Ok(None),
+    };
+    // e.g. "Week 3. Intro" has no extension, whatever Path thinks
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .filter(|extension| {
+            extension.len() <= 5 && extension.chars().all(|c| c.is_ascii_alphanumeric())
+        });
+    let fits = match &extension {
+        None => false,
+        Some(_) if !file_type.specific => true,
+        Some(extension) => {
+            extension == file_type.extension || file_type.also.contains(&extension.as_str())
+        }
+    };
+    Ok(if fits {
+        None
+    } else {
+        Some(file_type.extension)
+    })
+}
@@ -0,0 +1,233 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::syllabus::ModuleAssessment;
+use crate::util::walk_files;
+
+// Where a student stands in a module, going by the marks released so far and any assumed for
+// components still to come. Everything is in percent of the final grade.
+#[derive(Debug, Default)]
+pub struct Standing {
+    // earned on the components with a mark
+    pub earned: f64,
+    // the weightage of the components with a mark
+    pub graded_weight: f64,
+    // the components still to come, with their weightage
+    pub remaining: Vec<(String, f64)>,
+    // components that can't be counted, for lack of a weightage, or of a maximum for their mark
+    pub uncounted: Vec<String>,
+}
+
+impl Standing {
+    // `assumed` is component name => percent of its marks, for what-ifs. Names are matched
+    // ignoring case, and an assumed mark takes the place of a released one.
+    pub fn of(assessment: &ModuleAssessment, assumed: &[(String, f64)]) -> Standing {
+        let mut standing = Standing::default();
+        for component in &assessment.assessments {
+            let weightage = match component.weightage {
+                Some(weightage) if weightage > 0.0 => weightage,
+                _ => {
+                    standing.uncounted.push(component.name.clone());
+                    continue;
+                }
+            };
+            let assumed = assumed
+                .iter()
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case(component.name.trim()))
+                .map(|(_, percent)| *percent);
+            let percent = match (assumed, component.mark, component.max_mark) {
+                (Some(percent), _, _) => percent,
+                (None, Some(mark), Some(max_mark)) if max_mark > 0.0 => mark / max_mark * 100.0,
+                (None, Some(_), _) => {
+                    standing.uncounted.push(component.name.clone());
+                    continue;
+                }
+                (None, None, _) => {
+                    standing.remaining.push((component.name.clone(), weightage));
+                    continue;
+                }
+            };
+            standing.earned += percent * weightage / 100.0;
+            standing.graded_weight += weightage;
+        }
+        standing
+    }
+
+    pub fn remaining_weight(&self) -> f64 {
+        self.remaining.iter().map(|(_, weightage)| weightage).sum()
+    }
+
+    // the average on what has been graded
+    pub fn average(&self) -> Option<f64> {
+        if self.graded_weight > 0.0 {
+            Some(self.earned / self.graded_weight * 100.0)
+        } else {
+            None
+        }
+    }
+
+    // The average needed on the remaining components to finish with `target` percent. Weightages
+    // don't always add up to 100, so the target is taken out of what they do add up to.
+    // None once nothing remains.
+    pub fn needed_for(&self, target: f64) -> Option<f64> {
+        let remaining_weight = self.remaining_weight();
+        if remaining_weight <= 0.0 {
+            return None;
+        }
+        let total_weight = self.graded_weight + remaining_weight;
+        Some((target * total_weight / 100.0 - self.earned) / remaining_weight * 100.0)
+    }
+
+    // the final grade, once nothing remains
+    pub fn overall(&self) -> Option<f64> {
+        if self.remaining.is_empty() {
+            self.average()
+        } else {
+            None
+        }
+    }
+}
+
+// Every assessment.json under `dir`, as written by --export-syllabus-to, sorted by path.
+// Files that can't be read as one come with None, so that they can be pointed out.
+pub fn find_assessments(dir: &Path) -> Vec<(PathBuf, Option<ModuleAssessment>)> {
+    let mut paths = vec![];
+    walk_files(dir, &mut |path, _| {
+        if path
+            .file_name()
+            .is_some_and(|name| name == "assessment.json")
+        {
+            paths.push(path);
+        }
+    });
+    paths.sort();
+    paths
+        .into_iter()
+        .map(|path| {
+            let assessment = fs::read_to_string(&path)
+                .ok()
+                .and_then(|content| serde_json::from_str(&content).ok());
+            (path, assessment)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syllabus::Assessment;
+
+    fn component(name: &str, weightage: Option<f64>, mark: Option<(f64, f64)>) -> Assessment {
+        Assessment {
+            name: name.to_owned(),
+            weightage,
+            mark: mark.map(|(mark, _)| mark),
+            max_mark: mark.map(|(_, max_mark)| max_mark),
+        }
+    }
+
+    fn module(assessments: Vec<Assessment>) -> ModuleAssessment {
+        ModuleAssessment {
+            code: "CS1010".to_owned(),
+            name: "Programming Methodology".to_owned(),
+            assessments,
+        }
+    }
+
+    fn assert_close(actual: Option<f64>, expected: f64) {
+        let actual = actual.expect("no value");
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    // 80% on the midterm and 90% on the project, with the final to come
+    fn halfway() -> ModuleAssessment {
+        module(vec![
+            component("Midterm", Some(30.0), Some((40.0, 50.0))),
+            component("Project", Some(20.0), Some((18.0, 20.0))),
+            component("Final", Some(50.0), None),
+        ])
+    }
+
+    #[test]
+    fn needs_the_rest_of_each_target_from_what_remains() {
+        let standing = Standing::of(&halfway(), &[]);
+        assert_close(Some(standing.earned), 42.0);
+        assert_close(standing.average(), 84.0);
+        assert_close(standing.needed_for(70.0), 56.0);
+        assert_close(standing.needed_for(90.0), 96.0);
+        // out of reach, or already secured
+        assert_close(standing.needed_for(100.0), 116.0);
+        assert_close(standing.needed_for(20.0), -44.0);
+        assert_eq!(standing.overall(), None);
+    }
+
+    #[test]
+    fn takes_targets_out_of_what_the_weightages_add_up_to() {
+        let standing = Standing::of(
+            &module(vec![
+                component("Quizzes", Some(40.0), Some((10.0, 10.0))),
+                component("Final", Some(40.0), None),
+            ]),
+            &[],
+        );
+        assert_close(standing.needed_for(75.0), 50.0);
+    }
+
+    #[test]
+    fn nothing_is_needed_once_nothing_remains() {
+        let standing = Standing::of(&halfway(), &[("final".to_owned(), 80.0)]);
+        assert_eq!(standing.needed_for(70.0), None);
+        assert!(standing.remaining.is_empty());
+        assert_close(standing.overall(), 82.0);
+    }
+
+    #[test]
+    fn assumed_marks_take_the_place_of_released_ones() {
+        let standing = Standing::of(&halfway(), &[(" MIDTERM ".to_owned(), 100.0)]);
+        assert_close(Some(standing.earned), 48.0);
+        assert_close(Some(standing.graded_weight), 50.0);
+        assert_close(standing.needed_for(70.0), 44.0);
+    }
+
+    #[test]
+    fn assumptions_about_unknown_components_are_ignored() {
+        let standing = Standing::of(&halfway(), &[("Tutorials".to_owned(), 100.0)]);
+        assert_close(Some(standing.earned), 42.0);
+        assert_eq!(standing.remaining.len(), 1);
+    }
+
+    #[test]
+    fn components_without_marks_remain_or_go_uncounted() {
+        let standing = Standing::of(
+            &module(vec![
+                component("Participation", None, Some((5.0, 5.0))),
+                component("Bonus", Some(0.0), None),
+                component("Lab", Some(10.0), None),
+                component("Essay", Some(20.0), None),
+                Assessment {
+                    name: "Presentation".to_owned(),
+                    weightage: Some(10.0),
+                    max_mark: None,
+                    mark: Some(7.0),
+                },
+            ]),
+            &[],
+        );
+        assert_eq!(
+            standing.uncounted,
+            vec!["Participation", "Bonus", "Presentation"]
+        );
+        assert_eq!(
+            standing.remaining,
+            vec![("Lab".to_owned(), 10.0), ("Essay".to_owned(), 20.0)]
+        );
+        assert_close(Some(standing.remaining_weight()), 30.0);
+        assert_eq!(standing.average(), None);
+        assert_close(standing.needed_for(60.0), 60.0);
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use reqwest::Url;
+use serde::Serialize;
+
+// Timings of every request and download, grouped by host, so that a slow LumiNUS can be told apart
+// from a slow CDN or a slow network
+#[derive(Debug, Default)]
+pub struct TransferStats {
+    hosts: Mutex<BTreeMap<String, HostTimings>>,
+}
+
+#[derive(Debug, Default)]
+struct HostTimings {
+    requests: usize,
+    retries: usize,
+    bytes: u64,
+    transfer_time: Duration,
+    // time until the response headers arrived, for each request that got that far
+    latencies: Vec<Duration>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HostStats {
+    pub host: String,
+    pub requests: usize,
+    pub retries: usize,
+    pub bytes: u64,
+    // averaged over the time spent transferring bodies, not the time spent waiting for them
+    pub bytes_per_second: Option<f64>,
+    pub latency_median_ms: Option<f64>,
+    pub latency_p90_ms: Option<f64>,
+    pub latency_max_ms: Option<f64>,
+}
+
+fn host_of(url: &str) -> String {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(|host| host.to_owned()))
+        .unwrap_or_else(|| "unknown".to_owned())
+}
+
+fn percentile_ms(sorted: &[Duration], percentile: usize) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let index = (sorted.len() - 1) * percentile / 100;
+    Some(sorted[index].as_secs_f64() * 1000.0)
+}
+
+impl TransferStats {
+    fn update<F: FnOnce(&mut HostTimings)>(&self, url: &str, update: F) {
+        let mut hosts = self.hosts.lock().unwrap();
+        update(hosts.entry(host_of(url)).or_default());
+    }
+
+    // a request got as far as a response, after waiting `latency` for it
+    pub(crate) fn record_response(&self, url: &str, latency: Duration) {
+        self.update(url, |timings| {
+            timings.requests += 1;
+            timings.latencies.push(latency);
+        });
+    }
+
+    // an attempt failed in a way that we are going to try again
+    pub(crate) fn record_retry(&self, url: &str) {
+        self.update(url, |timings| timings.retries += 1);
+    }
+
+    // a body of `bytes` was received in full, which took `elapsed`
+    pub(crate) fn record_transfer(&self, url: &str, bytes: u64, elapsed: Duration) {
+        self.update(url, |timings| {
+            timings.bytes += bytes;
+            timings.transfer_time += elapsed;
+        });
+    }
+
+    pub fn summary(&self) -> Vec<HostStats> {
+        let hosts = self.hosts.lock().unwrap();
+        hosts
+            .iter()
+            .map(|(host, timings)| {
+                let mut latencies = timings.latencies.clone();
+                latencies.sort();
+                let seconds = timings.transfer_time.as_secs_f64();
+                HostStats {
+                    host: host.clone(),
+                    requests: timings.requests,
+                    retries: timings.retries,
+                    bytes: timings.bytes,
+                    bytes_per_second: if seconds > 0.0 {
+                        Some(timings.bytes as f64 / seconds)
+                    } else {
+                        None
+                    },
+                    latency_median_ms: percentile_ms(&latencies, 50),
+                    latency_p90_ms: percentile_ms(&latencies, 90),
+                    latency_max_ms: latencies.last().map(|max| max.as_secs_f64() * 1000.0),
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,67 @@
+use std::sync::Mutex;
+
+use serde_json::Value;
+
+// The drift that --strict found, by the path of the response it was in. The library doesn't print
+// anything, so this is kept for the caller to show next to the error the run ends with.
+#[derive(Debug, Default)]
+pub struct DriftLog {
+    responses: Mutex<Vec<(String, Vec<String>)>>,
+}
+
+impl DriftLog {
+    pub(crate) fn record(&self, path: &str, drift: Vec<String>) {
+        self.responses
+            .lock()
+            .unwrap()
+            .push((path.to_owned(), drift));
+    }
+
+    pub fn responses(&self) -> Vec<(String, Vec<String>)> {
+        self.responses.lock().unwrap().clone()
+    }
+}
+
+// Where a response and what we made of it disagree: fields the server sent that no struct has a place
+// for, and fields we expect that the server left out. Lenient parsing shrugs both off, so LumiNUS can
+// add or drop fields without breaking anything, but that hides the drift from whoever maintains this.
+// `understood` is the response deserialised and serialised again, so it has exactly the known fields.
+pub(crate) fn schema_drift(received: &Value, understood: &Value) -> Vec<String> {
+    let mut drift = vec![];
+    compare(received, understood, "", &mut drift);
+    drift
+}
+
+// e.g. data[0].fileName
+fn field_path(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{}.{}", path, name)
+    }
+}
+
+fn compare(received: &Value, understood: &Value, path: &str, drift: &mut Vec<String>) {
+    match (received, understood) {
+        (Value::Object(received), Value::Object(understood)) => {
+            for (name, value) in received {
+                let field = field_path(path, name);
+                match understood.get(name) {
+                    Some(known) => compare(value, known, &field, drift),
+                    None => drift.push(format!("unknown field {}", field)),
+                }
+            }
+            for name in understood.keys() {
+                if !received.contains_key(name) {
+                    drift.push(format!("missing field {}", field_path(path, name)));
+                }
+            }
+        }
+        (Value::Array(received), Value::Array(understood)) => {
+            for (i, (value, known)) in received.iter().zip(understood).enumerate() {
+                compare(value, known, &format!("{}[{}]", path, i), drift);
+            }
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,38 @@
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use crate::util::{csv_escape, format_size};
+
+// What a student handed in to an uploadable folder, without the file itself
+#[derive(Debug, Clone)]
+pub struct Submission {
+    pub folder: PathBuf,
+    pub student: String,
+    pub file_name: String,
+    pub size: Option<u64>,
+    pub submitted: SystemTime,
+    // the file was replaced after it was first uploaded
+    pub resubmitted: bool,
+}
+
+fn format_time(time: SystemTime) -> String {
+    chrono::DateTime::<chrono::Local>::from(time)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+pub fn to_csv(submissions: &[&Submission]) -> String {
+    let mut output = String::from("Folder,Student,File,Size,Submitted,Resubmitted\n");
+    for submission in submissions {
+        output.push_str(&format!(
+            "{},{},{},{},{},{}\n",
+            csv_escape(&submission.folder.to_string_lossy()),
+            csv_escape(&submission.student),
+            csv_escape(&submission.file_name),
+            submission.size.map(format_size).unwrap_or_default(),
+            format_time(submission.submitted),
+            if submission.resubmitted { "yes" } else { "no" }
+        ));
+    }
+    output
+}
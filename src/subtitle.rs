@@ -0,0 +1,64 @@
+use std::path::{Path, PathBuf};
+
+use tokio::process::Command;
+
+use crate::util::find_executable;
+use crate::Result;
+
+const INPUT_PLACEHOLDER: &str = "{input}";
+const OUTPUT_PLACEHOLDER: &str = "{output}";
+
+// A user-supplied command that transcribes a video into subtitles,
+// e.g. `whisper-cli -m ggml-base.en.bin -osrt -of {output} {input}`
+pub struct TranscriptionHook {
+    program: String,
+    args: Vec<String>,
+}
+
+pub fn subtitle_path(video: &Path) -> PathBuf {
+    video.with_extension("srt")
+}
+
+impl TranscriptionHook {
+    // The command is split on whitespace. {input} is replaced with the video path, and
+    // {output} with the subtitle path minus its .srt extension, which most tools append themselves
+    pub fn parse(command: &str) -> Result<TranscriptionHook> {
+        let mut parts = command.split_whitespace().map(|part| part.to_owned());
+        let program = parts.next().ok_or("Transcription command is empty")?;
+        let args = parts.collect::<Vec<_>>();
+        if !args.iter().any(|arg| arg.contains(INPUT_PLACEHOLDER)) {
+            return Err("Transcription command must contain {input}");
+        }
+        Ok(TranscriptionHook { program, args })
+    }
+
+    pub fn program(&self) -> &str {
+        &self.program
+    }
+
+    pub fn is_available(&self) -> bool {
+        find_executable(&self.program).is_some()
+    }
+
+    pub async fn run(&self, video: &Path) -> Result<PathBuf> {
+        let output = subtitle_path(video);
+        let output_stem = output.with_extension("");
+        let success = Command::new(&self.program)
+            .args(self.args.iter().map(|arg| {
+                arg.replace(INPUT_PLACEHOLDER, &video.to_string_lossy())
+                    .replace(OUTPUT_PLACEHOLDER, &output_stem.to_string_lossy())
+            }))
+            .output()
+            .await
+            .map_err(|_| "Failed to start transcription command")?
+            .status
+            .success();
+        if !success {
+            return Err("Transcription command returned nonzero exit code");
+        }
+        if !output.exists() {
+            return Err("Transcription command did not produce a subtitle file");
+        }
+        Ok(output)
+    }
+}
@@ -0,0 +1,186 @@
+use futures_util::future;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+
+use crate::gradebook::GradebookItem;
+use crate::util::html_to_text;
+use crate::{Api, ApiData, Result};
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ModuleDetail {
+    description: Option<String>,
+    workload: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Assessment {
+    pub name: String,
+    // percent of the final grade
+    pub weightage: Option<f64>,
+    // these two come from the gradebook, see `add_gradebook`
+    #[serde(default)]
+    pub max_mark: Option<f64>,
+    #[serde(default)]
+    pub mark: Option<f64>,
+}
+
+// What is written to assessment.json, for grade calculators
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModuleAssessment {
+    pub code: String,
+    pub name: String,
+    pub assessments: Vec<Assessment>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Lesson {
+    pub name: String,
+    pub start_date: Option<String>,
+    pub description: Option<String>,
+}
+
+pub struct Syllabus {
+    pub description: Option<String>,
+    pub workload: Option<String>,
+    pub assessments: Vec<Assessment>,
+    pub lessons: Vec<Lesson>,
+}
+
+impl Syllabus {
+    pub(crate) async fn load(api: &Api, module_id: &str) -> Result<Syllabus> {
+        let (detail, assessments, lessons) = future::join3(
+            api.api_as_json::<ApiData<ModuleDetail>>(
+                &format!("module/{}", module_id),
+                Method::GET,
+                None,
+            ),
+            api.api_as_json::<ApiData<Vec<Assessment>>>(
+                &format!("module/{}/assessment", module_id),
+                Method::GET,
+                None,
+            ),
+            api.api_as_json::<ApiData<Vec<Lesson>>>(
+                &format!("lessonplan/Lesson/?ModuleID={}", module_id),
+                Method::GET,
+                None,
+            ),
+        )
+        .await;
+        let detail = detail?
+            .data
+            .ok_or("Invalid API response from server: type mismatch")?;
+        // modules without a lesson plan or assessment breakdown are common, so these are optional
+        let assessments = assessments.ok().and_then(|a| a.data).unwrap_or_default();
+        let mut lessons = lessons.ok().and_then(|l| l.data).unwrap_or_default();
+        lessons.sort_by(|l1, l2| l1.start_date.cmp(&l2.start_date));
+        Ok(Syllabus {
+            description: detail.description,
+            workload: detail.workload,
+            assessments,
+            lessons,
+        })
+    }
+
+    // Fills in weightages and marks from the gradebook, matching components by name. Graded items
+    // that the syllabus doesn't list are added, since for some modules that is all there is to go by.
+    pub fn add_gradebook(&mut self, items: &[GradebookItem]) {
+        for item in items {
+            let name = item.name.trim();
+            match self
+                .assessments
+                .iter_mut()
+                .find(|assessment| assessment.name.trim().eq_ignore_ascii_case(name))
+            {
+                Some(assessment) => {
+                    assessment.weightage = assessment.weightage.or(item.weightage);
+                    assessment.max_mark = assessment.max_mark.or(item.max_mark);
+                    assessment.mark = assessment.mark.or(item.mark);
+                }
+                None => self.assessments.push(Assessment {
+                    name: item.name.clone(),
+                    weightage: item.weightage,
+                    max_mark: item.max_mark,
+                    mark: item.mark,
+                }),
+            }
+        }
+    }
+
+    pub fn assessment_json(&self, code: &str, name: &str) -> Result<String> {
+        serde_json::to_string_pretty(&ModuleAssessment {
+            code: code.to_owned(),
+            name: name.to_owned(),
+            assessments: self.assessments.clone(),
+        })
+        .map_err(|_| "Unable to serialise assessment breakdown")
+    }
+
+    pub fn to_markdown(&self, code: &str, name: &str) -> String {
+        let mut output = format!("# {} {}\n", code, name);
+        if let Some(description) = &self.description {
+            output.push_str(&format!(
+                "\n## Description\n\n{}\n",
+                html_to_text(description)
+            ));
+        }
+        if let Some(workload) = &self.workload {
+            output.push_str(&format!("\n## Workload\n\n{}\n", workload));
+        }
+        if !self.assessments.is_empty() {
+            // marks only show up once the gradebook has some
+            let has_marks = self
+                .assessments
+                .iter()
+                .any(|assessment| assessment.max_mark.is_some() || assessment.mark.is_some());
+            if has_marks {
+                output.push_str(
+                    "\n## Assessment\n\n| Component | Weightage | Mark |\n| --- | --- | --- |\n",
+                );
+            } else {
+                output.push_str("\n## Assessment\n\n| Component | Weightage |\n| --- | --- |\n");
+            }
+            for assessment in &self.assessments {
+                let weightage = assessment
+                    .weightage
+                    .map(|w| format!("{}%", w))
+                    .unwrap_or_else(|| "-".to_owned());
+                if has_marks {
+                    let mark = assessment
+                        .mark
+                        .map(|m| m.to_string())
+                        .unwrap_or_else(|| "-".to_owned());
+                    let mark = match assessment.max_mark {
+                        Some(max_mark) => format!("{} / {}", mark, max_mark),
+                        None => mark,
+                    };
+                    output.push_str(&format!(
+                        "| {} | {} | {} |\n",
+                        assessment.name, weightage, mark
+                    ));
+                } else {
+                    output.push_str(&format!("| {} | {} |\n", assessment.name, weightage));
+                }
+            }
+        }
+        if !self.lessons.is_empty() {
+            output.push_str("\n## Schedule\n\n");
+            for lesson in &self.lessons {
+                match lesson.start_date.as_deref().and_then(|d| d.get(..10)) {
+                    Some(date) => output.push_str(&format!("- {} {}\n", date, lesson.name)),
+                    None => output.push_str(&format!("- {}\n", lesson.name)),
+                }
+                if let Some(description) = &lesson.description {
+                    let text = html_to_text(description);
+                    if !text.trim().is_empty() {
+                        output.push_str(&format!("  {}\n", text.trim().replace('\n', "\n  ")));
+                    }
+                }
+            }
+        }
+        output
+    }
+}
@@ -0,0 +1,362 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use futures_util::future;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+use tokio::task::{JoinSet, LocalSet};
+use tokio_util::sync::CancellationToken;
+
+use crate::resource::{CompareBy, OverwriteMode, Resource, ORIGINALS_DIR};
+use crate::util::walk_files;
+use crate::{Error, Result};
+
+// A sync runs in stages, each finishing before the next starts:
+// traverse (list what the server has), plan (pick out what needs fetching), download, and finalize,
+// which is left to the caller since it decides what to report and what to do with new files.
+
+// Lists every root at once, keeping what loaded apart from what failed, so that one broken
+// module doesn't stop the others from syncing
+pub async fn traverse<R, Fut: Future<Output = Result<Vec<R>>>>(
+    listings: impl IntoIterator<Item = Fut>,
+) -> (Vec<R>, Vec<Error>) {
+    future::join_all(listings)
+        .await
+        .into_iter()
+        .fold((vec![], vec![]), |(mut ok, mut err), res| {
+            match res {
+                Ok(mut resources) => ok.append(&mut resources),
+                Err(e) => err.push(e),
+            }
+            (ok, err)
+        })
+}
+
+// Indices into the traversed resources
+pub struct Plan {
+    pub missing: Vec<usize>,
+    // older locally than what the server has
+    pub outdated: Vec<usize>,
+    // as big as what the server has, which the download stage compares by content
+    pub unverified: Vec<usize>,
+    pub up_to_date: Vec<usize>,
+}
+
+// One step of a sync, as written out for review before it is applied
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "action")]
+pub enum Action {
+    #[serde(rename_all = "camelCase")]
+    Download {
+        path: PathBuf,
+        last_updated: SystemTime,
+        size: Option<u64>,
+    },
+    // `overwrite` says what becomes of the local copy
+    #[serde(rename_all = "camelCase")]
+    Update {
+        path: PathBuf,
+        last_updated: SystemTime,
+        size: Option<u64>,
+        overwrite: String,
+    },
+    // newer on the server, but left alone because of the overwrite mode
+    #[serde(rename_all = "camelCase")]
+    Skip {
+        path: PathBuf,
+        last_updated: SystemTime,
+    },
+}
+
+// What a sync to one destination would do
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlannedSync {
+    pub destination: PathBuf,
+    pub overwrite: String,
+    pub actions: Vec<Action>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncPlan {
+    pub created: String,
+    pub files: Option<PlannedSync>,
+    pub multimedia: Option<PlannedSync>,
+}
+
+impl Action {
+    pub fn path(&self) -> &Path {
+        match self {
+            Action::Download { path, .. }
+            | Action::Update { path, .. }
+            | Action::Skip { path, .. } => path,
+        }
+    }
+}
+
+impl Plan {
+    pub fn pending(&self) -> Vec<usize> {
+        self.missing
+            .iter()
+            .chain(self.outdated.iter())
+            .chain(self.unverified.iter())
+            .copied()
+            .collect()
+    }
+
+    pub fn actions<T: Resource>(&self, resources: &[T], overwrite: OverwriteMode) -> Vec<Action> {
+        let downloads = self.missing.iter().map(|&index| Action::Download {
+            path: resources[index].path().to_owned(),
+            last_updated: resources[index].last_updated(),
+            size: resources[index].size(),
+        });
+        let updates = self.outdated.iter().map(|&index| match overwrite {
+            OverwriteMode::Skip => Action::Skip {
+                path: resources[index].path().to_owned(),
+                last_updated: resources[index].last_updated(),
+            },
+            _ => Action::Update {
+                path: resources[index].path().to_owned(),
+                last_updated: resources[index].last_updated(),
+                size: resources[index].size(),
+                overwrite: overwrite.as_str().to_owned(),
+            },
+        });
+        downloads.chain(updates).collect()
+    }
+}
+
+// Picks out the resources that need downloading, by looking at the local copies only.
+// The download stage still checks again, in case something changed in the meantime.
+// Comparing by content needs the server, so copies that might be up to date are only
+// told apart from those that can't be, by size. Plans leave those out of their actions.
+pub async fn plan<T: Resource>(resources: &[T], destination: &Path, compare_by: CompareBy) -> Plan {
+    let metadata = future::join_all(resources.iter().map(|resource| async move {
+        let metadata = tokio::fs::metadata(destination.join(resource.path()))
+            .await
+            .ok()?;
+        // post-processed copies are compared by the size of the original that was kept
+        let original = destination.join(ORIGINALS_DIR).join(resource.path());
+        let len = match tokio::fs::metadata(&original).await {
+            Ok(original) if original.is_file() => original.len(),
+            _ => metadata.len(),
+        };
+        Some((metadata.modified().ok()?, len))
+    }))
+    .await;
+    let mut plan = Plan {
+        missing: vec![],
+        outdated: vec![],
+        unverified: vec![],
+        up_to_date: vec![],
+    };
+    for (index, metadata) in metadata.into_iter().enumerate() {
+        let resource = &resources[index];
+        match (metadata, resource.size()) {
+            (None, _) => plan.missing.push(index),
+            (Some((_, len)), Some(size)) if compare_by == CompareBy::Content => {
+                if len == size {
+                    plan.unverified.push(index)
+                } else {
+                    plan.outdated.push(index)
+                }
+            }
+            (Some((modified, _)), _) if resource.last_updated() > modified => {
+                plan.outdated.push(index)
+            }
+            (Some(_), _) => plan.up_to_date.push(index),
+        }
+    }
+    plan
+}
+
+// Keeps the resources that a reviewed plan downloads or updates. Anything that changed on the server
+// since the plan was made is left out, so that applying a plan never does more than was reviewed.
+// Also returns how many planned actions were left out that way.
+pub fn select<T: Resource>(resources: Vec<T>, actions: &[Action]) -> (Vec<T>, usize) {
+    let planned = actions
+        .iter()
+        .filter_map(|action| match action {
+            Action::Download {
+                path, last_updated, ..
+            }
+            | Action::Update {
+                path, last_updated, ..
+            } => Some((path.as_path(), *last_updated)),
+            Action::Skip { .. } => None,
+        })
+        .collect::<HashMap<_, _>>();
+    let selected = resources
+        .into_iter()
+        .filter(|resource| planned.get(resource.path()) == Some(&resource.last_updated()))
+        .collect::<Vec<_>>();
+    let stale = planned.len().saturating_sub(selected.len());
+    (selected, stale)
+}
+
+// How a file that was downloaded by hand lines up with a resource on the server
+#[derive(Debug, PartialEq, Eq)]
+pub enum Found {
+    // where a sync would put it, with the same size
+    InPlace,
+    // somewhere else under the destination, the only file there with the same name and size
+    Elsewhere(PathBuf),
+    // where a sync would put it, but a different size, so probably another version
+    Differs,
+    Missing,
+}
+
+// Lines up what is already under `destination` with the resources, for taking over a mirror that
+// was made by hand. Only sizes are compared, since the server has no checksums to compare against.
+pub fn find_local<T: Resource>(resources: &[T], destination: &Path) -> Vec<Found> {
+    let mut files = vec![];
+    walk_files(destination, &mut |path, metadata| {
+        files.push((path, metadata.len()))
+    });
+    let expected = resources
+        .iter()
+        .map(|resource| destination.join(resource.path()))
+        .collect::<HashSet<_>>();
+    let sizes = files.iter().cloned().collect::<HashMap<_, _>>();
+    // (name, size) => files elsewhere that could be it, leaving out those already where something belongs
+    let mut candidates = HashMap::<(OsString, u64), Vec<PathBuf>>::new();
+    for (path, size) in files {
+        if let (Some(name), false) = (path.file_name(), expected.contains(&path)) {
+            candidates
+                .entry((name.to_owned(), size))
+                .or_default()
+                .push(path.clone());
+        }
+    }
+    let mut claimed = HashSet::new();
+    resources
+        .iter()
+        .map(|resource| {
+            let path = destination.join(resource.path());
+            match (sizes.get(&path), resource.size()) {
+                (Some(local), Some(remote)) if *local != remote => return Found::Differs,
+                (Some(_), _) => return Found::InPlace,
+                (None, _) => {}
+            }
+            let key = match (path.file_name(), resource.size()) {
+                (Some(name), Some(size)) => (name.to_owned(), size),
+                _ => return Found::Missing,
+            };
+            match candidates.get(&key).map(Vec::as_slice) {
+                // with more than one there is no telling which it is,
+                // and a file can't stand in for two resources with the same name either
+                Some([only]) if claimed.insert(only.clone()) => Found::Elsewhere(only.clone()),
+                _ => Found::Missing,
+            }
+        })
+        .collect()
+}
+
+// Lets the parts of a sync (announcements, files, multimedia) take turns by priority, so that
+// what matters most isn't held up by large videos. Listing still happens side by side, only the
+// heavy work waits. Parts that aren't in the order never wait and aren't waited for.
+pub struct Turns {
+    order: Vec<String>,
+    finished: RefCell<HashSet<String>>,
+    notify: Notify,
+}
+
+impl Turns {
+    pub fn new(order: Vec<String>) -> Turns {
+        Turns {
+            order,
+            finished: RefCell::new(HashSet::new()),
+            notify: Notify::new(),
+        }
+    }
+
+    fn is_turn(&self, part: &str) -> bool {
+        let finished = self.finished.borrow();
+        self.order
+            .iter()
+            .take_while(|earlier| *earlier != part)
+            .all(|earlier| finished.contains(earlier))
+    }
+
+    // Waits until every part before this one is done
+    pub async fn wait_for_turn(&self, part: &str) {
+        if !self.order.iter().any(|p| p == part) {
+            return;
+        }
+        loop {
+            let notified = self.notify.notified();
+            if self.is_turn(part) {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    // Runs a part, marking it done however it ends, so that later parts are never stuck
+    pub async fn take_turn<F: Future>(&self, part: &str, f: F) -> F::Output {
+        let output = f.await;
+        self.finished.borrow_mut().insert(part.to_owned());
+        self.notify.notify_waiters();
+        output
+    }
+}
+
+// Runs downloads as tasks owned by a JoinSet, at most `parallelism` at a time.
+// Once cancelled, no new downloads are started, and the ones in flight are waited for,
+// so that nothing is left half written. Nothing outlives `run`.
+pub struct Pipeline {
+    parallelism: usize,
+    cancellation: CancellationToken,
+}
+
+impl Pipeline {
+    pub fn new(parallelism: usize, cancellation: CancellationToken) -> Pipeline {
+        Pipeline {
+            parallelism: parallelism.max(1),
+            cancellation,
+        }
+    }
+
+    // Resources can't be sent across threads, so the tasks all run on this one
+    pub async fn run<I, O, F, Fut>(&self, items: Vec<I>, work: F) -> Vec<O>
+    where
+        I: 'static,
+        O: 'static,
+        F: Fn(I) -> Fut,
+        Fut: Future<Output = O> + 'static,
+    {
+        LocalSet::new()
+            .run_until(async {
+                let mut tasks = JoinSet::new();
+                let mut items = items.into_iter();
+                let mut outputs = vec![];
+                loop {
+                    while tasks.len() < self.parallelism && !self.cancellation.is_cancelled() {
+                        match items.next() {
+                            Some(item) => {
+                                tasks.spawn_local(work(item));
+                            }
+                            None => break,
+                        }
+                    }
+                    match tasks.join_next().await {
+                        Some(Ok(output)) => outputs.push(output),
+                        Some(Err(e)) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+                        Some(Err(_)) => {}
+                        None => break,
+                    }
+                }
+                outputs
+            })
+            .await
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.is_cancelled()
+    }
+}
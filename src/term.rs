@@ -0,0 +1,56 @@
+use std::fmt;
+
+use crate::module::Module;
+
+// A LumiNUS term code such as 2010, which is AY2020/21 Semester 1.
+// The first two digits are the year the academic year starts in, the third is the semester.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AcademicTerm {
+    code: String,
+}
+
+impl AcademicTerm {
+    pub fn parse(code: &str) -> Option<AcademicTerm> {
+        let code = code.trim();
+        if code.len() == 4 && code.chars().all(|c| c.is_ascii_digit()) {
+            Some(AcademicTerm {
+                code: code.to_owned(),
+            })
+        } else {
+            None
+        }
+    }
+
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    pub fn describe(&self) -> String {
+        let year = self.code[..2].parse::<u32>().unwrap_or(0);
+        let semester = match &self.code[2..3] {
+            "1" => "Semester 1",
+            "2" => "Semester 2",
+            "3" => "Special Term 1",
+            "4" => "Special Term 2",
+            _ => "unknown semester",
+        };
+        format!("AY20{:02}/{:02} {}", year, (year + 1) % 100, semester)
+    }
+}
+
+impl fmt::Display for AcademicTerm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.code)
+    }
+}
+
+// the terms that `modules` are in, oldest first
+pub fn terms_of(modules: &[Module]) -> Vec<AcademicTerm> {
+    let mut terms = modules
+        .iter()
+        .filter_map(|module| AcademicTerm::parse(&module.term))
+        .collect::<Vec<_>>();
+    terms.sort();
+    terms.dedup();
+    terms
+}
@@ -0,0 +1,112 @@
+use std::path::Path;
+
+use tokio::process::Command;
+
+use crate::{Api, Result};
+
+pub struct TranscodeProfile {
+    pub name: &'static str,
+    pub description: &'static str,
+    // ffmpeg arguments between the input and the output file
+    args: &'static [&'static str],
+}
+
+pub const PROFILES: &[TranscodeProfile] = &[
+    TranscodeProfile {
+        name: "h265-720p",
+        description: "H.265 video scaled down to 720p, AAC audio",
+        args: &[
+            "-vf",
+            "scale=-2:'min(720,ih)'",
+            "-c:v",
+            "libx265",
+            "-crf",
+            "28",
+            "-preset",
+            "medium",
+            "-c:a",
+            "aac",
+            "-b:a",
+            "96k",
+        ],
+    },
+    TranscodeProfile {
+        name: "h264-720p",
+        description: "H.264 video scaled down to 720p, AAC audio",
+        args: &[
+            "-vf",
+            "scale=-2:'min(720,ih)'",
+            "-c:v",
+            "libx264",
+            "-crf",
+            "26",
+            "-preset",
+            "medium",
+            "-c:a",
+            "aac",
+            "-b:a",
+            "96k",
+        ],
+    },
+    TranscodeProfile {
+        name: "h265-480p",
+        description: "H.265 video scaled down to 480p, AAC audio",
+        args: &[
+            "-vf",
+            "scale=-2:'min(480,ih)'",
+            "-c:v",
+            "libx265",
+            "-crf",
+            "30",
+            "-preset",
+            "medium",
+            "-c:a",
+            "aac",
+            "-b:a",
+            "64k",
+        ],
+    },
+    TranscodeProfile {
+        name: "audio-only",
+        description: "Drop the video track and keep Opus audio",
+        args: &["-vn", "-c:a", "libopus", "-b:a", "48k"],
+    },
+];
+
+pub fn find_profile(name: &str) -> Option<&'static TranscodeProfile> {
+    PROFILES.iter().find(|profile| profile.name == name)
+}
+
+impl TranscodeProfile {
+    // Re-encodes the video at `path` in place with ffmpeg.
+    // `temp_path` is where the re-encoded video is written before it replaces the original.
+    pub async fn apply(&self, api: &Api, path: &Path, temp_path: &Path) -> Result<()> {
+        let modified = tokio::fs::metadata(path)
+            .await
+            .and_then(|metadata| metadata.modified())
+            .map_err(|_| "Unable to retrieve file metadata")?;
+        let success = Command::new(&api.ffmpeg_path)
+            .arg("-y") // flag to overwrite output file without prompting
+            .arg("-i")
+            .arg(path.as_os_str())
+            .args(self.args)
+            .arg(temp_path.as_os_str())
+            .output()
+            .await
+            .map_err(|_| "Failed to start ffmpeg")?
+            .status
+            .success();
+        if !success {
+            // the partial output is useless, the original is still intact
+            let _ = tokio::fs::remove_file(temp_path).await;
+            return Err("ffmpeg returned nonzero exit code");
+        }
+        tokio::fs::rename(temp_path, path)
+            .await
+            .map_err(|_| "Unable to move temporary file")?;
+
+        // keep the server's last updated time, so that we don't download the original again
+        filetime::set_file_mtime(path, filetime::FileTime::from_system_time(modified))
+            .map_err(|_| "Unable to set last modified time")
+    }
+}
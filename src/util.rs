@@ -1,4 +1,9 @@
-use std::time::SystemTime;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 
 pub fn sanitise_filename(name: &str) -> String {
     if cfg!(windows) {
@@ -15,9 +20,276 @@ pub fn sanitise_filename(name: &str) -> String {
     }
 }
 
+// the extension of `path` in lower case, so that "Slides.PDF" is a PDF too
+pub fn extension_of(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+}
+
 pub fn parse_time(time: &str) -> SystemTime {
     SystemTime::from(
         chrono::DateTime::<chrono::FixedOffset>::parse_from_rfc3339(time)
             .expect("Failed to parse last updated time"),
     )
 }
+
+pub fn html_to_text(html: &str) -> String {
+    let stripped = ammonia::Builder::new()
+        .tags(HashSet::new())
+        .clean(html)
+        .to_string();
+    htmlescape::decode_html(&stripped)
+        .unwrap_or_else(|_| "Unable to decode HTML Entities".to_owned())
+}
+
+// Compares strings the way a human would sort file names, so that "Lecture 2" comes before "Lecture 10"
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+    loop {
+        match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(ca), Some(cb)) if ca.is_ascii_digit() && cb.is_ascii_digit() => {
+                let take_number = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+                    let mut digits = String::new();
+                    while let Some(c) = chars.peek().copied().filter(char::is_ascii_digit) {
+                        digits.push(c);
+                        chars.next();
+                    }
+                    digits
+                };
+                let na = take_number(&mut a_chars);
+                let nb = take_number(&mut b_chars);
+                let na_trimmed = na.trim_start_matches('0');
+                let nb_trimmed = nb.trim_start_matches('0');
+                let ordering = na_trimmed
+                    .len()
+                    .cmp(&nb_trimmed.len())
+                    .then_with(|| na_trimmed.cmp(nb_trimmed));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(ca), Some(cb)) => {
+                let ordering = ca.to_lowercase().cmp(cb.to_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a_chars.next();
+                b_chars.next();
+            }
+        }
+    }
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+// Parses durations such as "7d", "12h", "2w" or "30m"
+pub fn parse_duration(duration: &str) -> Option<Duration> {
+    let duration = duration.trim();
+    let unit = duration.chars().last()?;
+    let amount = duration[..duration.len() - unit.len_utf8()]
+        .parse::<u64>()
+        .ok()?;
+    let seconds = match unit {
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    Some(Duration::from_secs(amount.checked_mul(seconds)?))
+}
+
+// Parses sizes such as "512K", "256M" or "2G" into bytes. A bare number is taken as bytes.
+pub fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim().trim_end_matches(['B', 'b', 'i']);
+    let (amount, multiplier) = match size.chars().last()? {
+        'k' | 'K' => (&size[..size.len() - 1], 1024),
+        'm' | 'M' => (&size[..size.len() - 1], 1024 * 1024),
+        'g' | 'G' => (&size[..size.len() - 1], 1024 * 1024 * 1024),
+        _ => (size, 1),
+    };
+    amount
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|amount| amount.checked_mul(multiplier))
+}
+
+// The candidates that `reference` is most likely a typo of, closest first, ignoring case.
+// Candidates that merely contain it, such as CS2103T for 2103, are offered after close typos.
+pub fn closest_matches<I: IntoIterator<Item = String>>(
+    reference: &str,
+    candidates: I,
+    limit: usize,
+) -> Vec<String> {
+    let reference = reference.to_lowercase();
+    // allow about one typo per three characters
+    let tolerance = (reference.chars().count() / 3).max(1);
+    let mut matches = candidates
+        .into_iter()
+        .filter_map(|candidate| {
+            let lowercase = candidate.to_lowercase();
+            let distance = edit_distance(&reference, &lowercase);
+            if distance <= tolerance {
+                Some((distance, candidate))
+            } else if reference.chars().count() >= 3 && lowercase.contains(&reference) {
+                Some((tolerance + 1, candidate))
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    matches.sort();
+    matches.dedup_by(|(_, a), (_, b)| a.eq_ignore_ascii_case(b));
+    matches
+        .into_iter()
+        .take(limit)
+        .map(|(_, candidate)| candidate)
+        .collect()
+}
+
+// Levenshtein distance counted in characters, where swapping two neighbouring characters is one edit
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b) = (a.chars().collect::<Vec<_>>(), b.chars().collect::<Vec<_>>());
+    // distances[i][j] is the distance between the first i characters of a and the first j of b
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    distances[a.len()][b.len()]
+}
+
+pub fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+// Finds a program the way the shell would, so that missing tools can be reported before they are needed
+pub fn find_executable(program: &str) -> Option<PathBuf> {
+    let candidates = |path: PathBuf| {
+        if cfg!(windows) && path.extension().is_none() {
+            vec![path.with_extension("exe"), path]
+        } else {
+            vec![path]
+        }
+    };
+    if Path::new(program).components().count() > 1 {
+        return candidates(PathBuf::from(program))
+            .into_iter()
+            .find(|path| path.is_file());
+    }
+    env::split_paths(&env::var_os("PATH")?)
+        .flat_map(|dir| candidates(dir.join(program)))
+        .find(|path| path.is_file())
+}
+
+// Calls `f` with every file under `dir`, however deep, skipping what can't be read
+pub fn walk_files<F: FnMut(PathBuf, fs::Metadata)>(dir: &Path, f: &mut F) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        match entry.metadata() {
+            Ok(metadata) if metadata.is_dir() => walk_files(&entry.path(), f),
+            Ok(metadata) => f(entry.path(), metadata),
+            Err(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sizes_with_suffixes() {
+        assert_eq!(parse_size("512K"), Some(512 * 1024));
+        assert_eq!(parse_size("512k"), Some(512 * 1024));
+        assert_eq!(parse_size("256M"), Some(256 * 1024 * 1024));
+        assert_eq!(parse_size("2G"), Some(2 * 1024 * 1024 * 1024));
+        assert_eq!(parse_size("2GB"), Some(2 * 1024 * 1024 * 1024));
+        assert_eq!(parse_size("2GiB"), Some(2 * 1024 * 1024 * 1024));
+        assert_eq!(parse_size(" 3 M "), Some(3 * 1024 * 1024));
+    }
+
+    #[test]
+    fn takes_bare_numbers_as_bytes() {
+        assert_eq!(parse_size("0"), Some(0));
+        assert_eq!(parse_size("1500"), Some(1500));
+        assert_eq!(parse_size("1500B"), Some(1500));
+    }
+
+    #[test]
+    fn rejects_sizes_that_are_not_numbers() {
+        assert_eq!(parse_size(""), None);
+        assert_eq!(parse_size("M"), None);
+        assert_eq!(parse_size("-1K"), None);
+        assert_eq!(parse_size("1.5G"), None);
+        assert_eq!(parse_size("10T"), None);
+        assert_eq!(parse_size("lots"), None);
+    }
+
+    #[test]
+    fn rejects_sizes_that_overflow() {
+        assert_eq!(parse_size("18446744073709551615"), Some(u64::MAX));
+        assert_eq!(parse_size("18446744073709551616"), None);
+        assert_eq!(parse_size("17179869183G"), Some(17179869183 << 30));
+        assert_eq!(parse_size("17179869184G"), None);
+        assert_eq!(parse_size("18014398509481984K"), None);
+    }
+}
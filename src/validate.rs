@@ -0,0 +1,390 @@
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::sniff;
+use crate::util::extension_of;
+use crate::Result;
+
+// How far from the start and end of a PDF its header and %%EOF marker may be.
+// Readers are lenient about a little junk either side, and so are we.
+const PDF_SLACK: u64 = 1024;
+// The end of central directory record of a zip file is 22 bytes, plus a comment of up to 64KiB
+const ZIP_TAIL_SIZE: u64 = 22 + 0xffff;
+
+// Checks that the downloaded file at `path` looks whole, to catch files that were cut short or
+// mangled on the way. It is checked as the format its content turns out to be, so that a
+// "slides.pdf" that is really a pptx isn't failed for not being a PDF. Content that isn't
+// recognised is taken to be in the format that the extension of `name` says, since it may still
+// be under a temporary name. Formats we don't know are taken as they are.
+pub fn check(path: &Path, name: &Path) -> Result<()> {
+    let extension = match sniff::content_extension(path) {
+        Ok(Some(extension)) => Some(extension.to_owned()),
+        Ok(None) => extension_of(name),
+        Err(_) => return Err("Unable to read downloaded file"),
+    };
+    match extension.as_deref() {
+        Some("pdf") => check_pdf(path),
+        Some("zip") | Some("docx") | Some("docm") | Some("pptx") | Some("pptm") | Some("ppsx")
+        | Some("xlsx") | Some("xlsm") | Some("jar") | Some("epub") => check_zip(path),
+        Some("mp4") | Some("m4v") | Some("m4a") | Some("mov") => check_mp4(path),
+        Some("mkv") | Some("webm") | Some("mka") => check_mkv(path),
+        _ => Ok(()),
+    }
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
+
+fn read_at(file: &mut fs::File, from: SeekFrom, len: u64) -> io::Result<Vec<u8>> {
+    let mut buf = vec![];
+    file.seek(from)?;
+    file.take(len).read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+fn check_pdf(path: &Path) -> Result<()> {
+    let mut file = fs::File::open(path).map_err(|_| "Unable to open downloaded file")?;
+    let len = file
+        .metadata()
+        .map_err(|_| "Unable to open downloaded file")?
+        .len();
+    let head = read_at(&mut file, SeekFrom::Start(0), PDF_SLACK)
+        .map_err(|_| "Unable to read downloaded file")?;
+    if !contains(&head, b"%PDF-") {
+        return Err("Downloaded PDF has no PDF header, so it is probably not a PDF at all");
+    }
+    let tail = read_at(
+        &mut file,
+        SeekFrom::Start(len.saturating_sub(PDF_SLACK)),
+        PDF_SLACK,
+    )
+    .map_err(|_| "Unable to read downloaded file")?;
+    if !contains(&tail, b"%%EOF") {
+        return Err("Downloaded PDF has no %%EOF marker at the end, so it is probably cut short");
+    }
+    Ok(())
+}
+
+fn check_zip(path: &Path) -> Result<()> {
+    let mut file = fs::File::open(path).map_err(|_| "Unable to open downloaded file")?;
+    let len = file
+        .metadata()
+        .map_err(|_| "Unable to open downloaded file")?
+        .len();
+    let tail = read_at(
+        &mut file,
+        SeekFrom::Start(len.saturating_sub(ZIP_TAIL_SIZE)),
+        ZIP_TAIL_SIZE,
+    )
+    .map_err(|_| "Unable to read downloaded file")?;
+    if !contains(&tail, b"PK\x05\x06") {
+        return Err("Downloaded zip file has no central directory, so it is probably cut short");
+    }
+    check_zip_entries(file)
+}
+
+// reading every entry to the end makes the zip crate check its CRC
+#[cfg(feature = "extract")]
+fn check_zip_entries(file: fs::File) -> Result<()> {
+    let mut archive =
+        zip::ZipArchive::new(file).map_err(|_| "Downloaded zip file could not be opened")?;
+    for i in 0..archive.len() {
+        let mut entry = archive
+            .by_index(i)
+            .map_err(|_| "Downloaded zip file has an entry that could not be opened")?;
+        io::copy(&mut entry, &mut io::sink())
+            .map_err(|_| "Downloaded zip file has an entry that fails its CRC check")?;
+    }
+    Ok(())
+}
+
+// without the zip crate, the central directory being there has to do
+#[cfg(not(feature = "extract"))]
+fn check_zip_entries(_file: fs::File) -> Result<()> {
+    Ok(())
+}
+
+// Walks the top level boxes of an MP4 file. Players need the moov box to make sense of the
+// media data, and a box claiming to run past the end of the file means the file was cut short.
+fn check_mp4(path: &Path) -> Result<()> {
+    let mut file = fs::File::open(path).map_err(|_| "Unable to open downloaded file")?;
+    let len = file
+        .metadata()
+        .map_err(|_| "Unable to open downloaded file")?
+        .len();
+    let mut offset = 0;
+    let mut has_moov = false;
+    while offset < len {
+        let header = read_at(&mut file, SeekFrom::Start(offset), 16)
+            .map_err(|_| "Unable to read downloaded file")?;
+        if header.len() < 8 {
+            return Err("Downloaded video ends partway through a box, so it is probably cut short");
+        }
+        let size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let size = match size {
+            // the box runs to the end of the file
+            0 => len - offset,
+            // the real size follows the type, as 64 bits
+            1 if header.len() == 16 => {
+                let mut large = [0; 8];
+                large.copy_from_slice(&header[8..16]);
+                u64::from_be_bytes(large)
+            }
+            size => size,
+        };
+        if size < 8 || offset.checked_add(size).is_none_or(|end| end > len) {
+            return Err("Downloaded video ends partway through a box, so it is probably cut short");
+        }
+        if &header[4..8] == b"moov" {
+            has_moov = true;
+        }
+        offset += size;
+    }
+    if !has_moov {
+        return Err("Downloaded video has no moov atom, so players won't be able to open it");
+    }
+    Ok(())
+}
+
+// An EBML variable length integer at the start of `bytes`: its length, and its value with the
+// length marker taken off. The value is None when all its bits are set, which means unknown.
+fn read_vint(bytes: &[u8]) -> Option<(usize, Option<u64>)> {
+    let first = *bytes.first()?;
+    if first == 0 {
+        return None;
+    }
+    let len = first.leading_zeros() as usize + 1;
+    if bytes.len() < len {
+        return None;
+    }
+    let mut value = first as u64 & (0xff >> len);
+    for byte in &bytes[1..len] {
+        value = value << 8 | *byte as u64;
+    }
+    let unknown = (1u64 << (7 * len)) - 1;
+    Some((len, if value == unknown { None } else { Some(value) }))
+}
+
+// Multimedia is saved as Matroska by ffmpeg. A Matroska file is an EBML header followed by a
+// segment holding everything else. ffmpeg writes the size of the segment once it is done, so a
+// segment of unknown size, or one claiming to run past the end of the file, means it was cut short.
+fn check_mkv(path: &Path) -> Result<()> {
+    const CUT_SHORT: &str =
+        "Downloaded video ends partway through its segment, so it is probably cut short";
+    let mut file = fs::File::open(path).map_err(|_| "Unable to open downloaded file")?;
+    let len = file
+        .metadata()
+        .map_err(|_| "Unable to open downloaded file")?
+        .len();
+    let header =
+        read_at(&mut file, SeekFrom::Start(0), 12).map_err(|_| "Unable to read downloaded file")?;
+    if !header.starts_with(b"\x1a\x45\xdf\xa3") {
+        return Err("Downloaded video has no EBML header, so it is probably not a Matroska file");
+    }
+    let header_size = match read_vint(&header[4..]) {
+        Some((size_len, Some(size))) => 4 + size_len as u64 + size,
+        _ => return Err("Downloaded video has a broken EBML header"),
+    };
+    let segment = read_at(&mut file, SeekFrom::Start(header_size), 12)
+        .map_err(|_| "Unable to read downloaded file")?;
+    if !segment.starts_with(b"\x18\x53\x80\x67") {
+        return Err(CUT_SHORT);
+    }
+    match read_vint(&segment[4..]) {
+        Some((size_len, Some(size)))
+            if (header_size + 4 + size_len as u64)
+                .checked_add(size)
+                .is_some_and(|end| end <= len) =>
+        {
+            Ok(())
+        }
+        _ => Err(CUT_SHORT),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn fixture(name: &str, bytes: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "fluminurs-validate-{}-{}",
+            name,
+            std::process::id()
+        ));
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    fn mp4_box(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut bytes = ((8 + body.len()) as u32).to_be_bytes().to_vec();
+        bytes.extend_from_slice(kind);
+        bytes.extend_from_slice(body);
+        bytes
+    }
+
+    fn mp4(boxes: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = mp4_box(b"ftyp", b"isom\0\0\0\0");
+        for mp4_box in boxes {
+            bytes.extend_from_slice(mp4_box);
+        }
+        bytes
+    }
+
+    // an EBML header with a 4 byte body, then a segment whose size is `size`, then `body`
+    fn mkv(size: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut bytes = b"\x1a\x45\xdf\xa3\x84webm".to_vec();
+        bytes.extend_from_slice(b"\x18\x53\x80\x67");
+        bytes.extend_from_slice(size);
+        bytes.extend_from_slice(body);
+        bytes
+    }
+
+    #[test]
+    fn whole_mp4_passes() {
+        let path = fixture(
+            "whole.mp4",
+            &mp4(&[mp4_box(b"moov", &[0; 12]), mp4_box(b"mdat", &[1; 100])]),
+        );
+        assert_eq!(check_mp4(&path), Ok(()));
+    }
+
+    #[test]
+    fn truncated_mp4_fails() {
+        let mut bytes = mp4(&[mp4_box(b"moov", &[0; 12]), mp4_box(b"mdat", &[1; 100])]);
+        bytes.truncate(bytes.len() - 10);
+        let path = fixture("truncated.mp4", &bytes);
+        assert!(check_mp4(&path).unwrap_err().contains("cut short"));
+
+        let path = fixture("truncated-header.mp4", &bytes[..bytes.len() - 96]);
+        assert!(check_mp4(&path).unwrap_err().contains("cut short"));
+    }
+
+    #[test]
+    fn mp4_without_moov_fails() {
+        let path = fixture("no-moov.mp4", &mp4(&[mp4_box(b"mdat", &[1; 100])]));
+        assert!(check_mp4(&path).unwrap_err().contains("no moov atom"));
+    }
+
+    #[test]
+    fn mp4_box_of_size_zero_runs_to_the_end() {
+        let mut bytes = mp4(&[mp4_box(b"moov", &[0; 12])]);
+        bytes.extend_from_slice(b"\0\0\0\0mdat");
+        bytes.extend_from_slice(&[1; 100]);
+        let path = fixture("size-zero.mp4", &bytes);
+        assert_eq!(check_mp4(&path), Ok(()));
+    }
+
+    #[test]
+    fn mp4_box_of_size_one_has_a_64_bit_size() {
+        let large_mdat = |size: u64| {
+            let mut bytes = b"\0\0\0\x01mdat".to_vec();
+            bytes.extend_from_slice(&size.to_be_bytes());
+            bytes.extend_from_slice(&[1; 100]);
+            bytes
+        };
+        let path = fixture(
+            "large.mp4",
+            &mp4(&[mp4_box(b"moov", &[0; 12]), large_mdat(116)]),
+        );
+        assert_eq!(check_mp4(&path), Ok(()));
+
+        let path = fixture(
+            "large-truncated.mp4",
+            &mp4(&[mp4_box(b"moov", &[0; 12]), large_mdat(1 << 40)]),
+        );
+        assert!(check_mp4(&path).unwrap_err().contains("cut short"));
+    }
+
+    #[test]
+    fn reads_vints_of_every_length() {
+        assert_eq!(read_vint(b"\x84"), Some((1, Some(4))));
+        assert_eq!(read_vint(b"\x40\x02"), Some((2, Some(2))));
+        assert_eq!(read_vint(b"\x01\0\0\0\0\0\x01\0"), Some((8, Some(256))));
+        assert_eq!(read_vint(b"\xff"), Some((1, None)));
+        assert_eq!(
+            read_vint(b"\x01\xff\xff\xff\xff\xff\xff\xff"),
+            Some((8, None))
+        );
+        assert_eq!(read_vint(b"\x40"), None);
+        assert_eq!(read_vint(b"\0"), None);
+    }
+
+    #[test]
+    fn whole_mkv_passes() {
+        let path = fixture("whole.mkv", &mkv(b"\x84", b"data"));
+        assert_eq!(check_mkv(&path), Ok(()));
+    }
+
+    #[test]
+    fn mkv_segment_of_unknown_size_fails() {
+        let path = fixture("unknown-size.mkv", &mkv(b"\xff", b"data"));
+        assert!(check_mkv(&path).unwrap_err().contains("cut short"));
+    }
+
+    #[test]
+    fn mkv_segment_running_past_the_end_fails() {
+        let path = fixture("past-the-end.mkv", &mkv(b"\x90", b"data"));
+        assert!(check_mkv(&path).unwrap_err().contains("cut short"));
+    }
+
+    #[test]
+    fn mkv_without_ebml_header_fails() {
+        let path = fixture("no-header.mkv", b"not a matroska file");
+        assert!(check_mkv(&path).unwrap_err().contains("no EBML header"));
+    }
+
+    #[test]
+    fn whole_pdf_passes() {
+        let path = fixture("whole.pdf", b"%PDF-1.4\n1 0 obj\n<<>>\nendobj\n%%EOF\n");
+        assert_eq!(check_pdf(&path), Ok(()));
+    }
+
+    #[test]
+    fn pdf_without_eof_fails() {
+        let path = fixture("no-eof.pdf", b"%PDF-1.4\n1 0 obj\n<<>>\nend");
+        assert!(check_pdf(&path).unwrap_err().contains("%%EOF"));
+    }
+
+    #[test]
+    fn pdf_without_header_fails() {
+        let path = fixture("no-header.pdf", b"<html>Not found</html>");
+        assert!(check_pdf(&path).unwrap_err().contains("no PDF header"));
+    }
+
+    #[test]
+    fn zip_without_end_of_central_directory_fails() {
+        let path = fixture(
+            "no-eocd.zip",
+            b"PK\x03\x04 the first entry and nothing else",
+        );
+        assert!(check_zip(&path)
+            .unwrap_err()
+            .contains("no central directory"));
+    }
+
+    #[test]
+    fn empty_zip_passes() {
+        let mut bytes = b"PK\x05\x06".to_vec();
+        bytes.extend_from_slice(&[0; 18]);
+        let path = fixture("empty.zip", &bytes);
+        assert_eq!(check_zip(&path), Ok(()));
+    }
+
+    #[test]
+    fn checks_by_content_before_name() {
+        // a PDF under a .pptx name is checked as the PDF it is
+        let path = fixture("misnamed.pptx", b"%PDF-1.4\n%%EOF\n");
+        assert_eq!(check(&path, Path::new("slides.pptx")), Ok(()));
+        // content that isn't recognised goes by the name
+        let path = fixture("unrecognised.pdf", b"cut off");
+        assert!(check(&path, Path::new("notes.pdf")).is_err());
+        assert_eq!(check(&path, Path::new("notes.txt")), Ok(()));
+    }
+}
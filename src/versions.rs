@@ -0,0 +1,373 @@
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use chrono::format::{Item, StrftimeItems};
+
+use crate::util::{sanitise_filename, walk_files};
+use crate::Result;
+
+// How the old version of an updated file is named when it is renamed out of the way
+#[derive(Debug, Clone)]
+pub struct RenameScheme {
+    // the old version's name, minus its extension. {name} is the file's name and {time} is when
+    // the old version was last updated, e.g. "{name}_autorename_{time}" or "old {time} {name}"
+    pub template: String,
+    // strftime format for {time}
+    pub time_format: String,
+    // subfolder next to the file that old versions go into, instead of beside it
+    pub folder: Option<String>,
+    // which old versions are removed as newer ones come in
+    pub retention: Retention,
+}
+
+impl Default for RenameScheme {
+    fn default() -> RenameScheme {
+        RenameScheme {
+            template: "{name}_autorename_{time}".to_owned(),
+            time_format: "%Y-%m-%d".to_owned(),
+            folder: None,
+            retention: Retention::default(),
+        }
+    }
+}
+
+// Which old versions of a file to keep. A version has to pass every limit that is set to be kept.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Retention {
+    // the newest this many
+    pub keep: Option<usize>,
+    // those last updated within this long ago
+    pub max_age: Option<Duration>,
+}
+
+impl Retention {
+    pub fn is_set(&self) -> bool {
+        self.keep.is_some() || self.max_age.is_some()
+    }
+
+    // the ones to remove out of `versions`, which are oldest first
+    pub fn expired(&self, versions: &[PathBuf]) -> Vec<PathBuf> {
+        let excess = self
+            .keep
+            .map_or(0, |keep| versions.len().saturating_sub(keep));
+        let now = SystemTime::now();
+        versions
+            .iter()
+            .enumerate()
+            .filter(|(i, version)| {
+                *i < excess
+                    || self.max_age.is_some_and(|max_age| {
+                        fs::metadata(version)
+                            .and_then(|metadata| metadata.modified())
+                            .ok()
+                            .and_then(|modified| now.duration_since(modified).ok())
+                            .is_some_and(|age| age > max_age)
+                    })
+            })
+            .map(|(_, version)| version.clone())
+            .collect()
+    }
+}
+
+impl RenameScheme {
+    pub fn validate(&self) -> Result<()> {
+        // old versions are told apart from other files by the name they were renamed from
+        if self.template.matches("{name}").count() != 1 {
+            return Err("Rename template must contain {name} once");
+        }
+        if self.template.matches("{time}").count() > 1 {
+            return Err("Rename template may contain {time} at most once");
+        }
+        if StrftimeItems::new(&self.time_format).any(|item| item == Item::Error) {
+            return Err("Invalid time format for renamed files");
+        }
+        if let Some(folder) = &self.folder {
+            if folder.is_empty() || sanitise_filename(folder) != *folder {
+                return Err("Versions folder must be a plain folder name");
+            }
+        }
+        Ok(())
+    }
+
+    // where old versions of the file at `path` go
+    pub fn folder_for(&self, path: &Path) -> PathBuf {
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        match &self.folder {
+            Some(folder) => dir.join(folder),
+            None => dir.to_owned(),
+        }
+    }
+
+    // A free name for the old version of the file at `path`, last updated at `updated`.
+    // If the name is taken, _1, _2 and so on are added to it.
+    pub fn renamed_path(&self, path: &Path, updated: SystemTime) -> PathBuf {
+        let time = chrono::DateTime::<chrono::Local>::from(updated)
+            .format(&self.time_format)
+            .to_string();
+        let stem = self
+            .template
+            .replace("{name}", &stem_of(path))
+            .replace("{time}", &time);
+        let stem = sanitise_filename(&stem);
+        let folder = self.folder_for(path);
+        let mut i = 0;
+        loop {
+            let mut name = stem.clone();
+            if i > 0 {
+                name.push_str(&format!("_{}", i));
+            }
+            let renamed_path = with_extension_of(folder.join(name), path);
+            if !renamed_path.exists() && renamed_path != path {
+                return renamed_path;
+            }
+            i += 1;
+        }
+    }
+
+    // Old versions of the file at `path` that are still around, oldest first. They keep the last
+    // modified time that they had before being renamed, so that is what they are ordered by.
+    pub fn versions_of(&self, path: &Path) -> Vec<PathBuf> {
+        let entries = match fs::read_dir(self.folder_for(path)) {
+            Ok(entries) => entries,
+            Err(_) => return vec![],
+        };
+        let stem = stem_of(path);
+        let mut versions = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|candidate| candidate != path && candidate.extension() == path.extension())
+            .filter(|candidate| self.is_version(&stem_of(candidate), &stem))
+            .filter_map(|candidate| {
+                let modified = fs::metadata(&candidate).ok()?.modified().ok()?;
+                Some((modified, candidate))
+            })
+            .collect::<Vec<_>>();
+        versions.sort();
+        versions.into_iter().map(|(_, version)| version).collect()
+    }
+
+    // Removes the versions of the file at `path` that are past keeping, returning those that were removed
+    pub fn prune(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        if !self.retention.is_set() {
+            return Ok(vec![]);
+        }
+        let expired = self.retention.expired(&self.versions_of(path));
+        for version in &expired {
+            fs::remove_file(version)?;
+        }
+        Ok(expired)
+    }
+
+    // Every file under `dir` that has old versions, with them, oldest first. Versions of
+    // files that have since been deleted are left alone, since there is nothing to go by.
+    pub fn find_versions(&self, dir: &Path) -> Vec<(PathBuf, Vec<PathBuf>)> {
+        let mut files = vec![];
+        walk_files(dir, &mut |path, _| files.push(path));
+        files.sort();
+        let mut found = files
+            .into_iter()
+            .map(|path| {
+                let versions = self.versions_of(&path);
+                (path, versions)
+            })
+            .filter(|(_, versions)| !versions.is_empty())
+            .collect::<Vec<_>>();
+        // a template like "{name} old" would otherwise make "a old old" a version of the version "a old"
+        let versions = found
+            .iter()
+            .flat_map(|(_, versions)| versions.iter().cloned())
+            .collect::<HashSet<_>>();
+        found.retain(|(path, _)| !versions.contains(path));
+        found
+    }
+
+    // whether `candidate` is a name that the template gives an old version of `stem`,
+    // with any time in place of {time}, and maybe a _1, _2 and so on after it
+    fn is_version(&self, candidate: &str, stem: &str) -> bool {
+        let template = sanitise_filename(&self.template.replace("{name}", stem));
+        let (before, after) = match template.split_once("{time}") {
+            Some((before, after)) => (before, Some(after)),
+            None => (template.as_str(), None),
+        };
+        let rest = match candidate.strip_prefix(before) {
+            Some(rest) => rest,
+            None => return false,
+        };
+        let without_counter = |rest: &str| -> Vec<String> {
+            let mut options = vec![rest.to_owned()];
+            if let Some((start, counter)) = rest.rsplit_once('_') {
+                if !counter.is_empty() && counter.chars().all(|c| c.is_ascii_digit()) {
+                    options.push(start.to_owned());
+                }
+            }
+            options
+        };
+        without_counter(rest).iter().any(|rest| match after {
+            Some(after) => rest.len() > after.len() && rest.ends_with(after),
+            None => rest.is_empty(),
+        })
+    }
+}
+
+fn stem_of(path: &Path) -> String {
+    path.file_stem()
+        .map(OsStr::to_string_lossy)
+        .unwrap_or_default()
+        .into_owned()
+}
+
+fn with_extension_of(path: PathBuf, original: &Path) -> PathBuf {
+    match original.extension() {
+        Some(ext) => {
+            let mut name = path.file_name().unwrap_or_default().to_os_string();
+            name.push(".");
+            name.push(ext);
+            path.with_file_name(name)
+        }
+        None => path,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "fluminurs-versions-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // a file last modified `age` ago
+    fn touch(path: &Path, age: Duration) {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        let file = fs::File::create(path).unwrap();
+        file.set_modified(SystemTime::now() - age).unwrap();
+    }
+
+    fn names(paths: &[PathBuf]) -> Vec<String> {
+        paths
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn keeps_the_newest_versions_by_count() {
+        let versions = ["a", "b", "c", "d"].map(PathBuf::from);
+        let retention = Retention {
+            keep: Some(2),
+            max_age: None,
+        };
+        assert_eq!(names(&retention.expired(&versions)), ["a", "b"]);
+        let retention = Retention {
+            keep: Some(5),
+            max_age: None,
+        };
+        assert!(retention.expired(&versions).is_empty());
+        assert!(Retention::default().expired(&versions).is_empty());
+    }
+
+    #[test]
+    fn keeps_the_versions_within_the_age_limit() {
+        let dir = scratch_dir("age");
+        let versions = [("old", 30), ("older than a week", 8), ("recent", 1)]
+            .iter()
+            .map(|(name, days)| {
+                let path = dir.join(name);
+                touch(&path, DAY * *days);
+                path
+            })
+            .collect::<Vec<_>>();
+        let retention = Retention {
+            keep: None,
+            max_age: Some(DAY * 7),
+        };
+        assert_eq!(
+            names(&retention.expired(&versions)),
+            ["old", "older than a week"]
+        );
+        // both limits have to be passed
+        let retention = Retention {
+            keep: Some(1),
+            max_age: Some(DAY * 60),
+        };
+        assert_eq!(
+            names(&retention.expired(&versions)),
+            ["old", "older than a week"]
+        );
+    }
+
+    #[test]
+    fn names_old_versions_by_a_custom_template() {
+        let dir = scratch_dir("template");
+        let scheme = RenameScheme {
+            template: "old {time} {name}".to_owned(),
+            time_format: "%Y".to_owned(),
+            folder: Some("versions".to_owned()),
+            retention: Retention::default(),
+        };
+        assert_eq!(scheme.validate(), Ok(()));
+        let path = dir.join("notes.pdf");
+        touch(&path, Duration::ZERO);
+        // the middle of 2024, whatever the time zone
+        let updated = SystemTime::UNIX_EPOCH + Duration::from_secs(1_719_792_000);
+
+        let first = scheme.renamed_path(&path, updated);
+        assert_eq!(first, dir.join("versions").join("old 2024 notes.pdf"));
+        touch(&first, DAY * 2);
+        let second = scheme.renamed_path(&path, updated);
+        assert_eq!(second, dir.join("versions").join("old 2024 notes_1.pdf"));
+        touch(&second, DAY);
+        // neither another file's versions nor other kinds of file are taken for its versions
+        touch(&dir.join("versions").join("old 2024 slides.pdf"), DAY);
+        touch(&dir.join("versions").join("old 2024 notes.txt"), DAY);
+
+        assert_eq!(scheme.versions_of(&path), [first.clone(), second.clone()]);
+        assert_eq!(
+            scheme.find_versions(&dir),
+            [(path.clone(), vec![first.clone(), second.clone()])]
+        );
+
+        let scheme = RenameScheme {
+            retention: Retention {
+                keep: Some(1),
+                max_age: None,
+            },
+            ..scheme
+        };
+        assert_eq!(scheme.prune(&path).unwrap(), vec![first.clone()]);
+        assert!(!first.exists());
+        assert!(second.exists());
+    }
+
+    #[test]
+    fn rejects_templates_that_old_versions_cant_be_told_apart_by() {
+        let scheme = |template: &str| RenameScheme {
+            template: template.to_owned(),
+            ..RenameScheme::default()
+        };
+        assert!(scheme("{time}").validate().is_err());
+        assert!(scheme("{name} {name}").validate().is_err());
+        assert!(scheme("{name} {time} {time}").validate().is_err());
+        assert!(scheme("{name} old").validate().is_ok());
+        let scheme = RenameScheme {
+            folder: Some("a/b".to_owned()),
+            ..RenameScheme::default()
+        };
+        assert!(scheme.validate().is_err());
+    }
+}
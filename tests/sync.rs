@@ -0,0 +1,336 @@
+use std::path::{Path, PathBuf};
+
+use fluminurs::fake_server::{FakeFile, FakeFolder, FakeLuminus, FakeModule};
+use fluminurs::file::{File, FolderCache};
+use fluminurs::login::LoginError;
+use fluminurs::resource::{CompareBy, OverwriteMode, OverwriteResult, Resource};
+use fluminurs::Api;
+
+const TERM: &str = "2010";
+
+fn fake_module() -> FakeModule {
+    let mut module = FakeModule::new("module-1", "CS1010", "Programming Methodology", TERM);
+    module.workbin.folders.push(FakeFolder {
+        id: "folder-1".to_owned(),
+        name: "Lectures".to_owned(),
+        last_updated: "2020-08-01T00:00:00+08:00".to_owned(),
+        files: vec![FakeFile {
+            id: "file-1".to_owned(),
+            name: "Lecture 1.pdf".to_owned(),
+            last_updated: "2020-08-01T00:00:00+08:00".to_owned(),
+            content: b"first version".to_vec(),
+        }],
+        folders: vec![],
+    });
+    module
+}
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("fluminurs-test-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+async fn load_files(api: &Api) -> Vec<File> {
+    let modules = api.modules(Some(TERM.to_owned())).await.unwrap();
+    let mut files = vec![];
+    for module in &modules {
+        let root = module.workbin_root(|dir| dir.to_owned());
+        files.append(&mut root.load(api, false).await.unwrap());
+    }
+    files
+}
+
+async fn download(
+    api: &Api,
+    file: &File,
+    destination: &Path,
+    mode: OverwriteMode,
+) -> OverwriteResult {
+    let path = destination.join(file.path());
+    let temp_path = path.with_extension("tmp");
+    file.download(api, &path, &temp_path, mode).await.unwrap()
+}
+
+#[tokio::test]
+async fn wrong_password_is_reported() {
+    let server = FakeLuminus::start(TERM, vec![]).await.unwrap();
+    let result = Api::with_login_at(server.endpoints(), "e0000000", "wrong").await;
+    assert!(matches!(result, Err(LoginError::WrongPassword)));
+}
+
+#[tokio::test]
+async fn syncs_and_updates_files() {
+    let server = FakeLuminus::start(TERM, vec![fake_module()]).await.unwrap();
+    let api = Api::with_login_at(server.endpoints(), "e0000000", "password")
+        .await
+        .unwrap();
+    let destination = scratch_dir("sync");
+
+    let files = load_files(&api).await;
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].path(), Path::new("CS1010/Lectures/Lecture 1.pdf"));
+    assert!(matches!(
+        download(&api, &files[0], &destination, OverwriteMode::Skip).await,
+        OverwriteResult::NewFile
+    ));
+    assert!(matches!(
+        download(&api, &files[0], &destination, OverwriteMode::Skip).await,
+        OverwriteResult::AlreadyHave
+    ));
+
+    server.update_file("file-1", b"second version", "2020-08-08T00:00:00+08:00");
+    let files = load_files(&api).await;
+    assert!(matches!(
+        download(&api, &files[0], &destination, OverwriteMode::Skip).await,
+        OverwriteResult::Skipped
+    ));
+    assert!(matches!(
+        download(&api, &files[0], &destination, OverwriteMode::Overwrite).await,
+        OverwriteResult::Overwritten
+    ));
+    let content = std::fs::read(destination.join(files[0].path())).unwrap();
+    assert_eq!(content, b"second version");
+
+    let _ = std::fs::remove_dir_all(&destination);
+}
+
+#[tokio::test]
+async fn retries_dropped_connections() {
+    let server = FakeLuminus::start(TERM, vec![fake_module()]).await.unwrap();
+    let api = Api::with_login_at(server.endpoints(), "e0000000", "password")
+        .await
+        .unwrap();
+    let destination = scratch_dir("retry");
+
+    server.fail_next("/v2/api/module", 2);
+    server.fail_next("/download/", 2);
+    let files = load_files(&api).await;
+    assert!(matches!(
+        download(&api, &files[0], &destination, OverwriteMode::Skip).await,
+        OverwriteResult::NewFile
+    ));
+    let content = std::fs::read(destination.join(files[0].path())).unwrap();
+    assert_eq!(content, b"first version");
+    let module_requests = server
+        .requests()
+        .iter()
+        .filter(|request| request.starts_with("GET /v2/api/module"))
+        .count();
+    assert_eq!(module_requests, 3);
+
+    let _ = std::fs::remove_dir_all(&destination);
+}
+
+#[tokio::test]
+async fn waits_as_long_as_the_server_asks() {
+    let server = FakeLuminus::start(TERM, vec![fake_module()]).await.unwrap();
+    let api = Api::with_login_at(server.endpoints(), "e0000000", "password")
+        .await
+        .unwrap();
+    let destination = scratch_dir("throttle");
+
+    let files = load_files(&api).await;
+    server.throttle_next("/download/", 2, 1);
+    let started = std::time::Instant::now();
+    assert!(matches!(
+        download(&api, &files[0], &destination, OverwriteMode::Skip).await,
+        OverwriteResult::NewFile
+    ));
+    assert!(started.elapsed() >= std::time::Duration::from_secs(2));
+    let content = std::fs::read(destination.join(files[0].path())).unwrap();
+    assert_eq!(content, b"first version");
+
+    let _ = std::fs::remove_dir_all(&destination);
+}
+
+#[tokio::test]
+async fn replays_recorded_traffic() {
+    let server = FakeLuminus::start(TERM, vec![fake_module()]).await.unwrap();
+    let recording = scratch_dir("recording");
+    let api = Api::with_login_at(server.endpoints(), "e0000000", "password")
+        .await
+        .unwrap()
+        .with_recording(recording.clone());
+    let recorded = load_files(&api).await;
+
+    let replayed_api = Api::with_token(server.endpoints(), "replay")
+        .unwrap()
+        .with_replay(recording.clone());
+    let requests_before = server.requests().len();
+    let replayed = load_files(&replayed_api).await;
+    assert_eq!(server.requests().len(), requests_before);
+    assert_eq!(
+        recorded.iter().map(|file| file.path()).collect::<Vec<_>>(),
+        replayed.iter().map(|file| file.path()).collect::<Vec<_>>()
+    );
+
+    let _ = std::fs::remove_dir_all(&recording);
+}
+
+#[tokio::test]
+async fn refreshes_download_url_after_repeated_failures() {
+    let server = FakeLuminus::start(TERM, vec![fake_module()]).await.unwrap();
+    let api = Api::with_login_at(server.endpoints(), "e0000000", "password")
+        .await
+        .unwrap();
+    let destination = scratch_dir("refresh");
+
+    server.fail_next("/download/", 4);
+    let files = load_files(&api).await;
+    assert!(matches!(
+        download(&api, &files[0], &destination, OverwriteMode::Skip).await,
+        OverwriteResult::NewFile
+    ));
+    let url_requests = server
+        .requests()
+        .iter()
+        .filter(|request| request.ends_with("/downloadurl"))
+        .count();
+    assert_eq!(url_requests, 2);
+
+    let _ = std::fs::remove_dir_all(&destination);
+}
+
+#[tokio::test]
+async fn compares_copies_by_content() {
+    let server = FakeLuminus::start(TERM, vec![fake_module()]).await.unwrap();
+    let api = Api::with_login_at(server.endpoints(), "e0000000", "password")
+        .await
+        .unwrap()
+        .with_compare_by(CompareBy::Content);
+    let destination = scratch_dir("content");
+    let files = load_files(&api).await;
+    let path = destination.join(files[0].path());
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+
+    // a copy whose last modified time is older than the server's, as after restoring an archive
+    std::fs::write(&path, b"first version").unwrap();
+    filetime::set_file_mtime(&path, filetime::FileTime::zero()).unwrap();
+    assert!(matches!(
+        download(&api, &files[0], &destination, OverwriteMode::Overwrite).await,
+        OverwriteResult::AlreadyHave
+    ));
+    let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+    assert_eq!(modified, files[0].last_updated());
+
+    // the same size, but not the same content, even though it looks newer
+    std::fs::write(&path, b"other version").unwrap();
+    assert!(matches!(
+        download(&api, &files[0], &destination, OverwriteMode::Overwrite).await,
+        OverwriteResult::Overwritten
+    ));
+    assert_eq!(std::fs::read(&path).unwrap(), b"first version");
+
+    let _ = std::fs::remove_dir_all(&destination);
+}
+
+#[tokio::test]
+async fn keeps_old_versions_when_renaming() {
+    let server = FakeLuminus::start(TERM, vec![fake_module()]).await.unwrap();
+    let api = Api::with_login_at(server.endpoints(), "e0000000", "password")
+        .await
+        .unwrap();
+    let destination = scratch_dir("rename");
+
+    let files = load_files(&api).await;
+    download(&api, &files[0], &destination, OverwriteMode::Rename).await;
+    server.update_file("file-1", b"second version", "2020-08-08T00:00:00+08:00");
+    let files = load_files(&api).await;
+    let renamed_path = match download(&api, &files[0], &destination, OverwriteMode::Rename).await {
+        OverwriteResult::Renamed { renamed_path } => renamed_path,
+        _ => panic!("the old version was not renamed"),
+    };
+    assert_ne!(renamed_path, destination.join(files[0].path()));
+    assert_eq!(std::fs::read(&renamed_path).unwrap(), b"first version");
+    let content = std::fs::read(destination.join(files[0].path())).unwrap();
+    assert_eq!(content, b"second version");
+
+    let _ = std::fs::remove_dir_all(&destination);
+}
+
+// the trash on other platforms needs a desktop session, which CI doesn't have
+#[cfg(all(feature = "trash", target_os = "linux"))]
+#[tokio::test]
+async fn moves_old_versions_to_the_trash() {
+    let server = FakeLuminus::start(TERM, vec![fake_module()]).await.unwrap();
+    let api = Api::with_login_at(server.endpoints(), "e0000000", "password")
+        .await
+        .unwrap();
+    let destination = scratch_dir("trash");
+    // so that the test doesn't fill the real trash
+    let trash = scratch_dir("trash-home");
+    std::env::set_var("XDG_DATA_HOME", &trash);
+
+    let files = load_files(&api).await;
+    download(&api, &files[0], &destination, OverwriteMode::Trash).await;
+    server.update_file("file-1", b"second version", "2020-08-08T00:00:00+08:00");
+    let files = load_files(&api).await;
+    assert!(matches!(
+        download(&api, &files[0], &destination, OverwriteMode::Trash).await,
+        OverwriteResult::Trashed
+    ));
+    let content = std::fs::read(destination.join(files[0].path())).unwrap();
+    assert_eq!(content, b"second version");
+    let trashed = std::fs::read(trash.join("Trash/files/Lecture 1.pdf")).unwrap();
+    assert_eq!(trashed, b"first version");
+
+    let _ = std::fs::remove_dir_all(&destination);
+    let _ = std::fs::remove_dir_all(&trash);
+}
+
+#[tokio::test]
+async fn relists_top_folders_whose_date_stayed_the_same() {
+    let mut module = fake_module();
+    module.workbin.folders.push(FakeFolder {
+        id: "folder-2".to_owned(),
+        name: "Tutorials".to_owned(),
+        last_updated: "2020-08-01T00:00:00+08:00".to_owned(),
+        files: vec![],
+        folders: vec![FakeFolder {
+            id: "folder-3".to_owned(),
+            name: "Week 1".to_owned(),
+            last_updated: "2020-08-01T00:00:00+08:00".to_owned(),
+            files: vec![FakeFile {
+                id: "file-2".to_owned(),
+                name: "Tutorial 1.pdf".to_owned(),
+                last_updated: "2020-08-01T00:00:00+08:00".to_owned(),
+                content: b"questions".to_vec(),
+            }],
+            folders: vec![],
+        }],
+    });
+    let server = FakeLuminus::start(TERM, vec![module]).await.unwrap();
+    let api = Api::with_login_at(server.endpoints(), "e0000000", "password")
+        .await
+        .unwrap();
+    let load_cached = |cache| async {
+        let modules = api.modules(Some(TERM.to_owned())).await.unwrap();
+        let root = modules[0].workbin_root(|dir| dir.to_owned());
+        let mut files = root.load_cached(&api, false, Some(&cache)).await.unwrap();
+        files.sort_by(|a, b| a.path().cmp(b.path()));
+        (files, cache.into_snapshots())
+    };
+
+    let (_, snapshots) = load_cached(FolderCache::new(Default::default())).await;
+    // LumiNUS doesn't always move the folder's date when a file in it is updated
+    server.update_file("file-1", b"second version", "2020-08-08T00:00:00+08:00");
+    let requests_before = server.requests().len();
+    let (files, _) = load_cached(FolderCache::new(snapshots)).await;
+    assert_eq!(
+        files.iter().map(|file| file.path()).collect::<Vec<_>>(),
+        [
+            Path::new("CS1010/Lectures/Lecture 1.pdf"),
+            Path::new("CS1010/Tutorials/Week 1/Tutorial 1.pdf"),
+        ]
+    );
+    assert_eq!(
+        files[0].last_updated(),
+        fluminurs::util::parse_time("2020-08-08T00:00:00+08:00")
+    );
+    // a folder further down that hasn't moved and has no folders of its own is taken from the cache
+    assert!(!server.requests()[requests_before..]
+        .iter()
+        .any(|request| request.contains("folder-3")));
+}